@@ -1,49 +1,227 @@
 use crate::{
-    cli::{Cli, Commands},
+    cli::{Cli, Commands, NetworkArgs},
     commands::{self, serve::ServeOptions},
+    config::{UpstreamConfig, ZedexConfig},
+    exit::{Classify, Failure},
+    zed::{
+        RequestOptions, SshTunnel, TunnelGuard, current_request_id, set_request_budget,
+        set_request_options, set_upstream_credentials, set_upstream_proxy,
+    },
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use clap::Parser;
-use env_logger::Builder;
+use env_logger::{Builder, Target};
 use log::{LevelFilter, debug, info};
 use std::io::Write;
+use std::time::Duration;
 
 pub async fn run() -> Result<()> {
     let cli = Cli::parse();
-    init_logging(&cli.log_level, cli.log_timestamp);
+    init_logging(&cli.log_level, cli.log_filter.as_deref(), cli.log_timestamp);
 
     info!("Starting Zed Extension Mirror");
     debug!("Using root directory: {:?}", cli.root_dir);
 
+    let mut config =
+        ZedexConfig::load(cli.config.as_deref(), &cli.root_dir).classify(Failure::Config)?;
+    if let Some(scan_command) = cli.scan_command {
+        config.scan.command = Some(scan_command);
+    }
+    if let Some(max_cache_size) = cli.max_cache_size {
+        config.cache.max_size = Some(max_cache_size);
+    }
+    if cli.strict {
+        config.index.strict = true;
+    }
+    if let Commands::Get { network, .. } | Commands::Release { network, .. } = &cli.command {
+        if network.max_requests.is_some() {
+            config.upstream.max_requests = network.max_requests;
+        }
+        if network.max_requests_per_hour.is_some() {
+            config.upstream.max_requests_per_hour = network.max_requests_per_hour;
+        }
+    }
+    // Held until exit so the tunnel stays open for every upstream request
+    let _tunnel = connect_upstream(&config.upstream).await?;
+
     match cli.command {
-        Commands::Get { target } => {
-            commands::get::run(target, cli.root_dir.clone()).await?;
+        Commands::Get { target, network } => {
+            apply_network_args(network);
+            commands::get::run(target, cli.root_dir.clone(), &config).await?;
         }
-        Commands::Release { target } => {
-            commands::release::run(target, cli.root_dir.clone()).await?;
+        Commands::Release { target, network } => {
+            apply_network_args(network);
+            commands::release::run(target, cli.root_dir.clone(), &config).await?;
         }
         Commands::Serve {
             port,
             host,
+            bind_all,
+            tls_cert,
+            tls_key,
             extensions_dir,
             proxy_mode,
+            pull_through,
             domain,
+            drift_check_interval,
+            sync_interval,
+            verify_per_minute,
+            announce,
+            browse,
+            events,
+            check_config,
         } => {
             let options = ServeOptions {
                 port,
                 host,
+                bind_all,
+                tls_cert,
+                tls_key,
                 extensions_dir,
                 proxy_mode,
+                pull_through,
                 domain,
+                drift_check_interval,
+                sync_interval,
+                verify_per_minute,
+                announce,
+                browse,
+                events,
+                check_config,
             };
-            commands::serve::run(options, cli.root_dir.clone()).await?;
+            commands::serve::run(options, cli.root_dir.clone(), &config).await?;
+        }
+        Commands::Sync {
+            from,
+            rate_limit,
+            output_dir,
+            status_file,
+            triggered_by,
+        } => {
+            let output_dir = output_dir.unwrap_or_else(|| cli.root_dir.clone());
+            let run = commands::sync::SyncRun {
+                from: from.as_deref(),
+                rate_limit,
+                output_dir: &output_dir,
+                root_dir: &cli.root_dir,
+                status_file: status_file.as_deref(),
+                triggered_by: &triggered_by,
+            };
+            commands::sync::run(run, &config).await?;
+        }
+        Commands::Export { target } => {
+            commands::export::run(target, cli.root_dir.clone(), &config).await?;
+        }
+        Commands::Report { target } => {
+            commands::report::run(target, &cli.root_dir, &config, cli.output)?;
+        }
+        Commands::Import { source } => {
+            commands::import::run(source, cli.root_dir.clone(), &config).await?;
+        }
+        Commands::Quarantine {
+            specs,
+            reason,
+            extensions_dir,
+        } => {
+            let extensions_dir = extensions_dir.unwrap_or_else(|| cli.root_dir.clone());
+            commands::quarantine::quarantine(&specs, reason, &extensions_dir, cli.output)?;
+        }
+        Commands::ReleaseFromQuarantine {
+            specs,
+            extensions_dir,
+        } => {
+            let extensions_dir = extensions_dir.unwrap_or_else(|| cli.root_dir.clone());
+            commands::quarantine::release(&specs, &extensions_dir)?;
+        }
+        Commands::Integrity {
+            extensions_dir,
+            verify,
+            jobs,
+            incremental,
+        } => {
+            let extensions_dir = extensions_dir.unwrap_or_else(|| cli.root_dir.clone());
+            commands::integrity::run(&extensions_dir, verify, jobs, incremental, &config)?;
+        }
+        Commands::MigrateCache {
+            extensions_dir,
+            dry_run,
+        } => {
+            let extensions_dir = extensions_dir.unwrap_or_else(|| cli.root_dir.clone());
+            commands::migrate::run(&extensions_dir, dry_run, &config)?;
+        }
+        Commands::Index {
+            action,
+            extensions_dir,
+        } => {
+            let extensions_dir = extensions_dir.unwrap_or_else(|| cli.root_dir.clone());
+            commands::index::run(action, &extensions_dir, cli.output)?;
+        }
+        Commands::Inspect {
+            target,
+            extensions_dir,
+        } => {
+            let extensions_dir = extensions_dir.unwrap_or_else(|| cli.root_dir.clone());
+            commands::inspect::run(&target, &extensions_dir, cli.output)?;
+        }
+        Commands::Selftest {
+            url,
+            extension,
+            timeout,
+        } => {
+            commands::selftest::run(&url, extension.as_deref(), timeout, cli.output).await?;
+        }
+        Commands::Discover { timeout } => {
+            commands::discover::run(timeout, cli.output).await?;
+        }
+        Commands::Status {
+            extensions_dir,
+            top,
+            history,
+        } => {
+            if history {
+                commands::status::history(&cli.root_dir, cli.output)?;
+            } else {
+                commands::status::run(extensions_dir, cli.root_dir.clone(), top, cli.output)?;
+            }
+        }
+        Commands::DnsSetup {
+            ip,
+            apply,
+            revert,
+            hosts_file,
+        } => {
+            commands::dns_setup::run(ip, apply, revert, hosts_file)?;
+        }
+        Commands::ClientConfig {
+            url,
+            host,
+            port,
+            timeout,
+        } => {
+            commands::client_config::run(url.as_deref(), &host, port, timeout, cli.output, &config)
+                .await?;
+        }
+        Commands::SetupClient {
+            url,
+            ip,
+            write,
+            dns,
+            hosts_file,
+            settings_file,
+            timeout,
+        } => {
+            commands::setup_client::run(&url, ip, write, dns, hosts_file, settings_file, timeout)
+                .await?;
         }
     }
 
     Ok(())
 }
 
-fn init_logging(log_level: &str, log_timestamp: bool) {
+/// Modules at the root of the crate, every other `zedex::` module lives under `zed`
+const ROOT_MODULES: [&str; 6] = ["app", "cli", "commands", "config", "exit", "zed"];
+
+fn init_logging(log_level: &str, log_filter: Option<&str>, log_timestamp: bool) {
     let mut builder = Builder::new();
 
     let chosen_level = match log_level {
@@ -55,22 +233,79 @@ fn init_logging(log_level: &str, log_timestamp: bool) {
         _ => LevelFilter::Info,
     };
 
+    // stdout is left to command results, so they can be piped into scripts
+    builder.target(Target::Stderr);
     builder.filter_level(chosen_level);
-
-    if log_timestamp {
-        builder.format(|buf, record| {
-            writeln!(
-                buf,
-                "{} [{}] - {}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                record.level(),
-                record.args()
-            )
-        });
-    } else {
-        builder.format(|buf, record| writeln!(buf, "[{}] - {}", record.level(), record.args()));
+    if let Some(log_filter) = log_filter {
+        builder.parse_filters(&module_filters(log_filter));
     }
 
+    // Lines logged while serving a request carry its ID
+    builder.format(move |buf, record| {
+        if log_timestamp {
+            write!(buf, "{} ", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))?;
+        }
+        write!(buf, "[{}]", record.level())?;
+        if let Some(id) = current_request_id() {
+            write!(buf, " [{}]", id)?;
+        }
+        writeln!(buf, " - {}", record.args())
+    });
+
     // It's OK if init() fails because it was already initialized in tests.
     let _ = builder.try_init();
 }
+
+/// Expand `zedex::server` style module names in log filter directives to the modules'
+/// paths, e.g. `zedex::zed::server`
+fn module_filters(log_filter: &str) -> String {
+    log_filter
+        .split(',')
+        .map(|directive| match directive.trim().strip_prefix("zedex::") {
+            Some(module)
+                if !ROOT_MODULES
+                    .iter()
+                    .any(|root| module.split(['=', ':']).next() == Some(root)) =>
+            {
+                format!("zedex::zed::{}", module)
+            }
+            _ => directive.trim().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Apply the timeouts and retries given on the command line to upstream requests
+fn apply_network_args(network: NetworkArgs) {
+    set_request_options(RequestOptions {
+        timeout: network.timeout.map(Duration::from_secs),
+        connect_timeout: network.connect_timeout.map(Duration::from_secs),
+        retries: network.retries,
+    });
+}
+
+/// Authenticate and budget upstream requests and route them through the configured proxy or ssh tunnel
+async fn connect_upstream(upstream: &UpstreamConfig) -> Result<Option<TunnelGuard>> {
+    set_upstream_credentials(upstream.credentials().classify(Failure::Config)?);
+    set_request_budget(upstream.request_budget());
+    match (&upstream.proxy_url, &upstream.ssh_tunnel) {
+        (Some(_), Some(_)) => Err(anyhow!(
+            "Set either upstream.proxy_url or upstream.ssh_tunnel, not both"
+        ))
+        .classify(Failure::Config),
+        (Some(url), None) => {
+            set_upstream_proxy(url).classify(Failure::Config)?;
+            Ok(None)
+        }
+        (None, Some(tunnel)) => {
+            let tunnel = SshTunnel {
+                destination: tunnel.destination.clone(),
+                port: tunnel.port,
+                identity_file: tunnel.identity_file.clone(),
+                local_port: tunnel.local_port,
+            };
+            Ok(Some(tunnel.open().await.classify(Failure::Network)?))
+        }
+        (None, None) => Ok(None),
+    }
+}
@@ -1,4 +1,6 @@
-use clap::{Parser, Subcommand};
+use crate::zed::BundleCompression;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::net::IpAddr;
 use std::path::PathBuf;
 
 /// Command Line Interface definition for the zedex binary.
@@ -9,30 +11,73 @@ pub struct Cli {
     #[clap(long, default_value = ".zedex-cache")]
     pub root_dir: PathBuf,
 
+    /// Path to the configuration file (defaults to zedex.toml in the root directory)
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
+    /// Command used to scan each downloaded archive (e.g. "clamscan {path}");
+    /// archives failing the scan are quarantined
+    #[clap(long)]
+    pub scan_command: Option<String>,
+
+    /// Maximum size of the extensions directory (e.g. "200GB"); downloads stop, or evict
+    /// least recently served versions, once it is reached
+    #[clap(long)]
+    pub max_cache_size: Option<String>,
+
+    /// Fail when extensions.json has invalid entries instead of dropping them
+    #[clap(long)]
+    pub strict: bool,
+
     /// Log level: trace, debug, info, warn, error
     #[clap(long, default_value = "info")]
     pub log_level: String,
 
+    /// Per-module log levels overriding --log-level, in env_logger syntax
+    /// (e.g. "zedex::server=debug,zedex::downloader=info,actix_web=warn")
+    #[clap(long)]
+    pub log_filter: Option<String>,
+
     /// Enable timestamp in logs
     #[clap(long)]
     pub log_timestamp: bool,
 
+    /// Format of command results printed to stdout; logs and progress go to stderr
+    #[clap(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
     #[clap(subcommand)]
     pub command: Commands,
 }
 
+/// How commands print their results
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Tables and messages for people
+    #[default]
+    Text,
+    /// JSON for scripts
+    Json,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Fetch extensions
     Get {
         #[clap(subcommand)]
         target: GetTarget,
+
+        #[clap(flatten)]
+        network: NetworkArgs,
     },
 
     /// Fetch Zed releases
     Release {
         #[clap(subcommand)]
         target: ReleaseTarget,
+
+        #[clap(flatten)]
+        network: NetworkArgs,
     },
 
     /// Start a local server to serve Zed extensions API
@@ -41,9 +86,22 @@ pub enum Commands {
         #[clap(long, default_value = "2654")]
         port: u16,
 
-        /// Host IP address to bind the server to
-        #[clap(long, default_value = "127.0.0.1")]
-        host: String,
+        /// Host IP address to bind the server to (defaults to 127.0.0.1, or 0.0.0.0 when
+        /// running in a container)
+        #[clap(long)]
+        host: Option<String>,
+
+        /// Bind to all interfaces (0.0.0.0), making the mirror reachable from other machines
+        #[clap(long, conflicts_with = "host")]
+        bind_all: bool,
+
+        /// PEM certificate chain to serve HTTPS with, overriding tls_cert in [server]
+        #[clap(long)]
+        tls_cert: Option<PathBuf>,
+
+        /// PEM private key matching the certificate, overriding tls_key in [server]
+        #[clap(long)]
+        tls_key: Option<PathBuf>,
 
         /// Directory containing extension archives and metadata
         #[clap(long)]
@@ -53,9 +111,381 @@ pub enum Commands {
         #[clap(long)]
         proxy_mode: bool,
 
+        /// Download extension archives missing from the cache from zed.dev on first request
+        /// and keep them, so only the index needs to be mirrored up front
+        #[clap(long)]
+        pull_through: bool,
+
         /// Domain to use in URLs (e.g. http://localhost:2654)
         #[clap(long)]
         domain: Option<String>,
+
+        /// Interval in seconds between upstream drift checks (defaults to hourly in proxy mode)
+        #[clap(long)]
+        drift_check_interval: Option<u64>,
+
+        /// Refresh the index and download changed extensions into the extensions directory
+        /// every this many seconds, as `zedex sync` does
+        #[clap(long)]
+        sync_interval: Option<u64>,
+
+        /// Re-hash this many archives per minute in the background to catch corruption
+        #[clap(long)]
+        verify_per_minute: Option<usize>,
+
+        /// Announce the mirror on the local network over mDNS (`_zedex._tcp`)
+        #[clap(long)]
+        announce: bool,
+
+        /// Serve HTML index pages for /releases and /extensions-archive
+        #[clap(long)]
+        browse: bool,
+
+        /// Stream newly mirrored extension versions and releases as Server-Sent Events on
+        /// /events
+        #[clap(long)]
+        events: bool,
+
+        /// Validate the configuration, directories and listen address, print the effective
+        /// configuration and exit without serving
+        #[clap(long)]
+        check_config: bool,
+    },
+
+    /// Bring the mirror up to date: refresh the index and download only the extensions
+    /// whose version changed since the last run, or with --from replicate another zedex
+    /// mirror, transferring only files whose checksums differ
+    Sync {
+        /// Base URL of the zedex mirror to sync from (e.g. http://mirror:2654)
+        #[clap(long)]
+        from: Option<String>,
+
+        /// Rate limit between API requests in seconds when syncing from upstream
+        #[clap(long, default_value = "10", conflicts_with = "from")]
+        rate_limit: u64,
+
+        /// Directory to store the replicated extensions in
+        #[clap(long)]
+        output_dir: Option<PathBuf>,
+
+        /// JSON file continuously updated with the progress of the sync
+        #[clap(long)]
+        status_file: Option<PathBuf>,
+
+        /// What started the sync, recorded in the sync history (e.g. cron)
+        #[clap(long, default_value = "manual")]
+        triggered_by: String,
+    },
+
+    /// Pull extension versions out of serving without deleting them, or list quarantined
+    /// versions when none are given
+    Quarantine {
+        /// Extension versions to quarantine, as `id@version`
+        specs: Vec<String>,
+
+        /// Why the versions are quarantined
+        #[clap(long)]
+        reason: Option<String>,
+
+        /// Directory containing extension archives and metadata
+        #[clap(long)]
+        extensions_dir: Option<PathBuf>,
+    },
+
+    /// Restore quarantined extension versions so they are served again
+    ReleaseFromQuarantine {
+        /// Extension versions to restore, as `id@version`
+        #[clap(required = true)]
+        specs: Vec<String>,
+
+        /// Directory containing extension archives and metadata
+        #[clap(long)]
+        extensions_dir: Option<PathBuf>,
+    },
+
+    /// Export parts of the mirror for offline use
+    Export {
+        #[clap(subcommand)]
+        target: ExportTarget,
+    },
+
+    /// Report on the contents of the mirror
+    Report {
+        #[clap(subcommand)]
+        target: ReportTarget,
+    },
+
+    /// Mirror extensions used by an existing installation
+    Import {
+        #[clap(subcommand)]
+        source: ImportSource,
+    },
+
+    /// Write the INTEGRITY.json manifest of every mirrored directory, or check the files
+    /// against them
+    Integrity {
+        /// Directory containing extension archives and metadata
+        #[clap(long)]
+        extensions_dir: Option<PathBuf>,
+
+        /// Check the files against the existing manifests instead of writing them
+        #[clap(long)]
+        verify: bool,
+
+        /// Number of files hashed at once when verifying, all cores by default
+        #[clap(long, requires = "verify")]
+        jobs: Option<usize>,
+
+        /// Only hash files modified since the last verification that found no problems,
+        /// checking the size of the others
+        #[clap(long, requires = "verify")]
+        incremental: bool,
+    },
+
+    /// Rename archives and release assets stored by older versions of zedex to the current
+    /// names
+    MigrateCache {
+        /// Directory containing extension archives and metadata
+        #[clap(long)]
+        extensions_dir: Option<PathBuf>,
+
+        /// List the renames without making them
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// List the saved snapshots of the extension index, or roll back to one
+    Index {
+        #[clap(subcommand)]
+        action: IndexAction,
+
+        /// Directory containing extension archives and metadata
+        #[clap(long, global = true)]
+        extensions_dir: Option<PathBuf>,
+    },
+
+    /// Show what an extension archive contains and flag suspicious entries
+    Inspect {
+        /// Path of an archive, or a mirrored extension as `id` or `id@version`
+        target: String,
+
+        /// Directory containing extension archives and metadata
+        #[clap(long)]
+        extensions_dir: Option<PathBuf>,
+    },
+
+    /// Request what a Zed client requests from a mirror (index, update check, version
+    /// listing, a download and the latest release) and report pass or fail per check
+    Selftest {
+        /// Base URL of the mirror to test (e.g. http://mirror:2654)
+        #[clap(long)]
+        url: String,
+
+        /// Extension to check updates, versions and the download with (defaults to the
+        /// first one in the index)
+        #[clap(long)]
+        extension: Option<String>,
+
+        /// Seconds each request may take
+        #[clap(long, default_value = "30")]
+        timeout: u64,
+    },
+
+    /// List zedex mirrors announced on the local network
+    Discover {
+        /// Seconds to wait for announcements
+        #[clap(long, default_value = "3")]
+        timeout: u64,
+    },
+
+    /// Show bandwidth and transfer statistics recorded by the server
+    Status {
+        /// Directory containing extension archives and metadata
+        #[clap(long)]
+        extensions_dir: Option<PathBuf>,
+
+        /// Number of extensions to list, ordered by bytes served
+        #[clap(long, default_value = "10")]
+        top: usize,
+
+        /// Show the recorded `get all-extensions` and `sync` runs instead
+        #[clap(long)]
+        history: bool,
+    },
+
+    /// Print the hosts-file entries pointing zed.dev and api.zed.dev at the mirror, or
+    /// apply them to this machine's hosts file
+    DnsSetup {
+        /// Address of the mirror (defaults to this machine's address)
+        #[clap(long)]
+        ip: Option<IpAddr>,
+
+        /// Write the entries to the hosts file (needs root or Administrator)
+        #[clap(long)]
+        apply: bool,
+
+        /// Remove the entries written by --apply
+        #[clap(long, conflicts_with_all = ["apply", "ip"])]
+        revert: bool,
+
+        /// Hosts file to print for or change (defaults to the system hosts file)
+        #[clap(long)]
+        hosts_file: Option<PathBuf>,
+    },
+
+    /// Print the Zed settings and environment variables pointing Zed at a mirror, and check
+    /// the mirror serves every route they lead Zed to
+    ClientConfig {
+        /// Mirror to ask for its configuration (derived from [server] and --host and
+        /// --port when unset)
+        #[clap(long)]
+        url: Option<String>,
+
+        /// Host clients reach the mirror at, unless external_url is set under [server]
+        #[clap(long, default_value = "localhost", conflicts_with = "url")]
+        host: String,
+
+        /// Port the mirror is served on
+        #[clap(long, default_value = "2654", conflicts_with = "url")]
+        port: u16,
+
+        /// Seconds each request may take
+        #[clap(long, default_value = "30")]
+        timeout: u64,
+    },
+
+    /// Print the Zed setting, environment variable and hosts-file entries pointing a Zed
+    /// installation at a mirror, after checking each endpoint they lead to, or write them
+    SetupClient {
+        /// URL Zed should reach the mirror at (e.g. https://zed.example.com)
+        #[clap(long)]
+        url: String,
+
+        /// Address zed.dev and api.zed.dev resolve to in the hosts file (defaults to the
+        /// address of the URL's host)
+        #[clap(long)]
+        ip: Option<IpAddr>,
+
+        /// Write server_url to the Zed settings file, and with --dns the hosts-file entries
+        /// (needs root or Administrator), once every check passed
+        #[clap(long)]
+        write: bool,
+
+        /// Also check the mirror answers for zed.dev and api.zed.dev at that address, as a
+        /// client with the hosts-file entries reaches it
+        #[clap(long)]
+        dns: bool,
+
+        /// Hosts file to write (defaults to the system hosts file)
+        #[clap(long)]
+        hosts_file: Option<PathBuf>,
+
+        /// Zed settings file to write (defaults to the current user's)
+        #[clap(long)]
+        settings_file: Option<PathBuf>,
+
+        /// Seconds each request may take
+        #[clap(long, default_value = "30")]
+        timeout: u64,
+    },
+}
+
+/// Timeouts and retries of upstream requests, for slow or flaky links
+#[derive(Args, Debug)]
+pub struct NetworkArgs {
+    /// Seconds allowed for each request, including the download itself
+    #[clap(long, global = true)]
+    pub timeout: Option<u64>,
+
+    /// Seconds allowed to establish a connection
+    #[clap(long, global = true)]
+    pub connect_timeout: Option<u64>,
+
+    /// How often a request is retried after a connection error, timeout or 429/5xx response
+    #[clap(long, global = true, default_value = "0")]
+    pub retries: u32,
+
+    /// Upstream requests the run may make before it stops with an error
+    #[clap(long, global = true)]
+    pub max_requests: Option<u64>,
+
+    /// Upstream requests allowed per hour, spaced evenly across the hour
+    #[clap(long, global = true)]
+    pub max_requests_per_hour: Option<u64>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExportTarget {
+    /// Build a ready-to-serve cache with one Zed release and the extension versions it can load
+    ClientBundle {
+        /// Zed version the bundle is built for (e.g. 0.187.8)
+        #[clap(long)]
+        zed_version: String,
+
+        /// Comma-separated extension ids (optionally id@version), or a file listing one per line
+        #[clap(long)]
+        extensions: String,
+
+        /// Only include the Zed release for this platform, as os-arch (e.g. linux-x86_64)
+        #[clap(long)]
+        platform: Option<String>,
+
+        /// Directory to write the bundle to (defaults to zedex-bundle-<zed-version>)
+        #[clap(long)]
+        output_dir: Option<PathBuf>,
+
+        /// Also pack the bundle into a tarball next to the directory
+        #[clap(long)]
+        archive: bool,
+
+        /// Compression of the packed bundle, zstd (.tar.zst) or gzip (.tar.gz)
+        #[clap(long, default_value = "zstd")]
+        compression: BundleCompression,
+
+        /// Split the packed bundle into parts of at most this size (e.g. 4GB for FAT32),
+        /// named <bundle>.000, <bundle>.001, ...
+        #[clap(long)]
+        split_size: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IndexAction {
+    /// List the snapshots taken before each index update, oldest first
+    Snapshots,
+
+    /// Serve a snapshot of the index again, e.g. after a bad upstream sync
+    Rollback {
+        /// Timestamp of the snapshot, as listed by `zedex index snapshots`
+        timestamp: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImportSource {
+    /// Mirror the extensions installed in a local Zed, at their installed versions
+    ZedConfig {
+        /// Zed configuration directory (defaults to ~/.config/zed)
+        #[clap(long)]
+        path: Option<PathBuf>,
+
+        /// Zed data directory holding installed extensions (defaults to the platform location)
+        #[clap(long)]
+        data_path: Option<PathBuf>,
+
+        /// Output directory for downloaded extensions
+        #[clap(long)]
+        output_dir: Option<PathBuf>,
+    },
+
+    /// Merge a bundle packed by `zedex export ... --archive` into the mirror
+    Bundle {
+        /// The packed bundle, or the first of its parts
+        path: PathBuf,
+
+        /// Mirror directory to merge into (defaults to the root directory)
+        #[clap(long)]
+        output_dir: Option<PathBuf>,
     },
 }
 
@@ -70,13 +500,34 @@ pub enum GetTarget {
 
     /// Fetch a specific extension by ID
     Extension {
-        /// The IDs of the extensions to download
+        /// The IDs of the extensions to download, optionally pinned as `id@version`
         #[clap(required = true)]
         ids: Vec<String>,
 
         /// Output directory for downloaded extensions
         #[clap(long)]
         output_dir: Option<PathBuf>,
+
+        /// Don't mirror the extensions that the downloaded ones declare as dependencies
+        #[clap(long)]
+        no_dependencies: bool,
+
+        /// Base URL of a zedex mirror to download from instead of zed.dev (e.g.
+        /// http://mirror:2654)
+        #[clap(long)]
+        from_server: Option<String>,
+    },
+
+    /// Fetch the versions.json of extensions, listing every published version, without
+    /// downloading any archive
+    ExtensionVersions {
+        /// The IDs of the extensions
+        #[clap(required = true)]
+        ids: Vec<String>,
+
+        /// Output directory for the version listings
+        #[clap(long)]
+        output_dir: Option<PathBuf>,
     },
 
     /// Fetch all extensions listed in extensions.json
@@ -96,6 +547,41 @@ pub enum GetTarget {
         /// Rate limit between API requests in seconds (to avoid overwhelming the server)
         #[clap(long, default_value = "10")]
         rate_limit: u64,
+
+        /// Only download extension versions compatible with this Zed release (e.g. 0.187.8)
+        #[clap(long)]
+        zed_version: Option<String>,
+
+        /// JSON file continuously updated with the progress of the run
+        #[clap(long)]
+        status_file: Option<PathBuf>,
+
+        /// What started the run, recorded in the sync history (e.g. cron)
+        #[clap(long, default_value = "manual")]
+        triggered_by: String,
+
+        /// Also mirror each extension's README and icon from its GitHub repository, for
+        /// extension browsers showing them offline
+        #[clap(long)]
+        with_assets: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReportTarget {
+    /// List which mirrored extensions and versions a Zed release can load
+    Compatibility {
+        /// Zed version to check against (e.g. 0.187.8)
+        #[clap(long)]
+        zed_version: String,
+
+        /// Directory containing extension archives and metadata
+        #[clap(long)]
+        extensions_dir: Option<PathBuf>,
+
+        /// Print the report as JSON, like `--output json`
+        #[clap(long)]
+        json: bool,
     },
 }
 
@@ -112,6 +598,14 @@ pub enum ReleaseTarget {
         #[clap(long)]
         /// Output directory for downloaded Zed release
         output_dir: Option<PathBuf>,
+
+        /// Platforms to download, as os-arch (e.g. linux-x86_64, defaults to this machine's)
+        #[clap(long, value_delimiter = ',', conflicts_with = "all_platforms")]
+        platform: Vec<String>,
+
+        /// Download every supported platform instead of only this machine's
+        #[clap(long)]
+        all_platforms: bool,
     },
 
     /// Download the latest Zed Remote Server release
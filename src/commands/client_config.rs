@@ -0,0 +1,134 @@
+use crate::cli::OutputFormat;
+use crate::config::ZedexConfig;
+use crate::exit::{Classify, Failure};
+use crate::zed::{ClientConfig, current_platform};
+use anyhow::{Context, Result, anyhow, bail};
+use log::info;
+use serde::Serialize;
+use std::time::Duration;
+
+/// A route of the client configuration requested from the mirror
+#[derive(Debug, Serialize)]
+struct RouteCheck {
+    name: String,
+    url: String,
+    passed: bool,
+    /// The answer, or why the request failed
+    detail: String,
+}
+
+/// Entry point for `zedex client-config`, printing the settings and environment pointing
+/// Zed at a mirror and checking the mirror serves every route they lead Zed to. The
+/// configuration is asked from the mirror at `url`, or else derived from `external_url`
+/// and TLS under [server] and the given host and port.
+pub async fn run(
+    url: Option<&str>,
+    host: &str,
+    port: u16,
+    timeout_secs: u64,
+    output: OutputFormat,
+    config: &ZedexConfig,
+) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()?;
+
+    let client_config = match url {
+        Some(url) => {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(anyhow!("{} is not an http:// or https:// URL", url))
+                    .classify(Failure::Config);
+            }
+            let endpoint = format!("{}/client-config", url.trim_end_matches('/'));
+            info!("Asking {} for its client configuration", endpoint);
+            let response = client
+                .get(&endpoint)
+                .send()
+                .await
+                .with_context(|| format!("Cannot reach {}", endpoint))?;
+            if !response.status().is_success() {
+                bail!("{} answered {}", endpoint, response.status());
+            }
+            response
+                .json::<ClientConfig>()
+                .await
+                .with_context(|| format!("{} sent no client configuration", endpoint))?
+        }
+        None => ClientConfig::new(&server_url(host, port, config)),
+    };
+
+    let platform = current_platform();
+    let (os, arch) = platform.split_once('-').unwrap_or((&platform, ""));
+    let mut checks = Vec::new();
+    for route in &client_config.routes {
+        let url = route.url(&client_config.server_url, os, arch);
+        let result = match client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => {
+                Ok(format!("answered {}", response.status()))
+            }
+            Ok(response) => Err(format!("answered {}", response.status())),
+            Err(e) => Err(e.to_string()),
+        };
+        checks.push(RouteCheck {
+            name: route.name.clone(),
+            url,
+            passed: result.is_ok(),
+            detail: result.unwrap_or_else(|e| e),
+        });
+    }
+
+    if output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "config": client_config,
+                "checks": checks,
+            }))?
+        );
+    } else {
+        println!("Zed settings (settings.json):\n");
+        println!("{}", serde_json::to_string_pretty(&client_config.settings)?);
+        println!("\nOr, instead of the settings, in Zed's environment:\n");
+        for (name, value) in &client_config.env {
+            println!("  export {}={}", name, value);
+        }
+        println!("\nRoutes Zed will request, as a {} client:\n", platform);
+        for check in &checks {
+            println!(
+                "  {:<4}  {:<15} {}  {}",
+                if check.passed { "PASS" } else { "FAIL" },
+                check.name,
+                check.url,
+                check.detail
+            );
+        }
+    }
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        return Err(Failure::Verification.wrap(anyhow!(
+            "{} of {} routes aren't served at {}",
+            failed,
+            checks.len(),
+            client_config.server_url
+        )));
+    }
+    Ok(())
+}
+
+/// URL clients reach a mirror served with this configuration at: `external_url` when set,
+/// otherwise the host and port over HTTPS when [server] has TLS configured
+fn server_url(host: &str, port: u16, config: &ZedexConfig) -> String {
+    if let Some(external_url) = &config.server.external_url {
+        return external_url.trim_end_matches('/').to_string();
+    }
+    let scheme = if config.server.tls_cert.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    match (scheme, port) {
+        ("https", 443) | ("http", 80) => format!("{}://{}", scheme, host),
+        _ => format!("{}://{}:{}", scheme, host, port),
+    }
+}
@@ -0,0 +1,60 @@
+use crate::cli::OutputFormat;
+use crate::zed::discover;
+use anyhow::Result;
+use log::info;
+use serde_json::json;
+use std::time::Duration;
+
+/// How long a discovered mirror gets to answer its health check
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Entry point for `zedex discover`, listing mirrors announced on the local network.
+pub async fn run(timeout_secs: u64, output: OutputFormat) -> Result<()> {
+    info!("Browsing for mirrors for {} seconds...", timeout_secs);
+    let mirrors = discover(Duration::from_secs(timeout_secs)).await?;
+    if mirrors.is_empty() && output == OutputFormat::Text {
+        println!("No mirrors found. Start one with `zedex serve --announce`");
+        return Ok(());
+    }
+
+    // Mirrors live on the LAN, so health checks bypass any upstream proxy
+    let client = reqwest::Client::builder()
+        .timeout(HEALTH_CHECK_TIMEOUT)
+        .danger_accept_invalid_certs(true)
+        .build()?;
+
+    let mut found = Vec::new();
+    for mirror in mirrors {
+        let status = match client.get(format!("{}/health", mirror.url)).send().await {
+            Ok(response) => response
+                .json::<serde_json::Value>()
+                .await
+                .ok()
+                .and_then(|health| health["status"].as_str().map(str::to_string))
+                .unwrap_or_else(|| "UNKNOWN".to_string()),
+            Err(_) => "UNREACHABLE".to_string(),
+        };
+
+        if output == OutputFormat::Json {
+            found.push(json!({
+                "name": mirror.name,
+                "url": mirror.url,
+                "version": mirror.version,
+                "status": status,
+            }));
+            continue;
+        }
+        println!(
+            "  {:<40} {:<30} {:<10} {}",
+            mirror.name,
+            mirror.url,
+            mirror.version.as_deref().unwrap_or("-"),
+            status
+        );
+    }
+
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&found)?);
+    }
+    Ok(())
+}
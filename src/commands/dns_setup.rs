@@ -0,0 +1,148 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+
+/// Hosts Zed talks to that the mirror answers for
+pub const ZED_HOSTS: [&str; 2] = ["zed.dev", "api.zed.dev"];
+
+/// Markers around the entries zedex manages, so they can be replaced or removed
+const BEGIN_MARKER: &str = "# BEGIN zedex mirror";
+const END_MARKER: &str = "# END zedex mirror";
+
+#[cfg(windows)]
+pub const DEFAULT_HOSTS_FILE: &str = r"C:\Windows\System32\drivers\etc\hosts";
+#[cfg(not(windows))]
+pub const DEFAULT_HOSTS_FILE: &str = "/etc/hosts";
+
+/// Entry point for `zedex dns-setup`, printing, applying or reverting the hosts-file
+/// entries that point Zed's hosts at the mirror.
+pub fn run(
+    ip: Option<IpAddr>,
+    apply: bool,
+    revert: bool,
+    hosts_file: Option<PathBuf>,
+) -> Result<()> {
+    let hosts_file = hosts_file.unwrap_or_else(|| PathBuf::from(DEFAULT_HOSTS_FILE));
+
+    if revert {
+        let current = read_hosts(&hosts_file)?;
+        let (reverted, found) = remove_block(&current);
+        if !found {
+            println!("No zedex entries in {:?}", hosts_file);
+            return Ok(());
+        }
+        write_hosts(&hosts_file, &reverted)?;
+        println!("Removed the zedex entries from {:?}", hosts_file);
+        return Ok(());
+    }
+
+    let ip = match ip {
+        Some(ip) => ip,
+        None => local_ip().context("Cannot determine this machine's address, pass --ip")?,
+    };
+    if !apply {
+        println!("Add these lines to {:?} on each client:\n", hosts_file);
+        println!("{}", hosts_block(ip));
+        println!(
+            "Apply them with `zedex dns-setup --ip {} --apply` as root or Administrator.",
+            ip
+        );
+        print_tls_note();
+        return Ok(());
+    }
+
+    apply_hosts(ip, &hosts_file)?;
+    println!(
+        "{} now resolve to {} via {:?}",
+        ZED_HOSTS.join(" and "),
+        ip,
+        hosts_file
+    );
+    print_tls_note();
+    Ok(())
+}
+
+/// Write the entries pointing Zed's hosts at `ip` to a hosts file, replacing earlier ones
+pub fn apply_hosts(ip: IpAddr, hosts_file: &Path) -> Result<()> {
+    let current = read_hosts(hosts_file)?;
+    let (mut updated, _) = remove_block(&current);
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated.push_str(&hosts_block(ip));
+    write_hosts(hosts_file, &updated)
+}
+
+/// The marked hosts-file block mapping Zed's hosts to `ip`
+pub fn hosts_block(ip: IpAddr) -> String {
+    let mut block = format!("{}\n", BEGIN_MARKER);
+    for host in ZED_HOSTS {
+        block.push_str(&format!("{} {}\n", ip, host));
+    }
+    block.push_str(END_MARKER);
+    block.push('\n');
+    block
+}
+
+/// The hosts file without the zedex block, and whether there was one
+fn remove_block(content: &str) -> (String, bool) {
+    let mut kept = String::with_capacity(content.len());
+    let mut inside = false;
+    let mut found = false;
+    for line in content.lines() {
+        match line.trim() {
+            BEGIN_MARKER => {
+                inside = true;
+                found = true;
+            }
+            END_MARKER if inside => inside = false,
+            _ if inside => {}
+            _ => {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+    }
+    (kept, found)
+}
+
+fn read_hosts(path: &Path) -> Result<String> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e).with_context(|| format!("Cannot read {:?}", path)),
+    }
+}
+
+fn write_hosts(path: &Path, content: &str) -> Result<()> {
+    match fs::write(path, content) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => bail!(
+            "Cannot write {:?}: permission denied. Run again with sudo, or as Administrator on Windows",
+            path
+        ),
+        Err(e) => Err(e).with_context(|| format!("Cannot write {:?}", path)),
+    }
+}
+
+/// Address this machine reaches the network with. Connecting a UDP socket sends nothing,
+/// it only picks the outgoing interface.
+fn local_ip() -> Result<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect("192.0.2.1:80")?;
+    let ip = socket.local_addr()?.ip();
+    if ip.is_unspecified() {
+        bail!("No network interface with a route");
+    }
+    Ok(ip)
+}
+
+fn print_tls_note() {
+    println!(
+        "\nZed connects to https://zed.dev on port 443: the mirror must serve HTTPS there with a \
+         certificate for {} that clients trust (tls_cert and tls_key under [server]).",
+        ZED_HOSTS.join(" and ")
+    );
+}
@@ -0,0 +1,331 @@
+use crate::cli::ExportTarget;
+use crate::config::ZedexConfig;
+use crate::zed::{
+    Client, Extension, ExtensionCompatibility, ExtensionVersionTracker, QuarantineRegistry,
+    Version, WrappedExtensions, archive_name, find_archive, load_index, pack_bundle, parse_size,
+    write_release_checksums,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Entry point for handling `zedex export ...` commands.
+pub async fn run(target: ExportTarget, root_dir: PathBuf, config: &ZedexConfig) -> Result<()> {
+    match target {
+        ExportTarget::ClientBundle {
+            zed_version,
+            extensions,
+            platform,
+            output_dir,
+            archive,
+            compression,
+            split_size,
+        } => {
+            let part_size = split_size
+                .as_deref()
+                .map(parse_size)
+                .transpose()
+                .context("Invalid --split-size")?;
+            let output_dir = output_dir
+                .unwrap_or_else(|| PathBuf::from(format!("zedex-bundle-{}", zed_version)));
+            let specs = parse_extension_list(&extensions)?;
+
+            export_client_bundle(
+                &root_dir,
+                &output_dir,
+                &zed_version,
+                &specs,
+                platform.as_deref(),
+                config,
+            )
+            .await?;
+
+            if archive {
+                let mut archive_path = output_dir.clone().into_os_string();
+                archive_path.push(format!(".{}", compression.extension()));
+                let archive_path = PathBuf::from(archive_path);
+                let files = pack_bundle(&output_dir, &archive_path, compression, part_size)?;
+                match files.as_slice() {
+                    [file] => info!("Bundle packed into {:?}", file),
+                    files => info!(
+                        "Bundle packed into {} parts, {:?} to {:?}",
+                        files.len(),
+                        files[0],
+                        files[files.len() - 1]
+                    ),
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Read extension specs from a comma-separated list, or from a file with one spec per line
+fn parse_extension_list(extensions: &str) -> Result<Vec<String>> {
+    let path = Path::new(extensions);
+    let specs: Vec<String> = if path.is_file() {
+        fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    } else {
+        extensions
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    if specs.is_empty() {
+        bail!("No extensions given");
+    }
+    Ok(specs)
+}
+
+async fn export_client_bundle(
+    root_dir: &Path,
+    output_dir: &Path,
+    zed_version: &str,
+    specs: &[String],
+    platform: Option<&str>,
+    config: &ZedexConfig,
+) -> Result<()> {
+    let compatibility = ExtensionCompatibility::for_zed_version(zed_version)?;
+    let client = Client::new();
+    let yanked = config.extensions.yanked_versions();
+    let quarantine = QuarantineRegistry::load(root_dir)?;
+    let index_file = root_dir.join("extensions.json");
+    let local_index = if index_file.exists() {
+        load_index(&index_file, config.index.strict)?
+    } else {
+        Vec::new()
+    };
+
+    fs::create_dir_all(output_dir)?;
+    info!(
+        "Building client bundle for Zed {} in {:?}",
+        zed_version, output_dir
+    );
+
+    let mut bundled = Vec::new();
+    let mut tracker = ExtensionVersionTracker::new();
+
+    for spec in specs {
+        let (id, requested) = match spec.split_once('@') {
+            Some((id, version)) => (id, Some(version)),
+            None => (
+                spec.as_str(),
+                config.extensions.pins.get(spec).map(String::as_str),
+            ),
+        };
+
+        let versions = match load_versions(&root_dir.join(id).join("versions.json")) {
+            Some(versions) => versions,
+            None => match client.get_extension_versions(id).await {
+                Ok(versions) => versions,
+                Err(e) => {
+                    warn!("Could not list versions of {} upstream: {}", id, e);
+                    local_index.iter().filter(|e| e.id == id).cloned().collect()
+                }
+            },
+        };
+
+        let servable: Vec<Extension> = versions
+            .into_iter()
+            .filter(|ext| {
+                !yanked
+                    .get(id)
+                    .is_some_and(|versions| versions.contains(&ext.version))
+                    && !quarantine.is_quarantined(id, &ext.version)
+            })
+            .collect();
+
+        let extension = match requested {
+            Some(version) => {
+                let extension = servable
+                    .iter()
+                    .find(|ext| ext.version == version)
+                    .ok_or_else(|| anyhow!("Version {} of {} is not available", version, id))?;
+                if !compatibility.is_compatible(extension) {
+                    warn!(
+                        "{} {} is not compatible with Zed {}, bundling it anyway",
+                        id, version, zed_version
+                    );
+                }
+                extension.clone()
+            }
+            None => compatibility
+                .newest_compatible(&servable)
+                .cloned()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No version of {} compatible with Zed {} is available",
+                        id,
+                        zed_version
+                    )
+                })?,
+        };
+
+        let bytes = archive_bytes(root_dir, &client, &extension).await?;
+        let ext_dir = output_dir.join(id);
+        fs::create_dir_all(&ext_dir)?;
+        fs::write(ext_dir.join(archive_name(id, None)), &bytes)?;
+        fs::write(
+            ext_dir.join(archive_name(id, Some(&extension.version))),
+            &bytes,
+        )?;
+        write_index(&ext_dir.join("versions.json"), vec![extension.clone()])?;
+
+        info!("Bundled {} {}", id, extension.version);
+        tracker.update_extension(&extension);
+        bundled.push(extension);
+    }
+
+    write_index(&output_dir.join("extensions.json"), bundled)?;
+    fs::write(
+        output_dir.join("version_tracker.json"),
+        serde_json::to_string_pretty(&tracker)?,
+    )?;
+
+    export_release(root_dir, output_dir, zed_version, platform, &client).await?;
+    write_release_checksums(&output_dir.join("releases"))?;
+    config.signing.sign_metadata(output_dir)?;
+
+    info!(
+        "Client bundle ready, serve it with: zedex --root-dir {} serve",
+        output_dir.display()
+    );
+    Ok(())
+}
+
+/// Archive bytes for an extension version, from the local cache or upstream
+async fn archive_bytes(root_dir: &Path, client: &Client, extension: &Extension) -> Result<Vec<u8>> {
+    let id = &extension.id;
+
+    if let Some(versioned) = find_archive(root_dir, id, Some(&extension.version)) {
+        return Ok(fs::read(versioned)?);
+    }
+
+    let latest = find_archive(root_dir, id, None);
+    let tracked_version = fs::read_to_string(root_dir.join("version_tracker.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<ExtensionVersionTracker>(&content).ok())
+        .and_then(|tracker| tracker.extensions.get(id).cloned());
+    if let Some(latest) = latest
+        && tracked_version.as_deref() == Some(extension.version.as_str())
+    {
+        return Ok(fs::read(latest)?);
+    }
+
+    info!(
+        "{} {} is not in the local cache, downloading it",
+        id, extension.version
+    );
+    client
+        .download_extension_version_with_progress(id, &extension.version, |_, _| {})
+        .await
+        .with_context(|| format!("Failed to download {} {}", id, extension.version))
+}
+
+/// Copy the Zed release files for a version into the bundle, downloading the requested
+/// platform when it isn't cached
+async fn export_release(
+    root_dir: &Path,
+    output_dir: &Path,
+    zed_version: &str,
+    platform: Option<&str>,
+    client: &Client,
+) -> Result<()> {
+    let source_dir = root_dir.join("releases").join(zed_version);
+    let target_dir = output_dir.join("releases").join(zed_version);
+    fs::create_dir_all(&target_dir)?;
+
+    let mut files: HashSet<String> = fs::read_dir(&source_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if let Some(platform) = platform {
+        let file_name = format!("zed-{}.tar.gz", platform);
+        files.retain(|name| *name == file_name);
+
+        if files.is_empty() {
+            let (os, arch) = platform
+                .split_once('-')
+                .ok_or_else(|| anyhow!("Invalid platform {}, expected os-arch", platform))?;
+            let release = client
+                .get_release_asset("stable", zed_version, os, arch, "zed")
+                .await?;
+            info!(
+                "Zed {} for {} is not cached, downloading {}",
+                zed_version, platform, release.url
+            );
+            let bytes = client
+                .send(client.http_client.get(&release.url))
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            fs::write(target_dir.join(&file_name), bytes)?;
+            files.insert(file_name);
+        } else {
+            fs::copy(source_dir.join(&file_name), target_dir.join(&file_name))?;
+        }
+    } else {
+        for file_name in &files {
+            fs::copy(source_dir.join(file_name), target_dir.join(file_name))?;
+        }
+    }
+
+    if files.is_empty() {
+        warn!(
+            "No Zed {} release is cached, run `zedex release download` or pass --platform",
+            zed_version
+        );
+        return Ok(());
+    }
+
+    // Point the latest-version checks of every bundled platform at this release
+    for file_name in &files {
+        let Some(platform) = file_name.strip_suffix(".tar.gz") else {
+            continue;
+        };
+        let version = Version {
+            version: zed_version.to_string(),
+            url: format!(
+                "https://zed.dev/api/releases/stable/{}/{}",
+                zed_version, file_name
+            ),
+        };
+        fs::write(
+            output_dir
+                .join("releases")
+                .join(format!("{}.json", platform)),
+            serde_json::to_string(&version)?,
+        )?;
+    }
+
+    info!("Bundled {} Zed release files", files.len());
+    Ok(())
+}
+
+fn load_versions(path: &Path) -> Option<Vec<Extension>> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str::<WrappedExtensions>(&content)
+        .ok()
+        .map(|wrapped| wrapped.data)
+}
+
+fn write_index(path: &Path, data: Vec<Extension>) -> Result<()> {
+    fs::write(path, WrappedExtensions { data }.into_sorted_json()?)?;
+    Ok(())
+}
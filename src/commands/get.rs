@@ -1,107 +1,517 @@
 use crate::{
     cli::GetTarget,
+    config::ZedexConfig,
+    exit::{Classify, Failure, outcome},
     zed::{
-        Client, DownloadOptions, Extension, ExtensionVersionTracker, WrappedExtensions,
-        download_extension_by_id, download_extension_index, download_extensions,
+        ArchiveScanner, Client, DownloadFailure, DownloadOptions, Extension,
+        ExtensionCompatibility, ExtensionVersionTracker, RETRY_QUEUE_FILE, SyncProgress,
+        SyncRecord, WrappedExtensions, append_sync_record, archive_path, compare_versions,
+        declared_dependencies, download_extension_by_id, download_extension_index,
+        download_extension_version_by_id, download_extensions, find_archive, load_index,
+        log_request_usage, mirror_extension_assets, request_budget_spent, write_atomic,
+        write_integrity_manifests,
     },
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use futures_util::future;
-use log::{error, info};
+use log::{error, info, warn};
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
+/// Number of retry rounds at the end of a run
+const MAX_RETRY_ROUNDS: u32 = 3;
+
+/// Delay before the first retry round, doubled for every following round
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+
 /// Entry point for handling `zedex get ...` commands.
-pub async fn run(target: GetTarget, root_dir: PathBuf) -> Result<()> {
+pub async fn run(target: GetTarget, root_dir: PathBuf, config: &ZedexConfig) -> Result<()> {
     match target {
-        GetTarget::ExtensionIndex { provides } => handle_extension_index(root_dir, provides).await,
-        GetTarget::Extension { ids, output_dir } => {
-            handle_extension(ids, output_dir, root_dir).await
+        GetTarget::ExtensionIndex { provides } => {
+            handle_extension_index(root_dir, provides, config).await
+        }
+        GetTarget::Extension {
+            ids,
+            output_dir,
+            no_dependencies,
+            from_server,
+        } => {
+            handle_extension(
+                ids,
+                output_dir,
+                root_dir,
+                !no_dependencies,
+                from_server.as_deref(),
+                config,
+            )
+            .await
+        }
+        GetTarget::ExtensionVersions { ids, output_dir } => {
+            handle_extension_versions(ids, resolve_output_dir(output_dir, &root_dir)).await
         }
         GetTarget::AllExtensions {
             output_dir,
             async_mode,
             all_versions,
             rate_limit,
+            zed_version,
+            status_file,
+            triggered_by,
+            with_assets,
         } => {
-            handle_all_extensions(output_dir, root_dir, async_mode, all_versions, rate_limit).await
+            let webhooks = config.webhooks().classify(Failure::Config)?;
+            let progress = Arc::new(SyncProgress::new(
+                status_file.as_deref(),
+                "get all-extensions",
+            ));
+            let options = DownloadOptions {
+                async_mode,
+                all_versions,
+                rate_limit,
+                progress: Some(progress.clone()),
+                assets: with_assets,
+                ..Default::default()
+            };
+            let output_dir = resolve_output_dir(output_dir, &root_dir);
+            let result =
+                handle_all_extensions(&output_dir, zed_version, false, options, config).await;
+            log_request_usage().await;
+            progress.finish(&result);
+
+            let record = SyncRecord::new(
+                &progress.status(),
+                "upstream",
+                &triggered_by,
+                outcome(&result),
+            );
+            if let Err(e) = append_sync_record(&root_dir, &record) {
+                error!("Failed to record the run in the sync history: {:#}", e);
+            }
+            webhooks.sync_finished(&record).await;
+            result
         }
     }
 }
 
-async fn handle_extension_index(root_dir: PathBuf, provides: Vec<String>) -> Result<()> {
-    let client = Client::new();
-    download_extension_index(&client, &root_dir, &provides).await?;
+async fn handle_extension_index(
+    root_dir: PathBuf,
+    provides: Vec<String>,
+    config: &ZedexConfig,
+) -> Result<()> {
+    let client = Client::new().with_cached_index_listings();
+    let outcome = download_extension_index(&client, &root_dir, &provides).await?;
+    config.signing.sign_metadata(&root_dir)?;
+    if !outcome.failed_capabilities.is_empty() {
+        for (capability, error) in &outcome.failed_capabilities {
+            error!(
+                "Failed to fetch extensions providing {}: {}",
+                capability, error
+            );
+        }
+        let failed: Vec<&str> = outcome
+            .failed_capabilities
+            .keys()
+            .map(String::as_str)
+            .collect();
+        return Err(Failure::Partial.wrap(anyhow!(
+            "The index was saved without fresh listings of {}",
+            failed.join(", ")
+        )));
+    }
     Ok(())
 }
 
-async fn handle_extension(
+pub(crate) async fn handle_extension(
     ids: Vec<String>,
     output_dir: Option<PathBuf>,
     root_dir: PathBuf,
+    with_dependencies: bool,
+    from_server: Option<&str>,
+    config: &ZedexConfig,
 ) -> Result<()> {
     let output_dir = resolve_output_dir(output_dir, &root_dir);
     fs::create_dir_all(&output_dir)?;
 
-    let client = Client::new().with_extensions_local_dir(output_dir.to_string_lossy().to_string());
-    let extensions = ensure_extensions_index(&client, &output_dir, &[]).await?;
+    let mut client = Client::new();
+    if let Some(server) = from_server {
+        let scheme = url::Url::parse(server).map(|url| url.scheme().to_string());
+        if !matches!(scheme.as_deref(), Ok("http" | "https")) {
+            return Err(Failure::Config.wrap(anyhow!(
+                "--from-server {} must be an http:// or https:// URL",
+                server
+            )));
+        }
+        info!("Downloading from the zedex mirror at {}", server);
+        client = client.with_host(server);
+    }
+    let client = client
+        .with_cached_index_listings()
+        .with_extensions_local_dir(output_dir.to_string_lossy().to_string());
+    let extensions =
+        ensure_extensions_index(&client, &output_dir, &[], config.index.strict).await?;
+    let scanner = archive_scanner(config)?;
+    let quota = config.cache.quota().classify(Failure::Config)?;
+    let pins = &config.extensions.pins;
+
+    let mut requested: HashSet<String> = ids
+        .iter()
+        .map(|spec| parse_extension_spec(spec).0.to_string())
+        .collect();
+    let mut pending = ids;
+    let mut failed = Vec::new();
+
+    // Every round downloads the dependencies declared by the previous round's archives
+    while !pending.is_empty() {
+        let futures = pending.iter().map(|spec| {
+            let client = client.clone();
+            let output_dir = output_dir.clone();
+            let extensions = extensions.clone();
+            let scanner = scanner.as_ref();
+            let quota = quota.as_ref();
+
+            async move {
+                let (id, version) = parse_extension_spec(spec);
+                match version.or_else(|| pins.get(id).map(String::as_str)) {
+                    Some(version) => {
+                        download_extension_version_by_id(
+                            id,
+                            version,
+                            client,
+                            &output_dir,
+                            scanner,
+                            quota,
+                        )
+                        .await
+                    }
+                    None => {
+                        download_extension_by_id(
+                            id,
+                            client,
+                            &output_dir,
+                            &extensions,
+                            scanner,
+                            quota,
+                        )
+                        .await
+                    }
+                }
+            }
+        });
+
+        let results = future::join_all(futures).await;
+        for (spec, result) in pending.iter().zip(results) {
+            if let Err(err) = result {
+                error!("Failed to download extension {}: {}", spec, err);
+                failed.push(spec.clone());
+            }
+        }
+
+        if !with_dependencies {
+            break;
+        }
+
+        let mut dependencies = Vec::new();
+        for spec in &pending {
+            let (id, version) = parse_extension_spec(spec);
+            let archive = archive_path(
+                &output_dir,
+                id,
+                version.or_else(|| pins.get(id).map(String::as_str)),
+            );
+            if !archive.exists() {
+                continue;
+            }
+
+            match declared_dependencies(&archive) {
+                Ok(declared) => {
+                    for dependency in declared {
+                        if requested.insert(dependency.clone()) {
+                            info!("{} depends on {}, mirroring it as well", id, dependency);
+                            dependencies.push(dependency);
+                        }
+                    }
+                }
+                Err(e) => warn!("Could not read the dependencies of {}: {:#}", id, e),
+            }
+        }
+        pending = dependencies;
+    }
 
-    let futures = ids.into_iter().map(|id| {
-        let client = client.clone();
-        let output_dir = output_dir.clone();
-        let extensions = extensions.clone();
+    config.signing.sign_metadata(&output_dir)?;
+    write_integrity_manifests(&output_dir, config.integrity.algorithm)?;
 
-        async move { download_extension_by_id(&id, client, &output_dir, &extensions).await }
-    });
+    if !failed.is_empty() {
+        return Err(Failure::Partial.wrap(anyhow!("Failed to download {}", failed.join(", "))));
+    }
+    Ok(())
+}
 
-    let results = future::join_all(futures).await;
-    for (idx, result) in results.into_iter().enumerate() {
-        if let Err(err) = result {
-            error!("Failed to download extension #{}: {}", idx, err);
+/// Write the versions.json of each extension, leaving its archives alone
+async fn handle_extension_versions(ids: Vec<String>, output_dir: PathBuf) -> Result<()> {
+    let client = Client::new();
+    let mut failed = Vec::new();
+    for id in &ids {
+        let result = async {
+            let versions = client.get_extension_versions(id).await?;
+            let ext_dir = output_dir.join(id);
+            fs::create_dir_all(&ext_dir)?;
+            let newest = versions
+                .iter()
+                .map(|ext| ext.version.as_str())
+                .max_by(|a, b| compare_versions(a, b))
+                .map(str::to_string);
+            let count = versions.len();
+            write_atomic(
+                &ext_dir.join("versions.json"),
+                WrappedExtensions { data: versions }.into_sorted_json()?,
+            )?;
+            anyhow::Ok((count, newest))
+        }
+        .await;
+        match result {
+            Ok((count, newest)) => info!(
+                "Saved {} versions of {} (newest {})",
+                count,
+                id,
+                newest.as_deref().unwrap_or("none")
+            ),
+            Err(e) => {
+                error!("Failed to fetch the versions of {}: {:#}", id, e);
+                failed.push(id.as_str());
+            }
         }
     }
 
+    if !failed.is_empty() {
+        return Err(Failure::Partial.wrap(anyhow!(
+            "Failed to fetch the versions of {}",
+            failed.join(", ")
+        )));
+    }
     Ok(())
 }
 
-async fn handle_all_extensions(
-    output_dir: Option<PathBuf>,
-    root_dir: PathBuf,
-    async_mode: bool,
-    all_versions: bool,
-    rate_limit: u64,
+/// Mirror the latest version of every indexed extension, or with `incremental` refresh the
+/// index and download only the extensions whose version changed since the last run
+pub(crate) async fn handle_all_extensions(
+    output_dir: &Path,
+    zed_version: Option<String>,
+    incremental: bool,
+    mut options: DownloadOptions,
+    config: &ZedexConfig,
 ) -> Result<()> {
-    let output_dir = resolve_output_dir(output_dir, &root_dir);
+    let compatibility = zed_version
+        .as_deref()
+        .map(ExtensionCompatibility::for_zed_version)
+        .transpose()
+        .classify(Failure::Config)?;
+    if let Some(compat) = &compatibility {
+        info!(
+            "Targeting Zed {}: schema version <= {}, WASM API {} - {}",
+            compat.zed_version,
+            compat.max_schema_version,
+            compat.min_wasm_api_version,
+            compat.max_wasm_api_version
+        );
+    }
+
+    let output_dir = output_dir.to_path_buf();
     fs::create_dir_all(&output_dir)?;
 
-    let client = Client::new().with_extensions_local_dir(output_dir.to_string_lossy().to_string());
-    let extensions = ensure_extensions_index(&client, &output_dir, &[]).await?;
+    let client = Client::new()
+        .with_cached_index_listings()
+        .with_extensions_local_dir(output_dir.to_string_lossy().to_string());
+    let extensions = if incremental {
+        refresh_extensions_index(&client, &output_dir).await?
+    } else {
+        ensure_extensions_index(&client, &output_dir, &[], config.index.strict).await?
+    };
     let mut version_tracker = load_version_tracker(&output_dir);
 
-    let options = DownloadOptions {
-        async_mode,
-        all_versions,
-        rate_limit,
+    let queued = load_retry_queue(&output_dir);
+    if !queued.is_empty() {
+        info!(
+            "{} downloads failed in the previous run and will be retried",
+            queued.len()
+        );
+    }
+
+    options.compatibility = compatibility;
+    options.pins = config.extensions.pins.clone();
+    let extensions = if incremental {
+        changed_extensions(
+            extensions,
+            &version_tracker,
+            &queued,
+            &output_dir,
+            &options.pins,
+        )
+    } else {
+        extensions
     };
+    options.scanner = archive_scanner(config)?;
+    options.quota = config.cache.quota().classify(Failure::Config)?;
+    if let Some(progress) = &options.progress {
+        progress.start_phase("download", extensions.len());
+    }
 
-    let updated_tracker = download_extensions(
-        extensions,
-        client,
+    let outcome = download_extensions(
+        extensions.clone(),
+        client.clone(),
         &output_dir,
         version_tracker.clone(),
-        options,
+        options.clone(),
     )
     .await?;
 
-    version_tracker.merge(updated_tracker);
+    version_tracker.merge(outcome.tracker);
+    let mut failures = with_attempts(outcome.failures, &queued);
     persist_version_tracker(&output_dir, &version_tracker)?;
+    persist_retry_queue(&output_dir, &failures)?;
+
+    let mut delay = RETRY_BASE_DELAY;
+    for round in 1..=MAX_RETRY_ROUNDS {
+        if failures.is_empty() {
+            break;
+        }
+        if request_budget_spent().await {
+            warn!("Not retrying failed downloads, the upstream request budget is spent");
+            break;
+        }
+
+        let failed_ids: HashSet<&str> = failures.iter().map(|f| f.id.as_str()).collect();
+        let retry: Vec<Extension> = extensions
+            .iter()
+            .filter(|ext| failed_ids.contains(ext.id.as_str()))
+            .cloned()
+            .collect();
+
+        warn!(
+            "Retrying {} failed extensions in {}s (round {}/{})",
+            retry.len(),
+            delay.as_secs(),
+            round,
+            MAX_RETRY_ROUNDS
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        if let Some(progress) = &options.progress {
+            progress.start_phase(
+                &format!("retry {}/{}", round, MAX_RETRY_ROUNDS),
+                retry.len(),
+            );
+        }
+
+        let outcome = download_extensions(
+            retry,
+            client.clone(),
+            &output_dir,
+            version_tracker.clone(),
+            options.clone(),
+        )
+        .await?;
+
+        version_tracker.merge(outcome.tracker);
+        failures = with_attempts(outcome.failures, &failures);
+        persist_version_tracker(&output_dir, &version_tracker)?;
+        persist_retry_queue(&output_dir, &failures)?;
+    }
+
+    if options.assets {
+        mirror_extension_assets(&client, &output_dir, &extensions).await;
+    }
+
+    config.signing.sign_metadata(&output_dir)?;
+    write_integrity_manifests(&output_dir, config.integrity.algorithm)?;
+
+    if !failures.is_empty() {
+        for failure in &failures {
+            error!(
+                "Failed to download {}{} after {} attempts: {}",
+                failure.id,
+                failure
+                    .version
+                    .as_deref()
+                    .map(|v| format!(" version {}", v))
+                    .unwrap_or_default(),
+                failure.attempts,
+                failure.error
+            );
+        }
+        return Err(Failure::Partial.wrap(anyhow!(
+            "{} downloads failed, queued in {:?} for the next run",
+            failures.len(),
+            output_dir.join(RETRY_QUEUE_FILE)
+        )));
+    }
 
     info!("All extensions downloaded to {:?}", output_dir);
     Ok(())
 }
 
+/// Carry the attempt counts of earlier failures over to the same downloads failing again
+fn with_attempts(
+    mut failures: Vec<DownloadFailure>,
+    previous: &[DownloadFailure],
+) -> Vec<DownloadFailure> {
+    let attempts: BTreeMap<(&str, Option<&str>), u32> = previous
+        .iter()
+        .map(|f| ((f.id.as_str(), f.version.as_deref()), f.attempts))
+        .collect();
+
+    for failure in &mut failures {
+        if let Some(previous) = attempts.get(&(failure.id.as_str(), failure.version.as_deref())) {
+            failure.attempts += previous;
+        }
+    }
+    failures
+}
+
+fn load_retry_queue(output_dir: &Path) -> Vec<DownloadFailure> {
+    fs::read_to_string(output_dir.join(RETRY_QUEUE_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Write the outstanding failures, removing the queue once everything succeeded
+fn persist_retry_queue(output_dir: &Path, failures: &[DownloadFailure]) -> Result<()> {
+    let queue_file = output_dir.join(RETRY_QUEUE_FILE);
+    if failures.is_empty() {
+        if queue_file.exists() {
+            fs::remove_file(&queue_file)?;
+        }
+        return Ok(());
+    }
+
+    fs::write(&queue_file, serde_json::to_string_pretty(failures)?)?;
+    Ok(())
+}
+
+/// Splits an `id@version` spec into its id and optional version.
+pub(crate) fn parse_extension_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once('@') {
+        Some((id, version)) if !version.is_empty() => (id, Some(version)),
+        Some((id, _)) => (id, None),
+        None => (spec, None),
+    }
+}
+
+fn archive_scanner(config: &ZedexConfig) -> Result<Option<ArchiveScanner>> {
+    config
+        .scan
+        .command
+        .as_ref()
+        .map(ArchiveScanner::new)
+        .transpose()
+        .classify(Failure::Config)
+}
+
 fn resolve_output_dir(option: Option<PathBuf>, fallback: &Path) -> PathBuf {
     option.unwrap_or_else(|| fallback.to_path_buf())
 }
@@ -110,32 +520,85 @@ async fn ensure_extensions_index(
     client: &Client,
     output_dir: &Path,
     provides: &[String],
+    strict: bool,
 ) -> Result<Vec<Extension>> {
     let extensions_file = output_dir.join("extensions.json");
 
     if extensions_file.exists() {
         info!("Loading extension index from {:?}", extensions_file);
-        load_extensions_file(&extensions_file)
+        load_index(&extensions_file, strict)
     } else {
         info!("Extension index not found. Fetching from API...");
-        download_extension_index(client, output_dir, provides).await
+        let outcome = download_extension_index(client, output_dir, provides).await?;
+        if !outcome.failed_capabilities.is_empty() {
+            warn!(
+                "Continuing with a partial index, rerun `zedex get extension-index` to complete it"
+            );
+        }
+        Ok(outcome.extensions)
     }
 }
 
-fn load_extensions_file(path: &Path) -> Result<Vec<Extension>> {
-    let content = fs::read_to_string(path)?;
-    let wrapped: WrappedExtensions = serde_json::from_str(&content)?;
-    Ok(wrapped.data)
+/// Fetch the extension index from the API, replacing the one stored in `output_dir`
+async fn refresh_extensions_index(client: &Client, output_dir: &Path) -> Result<Vec<Extension>> {
+    info!("Refreshing extension index...");
+    let outcome = download_extension_index(client, output_dir, &[]).await?;
+    if !outcome.failed_capabilities.is_empty() {
+        warn!("Continuing with a partial index, extensions missing from it are not updated");
+    }
+    Ok(outcome.extensions)
+}
+
+/// Extensions of the index whose mirrored version is missing or outdated, or whose
+/// download is queued for retry
+fn changed_extensions(
+    extensions: Vec<Extension>,
+    tracker: &ExtensionVersionTracker,
+    queued: &[DownloadFailure],
+    output_dir: &Path,
+    pins: &HashMap<String, String>,
+) -> Vec<Extension> {
+    let queued: HashSet<&str> = queued.iter().map(|f| f.id.as_str()).collect();
+    let listed: HashSet<&str> = extensions.iter().map(|ext| ext.id.as_str()).collect();
+    let removed = tracker
+        .extensions
+        .keys()
+        .filter(|id| !listed.contains(id.as_str()))
+        .count();
+    let total = extensions.len();
+
+    let mut new = 0;
+    let changed: Vec<Extension> = extensions
+        .into_iter()
+        .filter(|ext| {
+            let wanted = pins.get(&ext.id).unwrap_or(&ext.version);
+            let tracked = tracker.extensions.get(&ext.id);
+            if tracked.is_none() {
+                new += 1;
+            }
+            tracked != Some(wanted)
+                || find_archive(output_dir, &ext.id, None).is_none()
+                || queued.contains(ext.id.as_str())
+        })
+        .collect();
+
+    info!(
+        "{} of {} extensions changed since the last sync ({} new, {} no longer listed)",
+        changed.len(),
+        total,
+        new,
+        removed
+    );
+    changed
 }
 
 fn load_version_tracker(output_dir: &Path) -> ExtensionVersionTracker {
     let version_tracker_file = output_dir.join("version_tracker.json");
-    if version_tracker_file.exists() {
-        if let Ok(content) = fs::read_to_string(&version_tracker_file) {
-            if let Ok(tracker) = serde_json::from_str(&content) {
-                return tracker;
-            }
-        }
+    if version_tracker_file.exists()
+        && let Ok(content) = fs::read_to_string(&version_tracker_file)
+        && let Ok(tracker) = serde_json::from_str(&content)
+    {
+        return tracker;
     }
 
     ExtensionVersionTracker::new()
@@ -144,6 +607,6 @@ fn load_version_tracker(output_dir: &Path) -> ExtensionVersionTracker {
 fn persist_version_tracker(output_dir: &Path, tracker: &ExtensionVersionTracker) -> Result<()> {
     let version_tracker_file = output_dir.join("version_tracker.json");
     let version_tracker_json = serde_json::to_string_pretty(tracker)?;
-    fs::write(&version_tracker_file, version_tracker_json)?;
+    write_atomic(&version_tracker_file, version_tracker_json)?;
     Ok(())
 }
@@ -0,0 +1,449 @@
+use crate::cli::ImportSource;
+use crate::config::ZedexConfig;
+use crate::zed::{
+    Extension, ExtensionVersionTracker, QUARANTINE_DIR, Version, WrappedExtensions, archive_name,
+    compare_versions, load_index, open_bundle, parse_index, partial_path, snapshot_index,
+    write_atomic, write_integrity_manifests, write_release_checksums,
+};
+use anyhow::{Context, Result, bail};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use super::get::handle_extension;
+
+/// Installed extensions as recorded in Zed's `extensions/index.json`
+#[derive(Debug, Deserialize)]
+struct InstalledIndex {
+    #[serde(default)]
+    extensions: HashMap<String, InstalledEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstalledEntry {
+    manifest: InstalledManifest,
+    /// Dev extensions are loaded from a local checkout and never come from the registry
+    #[serde(default)]
+    dev: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstalledManifest {
+    version: String,
+}
+
+/// Manifest of an installed extension (`extension.toml`)
+#[derive(Debug, Deserialize)]
+struct ExtensionToml {
+    id: String,
+    version: String,
+}
+
+/// The part of Zed's `settings.json` listing extensions to install automatically
+#[derive(Debug, Default, Deserialize)]
+struct ZedSettings {
+    #[serde(default)]
+    auto_install_extensions: HashMap<String, bool>,
+}
+
+/// Entry point for handling `zedex import ...` commands.
+pub async fn run(source: ImportSource, root_dir: PathBuf, config: &ZedexConfig) -> Result<()> {
+    match source {
+        ImportSource::ZedConfig {
+            path,
+            data_path,
+            output_dir,
+        } => {
+            let config_dir = path
+                .or_else(|| dirs::home_dir().map(|home| home.join(".config").join("zed")))
+                .context("Could not determine the Zed configuration directory, pass --path")?;
+            let data_dir = data_path.or_else(default_data_dir);
+
+            let extensions = collect_extensions(&config_dir, data_dir.as_deref())?;
+            if extensions.is_empty() {
+                bail!("No installed extensions found in {:?}", config_dir);
+            }
+
+            info!("Importing {} extensions from Zed", extensions.len());
+            let specs = extensions
+                .into_iter()
+                .map(|(id, version)| match version {
+                    Some(version) => format!("{}@{}", id, version),
+                    None => id,
+                })
+                .collect();
+            handle_extension(specs, output_dir, root_dir, true, None, config).await
+        }
+        ImportSource::Bundle { path, output_dir } => {
+            import_bundle(&path, &output_dir.unwrap_or(root_dir), config)
+        }
+    }
+}
+
+/// Metadata of a bundle, merged into the mirror once every file is in place
+#[derive(Default)]
+struct BundleMetadata {
+    index: Vec<Extension>,
+    versions: BTreeMap<String, Vec<Extension>>,
+    /// Latest-version pointers of releases, keyed by file name
+    release_pointers: BTreeMap<String, Version>,
+}
+
+/// Merge a bundle written by `zedex export` into a mirror, streaming its entries so
+/// memory use doesn't grow with the bundle. Newer versions already in the mirror stay
+/// its latest.
+fn import_bundle(path: &Path, output_dir: &Path, config: &ZedexConfig) -> Result<()> {
+    let mut archive = open_bundle(path)?;
+    fs::create_dir_all(output_dir)?;
+    info!("Importing bundle {:?} into {:?}", path, output_dir);
+
+    let mut metadata = BundleMetadata::default();
+    let mut files = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        // Entries are rooted at the bundle directory's name
+        let entry_path = entry.path()?.into_owned();
+        let relative: PathBuf = entry_path.components().skip(1).collect();
+        if relative.as_os_str().is_empty()
+            || relative.starts_with(QUARANTINE_DIR)
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            warn!("Skipping bundle entry {:?}", entry_path);
+            continue;
+        }
+
+        let parts: Vec<&str> = relative.iter().filter_map(|part| part.to_str()).collect();
+        match parts.as_slice() {
+            ["extensions.json"] => {
+                let content = read_entry(&mut entry)?;
+                metadata.index = parse_index(&content, &entry_path, config.index.strict)?;
+            }
+            [id, "versions.json"] => {
+                let versions: WrappedExtensions = serde_json::from_str(&read_entry(&mut entry)?)
+                    .with_context(|| format!("Invalid {:?} in the bundle", entry_path))?;
+                metadata.versions.insert(id.to_string(), versions.data);
+            }
+            ["releases", name] if name.ends_with(".json") => {
+                let version: Version = serde_json::from_str(&read_entry(&mut entry)?)
+                    .with_context(|| format!("Invalid {:?} in the bundle", entry_path))?;
+                metadata.release_pointers.insert(name.to_string(), version);
+            }
+            // Regenerated for the merged mirror
+            ["version_tracker.json"] => {}
+            [.., name]
+                if *name == "INTEGRITY.json"
+                    || *name == "SHA256SUMS"
+                    || name.ends_with(".asc")
+                    || name.ends_with(".minisig") => {}
+            // The latest copy is recreated from the versioned archive if the bundled
+            // version becomes the mirror's latest
+            [id, name] if *name == archive_name(id, None) => {}
+            _ => {
+                let target = output_dir.join(&relative);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let partial = partial_path(&target);
+                entry.unpack(&partial)?;
+                fs::rename(&partial, &target)?;
+                debug!("Imported {:?}", relative);
+                files += 1;
+            }
+        }
+    }
+
+    merge_bundle_metadata(output_dir, metadata, config)?;
+    write_release_checksums(&output_dir.join("releases"))?;
+    write_integrity_manifests(output_dir, config.integrity.algorithm)?;
+    config.signing.sign_metadata(output_dir)?;
+    info!("Imported {} files from {:?}", files, path);
+    Ok(())
+}
+
+fn read_entry(entry: &mut impl Read) -> Result<String> {
+    let mut content = String::new();
+    entry.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Merge the bundle's index, version listings and release pointers into the mirror's
+fn merge_bundle_metadata(
+    output_dir: &Path,
+    metadata: BundleMetadata,
+    config: &ZedexConfig,
+) -> Result<()> {
+    let index_file = output_dir.join("extensions.json");
+    let mut index = if index_file.exists() {
+        load_index(&index_file, config.index.strict)?
+    } else {
+        Vec::new()
+    };
+    let tracker_file = output_dir.join("version_tracker.json");
+    let mut tracker: ExtensionVersionTracker = fs::read_to_string(&tracker_file)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    let mut updated = 0;
+    for extension in metadata.index {
+        let position = index.iter().position(|ext| ext.id == extension.id);
+        if let Some(position) = position
+            && compare_versions(&index[position].version, &extension.version) != Ordering::Less
+        {
+            debug!(
+                "Keeping {} {}, newer than the bundled {}",
+                extension.id, index[position].version, extension.version
+            );
+            continue;
+        }
+
+        let ext_dir = output_dir.join(&extension.id);
+        let versioned = ext_dir.join(archive_name(&extension.id, Some(&extension.version)));
+        if !versioned.exists() {
+            warn!(
+                "The bundle has no archive of {} {}, leaving the index entry out",
+                extension.id, extension.version
+            );
+            continue;
+        }
+        fs::copy(&versioned, ext_dir.join(archive_name(&extension.id, None)))?;
+        tracker.update_extension(&extension);
+        match position {
+            Some(position) => index[position] = extension,
+            None => index.push(extension),
+        }
+        updated += 1;
+    }
+
+    if updated > 0 {
+        snapshot_index(output_dir)?;
+        write_atomic(
+            &index_file,
+            WrappedExtensions { data: index }.into_sorted_json()?,
+        )?;
+        write_atomic(&tracker_file, serde_json::to_string_pretty(&tracker)?)?;
+        info!("Updated {} extensions in the index", updated);
+    }
+
+    for (id, bundled) in metadata.versions {
+        let versions_file = output_dir.join(&id).join("versions.json");
+        let mut versions: Vec<Extension> = fs::read_to_string(&versions_file)
+            .ok()
+            .and_then(|content| serde_json::from_str::<WrappedExtensions>(&content).ok())
+            .map(|wrapped| wrapped.data)
+            .unwrap_or_default();
+        let known = versions.len();
+        for extension in bundled {
+            if !versions.iter().any(|ext| ext.version == extension.version) {
+                versions.push(extension);
+            }
+        }
+        if versions.len() > known || !versions_file.exists() {
+            write_atomic(
+                &versions_file,
+                WrappedExtensions { data: versions }.into_sorted_json()?,
+            )?;
+        }
+    }
+
+    for (name, bundled) in metadata.release_pointers {
+        let pointer = output_dir.join("releases").join(&name);
+        let current = fs::read_to_string(&pointer)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Version>(&content).ok());
+        if current.is_none_or(|current| current < bundled) {
+            fs::create_dir_all(output_dir.join("releases"))?;
+            write_atomic(&pointer, serde_json::to_string(&bundled)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Zed's platform data directory, where installed extensions live
+fn default_data_dir() -> Option<PathBuf> {
+    let data_dir = dirs::data_dir()?;
+    Some(if cfg!(target_os = "macos") {
+        data_dir.join("Zed")
+    } else {
+        data_dir.join("zed")
+    })
+}
+
+/// Installed extensions with their versions, plus auto-installed ones without a version
+fn collect_extensions(
+    config_dir: &Path,
+    data_dir: Option<&Path>,
+) -> Result<BTreeMap<String, Option<String>>> {
+    let mut extensions = BTreeMap::new();
+
+    // The extensions directory sits in the data directory, or next to the settings
+    // for portable setups
+    let extensions_dir = [Some(config_dir), data_dir]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("extensions"))
+        .find(|dir| dir.exists());
+
+    match extensions_dir {
+        Some(dir) => {
+            info!("Reading installed extensions from {:?}", dir);
+            extensions.extend(
+                installed_extensions(&dir)?
+                    .into_iter()
+                    .map(|(id, version)| (id, Some(version))),
+            );
+        }
+        None => warn!("No installed extensions directory found"),
+    }
+
+    let settings_file = config_dir.join("settings.json");
+    if settings_file.exists() {
+        let content = fs::read_to_string(&settings_file)?;
+        let settings: ZedSettings = serde_json::from_str(&strip_jsonc(&content))
+            .with_context(|| format!("Failed to parse {:?}", settings_file))?;
+        for (id, enabled) in settings.auto_install_extensions {
+            if enabled {
+                extensions.entry(id).or_insert(None);
+            }
+        }
+    }
+
+    Ok(extensions)
+}
+
+/// Read installed extensions from Zed's index, falling back to each extension's manifest
+fn installed_extensions(extensions_dir: &Path) -> Result<BTreeMap<String, String>> {
+    let index_file = extensions_dir.join("index.json");
+    if let Ok(content) = fs::read_to_string(&index_file) {
+        match serde_json::from_str::<InstalledIndex>(&content) {
+            Ok(index) => {
+                return Ok(index
+                    .extensions
+                    .into_iter()
+                    .filter(|(_, entry)| !entry.dev)
+                    .map(|(id, entry)| (id, entry.manifest.version))
+                    .collect());
+            }
+            Err(e) => warn!(
+                "Failed to parse {:?}, scanning manifests: {}",
+                index_file, e
+            ),
+        }
+    }
+
+    let mut extensions = BTreeMap::new();
+    let installed_dir = extensions_dir.join("installed");
+    let Ok(entries) = fs::read_dir(&installed_dir) else {
+        return Ok(extensions);
+    };
+
+    for entry in entries.flatten() {
+        let manifest_path = entry.path().join("extension.toml");
+        // Dev extensions are symlinks to a local checkout
+        if entry.file_type().is_ok_and(|t| t.is_symlink()) || !manifest_path.exists() {
+            continue;
+        }
+
+        match fs::read_to_string(&manifest_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(toml::from_str::<ExtensionToml>(&content)?))
+        {
+            Ok(manifest) => {
+                debug!("Found installed {} {}", manifest.id, manifest.version);
+                extensions.insert(manifest.id, manifest.version);
+            }
+            Err(e) => warn!("Skipping {:?}: {}", manifest_path, e),
+        }
+    }
+
+    Ok(extensions)
+}
+
+/// Strip comments and trailing commas from Zed's JSON-with-comments settings
+fn strip_jsonc(content: &str) -> String {
+    let without_comments = strip_comments(content);
+    let chars: Vec<char> = without_comments.chars().collect();
+    let mut output = String::with_capacity(chars.len());
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        output.push(c);
+    }
+
+    output
+}
+
+fn strip_comments(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            output.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        output.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                output.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        output.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = '\0';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
@@ -0,0 +1,29 @@
+use crate::cli::{IndexAction, OutputFormat};
+use crate::zed::{format_bytes, list_index_snapshots, rollback_index};
+use anyhow::Result;
+use std::path::Path;
+
+/// Entry point for `zedex index ...` commands.
+pub fn run(action: IndexAction, extensions_dir: &Path, output: OutputFormat) -> Result<()> {
+    match action {
+        IndexAction::Snapshots => {
+            let snapshots = list_index_snapshots(extensions_dir)?;
+            if output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&snapshots)?);
+                return Ok(());
+            }
+            if snapshots.is_empty() {
+                println!("No index snapshots in {:?}", extensions_dir);
+            }
+            for snapshot in snapshots {
+                println!(
+                    "{}  {:>10}",
+                    snapshot.timestamp,
+                    format_bytes(snapshot.size)
+                );
+            }
+            Ok(())
+        }
+        IndexAction::Rollback { timestamp } => rollback_index(extensions_dir, &timestamp),
+    }
+}
@@ -0,0 +1,94 @@
+use crate::cli::OutputFormat;
+use crate::commands::get::parse_extension_spec;
+use crate::zed::{archive_path, find_archive, format_bytes, inspect_archive};
+use anyhow::{Result, bail};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+/// Entry point for `zedex inspect`, describing an archive given by path or as a mirrored
+/// `id[@version]`.
+pub fn run(target: &str, extensions_dir: &Path, output: OutputFormat) -> Result<()> {
+    let archive = resolve_archive(target, extensions_dir)?;
+    let inspection = inspect_archive(&archive)?;
+
+    if output == OutputFormat::Json {
+        let report = json!({
+            "archive": archive,
+            "manifest": inspection.manifest,
+            "wasm": inspection.wasm.as_ref().map(|(size, api_version)| json!({
+                "size": size,
+                "api_version": api_version,
+            })),
+            "languages": inspection.languages,
+            "grammars": inspection.grammars,
+            "themes": inspection.themes,
+            "files": inspection.files.iter().map(|(path, size)| json!({
+                "path": path,
+                "size": size,
+            })).collect::<Vec<_>>(),
+            "anomalies": inspection.anomalies,
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("Archive: {}", archive.display());
+    match &inspection.manifest {
+        Some(manifest) => println!("\nextension.toml:\n{}", manifest),
+        None => println!("\nNo extension.toml"),
+    }
+
+    match &inspection.wasm {
+        Some((size, api_version)) => println!(
+            "WASM module: {}, API version {}",
+            format_bytes(*size),
+            api_version.as_deref().unwrap_or("unknown")
+        ),
+        None => println!("WASM module: none"),
+    }
+    print_list("Languages", &inspection.languages);
+    print_list("Grammars", &inspection.grammars);
+    print_list("Themes", &inspection.themes);
+
+    println!("\nFiles:");
+    for (path, size) in &inspection.files {
+        println!("  {:<60} {:>12}", path, format_bytes(*size));
+    }
+
+    if inspection.anomalies.is_empty() {
+        println!("\nNo anomalies found");
+    } else {
+        println!("\nAnomalies:");
+        for anomaly in &inspection.anomalies {
+            println!("  {}", anomaly);
+        }
+    }
+    Ok(())
+}
+
+fn print_list(label: &str, items: &[String]) {
+    if items.is_empty() {
+        println!("{}: none", label);
+    } else {
+        println!("{}: {}", label, items.join(", "));
+    }
+}
+
+/// An existing file is inspected as is, anything else is looked up in the extensions
+/// directory as `id[@version]`
+fn resolve_archive(target: &str, extensions_dir: &Path) -> Result<PathBuf> {
+    let path = Path::new(target);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+
+    let (id, version) = parse_extension_spec(target);
+    match find_archive(extensions_dir, id, version) {
+        Some(archive) => Ok(archive),
+        None => bail!(
+            "{} is neither an archive nor a mirrored extension ({:?} not found)",
+            target,
+            archive_path(extensions_dir, id, version)
+        ),
+    }
+}
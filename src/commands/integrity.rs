@@ -0,0 +1,43 @@
+use crate::config::ZedexConfig;
+use crate::exit::Failure;
+use crate::zed::{verify_integrity_manifests, write_integrity_manifests};
+use anyhow::{Result, anyhow};
+use log::{error, info};
+use std::path::Path;
+
+/// Entry point for `zedex integrity`, writing or verifying the per-directory manifests.
+pub fn run(
+    extensions_dir: &Path,
+    verify: bool,
+    jobs: Option<usize>,
+    incremental: bool,
+    config: &ZedexConfig,
+) -> Result<()> {
+    if !verify {
+        let written = write_integrity_manifests(extensions_dir, config.integrity.algorithm)?;
+        if written == 0 {
+            info!(
+                "All integrity manifests in {:?} are up to date",
+                extensions_dir
+            );
+        }
+        return Ok(());
+    }
+
+    let problems = verify_integrity_manifests(extensions_dir, jobs, incremental)?;
+    if problems.is_empty() {
+        info!(
+            "All files in {:?} match their integrity manifests",
+            extensions_dir
+        );
+        return Ok(());
+    }
+
+    for problem in &problems {
+        error!("{}", problem);
+    }
+    Err(Failure::Verification.wrap(anyhow!(
+        "{} files don't match their integrity manifests",
+        problems.len()
+    )))
+}
@@ -0,0 +1,41 @@
+use crate::config::ZedexConfig;
+use crate::zed::{legacy_files, write_integrity_manifests};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::fs;
+use std::path::Path;
+
+/// Entry point for `zedex migrate-cache`, renaming archives and release assets stored
+/// under the names of older versions of zedex to the canonical ones.
+pub fn run(extensions_dir: &Path, dry_run: bool, config: &ZedexConfig) -> Result<()> {
+    let releases_dir = extensions_dir.join("releases");
+    let moves = legacy_files(extensions_dir, Some(&releases_dir));
+    if moves.is_empty() {
+        info!("All files in {:?} use the current layout", extensions_dir);
+        return Ok(());
+    }
+
+    let mut moved = 0;
+    for (from, to) in &moves {
+        if to.exists() {
+            warn!("Keeping {:?}: {:?} already exists", from, to);
+            continue;
+        }
+        if dry_run {
+            println!("Would move {} -> {}", from.display(), to.display());
+            continue;
+        }
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(from, to).with_context(|| format!("Cannot move {:?} to {:?}", from, to))?;
+        println!("Moved {} -> {}", from.display(), to.display());
+        moved += 1;
+    }
+
+    if moved > 0 {
+        write_integrity_manifests(extensions_dir, config.integrity.algorithm)?;
+        info!("Migrated {} files in {:?}", moved, extensions_dir);
+    }
+    Ok(())
+}
@@ -1,3 +1,18 @@
+pub mod client_config;
+pub mod discover;
+pub mod dns_setup;
+pub mod export;
 pub mod get;
+pub mod import;
+pub mod index;
+pub mod inspect;
+pub mod integrity;
+pub mod migrate;
+pub mod quarantine;
 pub mod release;
+pub mod report;
+pub mod selftest;
 pub mod serve;
+pub mod setup_client;
+pub mod status;
+pub mod sync;
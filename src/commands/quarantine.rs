@@ -0,0 +1,87 @@
+use crate::cli::OutputFormat;
+use crate::zed::QuarantineRegistry;
+use anyhow::{Result, anyhow};
+use log::info;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Entry point for `zedex quarantine`. Lists quarantined versions when no specs are given.
+pub fn quarantine(
+    specs: &[String],
+    reason: Option<String>,
+    extensions_dir: &Path,
+    output: OutputFormat,
+) -> Result<()> {
+    if specs.is_empty() {
+        return list(extensions_dir, output);
+    }
+
+    for spec in specs {
+        let (id, version) = parse_spec(spec)?;
+        let moved = QuarantineRegistry::update(extensions_dir, |registry| {
+            registry.quarantine(id, version, reason.clone())
+        })?;
+
+        if moved.is_empty() {
+            info!(
+                "Quarantined {} (no local archives, it won't be fetched or proxied)",
+                spec
+            );
+        } else {
+            for path in moved {
+                info!("Quarantined {} to {:?}", spec, path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Entry point for `zedex release-from-quarantine`.
+pub fn release(specs: &[String], extensions_dir: &Path) -> Result<()> {
+    for spec in specs {
+        let (id, version) = parse_spec(spec)?;
+        QuarantineRegistry::update(extensions_dir, |registry| registry.release(id, version))?;
+        info!("Released {} from quarantine", spec);
+    }
+
+    Ok(())
+}
+
+fn list(extensions_dir: &Path, output: OutputFormat) -> Result<()> {
+    let registry = QuarantineRegistry::load(extensions_dir)?;
+    if output == OutputFormat::Json {
+        let entries: BTreeMap<_, _> = registry.quarantined().collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    let mut empty = true;
+
+    for (spec, entry) in registry.quarantined() {
+        empty = false;
+        let quarantined_at = chrono::DateTime::from_timestamp(entry.updated_at as i64, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        println!(
+            "{:<40} {:<16} {}",
+            spec,
+            quarantined_at,
+            entry.reason.as_deref().unwrap_or("")
+        );
+    }
+
+    if empty {
+        println!("No extension versions are quarantined");
+    }
+    Ok(())
+}
+
+fn parse_spec(spec: &str) -> Result<(&str, &str)> {
+    match spec.split_once('@') {
+        Some((id, version)) if !id.is_empty() && !version.is_empty() => Ok((id, version)),
+        _ => Err(anyhow!(
+            "Invalid extension version '{}', expected id@version",
+            spec
+        )),
+    }
+}
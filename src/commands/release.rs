@@ -1,11 +1,13 @@
 use crate::cli::ReleaseTarget;
-use crate::zed::{self, Client};
-use anyhow::Result;
+use crate::config::ZedexConfig;
+use crate::exit::{Classify, Failure};
+use crate::zed::{self, Client, write_integrity_manifests, write_release_checksums};
+use anyhow::{Result, anyhow};
 use log::info;
 use std::path::PathBuf;
 
 /// Entry point for handling `zedex release ...` commands.
-pub async fn run(target: ReleaseTarget, root_dir: PathBuf) -> Result<()> {
+pub async fn run(target: ReleaseTarget, root_dir: PathBuf, config: &ZedexConfig) -> Result<()> {
     match target {
         ReleaseTarget::Latest => {
             info!("Not implemented yet: Fetching latest Zed release info");
@@ -15,12 +17,38 @@ pub async fn run(target: ReleaseTarget, root_dir: PathBuf) -> Result<()> {
             info!("Not implemented yet: Fetching latest Zed Remote Server release info");
             Ok(())
         }
-        ReleaseTarget::Download { output_dir } => {
+        ReleaseTarget::Download {
+            output_dir,
+            platform,
+            all_platforms,
+        } => {
             let output_dir = output_dir.unwrap_or_else(|| root_dir.clone());
             let client = Client::new();
 
+            let platforms = if all_platforms {
+                Vec::new()
+            } else if platform.is_empty() {
+                let current = zed::current_platform();
+                info!(
+                    "Downloading for this machine ({}), pass --all-platforms for every platform",
+                    current
+                );
+                vec![current]
+            } else {
+                platform
+            };
+            let assets = zed::release_assets(&platforms).classify(Failure::Config)?;
+
             info!("Downloading latest Zed release to {:?}", output_dir);
-            zed::download_zed_release(&client, &output_dir).await;
+            let failed = zed::download_zed_release(&client, &output_dir, &assets).await;
+            write_release_checksums(&output_dir.join("releases"))?;
+            config.signing.sign_metadata(&output_dir)?;
+            write_integrity_manifests(&output_dir.join("releases"), config.integrity.algorithm)?;
+            if failed > 0 {
+                return Err(
+                    Failure::Partial.wrap(anyhow!("{} Zed release downloads failed", failed))
+                );
+            }
             info!("Zed release download complete");
             Ok(())
         }
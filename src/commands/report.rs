@@ -0,0 +1,187 @@
+use crate::cli::{OutputFormat, ReportTarget};
+use crate::config::ZedexConfig;
+use crate::exit::{Classify, Failure};
+use crate::zed::{
+    Extension, ExtensionCompatibility, ExtensionVersionTracker, QuarantineRegistry,
+    WrappedExtensions, compare_versions, find_archive, load_index,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Compatibility of one extension's mirrored versions with a Zed release
+#[derive(Debug, Serialize)]
+struct CompatibilityEntry {
+    id: String,
+    /// Mirrored versions that may be served, newest first
+    mirrored: Vec<String>,
+    /// Mirrored versions the Zed release can load, newest first
+    compatible: Vec<String>,
+    /// Newest version the Zed release can load, mirrored or not
+    newest_compatible: Option<String>,
+    /// `ok`, `outdated` when a newer loadable version isn't mirrored, `needs-archive` when
+    /// no mirrored version is loadable but one exists, or `incompatible`
+    status: &'static str,
+}
+
+/// Entry point for `zedex report ...` commands.
+pub fn run(
+    target: ReportTarget,
+    root_dir: &Path,
+    config: &ZedexConfig,
+    output: OutputFormat,
+) -> Result<()> {
+    match target {
+        ReportTarget::Compatibility {
+            zed_version,
+            extensions_dir,
+            json,
+        } => {
+            let extensions_dir = extensions_dir.unwrap_or_else(|| root_dir.to_path_buf());
+            let compatibility =
+                ExtensionCompatibility::for_zed_version(&zed_version).classify(Failure::Config)?;
+            let entries = compatibility_report(&extensions_dir, &compatibility, config)?;
+            if json || output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else {
+                print_compatibility(&entries, &compatibility);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn compatibility_report(
+    extensions_dir: &Path,
+    compatibility: &ExtensionCompatibility,
+    config: &ZedexConfig,
+) -> Result<Vec<CompatibilityEntry>> {
+    let index_file = extensions_dir.join("extensions.json");
+    let index = if index_file.exists() {
+        load_index(&index_file, config.index.strict)?
+    } else {
+        Vec::new()
+    };
+    let tracker: ExtensionVersionTracker =
+        fs::read_to_string(extensions_dir.join("version_tracker.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+    let quarantine = QuarantineRegistry::load(extensions_dir)?;
+    let yanked = config.extensions.yanked_versions();
+
+    // Every version known per extension: its versions.json plus the index entry
+    let mut known: BTreeMap<String, Vec<Extension>> = BTreeMap::new();
+    for extension in index {
+        known
+            .entry(extension.id.clone())
+            .or_default()
+            .push(extension);
+    }
+    for entry in fs::read_dir(extensions_dir)?.flatten() {
+        let Some(id) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if id.starts_with('.') || !entry.path().join("versions.json").is_file() {
+            continue;
+        }
+        let listed = fs::read_to_string(entry.path().join("versions.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str::<WrappedExtensions>(&content).ok())
+            .map(|wrapped| wrapped.data)
+            .unwrap_or_default();
+        known.entry(id).or_default().extend(listed);
+    }
+
+    let withheld = |id: &str, version: &str| {
+        yanked
+            .get(id)
+            .is_some_and(|versions| versions.contains(version))
+            || quarantine.is_quarantined(id, version)
+    };
+
+    let mut entries = Vec::new();
+    for (id, versions) in known {
+        let mut seen = HashSet::new();
+        let mut versions: Vec<Extension> = versions
+            .into_iter()
+            .filter(|ext| seen.insert(ext.version.clone()))
+            .collect();
+        versions.sort_by(|a, b| compare_versions(&b.version, &a.version));
+
+        let latest_archive = find_archive(extensions_dir, &id, None).is_some();
+        let latest_version = tracker.extensions.get(&id);
+        let mirrored: Vec<&Extension> = versions
+            .iter()
+            .filter(|ext| !withheld(&id, &ext.version))
+            .filter(|ext| {
+                find_archive(extensions_dir, &id, Some(&ext.version)).is_some()
+                    || (latest_archive && latest_version == Some(&ext.version))
+            })
+            .collect();
+        let compatible: Vec<&Extension> = mirrored
+            .iter()
+            .copied()
+            .filter(|ext| compatibility.is_compatible(ext))
+            .collect();
+        let newest_compatible = compatibility.newest_compatible(&versions);
+
+        let status = match (compatible.first(), newest_compatible) {
+            (_, None) => "incompatible",
+            (None, Some(_)) => "needs-archive",
+            (Some(mirrored), Some(newest)) if mirrored.version != newest.version => "outdated",
+            _ => "ok",
+        };
+        entries.push(CompatibilityEntry {
+            id,
+            mirrored: mirrored.iter().map(|ext| ext.version.clone()).collect(),
+            compatible: compatible.iter().map(|ext| ext.version.clone()).collect(),
+            newest_compatible: newest_compatible.map(|ext| ext.version.clone()),
+            status,
+        });
+    }
+    Ok(entries)
+}
+
+fn print_compatibility(entries: &[CompatibilityEntry], compatibility: &ExtensionCompatibility) {
+    println!(
+        "Zed {} loads schema version <= {} and WASM API {} to {}\n",
+        compatibility.zed_version,
+        compatibility.max_schema_version,
+        compatibility.min_wasm_api_version,
+        compatibility.max_wasm_api_version
+    );
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for entry in entries {
+        *counts.entry(entry.status).or_default() += 1;
+        let newest_mirrored = entry.compatible.first().map(String::as_str);
+        println!(
+            "{:<40} {:<14} {:>3}/{:<3} compatible  {}",
+            entry.id,
+            entry.status,
+            entry.compatible.len(),
+            entry.mirrored.len(),
+            newest_mirrored.unwrap_or("-")
+        );
+        if matches!(entry.status, "outdated" | "needs-archive")
+            && let Some(newest) = &entry.newest_compatible
+        {
+            println!(
+                "    mirror it with: zedex get extension {}@{}",
+                entry.id, newest
+            );
+        }
+    }
+
+    println!(
+        "\n{} extensions: {} ok, {} outdated, {} need archives, {} incompatible",
+        entries.len(),
+        counts.get("ok").unwrap_or(&0),
+        counts.get("outdated").unwrap_or(&0),
+        counts.get("needs-archive").unwrap_or(&0),
+        counts.get("incompatible").unwrap_or(&0)
+    );
+}
@@ -0,0 +1,232 @@
+use crate::cli::OutputFormat;
+use crate::exit::{Classify, Failure};
+use crate::zed::{UpstreamSchema, WrappedExtensions, current_platform, format_bytes};
+use anyhow::{Result, anyhow, bail, ensure};
+use log::info;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Compatibility range sent with update checks, wide enough for any mirrored extension
+const UPDATE_CHECK_QUERY: &str = "min_schema_version=0&max_schema_version=1&min_wasm_api_version=0.0.0&max_wasm_api_version=999.999.999";
+
+/// Characters of an error response shown with a failed check
+const MAX_ERROR_EXCERPT: usize = 200;
+
+/// Outcome of one check of a `zedex selftest` run
+#[derive(Debug, Serialize)]
+struct Check {
+    name: &'static str,
+    path: String,
+    passed: bool,
+    /// What was found, or why the check failed
+    detail: String,
+    millis: u128,
+}
+
+/// Entry point for `zedex selftest`, requesting what a Zed client requests from a mirror
+/// and reporting pass or fail per endpoint.
+pub async fn run(
+    url: &str,
+    extension: Option<&str>,
+    timeout_secs: u64,
+    output: OutputFormat,
+) -> Result<()> {
+    let base = url.trim_end_matches('/');
+    if !base.starts_with("http://") && !base.starts_with("https://") {
+        return Err(anyhow!("{} is not an http:// or https:// URL", url)).classify(Failure::Config);
+    }
+    // The mirror is tested as a client sees it, without the upstream proxy and tokens
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()?;
+    let tester = Tester { client, base };
+    info!("Testing the mirror at {}", base);
+
+    let mut checks = Vec::new();
+    checks.push(tester.check("health", "/health".to_string(), health).await);
+
+    let index_path = "/extensions?max_schema_version=1&include_native=false".to_string();
+    let mut sample = None;
+    checks.push(
+        tester
+            .check("index", index_path, |body| {
+                let index = extensions(UpstreamSchema::Index, body)?;
+                let chosen = match extension {
+                    Some(id) => index.data.iter().find(|ext| ext.id == id),
+                    None => index.data.first(),
+                };
+                sample = chosen.map(|ext| (ext.id.clone(), ext.version.clone()));
+                Ok(format!("{} extensions", index.data.len()))
+            })
+            .await,
+    );
+
+    match &sample {
+        Some((id, version)) => {
+            checks.push(
+                tester
+                    .check(
+                        "updates",
+                        format!("/extensions/updates?{}&ids={}", UPDATE_CHECK_QUERY, id),
+                        |body| {
+                            let updates = extensions(UpstreamSchema::Index, body)?;
+                            ensure!(
+                                updates.data.iter().any(|ext| &ext.id == id),
+                                "{} is in the index but not offered as an update",
+                                id
+                            );
+                            Ok(format!("{} offered at {}", id, version))
+                        },
+                    )
+                    .await,
+            );
+            checks.push(
+                tester
+                    .check("versions", format!("/extensions/{}", id), |body| {
+                        let versions = extensions(UpstreamSchema::Versions, body)?;
+                        ensure!(!versions.data.is_empty(), "no versions listed for {}", id);
+                        Ok(format!("{} versions of {}", versions.data.len(), id))
+                    })
+                    .await,
+            );
+            checks.push(
+                tester
+                    .check(
+                        "download",
+                        format!("/extensions/{}/{}/download", id, version),
+                        |body| {
+                            ensure!(
+                                body.starts_with(&[0x1f, 0x8b]),
+                                "the archive is not gzip-compressed"
+                            );
+                            Ok(format!(
+                                "{}@{}, {}",
+                                id,
+                                version,
+                                format_bytes(body.len() as u64)
+                            ))
+                        },
+                    )
+                    .await,
+            );
+        }
+        None => {
+            let reason = match extension {
+                Some(id) => format!("{} is not in the index", id),
+                None => "no extension in the index to test with".to_string(),
+            };
+            for name in ["updates", "versions", "download"] {
+                checks.push(Check {
+                    name,
+                    path: String::new(),
+                    passed: false,
+                    detail: reason.clone(),
+                    millis: 0,
+                });
+            }
+        }
+    }
+
+    let platform = current_platform();
+    let (os, arch) = platform.split_once('-').unwrap_or((&platform, ""));
+    checks.push(
+        tester
+            .check(
+                "latest release",
+                format!("/api/releases/latest?asset=zed&os={}&arch={}", os, arch),
+                |body| {
+                    UpstreamSchema::LatestRelease.validate(body)?;
+                    let release: serde_json::Value = serde_json::from_slice(body)?;
+                    Ok(format!(
+                        "Zed {}",
+                        release["version"].as_str().unwrap_or("?")
+                    ))
+                },
+            )
+            .await,
+    );
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&checks)?);
+    } else {
+        for check in &checks {
+            println!(
+                "  {:<4}  {:<15} {:>6}ms  {}",
+                if check.passed { "PASS" } else { "FAIL" },
+                check.name,
+                check.millis,
+                check.detail
+            );
+        }
+    }
+
+    if failed > 0 {
+        return Err(Failure::Verification.wrap(anyhow!(
+            "{} of {} checks failed against {}",
+            failed,
+            checks.len(),
+            base
+        )));
+    }
+    info!("All {} checks passed", checks.len());
+    Ok(())
+}
+
+struct Tester<'a> {
+    client: reqwest::Client,
+    base: &'a str,
+}
+
+impl Tester<'_> {
+    /// Request `path` and judge the response body with `inspect`, which describes what it
+    /// found or fails the check
+    async fn check(
+        &self,
+        name: &'static str,
+        path: String,
+        inspect: impl FnOnce(&[u8]) -> Result<String>,
+    ) -> Check {
+        let started = Instant::now();
+        let result = match self.get(&path).await {
+            Ok(body) => inspect(&body),
+            Err(e) => Err(e),
+        };
+        Check {
+            name,
+            passed: result.is_ok(),
+            detail: result.unwrap_or_else(|e| format!("{:#}", e)),
+            millis: started.elapsed().as_millis(),
+            path,
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<Vec<u8>> {
+        let response = self
+            .client
+            .get(format!("{}{}", self.base, path))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let excerpt: String = body.trim().chars().take(MAX_ERROR_EXCERPT).collect();
+            bail!("{} answered {}: {}", path, status, excerpt);
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+fn health(body: &[u8]) -> Result<String> {
+    let health: serde_json::Value = serde_json::from_slice(body)?;
+    let status = health["status"]
+        .as_str()
+        .ok_or_else(|| anyhow!("no status in the health response"))?;
+    Ok(status.to_string())
+}
+
+/// Parse an extension listing after checking it has the shape Zed expects
+fn extensions(schema: UpstreamSchema, body: &[u8]) -> Result<WrappedExtensions> {
+    schema.validate(body)?;
+    Ok(serde_json::from_slice(body)?)
+}
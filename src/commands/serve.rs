@@ -1,29 +1,326 @@
-use crate::zed::{LocalServer, ServerConfig};
-use anyhow::Result;
-use std::path::PathBuf;
+use crate::commands::sync::{self, SyncRun};
+use crate::config::{TenantConfig, ZedexConfig};
+use crate::exit::{Classify, Failure};
+use crate::zed::{ArchiveScanner, LocalServer, ProxyRules, ServerConfig, TlsConfig};
+use anyhow::{Result, anyhow};
+use log::{error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+/// Default drift check interval when running in proxy mode
+const PROXY_DRIFT_CHECK_INTERVAL_SECS: u64 = 3600;
+
+/// Address bound when neither --host nor --bind-all is given outside a container
+const LOCAL_HOST: &str = "127.0.0.1";
+
+/// Address binding every interface
+const ALL_INTERFACES: &str = "0.0.0.0";
+
+/// Seconds between upstream API requests of background syncs, as for `zedex sync`
+const BACKGROUND_SYNC_RATE_LIMIT_SECS: u64 = 10;
 
 pub struct ServeOptions {
     pub port: u16,
-    pub host: String,
+    pub host: Option<String>,
+    pub bind_all: bool,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
     pub extensions_dir: Option<PathBuf>,
     pub proxy_mode: bool,
+    pub pull_through: bool,
     pub domain: Option<String>,
+    pub drift_check_interval: Option<u64>,
+    pub sync_interval: Option<u64>,
+    pub verify_per_minute: Option<usize>,
+    pub announce: bool,
+    pub browse: bool,
+    pub events: bool,
+    pub check_config: bool,
 }
 
-pub async fn run(options: ServeOptions, root_dir: PathBuf) -> Result<()> {
-    let mut config = ServerConfig::default();
-    config.port = options.port;
-    config.host = options.host;
-    config.proxy_mode = options.proxy_mode;
-    config.domain = options.domain;
+pub async fn run(
+    options: ServeOptions,
+    root_dir: PathBuf,
+    zedex_config: &ZedexConfig,
+) -> Result<()> {
+    let resolved_extensions_dir = options.extensions_dir.unwrap_or_else(|| root_dir.clone());
+    let defaults = ServerConfig::default();
+    let listener = &zedex_config.server;
 
-    let resolved_extensions_dir = options.extensions_dir.unwrap_or(root_dir);
-    config.extensions_dir = resolved_extensions_dir.clone();
+    let tls_cert = options.tls_cert.as_ref().or(listener.tls_cert.as_ref());
+    let tls_key = options.tls_key.as_ref().or(listener.tls_key.as_ref());
+    let tls = match (tls_cert, tls_key) {
+        (Some(cert_path), Some(key_path)) => Some(TlsConfig {
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+        }),
+        (None, None) => None,
+        _ => {
+            return Err(anyhow!(
+                "Both a TLS certificate and key must be set, with --tls-cert and --tls-key or in [server], to serve HTTPS"
+            ))
+            .classify(Failure::Config);
+        }
+    };
+    if let Some(http_port) = listener.http_port {
+        if tls.is_none() {
+            return Err(anyhow!(
+                "http_port in [server] adds a plaintext port next to HTTPS, set tls_cert and tls_key too"
+            ))
+            .classify(Failure::Config);
+        }
+        if http_port == options.port {
+            return Err(anyhow!(
+                "http_port {} in [server] must differ from the HTTPS port",
+                http_port
+            ))
+            .classify(Failure::Config);
+        }
+    }
 
-    if let Some(releases_dir) = config.releases_dir.as_mut() {
-        *releases_dir = resolved_extensions_dir.join("releases");
+    if let Some((name, port)) = zedex_config
+        .channels
+        .iter()
+        .filter_map(|(name, channel)| Some((name, channel.port?)))
+        .find(|(_, port)| *port == options.port || Some(*port) == listener.http_port)
+    {
+        return Err(anyhow!(
+            "Port {} of channel {} is already served by [server]",
+            port,
+            name
+        ))
+        .classify(Failure::Config);
+    }
+    if let Some(external_url) = &listener.external_url
+        && !matches!(
+            url::Url::parse(external_url)
+                .map(|url| url.scheme().to_string())
+                .as_deref(),
+            Ok("http" | "https")
+        )
+    {
+        return Err(anyhow!(
+            "external_url {} in [server] must be an http:// or https:// URL",
+            external_url
+        ))
+        .classify(Failure::Config);
     }
 
+    let mut config = ServerConfig {
+        port: options.port,
+        host: bind_host(options.host, options.bind_all),
+        proxy_mode: options.proxy_mode,
+        pull_through: options.pull_through,
+        scanner: zedex_config
+            .scan
+            .command
+            .as_ref()
+            .map(ArchiveScanner::new)
+            .transpose()
+            .classify(Failure::Config)?,
+        cache_quota: zedex_config.cache.quota().classify(Failure::Config)?,
+        domain: options.domain.or_else(|| listener.external_url.clone()),
+        trusted_proxies: listener.trusted_proxies().classify(Failure::Config)?,
+        extensions_dir: resolved_extensions_dir.clone(),
+        fallback_extensions_dirs: listener.fallback_extensions_dirs.clone(),
+        root_dir: Some(root_dir.clone()),
+        drift_check_interval: options
+            .drift_check_interval
+            .or(options
+                .proxy_mode
+                .then_some(PROXY_DRIFT_CHECK_INTERVAL_SECS))
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs),
+        verify_per_minute: options.verify_per_minute,
+        announce: options.announce,
+        browsable_indexes: options.browse,
+        events: options.events,
+        strict_index: zedex_config.index.strict,
+        pins: zedex_config.extensions.pins.clone(),
+        yanked: zedex_config.extensions.yanked_versions(),
+        proxy_rules: ProxyRules {
+            allow: zedex_config.proxy.allow.clone(),
+            deny: zedex_config.proxy.deny.clone(),
+        },
+        proxy_cache: zedex_config.proxy.cache.proxy_cache(),
+        relay_schema_mismatches: zedex_config.proxy.relay_schema_mismatches,
+        upstream_limit: zedex_config.proxy.upstream_limit(),
+        releases_dir: defaults
+            .releases_dir
+            .map(|_| resolved_extensions_dir.join("releases")),
+        tenants: Default::default(),
+        keep_alive: listener.keep_alive_secs.map(Duration::from_secs),
+        backlog: listener.backlog.unwrap_or(defaults.backlog),
+        max_connections: listener.max_connections.unwrap_or(defaults.max_connections),
+        workers: listener.workers,
+        tls,
+        http_port: listener.http_port,
+        redirect_http: listener.redirect_http,
+        timeouts: listener.timeouts.server_timeouts(),
+        signer: zedex_config.signing.signer(),
+        admin_token: listener.admin_token.clone(),
+        webhooks: zedex_config.webhooks().classify(Failure::Config)?,
+        channels: zedex_config.index_channels().classify(Failure::Config)?,
+        default_channel: listener.default_channel.clone(),
+        max_sync_age: listener
+            .max_sync_age_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        cors: listener.cors.cors_policy().classify(Failure::Config)?,
+        virtual_hosts: listener.virtual_hosts.virtual_hosts(),
+    };
+
+    config.tenants = zedex_config
+        .tenants
+        .iter()
+        .map(|(name, tenant)| Ok((name.clone(), tenant_config(&config, name, tenant)?)))
+        .collect::<Result<_>>()
+        .classify(Failure::Config)?;
+
     let server = LocalServer::new(config);
+    if options.check_config {
+        return server.check().classify(Failure::Config);
+    }
+
+    if let Some(interval) = options.sync_interval.filter(|secs| *secs > 0) {
+        info!(
+            "Syncing {:?} from upstream every {} seconds",
+            resolved_extensions_dir, interval
+        );
+        tokio::spawn(run_background_sync(
+            resolved_extensions_dir,
+            root_dir,
+            Duration::from_secs(interval),
+            zedex_config.clone(),
+        ));
+    }
     server.run().await
 }
+
+/// Run an incremental `zedex sync` into the served extensions directory every `interval`.
+/// Archives and metadata are replaced atomically, so requests see either the old or the
+/// new file.
+async fn run_background_sync(
+    extensions_dir: PathBuf,
+    root_dir: PathBuf,
+    interval: Duration,
+    config: ZedexConfig,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    // A sync outlasting the interval is followed by a full interval, not another sync
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes immediately, serving starts with the mirror as it is
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        info!("Background sync started");
+        let run = SyncRun {
+            from: None,
+            rate_limit: BACKGROUND_SYNC_RATE_LIMIT_SECS,
+            output_dir: &extensions_dir,
+            root_dir: &root_dir,
+            status_file: None,
+            triggered_by: "serve",
+        };
+        match sync::run(run, &config).await {
+            Ok(()) => info!("Background sync finished"),
+            Err(e) => error!("Background sync failed: {:#}", e),
+        }
+    }
+}
+
+/// Derive a tenant's server configuration from the main one, overriding its
+/// directories and policies. Channels and proxy rules are the tenant's own; a proxying
+/// tenant without rules is refused while the main server has some, rather than let it
+/// forward what the main server doesn't.
+fn tenant_config(base: &ServerConfig, name: &str, tenant: &TenantConfig) -> Result<ServerConfig> {
+    let extensions_dir = tenant
+        .extensions_dir
+        .clone()
+        .unwrap_or_else(|| tenant.root_dir.clone());
+    let proxy_mode = tenant.proxy_mode.unwrap_or(base.proxy_mode);
+
+    let proxy_rules = match &tenant.proxy {
+        Some(proxy) => ProxyRules {
+            allow: proxy.allow.clone(),
+            deny: proxy.deny.clone(),
+        },
+        None if proxy_mode
+            && !(base.proxy_rules.allow.is_empty() && base.proxy_rules.deny.is_empty()) =>
+        {
+            return Err(anyhow!(
+                "Tenant {} proxies to upstream but has no [tenants.{}.proxy] rules, while [proxy] has some",
+                name,
+                name
+            ));
+        }
+        None => ProxyRules::default(),
+    };
+
+    Ok(ServerConfig {
+        extensions_dir,
+        fallback_extensions_dirs: tenant.fallback_extensions_dirs.clone(),
+        releases_dir: Some(
+            tenant
+                .releases_dir
+                .clone()
+                .unwrap_or_else(|| tenant.root_dir.join("releases")),
+        ),
+        root_dir: Some(tenant.root_dir.clone()),
+        proxy_mode,
+        domain: base
+            .domain
+            .as_ref()
+            .map(|domain| format!("{}/{}", domain.trim_end_matches('/'), name)),
+        pins: tenant.extensions.pins.clone(),
+        yanked: tenant.extensions.yanked_versions(),
+        proxy_rules,
+        channels: tenant.index_channels(name)?,
+        default_channel: tenant.default_channel.clone(),
+        tenants: Default::default(),
+        ..base.clone()
+    })
+}
+
+/// The address to listen on. Inside a container 127.0.0.1 is only reachable from the
+/// container itself, so a published port would silently lead nowhere; there the default is
+/// all interfaces instead.
+fn bind_host(host: Option<String>, bind_all: bool) -> String {
+    if let Some(host) = host {
+        return host;
+    }
+    if bind_all {
+        warn!(
+            "Binding {}, the mirror is reachable from other machines",
+            ALL_INTERFACES
+        );
+        return ALL_INTERFACES.to_string();
+    }
+    if in_container() {
+        warn!(
+            "Running in a container, binding {} so published ports reach the mirror. Pass --host {} to keep it local to the container",
+            ALL_INTERFACES, LOCAL_HOST
+        );
+        return ALL_INTERFACES.to_string();
+    }
+    LOCAL_HOST.to_string()
+}
+
+/// Whether zedex runs in a Docker, Podman, Kubernetes, LXC or systemd-nspawn container
+fn in_container() -> bool {
+    if Path::new("/.dockerenv").exists() || Path::new("/run/.containerenv").exists() {
+        return true;
+    }
+    if std::env::var_os("container").is_some()
+        || std::env::var_os("KUBERNETES_SERVICE_HOST").is_some()
+    {
+        return true;
+    }
+    fs::read_to_string("/proc/1/cgroup").is_ok_and(|cgroup| {
+        ["docker", "kubepods", "containerd", "libpod", "lxc"]
+            .iter()
+            .any(|runtime| cgroup.contains(runtime))
+    })
+}
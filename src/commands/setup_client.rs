@@ -0,0 +1,276 @@
+use super::dns_setup::{self, DEFAULT_HOSTS_FILE, ZED_HOSTS};
+use crate::exit::{Classify, Failure};
+use crate::zed::{SERVER_URL_ENV, current_platform};
+use anyhow::{Context, Result, anyhow, bail};
+use log::info;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use url::{Host, Url};
+
+/// Port Zed connects to zed.dev and api.zed.dev on
+const HTTPS_PORT: u16 = 443;
+
+/// Entry point for `zedex setup-client`, printing the Zed settings, environment variables
+/// and hosts-file entries that point a Zed installation at a mirror after checking that
+/// each endpoint they lead to answers. With `write`, the setting and, with `dns`, the
+/// hosts-file entries are written once every check passed.
+pub async fn run(
+    url: &str,
+    ip: Option<IpAddr>,
+    write: bool,
+    dns: bool,
+    hosts_file: Option<PathBuf>,
+    settings_file: Option<PathBuf>,
+    timeout_secs: u64,
+) -> Result<()> {
+    let mirror = Url::parse(url)
+        .ok()
+        .filter(|mirror| matches!(mirror.scheme(), "http" | "https") && mirror.has_host())
+        .ok_or_else(|| anyhow!("{} is not an http:// or https:// URL", url))
+        .classify(Failure::Config)?;
+    let base = url.trim_end_matches('/');
+    let hosts_file = hosts_file.unwrap_or_else(|| PathBuf::from(DEFAULT_HOSTS_FILE));
+    let settings_file = settings_file
+        .or_else(zed_settings_file)
+        .context("Cannot determine the Zed settings file, pass --settings-file")?;
+
+    // Zed keeps connecting to zed.dev over HTTPS on the default port, so hosts-file
+    // entries only work for a mirror serving exactly that
+    let overridable =
+        mirror.scheme() == "https" && mirror.port_or_known_default() == Some(HTTPS_PORT);
+    if dns && !overridable {
+        return Err(anyhow!(
+            "Pointing {} at the mirror needs it served over HTTPS on port {}, unlike {}",
+            ZED_HOSTS.join(" and "),
+            HTTPS_PORT,
+            base
+        ))
+        .classify(Failure::Config);
+    }
+    let ip = match (ip, overridable) {
+        (Some(ip), _) => Some(ip),
+        (None, true) => Some(resolve(&mirror).await.classify(Failure::Config)?),
+        (None, false) => None,
+    };
+
+    let timeout = Duration::from_secs(timeout_secs);
+    let platform = current_platform();
+    let (os, arch) = platform.split_once('-').unwrap_or((&platform, ""));
+    let extensions_path = "/extensions?max_schema_version=1&include_native=false".to_string();
+    let releases_path = format!("/api/releases/latest?asset=zed&os={}&arch={}", os, arch);
+
+    info!("Checking the endpoints Zed will use");
+    let mut endpoints = vec![
+        format!("{}{}", base, extensions_path),
+        format!("{}{}", base, releases_path),
+    ];
+    let mut client = reqwest::Client::builder().timeout(timeout);
+    if let Some(ip) = ip.filter(|_| dns) {
+        // Reach Zed's hosts the way a client with the hosts-file entries will
+        for host in ZED_HOSTS {
+            client = client.resolve(host, SocketAddr::new(ip, HTTPS_PORT));
+        }
+        endpoints.push(format!("https://api.zed.dev{}", extensions_path));
+        endpoints.push(format!("https://zed.dev{}", releases_path));
+    }
+    let client = client.build()?;
+
+    let mut failed = 0;
+    for endpoint in &endpoints {
+        let started = Instant::now();
+        let result = check(&client, endpoint).await;
+        if result.is_err() {
+            failed += 1;
+        }
+        println!(
+            "  {:<4}  {:>6}ms  {}  {}",
+            if result.is_ok() { "PASS" } else { "FAIL" },
+            started.elapsed().as_millis(),
+            endpoint,
+            result.unwrap_or_else(|e| format!("{:#}", e))
+        );
+    }
+
+    println!("\nZed settings ({:?}):\n", settings_file);
+    println!("{{\n  \"server_url\": {}\n}}", serde_json::to_string(base)?);
+    println!("\nOr, instead of the setting, in Zed's environment:\n");
+    println!("  export {}={}", SERVER_URL_ENV, base);
+    match ip {
+        Some(ip) => {
+            println!(
+                "\nOr, for clients that keep their settings, in {:?}:\n",
+                hosts_file
+            );
+            println!("{}", dns_setup::hosts_block(ip));
+        }
+        None => println!(
+            "\nHosts-file entries aren't possible: Zed connects to {} over HTTPS on port {}, \
+             where {} isn't served.",
+            ZED_HOSTS.join(" and "),
+            HTTPS_PORT,
+            base
+        ),
+    }
+
+    if failed > 0 {
+        return Err(Failure::Verification.wrap(anyhow!(
+            "{} of {} endpoints failed{}",
+            failed,
+            endpoints.len(),
+            if write { ", nothing was written" } else { "" }
+        )));
+    }
+    if !write {
+        return Ok(());
+    }
+
+    write_server_url(&settings_file, base)?;
+    println!("\nSet server_url in {:?}", settings_file);
+    if let Some(ip) = ip.filter(|_| dns) {
+        dns_setup::apply_hosts(ip, &hosts_file)?;
+        println!(
+            "{} now resolve to {} via {:?}",
+            ZED_HOSTS.join(" and "),
+            ip,
+            hosts_file
+        );
+    }
+    Ok(())
+}
+
+/// Request an endpoint and describe its answer, failing on anything but success
+async fn check(client: &reqwest::Client, endpoint: &str) -> Result<String> {
+    let response = client.get(endpoint).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        bail!("answered {}", status);
+    }
+    Ok(format!("answered {}", status))
+}
+
+/// Address of the mirror's host, for the hosts-file entries
+async fn resolve(mirror: &Url) -> Result<IpAddr> {
+    match mirror.host() {
+        Some(Host::Ipv4(ip)) => Ok(ip.into()),
+        Some(Host::Ipv6(ip)) => Ok(ip.into()),
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, HTTPS_PORT))
+            .await
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .map(|address| address.ip())
+            .with_context(|| format!("Cannot resolve {}, pass --ip", domain)),
+        None => bail!("{} has no host", mirror),
+    }
+}
+
+/// The current user's Zed settings file
+fn zed_settings_file() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return dirs::config_dir().map(|dir| dir.join("Zed").join("settings.json"));
+    }
+    // Zed uses ~/.config on macOS as well
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_dir.join("zed").join("settings.json"))
+}
+
+/// Add `server_url` to a Zed settings file, keeping its comments and layout
+fn write_server_url(path: &Path, url: &str) -> Result<()> {
+    let mut settings = match fs::read_to_string(path) {
+        Ok(settings) => settings,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Cannot read {:?}", path)),
+    };
+    let url = serde_json::to_string(url)?;
+    if settings.trim().is_empty() {
+        settings = format!("{{\n  \"server_url\": {}\n}}\n", url);
+    } else {
+        let scan = scan_settings(&settings).with_context(|| {
+            format!(
+                "{:?} holds no settings object, add \"server_url\": {} by hand",
+                path, url
+            )
+        })?;
+        if scan.has_server_url {
+            bail!(
+                "{:?} already sets server_url, change it to {} by hand",
+                path,
+                url
+            );
+        }
+        let separator = if scan.empty { "\n" } else { "," };
+        settings.insert_str(
+            scan.open + 1,
+            &format!("\n  \"server_url\": {}{}", url, separator),
+        );
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Cannot create {:?}", parent))?;
+    }
+    fs::write(path, settings).with_context(|| format!("Cannot write {:?}", path))
+}
+
+/// What a settings file holds at its top level
+struct SettingsScan {
+    /// Offset of the brace opening the settings object
+    open: usize,
+    /// The object has no entries yet
+    empty: bool,
+    has_server_url: bool,
+}
+
+/// Find the settings object in a settings file, skipping the comments Zed allows there
+fn scan_settings(settings: &str) -> Option<SettingsScan> {
+    let bytes = settings.as_bytes();
+    let mut open = None;
+    let mut depth = 0usize;
+    let mut empty = true;
+    let mut has_server_url = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = settings[i..].find('\n').map_or(bytes.len(), |end| i + end);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = settings[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                continue;
+            }
+            b'"' => {
+                let start = i + 1;
+                let mut end = start;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                if depth == 1 {
+                    empty = false;
+                    has_server_url |= settings.get(start..end) == Some("server_url");
+                }
+                i = end + 1;
+                continue;
+            }
+            b'{' if depth == 0 && open.is_none() => {
+                open = Some(i);
+                depth = 1;
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            byte if depth == 1 && !byte.is_ascii_whitespace() && byte != b',' => empty = false,
+            _ => {}
+        }
+        i += 1;
+    }
+    Some(SettingsScan {
+        open: open?,
+        empty,
+        has_server_url,
+    })
+}
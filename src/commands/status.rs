@@ -0,0 +1,103 @@
+use crate::cli::OutputFormat;
+use crate::zed::{TRANSFER_STATS_FILE, TransferCounters, format_bytes, load_sync_history};
+use anyhow::Result;
+use chrono::DateTime;
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+
+/// Entry point for `zedex status`, printing the transfer counters persisted by the server.
+pub fn run(
+    extensions_dir: Option<PathBuf>,
+    root_dir: PathBuf,
+    top: usize,
+    output: OutputFormat,
+) -> Result<()> {
+    let path = extensions_dir.unwrap_or(root_dir).join(TRANSFER_STATS_FILE);
+    let counters = if path.exists() {
+        TransferCounters::load(&path)?
+    } else if output == OutputFormat::Json {
+        TransferCounters::default()
+    } else {
+        println!("No transfer statistics recorded yet ({:?} not found)", path);
+        return Ok(());
+    };
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&counters)?);
+        return Ok(());
+    }
+
+    println!(
+        "Total served: {} in {} requests",
+        format_bytes(counters.total_bytes),
+        counters.total_requests
+    );
+
+    println!("\nBy endpoint:");
+    let mut endpoints: Vec<_> = counters.by_endpoint.iter().collect();
+    endpoints.sort_by_key(|(_, bytes)| Reverse(**bytes));
+    for (endpoint, bytes) in endpoints {
+        println!("  {:<50} {:>12}", endpoint, format_bytes(*bytes));
+    }
+
+    println!("\nBy day:");
+    for (day, bytes) in counters.by_day.iter().rev() {
+        println!("  {:<50} {:>12}", day, format_bytes(*bytes));
+    }
+
+    println!("\nTop extensions:");
+    let mut extensions: Vec<_> = counters.by_extension.iter().collect();
+    extensions.sort_by_key(|(_, bytes)| Reverse(**bytes));
+    for (id, bytes) in extensions.into_iter().take(top) {
+        println!("  {:<50} {:>12}", id, format_bytes(*bytes));
+    }
+
+    Ok(())
+}
+
+/// Entry point for `zedex status --history`, listing the recorded sync runs
+pub fn history(root_dir: &Path, output: OutputFormat) -> Result<()> {
+    let records = load_sync_history(root_dir)?;
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+    if records.is_empty() {
+        println!("No sync runs recorded in {:?}", root_dir);
+        return Ok(());
+    }
+
+    let time = |timestamp: i64| {
+        DateTime::from_timestamp(timestamp, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_default()
+    };
+    match records.iter().rev().find(|record| record.succeeded()) {
+        Some(last) => println!(
+            "Last successful sync: {} ({} from {})",
+            time(last.finished_at),
+            last.operation,
+            last.source
+        ),
+        None => println!("No successful sync recorded"),
+    }
+
+    println!();
+    for record in &records {
+        println!(
+            "{}  {:<18} {:<9} {:>6} ok {:>5} failed {:>10} {:>5}s  {} ({})",
+            time(record.started_at),
+            record.operation,
+            record.outcome,
+            record.completed,
+            record.failed,
+            format_bytes(record.bytes),
+            record.finished_at - record.started_at,
+            record.source,
+            record.triggered_by
+        );
+        if let Some(error) = &record.error {
+            println!("    {}", error);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use crate::commands::get::handle_all_extensions;
+use crate::config::ZedexConfig;
+use crate::exit::{Classify, Failure, outcome};
+use crate::zed::{
+    CacheQuota, Client, DownloadOptions, ManifestEntry, QUARANTINE_DIR, QuarantineRegistry,
+    SyncProgress, SyncRecord, append_sync_record, cached_sha256, format_bytes, is_disk_full,
+    log_request_usage, sha256_bytes, snapshot_index, write_atomic, write_integrity_manifests,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Hidden file in the output directory remembering the last manifest synced from each peer
+const PEER_SYNC_STATE_FILE: &str = ".peer_sync.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PeerSyncState {
+    /// Entity tag of the last fully synced manifest, keyed by peer URL
+    etags: BTreeMap<String, String>,
+}
+
+/// Options of a `zedex sync` run
+pub struct SyncRun<'a> {
+    /// Peer mirror to replicate, upstream when unset
+    pub from: Option<&'a str>,
+    /// Seconds between upstream API requests
+    pub rate_limit: u64,
+    pub output_dir: &'a Path,
+    /// Root directory holding the sync history
+    pub root_dir: &'a Path,
+    pub status_file: Option<&'a Path>,
+    pub triggered_by: &'a str,
+}
+
+/// Entry point for `zedex sync`. Without `--from`, the index is refreshed and only the
+/// extensions whose version differs from `version_tracker.json` are downloaded.
+///
+/// With `--from <url>`, a peer mirror's extensions directory is replicated. Files whose
+/// SHA-256 already matches the peer's manifest are not transferred. With a trusted key
+/// configured, the manifest must carry a valid signature.
+pub async fn run(run: SyncRun<'_>, config: &ZedexConfig) -> Result<()> {
+    let webhooks = config.webhooks().classify(Failure::Config)?;
+    let progress = Arc::new(SyncProgress::new(run.status_file, "sync"));
+    let result = match run.from {
+        Some(from) => replicate(from, run.output_dir, Some(&progress), config).await,
+        None => {
+            let options = DownloadOptions {
+                rate_limit: run.rate_limit,
+                progress: Some(progress.clone()),
+                ..Default::default()
+            };
+            handle_all_extensions(run.output_dir, None, true, options, config).await
+        }
+    };
+    log_request_usage().await;
+    progress.finish(&result);
+
+    let record = SyncRecord::new(
+        &progress.status(),
+        run.from.unwrap_or("upstream"),
+        run.triggered_by,
+        outcome(&result),
+    );
+    if let Err(e) = append_sync_record(run.root_dir, &record) {
+        error!("Failed to record the sync in the history: {:#}", e);
+    }
+    webhooks.sync_finished(&record).await;
+    result
+}
+
+async fn replicate(
+    from: &str,
+    output_dir: &Path,
+    progress: Option<&SyncProgress>,
+    config: &ZedexConfig,
+) -> Result<()> {
+    let client = Client::new().with_host(from);
+    let quota = config.cache.quota().classify(Failure::Config)?;
+    fs::create_dir_all(output_dir)?;
+
+    let state_file = output_dir.join(PEER_SYNC_STATE_FILE);
+    let mut state: PeerSyncState = fs::read_to_string(&state_file)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    info!("Fetching checksum manifest from {}", client.host());
+    let known_etag = state.etags.get(client.host()).cloned();
+    let Some((manifest, etag)) = client.get_checksum_manifest(known_etag.as_deref()).await? else {
+        info!("Peer manifest unchanged since the last sync, nothing to do");
+        return Ok(());
+    };
+
+    if let Some(verifier) = config.signing.verifier() {
+        let (signature, signed_etag) = client.get_manifest_signature().await.context(
+            "The peer's manifest must be signed, but its signature could not be fetched",
+        )?;
+        if signed_etag.as_deref() != Some(manifest.etag().as_str()) {
+            return Err(Failure::Verification.wrap(anyhow!(
+                "The peer's manifest changed while fetching its signature, run the sync again"
+            )));
+        }
+        verifier
+            .verify_bytes(&manifest.canonical_bytes(), &signature)
+            .context("The peer's manifest is not signed by the trusted key")
+            .classify(Failure::Verification)?;
+        info!("Peer manifest signature verified");
+    }
+
+    let quarantine = QuarantineRegistry::load(output_dir)?;
+
+    // Archives go first so metadata never references files that aren't there yet
+    let mut files: Vec<_> = manifest.files.iter().collect();
+    files.sort_by_key(|(path, _)| path.ends_with(".json"));
+
+    if let Some(progress) = progress {
+        progress.start_phase("transfer", files.len());
+    }
+
+    let mut transferred = 0;
+    let mut transferred_bytes = 0;
+    let mut unchanged = 0;
+    let mut failed = 0;
+
+    for (relative, entry) in files {
+        let target = match local_path(output_dir, relative) {
+            Ok(target) => target,
+            Err(e) => {
+                warn!("Skipping {}: {}", relative, e);
+                continue;
+            }
+        };
+
+        if let Some((id, file_name)) = relative.split_once('/')
+            && quarantine.covers_archive(id, file_name)
+        {
+            debug!("Skipping quarantined {}", relative);
+            continue;
+        }
+
+        if matches_local(&target, entry) {
+            unchanged += 1;
+            if let Some(progress) = progress {
+                progress.finish_item(relative, true);
+            }
+            continue;
+        }
+
+        if let Some(progress) = progress {
+            progress.start_item(relative);
+        }
+        let result = fetch_file(
+            &client,
+            relative,
+            entry,
+            &target,
+            output_dir,
+            quota.as_ref(),
+        )
+        .await;
+        if let Some(progress) = progress {
+            if result.is_ok() {
+                progress.add_bytes(entry.size);
+            }
+            progress.finish_item(relative, result.is_ok());
+        }
+
+        match result {
+            Ok(()) => {
+                debug!("Synced {}", relative);
+                transferred += 1;
+                transferred_bytes += entry.size;
+            }
+            Err(e) if quota.as_ref().is_some_and(|q| q.exceeded().is_some()) => {
+                bail!(
+                    "{}. Synced {} files before the sync was stopped",
+                    e,
+                    transferred
+                );
+            }
+            Err(e) if is_disk_full(&e) => {
+                bail!(
+                    "{:#}. Synced {} files before running out of space; free up space in {:?} and run the sync again",
+                    e,
+                    transferred,
+                    output_dir
+                );
+            }
+            Err(e) => {
+                error!("Failed to sync {}: {}", relative, e);
+                failed += 1;
+            }
+        }
+    }
+
+    if let Some(quota) = &quota {
+        quota.log_summary();
+    }
+    write_integrity_manifests(output_dir, config.integrity.algorithm)?;
+    info!(
+        "Synced {} files ({}) from {}, {} already up to date",
+        transferred,
+        format_bytes(transferred_bytes),
+        client.host(),
+        unchanged
+    );
+
+    if failed > 0 {
+        return Err(Failure::Partial.wrap(anyhow!("{} files failed to sync", failed)));
+    }
+
+    if let Some(etag) = etag {
+        state.etags.insert(client.host().to_string(), etag);
+        fs::write(&state_file, serde_json::to_string_pretty(&state)?)?;
+    }
+
+    Ok(())
+}
+
+/// Resolve a manifest path inside the output directory, rejecting anything that could escape it
+fn local_path(output_dir: &Path, relative: &str) -> Result<PathBuf> {
+    let path = Path::new(relative);
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(anyhow!("path is not relative to the mirror"));
+    }
+    if path.starts_with(QUARANTINE_DIR) {
+        return Err(anyhow!("path is inside the quarantine directory"));
+    }
+    Ok(output_dir.join(path))
+}
+
+fn matches_local(path: &Path, entry: &ManifestEntry) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| {
+        metadata.len() == entry.size
+            && cached_sha256(path, &metadata).is_ok_and(|sha256| sha256 == entry.sha256)
+    })
+}
+
+/// Download a file from the peer, verify it against the manifest and move it into place
+async fn fetch_file(
+    client: &Client,
+    relative: &str,
+    entry: &ManifestEntry,
+    target: &Path,
+    output_dir: &Path,
+    quota: Option<&CacheQuota>,
+) -> Result<()> {
+    let bytes = client.download_mirrored_file(relative).await?;
+    let sha256 = sha256_bytes(&bytes);
+    if sha256 != entry.sha256 {
+        bail!(
+            "checksum mismatch (expected {}, got {})",
+            entry.sha256,
+            sha256
+        );
+    }
+
+    if let Some(quota) = quota {
+        quota.reserve(output_dir, target, entry.size)?;
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if relative == "extensions.json" {
+        snapshot_index(output_dir)?;
+    }
+    write_atomic(target, bytes)
+}
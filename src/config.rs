@@ -0,0 +1,717 @@
+use crate::zed::{
+    CacheQuota, ChecksumAlgorithm, CorsPolicy, EvictionPolicy, IndexChannel, ProxyCache,
+    RequestBudget, ServerTimeouts, SignatureTool, SignatureVerifier, Signer, UpstreamCredential,
+    UpstreamLimit, VirtualHosts, Webhook, WebhookEvent, WebhookFormat, Webhooks, parse_size,
+    sign_metadata,
+};
+use actix_web::http::Method;
+use anyhow::{Context, Result, bail};
+use ipnet::IpNet;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the configuration file looked up in the root directory
+const DEFAULT_CONFIG_FILE: &str = "zedex.toml";
+
+/// Seconds a proxied request waits for a free upstream slot before it gets a 503
+const DEFAULT_UPSTREAM_QUEUE_SECS: u64 = 30;
+
+/// Settings loaded from the zedex configuration file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ZedexConfig {
+    pub extensions: ExtensionsConfig,
+    pub scan: ScanConfig,
+    pub proxy: ProxyConfig,
+    pub server: ListenerConfig,
+    pub upstream: UpstreamConfig,
+    pub cache: CacheConfig,
+    pub index: IndexConfig,
+    pub integrity: IntegrityConfig,
+    pub signing: SigningConfig,
+    /// Independent caches served under `/{name}`, keyed by name
+    pub tenants: BTreeMap<String, TenantConfig>,
+    /// URLs notified when runs finish or the mirror falls behind upstream
+    pub webhooks: Vec<WebhookConfig>,
+    /// Views of the index limited to some extensions, keyed by name
+    pub channels: BTreeMap<String, ChannelConfig>,
+}
+
+/// An index view listing only some extensions, e.g. those approved for the whole fleet.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ChannelConfig {
+    /// Extension id patterns listed in the channel, e.g. `catppuccin*` (all when empty)
+    pub allow: Vec<String>,
+    /// Extension id patterns left out of the channel, winning over `allow`
+    pub deny: Vec<String>,
+    /// Port serving this channel to clients that don't ask for another one
+    pub port: Option<u16>,
+}
+
+/// Per-extension policies applied when mirroring and serving.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExtensionsConfig {
+    /// Extensions frozen at an exact version, keyed by extension id
+    pub pins: HashMap<String, String>,
+    /// Versions withdrawn from serving, as `id@version`
+    pub yanked: Vec<String>,
+}
+
+/// A separately served cache with its own directories and policies.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenantConfig {
+    /// Root directory of the tenant's cache
+    pub root_dir: PathBuf,
+    /// Directory containing extension archives (defaults to the root directory)
+    #[serde(default)]
+    pub extensions_dir: Option<PathBuf>,
+    /// Extensions directories served, in lookup order, after the tenant's own
+    #[serde(default)]
+    pub fallback_extensions_dirs: Vec<PathBuf>,
+    /// Directory containing Zed releases (defaults to `releases` in the root directory)
+    #[serde(default)]
+    pub releases_dir: Option<PathBuf>,
+    /// Whether to proxy requests to zed.dev for missing content
+    #[serde(default)]
+    pub proxy_mode: Option<bool>,
+    #[serde(default)]
+    pub extensions: ExtensionsConfig,
+    /// Views of the tenant's index, keyed by name; the main `[channels]` are not shared
+    #[serde(default)]
+    pub channels: BTreeMap<String, ChannelConfig>,
+    /// Channel served to the tenant's clients that don't select one
+    #[serde(default)]
+    pub default_channel: Option<String>,
+    /// Upstream API paths the tenant may proxy; the main `[proxy]` rules are not shared
+    #[serde(default)]
+    pub proxy: Option<TenantProxyConfig>,
+}
+
+/// Rules for which upstream API paths a tenant may forward in proxy mode.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TenantProxyConfig {
+    /// Path patterns that may be proxied (all when empty)
+    pub allow: Vec<String>,
+    /// Path patterns that are never proxied
+    pub deny: Vec<String>,
+}
+
+/// Rules for which upstream API paths proxy mode may forward.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Path patterns that may be proxied, e.g. `extensions/*` (all when empty)
+    pub allow: Vec<String>,
+    /// Path patterns that are never proxied, e.g. `telemetry/*`
+    pub deny: Vec<String>,
+    /// Reuse of proxied API responses
+    pub cache: ProxyCacheConfig,
+    /// Upstream fetches run at once by proxy mode and pull-through, unlimited when unset
+    pub max_concurrent_fetches: Option<usize>,
+    /// Seconds a fetch waits for a free slot before the client gets a 503
+    pub queue_timeout_secs: Option<u64>,
+    /// Relay upstream responses that don't match their expected schema instead of
+    /// answering 502
+    pub relay_schema_mismatches: bool,
+}
+
+impl ProxyConfig {
+    /// The bound on concurrent upstream fetches used by the server
+    pub fn upstream_limit(&self) -> UpstreamLimit {
+        UpstreamLimit::new(
+            self.max_concurrent_fetches,
+            Duration::from_secs(
+                self.queue_timeout_secs
+                    .unwrap_or(DEFAULT_UPSTREAM_QUEUE_SECS),
+            ),
+        )
+    }
+}
+
+/// TTL cache for idempotent API responses fetched in proxy mode.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProxyCacheConfig {
+    /// Seconds responses are reused per upstream path pattern, 0 disables a pattern
+    pub ttl_secs: BTreeMap<String, u64>,
+    /// Keep cached responses on disk across restarts
+    pub persist: bool,
+    /// Maximum number of responses held in memory
+    pub max_entries: Option<usize>,
+    /// Seconds past their TTL responses are still served when upstream fails, 0 disables
+    pub stale_if_error_secs: Option<u64>,
+}
+
+impl Default for ProxyCacheConfig {
+    fn default() -> Self {
+        let ttl_secs = [
+            ("releases/latest", 60),
+            ("releases/*/latest", 60),
+            ("extensions/updates", 60),
+            ("extensions/*", 300),
+        ];
+        Self {
+            ttl_secs: ttl_secs
+                .into_iter()
+                .map(|(pattern, secs)| (pattern.to_string(), secs))
+                .collect(),
+            persist: false,
+            max_entries: None,
+            stale_if_error_secs: None,
+        }
+    }
+}
+
+impl ProxyCacheConfig {
+    /// The cache settings used by the server
+    pub fn proxy_cache(&self) -> ProxyCache {
+        let defaults = ProxyCache::default();
+        ProxyCache {
+            ttls: self
+                .ttl_secs
+                .iter()
+                .map(|(pattern, secs)| (pattern.clone(), Duration::from_secs(*secs)))
+                .collect(),
+            persist: self.persist,
+            max_entries: self.max_entries.unwrap_or(defaults.max_entries),
+            stale_if_error: self
+                .stale_if_error_secs
+                .map_or(defaults.stale_if_error, Duration::from_secs),
+        }
+    }
+}
+
+/// Connection tuning for the HTTP listener of `zedex serve`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ListenerConfig {
+    /// Seconds idle keep-alive connections stay open, 0 disables keep-alive
+    pub keep_alive_secs: Option<u64>,
+    /// Maximum number of pending connections queued by the listener
+    pub backlog: Option<u32>,
+    /// Maximum number of concurrent connections per worker
+    pub max_connections: Option<usize>,
+    /// Number of worker threads
+    pub workers: Option<usize>,
+    /// PEM certificate chain to serve HTTPS and HTTP/2 with
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key matching `tls_cert`
+    pub tls_key: Option<PathBuf>,
+    /// Port also serving plaintext HTTP when TLS is enabled, e.g. for localhost clients
+    pub http_port: Option<u16>,
+    /// Redirect requests on `http_port` to HTTPS instead of serving them
+    pub redirect_http: bool,
+    /// Bearer token enabling the `/admin` endpoints
+    pub admin_token: Option<String>,
+    /// URL clients reach the mirror at, e.g. `https://zed.example.com`, used in generated
+    /// links unless `--domain` is given
+    pub external_url: Option<String>,
+    /// Addresses or CIDR ranges of reverse proxies whose `Forwarded` and `X-Forwarded-*`
+    /// headers are trusted for generated links
+    pub trusted_proxies: Vec<String>,
+    /// Channel served to clients that don't ask for one, the whole index when unset
+    pub default_channel: Option<String>,
+    /// Days after the last successful sync at which /health reports the mirror DEGRADED
+    pub max_sync_age_days: Option<u64>,
+    /// Extensions directories served, in lookup order, after the extensions directory
+    pub fallback_extensions_dirs: Vec<PathBuf>,
+    /// Limits on slow and stalled clients
+    pub timeouts: TimeoutsConfig,
+    /// Origins allowed to call the JSON endpoints from a browser
+    pub cors: CorsConfig,
+    /// Hostnames of Zed's API and website, routed apart when both resolve to the mirror
+    pub virtual_hosts: VirtualHostsConfig,
+}
+
+impl ListenerConfig {
+    /// The trusted proxy networks, single addresses as one-address networks
+    pub fn trusted_proxies(&self) -> Result<Vec<IpNet>> {
+        self.trusted_proxies
+            .iter()
+            .map(|proxy| {
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .with_context(|| format!("Invalid trusted proxy '{}' in [server]", proxy))
+            })
+            .collect()
+    }
+}
+
+/// Hostnames Zed's requests arrive with when its hosts are pointed at the mirror.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VirtualHostsConfig {
+    /// Hosts served only the extensions API, `api.zed.dev` when unset
+    pub api: Option<Vec<String>>,
+    /// Hosts served only releases and `/api`, `zed.dev` when unset
+    pub web: Option<Vec<String>>,
+}
+
+impl VirtualHostsConfig {
+    /// The hostnames routed by the server
+    pub fn virtual_hosts(&self) -> VirtualHosts {
+        let defaults = VirtualHosts::default();
+        let hosts = |names: &Option<Vec<String>>, default: Vec<String>| match names {
+            Some(names) => names.iter().map(|name| name.to_ascii_lowercase()).collect(),
+            None => default,
+        };
+        VirtualHosts {
+            api: hosts(&self.api, defaults.api),
+            web: hosts(&self.web, defaults.web),
+        }
+    }
+}
+
+/// Cross-origin access to the server, e.g. for a dashboard served from another host.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins allowed, e.g. `https://dash.example.com`, or `*` for any. CORS is off when
+    /// empty.
+    pub allowed_origins: Vec<String>,
+    /// Methods cross-origin requests may use
+    pub allowed_methods: Vec<String>,
+    /// Seconds browsers may cache a preflight answer
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "HEAD", "OPTIONS"].map(String::from).to_vec(),
+            max_age_secs: None,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// The CORS policy served, `None` when no origin is allowed
+    pub fn cors_policy(&self) -> Result<Option<CorsPolicy>> {
+        if self.allowed_origins.is_empty() {
+            return Ok(None);
+        }
+        let origins = self
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                if origin == "*" {
+                    return Ok(origin.clone());
+                }
+                let url = url::Url::parse(origin)
+                    .ok()
+                    .filter(|url| matches!(url.scheme(), "http" | "https"))
+                    .with_context(|| {
+                        format!(
+                            "Invalid origin '{}' in [server.cors], expected e.g. https://dash.example.com",
+                            origin
+                        )
+                    })?;
+                Ok(url.origin().ascii_serialization())
+            })
+            .collect::<Result<_>>()?;
+        let methods = self
+            .allowed_methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .with_context(|| format!("Invalid method '{}' in [server.cors]", method))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(CorsPolicy {
+            origins,
+            methods,
+            max_age: self.max_age_secs.map(Duration::from_secs),
+        }))
+    }
+}
+
+/// Timeouts freeing connections held by slow or stalled clients.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TimeoutsConfig {
+    /// Seconds a client may take to send the request headers
+    pub request_header_secs: Option<u64>,
+    /// Seconds sent data may go unacknowledged before the connection is dropped (Linux only)
+    pub stall_secs: Option<u64>,
+    /// Seconds a request may take until its response is fully sent, per request path
+    /// pattern, e.g. `"extensions/*/download" = 600`. 0 disables a pattern.
+    pub route_secs: BTreeMap<String, u64>,
+}
+
+impl TimeoutsConfig {
+    /// The timeouts used by the server
+    pub fn server_timeouts(&self) -> ServerTimeouts {
+        let nonzero = |secs: &u64| (*secs > 0).then(|| Duration::from_secs(*secs));
+        ServerTimeouts {
+            request_header: self.request_header_secs.as_ref().and_then(nonzero),
+            stall: self.stall_secs.as_ref().and_then(nonzero),
+            routes: self
+                .route_secs
+                .iter()
+                .map(|(pattern, secs)| (pattern.clone(), Duration::from_secs(*secs)))
+                .collect(),
+        }
+    }
+}
+
+/// How requests to zed.dev leave this machine.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UpstreamConfig {
+    /// Proxy for all upstream requests, e.g. `socks5h://127.0.0.1:1080`
+    pub proxy_url: Option<String>,
+    /// SSH jump host to open a SOCKS5 tunnel through
+    pub ssh_tunnel: Option<SshTunnelConfig>,
+    /// Bearer tokens of upstreams requiring authentication
+    pub credentials: Vec<CredentialConfig>,
+    /// Upstream requests a run may make before it stops with an error
+    pub max_requests: Option<u64>,
+    /// Upstream requests allowed per hour, spaced evenly across the hour
+    pub max_requests_per_hour: Option<u64>,
+}
+
+/// Token for an upstream, given inline, in an environment variable or in a secret file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CredentialConfig {
+    /// Base URL the token is sent to, e.g. `https://mirror.example.com:2654`
+    pub url: String,
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Environment variable holding the token
+    #[serde(default)]
+    pub token_env: Option<String>,
+    /// File holding the token, e.g. a mounted secret
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+}
+
+/// Dynamic port forward through an SSH host, opened with the system `ssh` client.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SshTunnelConfig {
+    /// ssh destination, e.g. `user@jump.example.com`
+    pub destination: String,
+    /// SSH port of the destination
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Private key to authenticate with
+    #[serde(default)]
+    pub identity_file: Option<PathBuf>,
+    /// Local port the tunnel's SOCKS5 proxy listens on
+    #[serde(default = "default_tunnel_port")]
+    pub local_port: u16,
+}
+
+fn default_tunnel_port() -> u16 {
+    1080
+}
+
+/// Disk usage limits for downloads.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Maximum size of the extensions directory, e.g. `200GB`
+    pub max_size: Option<String>,
+    /// What to do once a download would exceed `max_size`
+    pub eviction: EvictionPolicy,
+}
+
+/// Validation of extensions.json when it is loaded.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct IndexConfig {
+    /// Fail on invalid entries instead of dropping them
+    pub strict: bool,
+}
+
+/// Per-directory integrity manifests written after downloads.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct IntegrityConfig {
+    /// Digest algorithm of INTEGRITY.json, `sha256` or `blake3`
+    pub algorithm: ChecksumAlgorithm,
+}
+
+/// Detached signatures of generated metadata and of the manifest peers sync from.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SigningConfig {
+    /// Tool creating and checking signatures, `gpg` or `minisign`
+    pub tool: SignatureTool,
+    /// Key to sign with, a gpg key id or a minisign secret key file
+    pub key: Option<String>,
+    /// Key `zedex sync --from` requires the peer's manifest to be signed with, a gpg
+    /// keyring or a minisign public key file
+    pub trusted_key: Option<PathBuf>,
+}
+
+/// A webhook, given inline or in an environment variable since chat webhook URLs are
+/// secrets.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Environment variable holding the URL
+    #[serde(default)]
+    pub url_env: Option<String>,
+    /// Body to send, `json` or `slack`
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Events to send, `sync-completed`, `sync-failed` and `drift` (all when empty)
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+/// Scan hook settings for downloaded archives.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ScanConfig {
+    /// Command run for each new archive, with `{path}` replaced by the archive path
+    pub command: Option<String>,
+}
+
+impl TenantConfig {
+    /// The tenant's own index channels. They are only served under the tenant's prefix, so
+    /// unlike the main channels they can't have a port.
+    pub fn index_channels(&self, tenant: &str) -> Result<BTreeMap<String, IndexChannel>> {
+        if let Some(name) = self
+            .channels
+            .iter()
+            .find_map(|(name, channel)| channel.port.is_some().then_some(name))
+        {
+            bail!(
+                "Channel {} of tenant {} sets a port, tenant channels are only served under /{}",
+                name,
+                tenant,
+                tenant
+            );
+        }
+        index_channels(
+            &self.channels,
+            self.default_channel.as_deref(),
+            &format!("[tenants.{}]", tenant),
+        )
+    }
+}
+
+/// Index channels from their configuration, checking the default channel named in `section`
+fn index_channels(
+    channels: &BTreeMap<String, ChannelConfig>,
+    default_channel: Option<&str>,
+    section: &str,
+) -> Result<BTreeMap<String, IndexChannel>> {
+    if let Some(default) = default_channel
+        && !channels.contains_key(default)
+    {
+        bail!(
+            "default_channel '{}' in {} is not a configured channel",
+            default,
+            section
+        );
+    }
+
+    Ok(channels
+        .iter()
+        .map(|(name, channel)| {
+            (
+                name.clone(),
+                IndexChannel {
+                    allow: channel.allow.clone(),
+                    deny: channel.deny.clone(),
+                    port: channel.port,
+                },
+            )
+        })
+        .collect())
+}
+
+impl ExtensionsConfig {
+    /// Yanked versions grouped by extension id
+    pub fn yanked_versions(&self) -> HashMap<String, HashSet<String>> {
+        let mut yanked: HashMap<String, HashSet<String>> = HashMap::new();
+        for spec in &self.yanked {
+            match spec.split_once('@') {
+                Some((id, version)) if !id.is_empty() && !version.is_empty() => {
+                    yanked
+                        .entry(id.to_string())
+                        .or_default()
+                        .insert(version.to_string());
+                }
+                _ => warn!(
+                    "Ignoring invalid yanked entry '{}', expected id@version",
+                    spec
+                ),
+            }
+        }
+        yanked
+    }
+}
+
+impl CacheConfig {
+    /// The configured quota, if a maximum size is set
+    pub fn quota(&self) -> Result<Option<CacheQuota>> {
+        self.max_size
+            .as_deref()
+            .map(|size| {
+                let max_bytes = parse_size(size).context("Invalid maximum cache size")?;
+                Ok(CacheQuota::new(max_bytes, self.eviction))
+            })
+            .transpose()
+    }
+}
+
+impl UpstreamConfig {
+    /// The configured limits on upstream requests
+    pub fn request_budget(&self) -> RequestBudget {
+        RequestBudget {
+            per_run: self.max_requests,
+            per_hour: self.max_requests_per_hour,
+        }
+    }
+
+    /// Resolve the configured tokens, reading environment variables and secret files
+    pub fn credentials(&self) -> Result<Vec<UpstreamCredential>> {
+        self.credentials
+            .iter()
+            .map(|credential| {
+                Ok(UpstreamCredential {
+                    url: credential.url.clone(),
+                    token: credential.token()?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl CredentialConfig {
+    fn token(&self) -> Result<String> {
+        let token = match (&self.token, &self.token_env, &self.token_file) {
+            (Some(token), None, None) => token.clone(),
+            (None, Some(var), None) => std::env::var(var).with_context(|| {
+                format!(
+                    "Environment variable {} with the token for {} is not set",
+                    var, self.url
+                )
+            })?,
+            (None, None, Some(file)) => fs::read_to_string(file).with_context(|| {
+                format!("Failed to read the token for {} from {:?}", self.url, file)
+            })?,
+            _ => bail!(
+                "Set exactly one of token, token_env or token_file for upstream {}",
+                self.url
+            ),
+        };
+
+        let token = token.trim();
+        if token.is_empty() {
+            bail!("The token for upstream {} is empty", self.url);
+        }
+        Ok(token.to_string())
+    }
+}
+
+impl WebhookConfig {
+    fn webhook(&self) -> Result<Webhook> {
+        let url = match (&self.url, &self.url_env) {
+            (Some(url), None) => url.clone(),
+            (None, Some(var)) => std::env::var(var).with_context(|| {
+                format!("Environment variable {} with a webhook URL is not set", var)
+            })?,
+            _ => bail!("Set exactly one of url or url_env for each webhook"),
+        };
+        reqwest::Url::parse(url.trim()).context("Invalid webhook URL")?;
+        Ok(Webhook {
+            url: url.trim().to_string(),
+            format: self.format,
+            events: self.events.clone(),
+        })
+    }
+}
+
+impl SigningConfig {
+    /// The configured signer, if a signing key is set
+    pub fn signer(&self) -> Option<Signer> {
+        self.key.clone().map(|key| Signer {
+            tool: self.tool,
+            key,
+        })
+    }
+
+    /// The configured verifier, if a trusted key is set
+    pub fn verifier(&self) -> Option<SignatureVerifier> {
+        self.trusted_key
+            .clone()
+            .map(|trusted_key| SignatureVerifier {
+                tool: self.tool,
+                trusted_key,
+            })
+    }
+
+    /// Sign the generated metadata below `root` when a signing key is set
+    pub fn sign_metadata(&self, root: &Path) -> Result<()> {
+        if let Some(signer) = self.signer() {
+            sign_metadata(root, &signer)?;
+        }
+        Ok(())
+    }
+}
+
+impl ZedexConfig {
+    /// The index channels served, checking the default channel and ports
+    pub fn index_channels(&self) -> Result<BTreeMap<String, IndexChannel>> {
+        let mut ports = HashSet::new();
+        for (name, channel) in &self.channels {
+            if let Some(port) = channel.port
+                && !ports.insert(port)
+            {
+                bail!("Channel {} shares port {} with another channel", name, port);
+            }
+        }
+        index_channels(
+            &self.channels,
+            self.server.default_channel.as_deref(),
+            "[server]",
+        )
+    }
+
+    /// The configured webhooks, resolving URLs given in environment variables
+    pub fn webhooks(&self) -> Result<Webhooks> {
+        Ok(Webhooks {
+            hooks: self
+                .webhooks
+                .iter()
+                .map(WebhookConfig::webhook)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Load the configuration from an explicit path, or from `zedex.toml` in the root
+    /// directory when present. Missing default files yield the default configuration.
+    pub fn load(path: Option<&Path>, root_dir: &Path) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let default_path = root_dir.join(DEFAULT_CONFIG_FILE);
+                if !default_path.exists() {
+                    debug!("No configuration file found at {:?}", default_path);
+                    return Ok(Self::default());
+                }
+                default_path
+            }
+        };
+
+        debug!("Loading configuration from {:?}", path);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse config file {:?}", path))
+    }
+}
@@ -0,0 +1,104 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::process::ExitCode;
+
+/// Class of failure a command ended with, reported through the process exit code so
+/// scripts can tell a flaky network from a broken config or a tampered mirror
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Some items failed while the rest were mirrored
+    Partial,
+    /// Upstream or a peer could not be reached
+    Network,
+    /// The config file or command line options are invalid
+    Config,
+    /// Files or signatures did not match what they were checked against
+    Verification,
+}
+
+impl Failure {
+    /// Exit code of the failure class; 1 is left for unclassified errors and 2 for
+    /// usage errors reported by clap
+    pub fn code(self) -> u8 {
+        match self {
+            Failure::Partial => 3,
+            Failure::Network => 4,
+            Failure::Config => 5,
+            Failure::Verification => 6,
+        }
+    }
+
+    /// Tag an error with this failure class
+    pub fn wrap(self, error: anyhow::Error) -> anyhow::Error {
+        Classified {
+            failure: self,
+            source: error.into(),
+        }
+        .into()
+    }
+}
+
+/// An error tagged with its failure class, displayed exactly like the error it wraps
+#[derive(Debug)]
+struct Classified {
+    failure: Failure,
+    source: Box<dyn StdError + Send + Sync>,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.source, f)
+    }
+}
+
+impl StdError for Classified {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.source()
+    }
+}
+
+pub trait Classify<T> {
+    /// Tag the error, if any, with a failure class
+    fn classify(self, failure: Failure) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> Classify<T> for Result<T, E> {
+    fn classify(self, failure: Failure) -> anyhow::Result<T> {
+        self.map_err(|e| failure.wrap(e.into()))
+    }
+}
+
+/// Failure class of an error. Errors that weren't tagged still count as network failures
+/// when a failed request caused them.
+pub fn failure_of(error: &anyhow::Error) -> Option<Failure> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<Classified>())
+        .map(|classified| classified.failure)
+        .or_else(|| {
+            error
+                .chain()
+                .any(|cause| cause.is::<reqwest::Error>())
+                .then_some(Failure::Network)
+        })
+}
+
+/// Exit code for the outcome of a command
+pub fn exit_code(result: &anyhow::Result<()>) -> ExitCode {
+    let Err(error) = result else {
+        return ExitCode::SUCCESS;
+    };
+    match failure_of(error) {
+        Some(failure) => ExitCode::from(failure.code()),
+        None => ExitCode::FAILURE,
+    }
+}
+
+/// Outcome of a run as recorded in the sync history: `succeeded`, `partial` or `failed`
+pub fn outcome(result: &anyhow::Result<()>) -> &'static str {
+    match result {
+        Ok(()) => "succeeded",
+        Err(error) if failure_of(error) == Some(Failure::Partial) => "partial",
+        Err(_) => "failed",
+    }
+}
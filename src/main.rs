@@ -1,11 +1,17 @@
 mod app;
 mod cli;
 mod commands;
+mod config;
+mod exit;
 mod zed;
 
-use anyhow::Result;
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    app::run().await
+async fn main() -> ExitCode {
+    let result = app::run().await;
+    if let Err(e) = &result {
+        eprintln!("Error: {:?}", e);
+    }
+    exit::exit_code(&result)
 }
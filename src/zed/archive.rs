@@ -0,0 +1,212 @@
+use anyhow::{Context, Result, bail};
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path};
+
+/// File name of the manifest at the root of every extension archive
+const MANIFEST_FILE: &str = "extension.toml";
+
+/// File name of the compiled extension module at the root of an archive
+const WASM_FILE: &str = "extension.wasm";
+
+/// Custom WASM section holding the extension API version a module was built against
+const API_VERSION_SECTION: &str = "zed:api-version";
+
+/// Files larger than this are flagged when inspecting an archive
+const LARGE_FILE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// What an extension archive contains, see [`inspect_archive`]
+#[derive(Debug, Default)]
+pub struct ArchiveInspection {
+    /// Paths and sizes of every entry, in archive order
+    pub files: Vec<(String, u64)>,
+    pub manifest: Option<toml::Table>,
+    /// Size of `extension.wasm` and the API version embedded in it
+    pub wasm: Option<(u64, Option<String>)>,
+    pub grammars: Vec<String>,
+    pub themes: Vec<String>,
+    pub languages: Vec<String>,
+    /// Entries that have no business in an extension archive
+    pub anomalies: Vec<String>,
+}
+
+/// Parse the `extension.toml` manifest of an extension archive
+pub fn read_manifest(archive: &Path) -> Result<toml::Table> {
+    let file = File::open(archive).with_context(|| format!("Failed to open {:?}", archive))?;
+    let mut entries = tar::Archive::new(GzDecoder::new(file));
+
+    for entry in entries.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut components = path
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir));
+        let at_root = components
+            .next()
+            .is_some_and(|name| name.as_os_str() == MANIFEST_FILE)
+            && components.next().is_none();
+        if !at_root {
+            continue;
+        }
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        return toml::from_str(&content)
+            .with_context(|| format!("Failed to parse the manifest in {:?}", archive));
+    }
+
+    bail!("{:?} has no {}", archive, MANIFEST_FILE)
+}
+
+/// Ids of the extensions an archive's manifest declares under `dependencies`, either as a
+/// list of ids or as a table keyed by id
+pub fn declared_dependencies(archive: &Path) -> Result<Vec<String>> {
+    let manifest = read_manifest(archive)?;
+    let dependencies = match manifest.get("dependencies") {
+        Some(toml::Value::Array(ids)) => ids
+            .iter()
+            .filter_map(|id| id.as_str().map(str::to_string))
+            .collect(),
+        Some(toml::Value::Table(table)) => table.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+    Ok(dependencies)
+}
+
+/// Read a gzipped tarball to the end, failing if it is truncated or malformed
+pub fn check_archive(archive: &Path) -> Result<()> {
+    let file = File::open(archive).with_context(|| format!("Failed to open {:?}", archive))?;
+    let mut entries = tar::Archive::new(GzDecoder::new(file));
+    for entry in entries.entries()? {
+        std::io::copy(&mut entry?, &mut std::io::sink())?;
+    }
+    Ok(())
+}
+
+/// List the contents of an extension archive: its manifest, compiled module, grammars,
+/// themes and languages, and entries with absolute or escaping paths, links, special
+/// files or unexpected sizes
+pub fn inspect_archive(archive: &Path) -> Result<ArchiveInspection> {
+    let file = File::open(archive).with_context(|| format!("Failed to open {:?}", archive))?;
+    let mut entries = tar::Archive::new(GzDecoder::new(file));
+    let mut inspection = ArchiveInspection::default();
+
+    for entry in entries.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let name = path.to_string_lossy().to_string();
+        let size = entry.size();
+        let entry_type = entry.header().entry_type();
+
+        if path.has_root() {
+            inspection
+                .anomalies
+                .push(format!("{}: absolute path", name));
+        }
+        if path
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            inspection
+                .anomalies
+                .push(format!("{}: path escapes the extension directory", name));
+        }
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let target = entry
+                .link_name()?
+                .map(|target| target.to_string_lossy().to_string())
+                .unwrap_or_default();
+            inspection
+                .anomalies
+                .push(format!("{}: link to {}", name, target));
+        } else if !entry_type.is_file() && !entry_type.is_dir() {
+            inspection
+                .anomalies
+                .push(format!("{}: special file ({:?})", name, entry_type));
+        }
+        if size > LARGE_FILE_SIZE {
+            inspection
+                .anomalies
+                .push(format!("{}: unexpectedly large ({} bytes)", name, size));
+        }
+
+        let relative: Vec<String> = path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        let relative: Vec<&str> = relative.iter().map(String::as_str).collect();
+        if entry_type.is_file() {
+            match relative.as_slice() {
+                [MANIFEST_FILE] => {
+                    let mut content = String::new();
+                    entry.read_to_string(&mut content)?;
+                    match toml::from_str(&content) {
+                        Ok(manifest) => inspection.manifest = Some(manifest),
+                        Err(e) => inspection
+                            .anomalies
+                            .push(format!("{}: invalid manifest: {}", name, e)),
+                    }
+                }
+                [WASM_FILE] => {
+                    let mut module = Vec::new();
+                    entry.read_to_end(&mut module)?;
+                    inspection.wasm = Some((size, wasm_api_version(&module)));
+                }
+                ["grammars", grammar] if grammar.ends_with(".wasm") => {
+                    inspection
+                        .grammars
+                        .push(grammar.trim_end_matches(".wasm").to_string());
+                }
+                ["themes", theme] if theme.ends_with(".json") => {
+                    inspection.themes.push(theme.to_string());
+                }
+                ["languages", language, "config.toml"] => {
+                    inspection.languages.push(language.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        inspection.files.push((name, size));
+    }
+
+    Ok(inspection)
+}
+
+/// API version a WASM module declares in its `zed:api-version` custom section, stored as
+/// three big-endian u16s
+fn wasm_api_version(module: &[u8]) -> Option<String> {
+    let mut rest = module.strip_prefix(b"\0asm")?.get(4..)?;
+    while !rest.is_empty() {
+        let id = rest[0];
+        let (size, after) = read_leb128(&rest[1..])?;
+        let section = after.get(..size)?;
+        rest = &after[size..];
+        if id != 0 {
+            continue;
+        }
+
+        let (name_len, after_len) = read_leb128(section)?;
+        let name = after_len.get(..name_len)?;
+        if name == API_VERSION_SECTION.as_bytes() {
+            let data = &after_len[name_len..];
+            let part = |i: usize| Some(u16::from_be_bytes([*data.get(i)?, *data.get(i + 1)?]));
+            return Some(format!("{}.{}.{}", part(0)?, part(2)?, part(4)?));
+        }
+    }
+    None
+}
+
+/// Decode an unsigned LEB128 number, returning it with the bytes after it
+fn read_leb128(bytes: &[u8]) -> Option<(usize, &[u8])> {
+    let mut value: usize = 0;
+    for (i, byte) in bytes.iter().enumerate().take(5) {
+        value |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, &bytes[i + 1..]));
+        }
+    }
+    None
+}
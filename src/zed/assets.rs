@@ -0,0 +1,140 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use super::{Client, Extension, write_atomic};
+
+/// README of an extension, as mirrored from its repository
+pub const README_FILE: &str = "README.md";
+
+/// Record of the assets mirrored for an extension, in its directory
+const ASSETS_FILE: &str = "assets.json";
+
+/// Icons looked up at the root of a repository, in order
+const ICON_FILES: [&str; 2] = ["icon.png", "icon.svg"];
+
+/// Largest README or icon mirrored, bigger ones are left out
+const MAX_ASSET_SIZE: usize = 2 * 1024 * 1024;
+
+/// The README and icon mirrored for an extension, and the version they were taken at
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtensionAssets {
+    pub version: String,
+    pub readme: bool,
+    /// File name of the icon in the extension directory
+    pub icon: Option<String>,
+}
+
+impl ExtensionAssets {
+    /// The assets recorded in an extension directory
+    pub fn load(ext_dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(ext_dir.join(ASSETS_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+}
+
+/// Content type an icon is served with
+pub fn icon_content_type(name: &str) -> &'static str {
+    if name.ends_with(".svg") {
+        "image/svg+xml"
+    } else {
+        "image/png"
+    }
+}
+
+/// Mirror the README and icon of each extension from its repository, so clients can show
+/// them offline. Extensions whose assets were mirrored at their current version are
+/// skipped. Assets are supplementary: failures are logged and counted, never fatal.
+pub async fn mirror_extension_assets(client: &Client, output_dir: &Path, extensions: &[Extension]) {
+    let mut mirrored = 0;
+    let mut failed = 0;
+    for extension in extensions {
+        let ext_dir = output_dir.join(&extension.id);
+        if ExtensionAssets::load(&ext_dir).is_some_and(|assets| assets.version == extension.version)
+        {
+            continue;
+        }
+        let Some(repository) = &extension.repository else {
+            continue;
+        };
+        match mirror_assets(client, &ext_dir, extension, repository).await {
+            Ok(assets) => {
+                debug!(
+                    "Mirrored assets of {} (readme: {}, icon: {:?})",
+                    extension.id, assets.readme, assets.icon
+                );
+                mirrored += 1;
+            }
+            Err(e) => {
+                warn!("Failed to mirror assets of {}: {:#}", extension.id, e);
+                failed += 1;
+            }
+        }
+    }
+    info!(
+        "Mirrored READMEs and icons of {} extensions, {} failed",
+        mirrored, failed
+    );
+}
+
+async fn mirror_assets(
+    client: &Client,
+    ext_dir: &Path,
+    extension: &Extension,
+    repository: &str,
+) -> Result<ExtensionAssets> {
+    let mut assets = ExtensionAssets {
+        version: extension.version.clone(),
+        ..Default::default()
+    };
+    fs::create_dir_all(ext_dir)?;
+
+    let readme = client
+        .get_repository_file(repository, README_FILE)
+        .await?
+        .filter(|content| content.len() <= MAX_ASSET_SIZE);
+    match readme {
+        Some(content) => {
+            write_atomic(&ext_dir.join(README_FILE), content)?;
+            assets.readme = true;
+        }
+        None => remove_stale(&ext_dir.join(README_FILE))?,
+    }
+
+    for name in ICON_FILES {
+        let icon = match assets.icon {
+            Some(_) => None,
+            None => client
+                .get_repository_file(repository, name)
+                .await?
+                .filter(|content| content.len() <= MAX_ASSET_SIZE),
+        };
+        match icon {
+            Some(content) => {
+                write_atomic(&ext_dir.join(name), content)?;
+                assets.icon = Some(name.to_string());
+            }
+            // An icon the repository no longer has, or one replaced by the preferred format
+            None if assets.icon.as_deref() != Some(name) => remove_stale(&ext_dir.join(name))?,
+            None => {}
+        }
+    }
+
+    write_atomic(
+        &ext_dir.join(ASSETS_FILE),
+        serde_json::to_string_pretty(&assets)?,
+    )?;
+    Ok(assets)
+}
+
+/// Remove an asset the repository no longer has
+fn remove_stale(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{Result, bail};
+use log::{debug, info};
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Limits on the number of upstream requests, set once at startup
+static BUDGET: OnceCell<RequestBudget> = OnceCell::new();
+
+/// Upstream requests made by this process
+static USAGE: Lazy<Mutex<BudgetUsage>> = Lazy::new(|| Mutex::new(BudgetUsage::default()));
+
+/// How many upstream requests a run may make
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestBudget {
+    /// Requests allowed in the whole run, further requests fail
+    pub per_run: Option<u64>,
+    /// Requests allowed per hour, requests are spaced evenly to stay below it
+    pub per_hour: Option<u64>,
+}
+
+#[derive(Default)]
+struct BudgetUsage {
+    made: u64,
+    /// Times of the requests made in the last hour
+    recent: VecDeque<Instant>,
+    last: Option<Instant>,
+}
+
+/// Limit the upstream requests of this run. Must be called before any request is sent.
+pub fn set_request_budget(budget: RequestBudget) {
+    if budget.per_run.is_none() && budget.per_hour.is_none() {
+        return;
+    }
+    info!(
+        "Upstream request budget: {} per run, {} per hour",
+        budget
+            .per_run
+            .map_or("unlimited".to_string(), |n| n.to_string()),
+        budget
+            .per_hour
+            .map_or("unlimited".to_string(), |n| n.to_string())
+    );
+    let _ = BUDGET.set(budget);
+}
+
+/// Account for an upstream request about to be sent, waiting as long as needed to stay
+/// within the hourly budget. Fails once the budget of the run is spent.
+pub(crate) async fn acquire_request() -> Result<()> {
+    let budget = BUDGET.get().copied().unwrap_or_default();
+    // Held while pacing, so concurrent requests queue up behind each other
+    let mut usage = USAGE.lock().await;
+
+    if let Some(per_run) = budget.per_run
+        && usage.made >= per_run
+    {
+        bail!(
+            "The budget of {} upstream requests for this run is spent",
+            per_run
+        );
+    }
+
+    if let Some(per_hour) = budget.per_hour.filter(|n| *n > 0)
+        && let Some(last) = usage.last
+    {
+        let spacing = HOUR / per_hour.min(u32::MAX as u64) as u32;
+        let wait = spacing.saturating_sub(last.elapsed());
+        if !wait.is_zero() {
+            debug!("Pacing upstream requests, waiting {}ms", wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    let now = Instant::now();
+    usage.made += 1;
+    usage.last = Some(now);
+    usage.recent.push_back(now);
+    while usage
+        .recent
+        .front()
+        .is_some_and(|at| now.duration_since(*at) > HOUR)
+    {
+        usage.recent.pop_front();
+    }
+    Ok(())
+}
+
+/// Whether the run has made all the requests its budget allows
+pub async fn request_budget_spent() -> bool {
+    match BUDGET.get().and_then(|budget| budget.per_run) {
+        Some(per_run) => USAGE.lock().await.made >= per_run,
+        None => false,
+    }
+}
+
+/// Log how many upstream requests the run made
+pub async fn log_request_usage() {
+    let usage = USAGE.lock().await;
+    let budget = BUDGET.get().copied().unwrap_or_default();
+    match budget.per_run {
+        Some(per_run) => info!(
+            "Made {} of {} budgeted upstream requests, {} in the last hour",
+            usage.made,
+            per_run,
+            usage.recent.len()
+        ),
+        None => info!(
+            "Made {} upstream requests, {} in the last hour",
+            usage.made,
+            usage.recent.len()
+        ),
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{Context, Result, anyhow, bail};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// zstd level used for bundles. Extension archives are already gzipped, so higher levels
+/// cost a lot of time for little gain.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Digits in the suffix of split bundle parts, e.g. `bundle.tar.zst.000`
+const PART_SUFFIX_DIGITS: usize = 3;
+
+/// Compression of a packed bundle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BundleCompression {
+    Gzip,
+    #[default]
+    Zstd,
+}
+
+impl BundleCompression {
+    /// File extension of a tarball with this compression
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gzip => "tar.gz",
+            Self::Zstd => "tar.zst",
+        }
+    }
+}
+
+impl FromStr for BundleCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "gzip" | "gz" => Ok(Self::Gzip),
+            "zstd" | "zst" => Ok(Self::Zstd),
+            _ => Err(format!("unknown compression '{}', use gzip or zstd", s)),
+        }
+    }
+}
+
+/// Pack a directory into a compressed tarball rooted at the directory's name, streaming
+/// file by file. With `part_size`, the tarball is split into `<archive>.000`, `.001`, ...
+/// of at most that many bytes, e.g. to fit FAT32 media. Returns the files written.
+pub fn pack_bundle(
+    bundle_dir: &Path,
+    archive_path: &Path,
+    compression: BundleCompression,
+    part_size: Option<u64>,
+) -> Result<Vec<PathBuf>> {
+    let name = bundle_dir
+        .file_name()
+        .ok_or_else(|| anyhow!("Bundle path {:?} has no directory name", bundle_dir))?;
+
+    let mut output = match part_size {
+        Some(0) => bail!("The part size must be larger than zero"),
+        Some(part_size) => SplitWriter::new(archive_path, part_size),
+        None => SplitWriter::new(archive_path, u64::MAX),
+    };
+    match compression {
+        BundleCompression::Gzip => {
+            let encoder = GzEncoder::new(&mut output, Compression::default());
+            let mut builder = tar::Builder::new(encoder);
+            builder.append_dir_all(name, bundle_dir)?;
+            builder.into_inner()?.finish()?;
+        }
+        BundleCompression::Zstd => {
+            let encoder = zstd::stream::write::Encoder::new(&mut output, ZSTD_LEVEL)?;
+            let mut builder = tar::Builder::new(encoder);
+            builder.append_dir_all(name, bundle_dir)?;
+            builder.into_inner()?.finish()?;
+        }
+    }
+    output.flush()?;
+
+    if part_size.is_none() {
+        // A single file keeps the plain archive name
+        let part = output.parts.pop().context("Nothing was written")?;
+        fs::rename(&part, archive_path)?;
+        return Ok(vec![archive_path.to_path_buf()]);
+    }
+    Ok(output.parts)
+}
+
+/// Open a bundle packed by [`pack_bundle`] as a stream of tar entries, detecting its
+/// compression and joining its parts. `path` may name the archive or its first part.
+pub fn open_bundle(path: &Path) -> Result<tar::Archive<Box<dyn Read>>> {
+    let parts = bundle_parts(path)?;
+    let mut reader = BufReader::new(PartsReader {
+        parts: parts.into(),
+        current: None,
+    });
+
+    let magic = io::BufRead::fill_buf(&mut reader)?;
+    let decoder: Box<dyn Read> = if magic.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(reader))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)
+    } else {
+        bail!("{:?} is neither a gzip nor a zstd compressed bundle", path);
+    };
+    Ok(tar::Archive::new(decoder))
+}
+
+/// The files making up a bundle, in order
+fn bundle_parts(path: &Path) -> Result<Vec<PathBuf>> {
+    let name = path.to_string_lossy();
+    let base = match name.rsplit_once('.') {
+        Some((base, suffix))
+            if suffix.len() == PART_SUFFIX_DIGITS && suffix.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            base.to_string()
+        }
+        _ if path.is_file() => return Ok(vec![path.to_path_buf()]),
+        _ => name.to_string(),
+    };
+
+    let parts: Vec<PathBuf> = (0..)
+        .map(|index| part_path(Path::new(&base), index))
+        .take_while(|part| part.is_file())
+        .collect();
+    if parts.is_empty() {
+        bail!("Bundle {:?} not found", path);
+    }
+    Ok(parts)
+}
+
+fn part_path(archive_path: &Path, index: usize) -> PathBuf {
+    let mut name = archive_path.as_os_str().to_owned();
+    name.push(format!(".{:0width$}", index, width = PART_SUFFIX_DIGITS));
+    PathBuf::from(name)
+}
+
+/// Writer spreading its output over numbered part files of a maximum size
+struct SplitWriter {
+    archive_path: PathBuf,
+    part_size: u64,
+    current: Option<(File, u64)>,
+    parts: Vec<PathBuf>,
+}
+
+impl SplitWriter {
+    fn new(archive_path: &Path, part_size: u64) -> Self {
+        Self {
+            archive_path: archive_path.to_path_buf(),
+            part_size,
+            current: None,
+            parts: Vec::new(),
+        }
+    }
+}
+
+impl Write for SplitWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self
+            .current
+            .as_ref()
+            .is_none_or(|(_, written)| *written >= self.part_size)
+        {
+            if let Some((mut file, _)) = self.current.take() {
+                file.flush()?;
+            }
+            let part = part_path(&self.archive_path, self.parts.len());
+            self.current = Some((File::create(&part)?, 0));
+            self.parts.push(part);
+        }
+
+        let Some((file, written)) = self.current.as_mut() else {
+            return Ok(0);
+        };
+        let room = usize::try_from(self.part_size - *written).unwrap_or(usize::MAX);
+        let n = file.write(&buf[..buf.len().min(room)])?;
+        *written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some((file, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reader over the parts of a split bundle, one after the other
+struct PartsReader {
+    parts: VecDeque<PathBuf>,
+    current: Option<File>,
+}
+
+impl Read for PartsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let file = match self.current.as_mut() {
+                Some(file) => file,
+                None => match self.parts.pop_front() {
+                    Some(part) => self.current.insert(File::open(part)?),
+                    None => return Ok(0),
+                },
+            };
+            match file.read(buf)? {
+                0 if !buf.is_empty() => self.current = None,
+                n => return Ok(n),
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Cached checksum along with the file size and mtime it was computed for
+type CachedChecksum = (u64, SystemTime, String);
+
+/// Checksums keyed by path and algorithm, invalidated when the file's size or mtime changes
+static CHECKSUM_CACHE: Lazy<Mutex<HashMap<(PathBuf, ChecksumAlgorithm), CachedChecksum>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Digest algorithm used for integrity manifests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    /// Compute the hex-encoded digest of a file without loading it into memory
+    pub fn hash_file(self, path: &Path) -> Result<String> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut buffer = [0u8; 64 * 1024];
+        let mut sha256 = Sha256::new();
+        let mut blake3 = blake3::Hasher::new();
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            match self {
+                Self::Sha256 => sha256.update(&buffer[..read]),
+                Self::Blake3 => {
+                    blake3.update(&buffer[..read]);
+                }
+            }
+        }
+
+        Ok(match self {
+            Self::Sha256 => format!("{:x}", sha256.finalize()),
+            Self::Blake3 => blake3.finalize().to_hex().to_string(),
+        })
+    }
+
+    /// Digest of a file, reusing the last result while its size and mtime are unchanged
+    pub fn cached_hash(self, path: &Path, metadata: &fs::Metadata) -> Result<String> {
+        let size = metadata.len();
+        let modified = metadata.modified()?;
+        let key = (path.to_path_buf(), self);
+
+        if let Ok(cache) = CHECKSUM_CACHE.lock()
+            && let Some((cached_size, cached_modified, checksum)) = cache.get(&key)
+            && *cached_size == size
+            && *cached_modified == modified
+        {
+            return Ok(checksum.clone());
+        }
+
+        let checksum = self.hash_file(path)?;
+        if let Ok(mut cache) = CHECKSUM_CACHE.lock() {
+            cache.insert(key, (size, modified, checksum.clone()));
+        }
+        Ok(checksum)
+    }
+}
+
+/// Compute the hex-encoded SHA-256 digest of a file without loading it into memory
+pub fn sha256_file(path: &Path) -> Result<String> {
+    ChecksumAlgorithm::Sha256.hash_file(path)
+}
+
+/// Compute the hex-encoded SHA-256 digest of a byte buffer
+pub fn sha256_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// SHA-256 digest of a file, reusing the last result while its size and mtime are unchanged
+pub fn cached_sha256(path: &Path, metadata: &fs::Metadata) -> Result<String> {
+    ChecksumAlgorithm::Sha256.cached_hash(path, metadata)
+}
@@ -1,8 +1,23 @@
 use anyhow::Result;
-use log::{debug, error, info};
-use std::sync::Arc;
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use super::{Extensions, WrappedExtensions};
+use super::error::ZedError;
+use super::{
+    ChecksumManifest, Extensions, REQUEST_ID_HEADER, Version, WrappedExtensions, budget,
+    current_request_id, upstream,
+};
+
+/// Delay before the first retry of a failed request, doubled for every following retry
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Index listings fetched by a client, keyed by capability
+type IndexListings = Mutex<HashMap<Option<String>, Extensions>>;
 
 /// Client configuration for interacting with Zed's API
 #[derive(Clone)]
@@ -11,6 +26,10 @@ pub struct Client {
     host: String,
     max_schema_version: i32,
     extensions_local_dir: Option<String>,
+    /// How often a request failing transiently is retried
+    retries: u32,
+    /// Index listings already fetched by this client, keyed by capability, when enabled
+    index_listings: Option<Arc<IndexListings>>,
     pub(crate) http_client: Arc<reqwest::Client>,
 }
 
@@ -23,21 +42,38 @@ impl Default for Client {
 impl Client {
     /// Creates a new client with default configuration
     pub fn new() -> Self {
-        let http_client = reqwest::Client::builder()
-            .user_agent("zedex")
-            .build()
-            .expect("Failed to create HTTP client");
+        Self::with_http_client(upstream::http_client().expect("Failed to create HTTP client"))
+    }
 
+    /// Creates a client sending its requests through an existing HTTP client
+    pub fn with_http_client(http_client: reqwest::Client) -> Self {
         Self {
             api_host: std::env::var("ZED_API_HOST")
                 .unwrap_or_else(|_| "https://api.zed.dev".to_string()),
             host: std::env::var("ZED_HOST").unwrap_or_else(|_| "https://zed.dev".to_string()),
             max_schema_version: 1, // Default max schema version
             extensions_local_dir: None,
+            retries: upstream::request_options().retries,
+            index_listings: None,
             http_client: Arc::new(http_client),
         }
     }
 
+    /// Point the client at another zedex mirror, which serves the same API under one host
+    pub fn with_host(mut self, host: &str) -> Self {
+        let host = host.trim_end_matches('/').to_string();
+        self.api_host = host.clone();
+        self.host = host;
+        self
+    }
+
+    /// Fetch every index listing at most once, for clients used by a single run. Long-lived
+    /// clients must not cache, they would never see the index change.
+    pub fn with_cached_index_listings(mut self) -> Self {
+        self.index_listings = Some(Arc::default());
+        self
+    }
+
     /// Set the local directory for extension storage
     pub fn with_extensions_local_dir(mut self, dir: String) -> Self {
         self.extensions_local_dir = Some(dir);
@@ -46,28 +82,62 @@ impl Client {
 
     /// Get the current extensions index, optionally filtering by a capability
     pub async fn get_extensions_index(&self, provides: Option<&str>) -> Result<Extensions> {
-        // Build base URL
-        let mut url = format!(
-            "{}/extensions?max_schema_version={}&include_native=false",
-            self.api_host, self.max_schema_version
-        );
-        // Append provides filter if present
-        if let Some(cap) = provides {
-            url.push_str(&format!("&provides={}", cap));
+        let key = provides.map(str::to_string);
+        if let Some(listing) = self.cached_listing(&key) {
+            debug!("Using the index listing fetched earlier for {:?}", provides);
+            return Ok(listing);
+        }
+
+        let extensions = self.search_extensions(None, provides).await?;
+        if let Some(listings) = &self.index_listings {
+            listings
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key, extensions.clone());
+        }
+        Ok(extensions)
+    }
+
+    /// Search the extension index for extensions matching a text filter, optionally
+    /// only those providing a capability
+    pub async fn search_extensions(
+        &self,
+        filter: Option<&str>,
+        provides: Option<&str>,
+    ) -> Result<Extensions> {
+        let mut url = reqwest::Url::parse(&format!("{}/extensions", self.api_host))?;
+        url.query_pairs_mut()
+            .append_pair("max_schema_version", &self.max_schema_version.to_string())
+            .append_pair("include_native", "false");
+        if let Some(filter) = filter {
+            url.query_pairs_mut().append_pair("filter", filter);
+        }
+        if let Some(provides) = provides {
+            url.query_pairs_mut().append_pair("provides", provides);
         }
         info!("Fetching extensions index from URL: {}", url);
-        // Send request
-        let response = self
-            .http_client
-            .get(&url)
-            .send()
-            .await?
-            .error_for_status()?;
-        // Parse and return data
-        let wrapped: WrappedExtensions = response.json().await?;
+
+        let response = self.send(self.http_client.get(url.clone())).await?;
+        if !response.status().is_success() {
+            return Err(ZedError::UnexpectedStatus {
+                url: url.to_string(),
+                status: response.status(),
+            }
+            .into());
+        }
+        let wrapped: WrappedExtensions = response.json().await.map_err(ZedError::from)?;
         Ok(wrapped.data)
     }
 
+    fn cached_listing(&self, key: &Option<String>) -> Option<Extensions> {
+        self.index_listings
+            .as_ref()?
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .cloned()
+    }
+
     /// Get all versions of a specific extension
     pub async fn get_extension_versions(&self, extension_id: &str) -> Result<Extensions> {
         let url = format!("{}/extensions/{}", self.api_host, extension_id);
@@ -78,9 +148,7 @@ impl Client {
         );
 
         let response = self
-            .http_client
-            .get(&url)
-            .send()
+            .send(self.http_client.get(&url))
             .await?
             .error_for_status()?;
 
@@ -88,6 +156,88 @@ impl Client {
         Ok(wrapped.data)
     }
 
+    /// Fetch a file from the default branch of an extension's repository, `None` when the
+    /// repository isn't hosted on GitHub or has no such file
+    pub async fn get_repository_file(
+        &self,
+        repository: &str,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(raw_base) = github_raw_base(repository) else {
+            return Ok(None);
+        };
+        let url = format!("{}/HEAD/{}", raw_base, path);
+        debug!("Fetching repository file from URL: {}", url);
+
+        let response = self.send(self.http_client.get(&url)).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.bytes().await?.to_vec()))
+    }
+
+    /// Get the latest Zed release advertised for a platform, on the stable channel unless
+    /// another one is given
+    pub async fn get_latest_release(
+        &self,
+        channel: Option<&str>,
+        os: &str,
+        arch: &str,
+        asset: &str,
+    ) -> Result<Version> {
+        let path = match channel {
+            Some(channel) => format!("{}/latest", channel),
+            None => "latest".to_string(),
+        };
+        let release = format!("{}/latest", channel.unwrap_or("stable"));
+        self.get_release(&path, &release, os, arch, asset).await
+    }
+
+    /// Get the download location of an asset of an exact Zed version
+    pub async fn get_release_asset(
+        &self,
+        channel: &str,
+        version: &str,
+        os: &str,
+        arch: &str,
+        asset: &str,
+    ) -> Result<Version> {
+        let release = format!("{}/{}", channel, version);
+        self.get_release(&format!("{}/asset", release), &release, os, arch, asset)
+            .await
+    }
+
+    /// Fetch the release at an API path below `/api/releases`, `release` naming it in errors
+    async fn get_release(
+        &self,
+        path: &str,
+        release: &str,
+        os: &str,
+        arch: &str,
+        asset: &str,
+    ) -> Result<Version> {
+        let url = format!(
+            "{}/api/releases/{}?asset={}&os={}&arch={}",
+            self.host, path, asset, os, arch
+        );
+        debug!("Fetching release from URL: {}", url);
+
+        let response = self.send(self.http_client.get(&url)).await?;
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Err(ZedError::ReleaseNotFound {
+                release: release.to_string(),
+                asset: asset.to_string(),
+                os: os.to_string(),
+                arch: arch.to_string(),
+            }
+            .into()),
+            status if !status.is_success() => {
+                Err(ZedError::UnexpectedStatus { url, status }.into())
+            }
+            _ => Ok(response.json().await.map_err(ZedError::from)?),
+        }
+    }
+
     /// Download a specific version of an extension archive with progress reporting
     pub async fn download_extension_version_with_progress(
         &self,
@@ -102,7 +252,7 @@ impl Client {
 
         debug!("Requesting specific extension version from URL: {}", url);
 
-        let response = match self.http_client.get(&url).send().await {
+        let response = match self.send(self.http_client.get(&url)).await {
             Ok(resp) => {
                 debug!("Received response with status: {}", resp.status());
                 match resp.error_for_status() {
@@ -142,11 +292,220 @@ impl Client {
         Ok(bytes)
     }
 
+    /// Download a specific version of an extension into `partial`, continuing from the
+    /// bytes a previous, interrupted download left in it when upstream honors the range.
+    /// Returns the size of the complete file.
+    pub async fn download_extension_version_resumable(
+        &self,
+        extension_id: &str,
+        version: &str,
+        partial: &Path,
+        progress_callback: impl Fn(u64, u64) + 'static,
+    ) -> Result<u64> {
+        let url = format!(
+            "{}/extensions/{}/{}/download",
+            self.api_host, extension_id, version
+        );
+        let offset = fs::metadata(partial).map(|meta| meta.len()).unwrap_or(0);
+
+        let mut request = self.http_client.get(&url);
+        if offset > 0 {
+            debug!(
+                "Resuming {} at byte {} from URL: {}",
+                extension_id, offset, url
+            );
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        } else {
+            debug!("Requesting specific extension version from URL: {}", url);
+        }
+        let response = self.send(request).await?;
+
+        let (mut file, mut downloaded) = match response.status() {
+            reqwest::StatusCode::PARTIAL_CONTENT if offset > 0 => {
+                info!(
+                    "Resuming the download of {} version {} at {} bytes",
+                    extension_id, version, offset
+                );
+                (OpenOptions::new().append(true).open(partial)?, offset)
+            }
+            // The leftover is as long as the whole file or upstream changed it, start over
+            reqwest::StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                warn!(
+                    "Discarding the partial download of {} version {}",
+                    extension_id, version
+                );
+                fs::remove_file(partial)?;
+                return Box::pin(self.download_extension_version_resumable(
+                    extension_id,
+                    version,
+                    partial,
+                    progress_callback,
+                ))
+                .await;
+            }
+            status if status.is_success() => (File::create(partial)?, 0),
+            status => {
+                return Err(anyhow::anyhow!(
+                    "Request failed: {} for {} version {}",
+                    status,
+                    extension_id,
+                    version
+                ));
+            }
+        };
+
+        let total_size = response
+            .content_length()
+            .map_or(0, |length| length + downloaded);
+        let mut stream = response.bytes_stream();
+        use futures_util::StreamExt;
+
+        while let Some(item) = stream.next().await {
+            let chunk = item?;
+            file.write_all(&chunk)?;
+            downloaded += chunk.len() as u64;
+            progress_callback(downloaded, total_size);
+        }
+        file.sync_all()?;
+
+        debug!(
+            "Downloaded {} bytes for extension {} version {}",
+            downloaded, extension_id, version
+        );
+        Ok(downloaded)
+    }
+
+    /// Fetch the checksum manifest of a zedex mirror. Returns `None` when it still matches
+    /// the given entity tag, otherwise the manifest along with its current entity tag.
+    pub async fn get_checksum_manifest(
+        &self,
+        etag: Option<&str>,
+    ) -> Result<Option<(ChecksumManifest, Option<String>)>> {
+        let url = format!("{}/checksums.json", self.host);
+        debug!("Fetching checksum manifest from URL: {}", url);
+
+        let mut request = self.http_client.get(&url);
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+
+        let response = self.send(request).await?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        Ok(Some((response.json().await?, etag)))
+    }
+
+    /// Fetch the detached signature of a zedex mirror's checksum manifest, with the
+    /// entity tag of the manifest revision it covers
+    pub async fn get_manifest_signature(&self) -> Result<(Vec<u8>, Option<String>)> {
+        let url = format!("{}/checksums.json.sig", self.host);
+        debug!("Fetching manifest signature from URL: {}", url);
+
+        let response = self
+            .send(self.http_client.get(&url))
+            .await?
+            .error_for_status()?;
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        Ok((response.bytes().await?.to_vec(), etag))
+    }
+
+    /// Download a file from a zedex mirror's archive, by path relative to its extensions directory
+    pub async fn download_mirrored_file(&self, path: &str) -> Result<Vec<u8>> {
+        let url = format!("{}/extensions-archive/{}", self.host, path);
+        debug!("Downloading mirrored file from URL: {}", url);
+
+        let response = self
+            .send(self.http_client.get(&url))
+            .await?
+            .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Send a request, retrying connection errors, timeouts and 429/5xx responses with
+    /// exponential backoff. The last response is returned whatever its status. Every
+    /// attempt counts against the request budget.
+    pub(crate) async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let (http_client, request) = request.build_split();
+        let mut request = request?;
+        if let Some(token) = upstream::token_for(request.url().as_str())
+            && let Ok(value) = format!("Bearer {}", token).parse()
+        {
+            request
+                .headers_mut()
+                .insert(reqwest::header::AUTHORIZATION, value);
+        }
+        if let Some(id) = current_request_id()
+            && let Ok(value) = id.parse()
+        {
+            request.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+
+        let mut delay = RETRY_BASE_DELAY;
+        let mut attempt = 0;
+        loop {
+            budget::acquire_request().await?;
+            let Some(retry) = request.try_clone().filter(|_| attempt < self.retries) else {
+                return Ok(http_client.execute(request).await?);
+            };
+            attempt += 1;
+
+            let problem = match http_client.execute(retry).await {
+                Ok(response)
+                    if response.status().is_server_error()
+                        || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    response.status().to_string()
+                }
+                Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => e.to_string(),
+                result => return Ok(result?),
+            };
+
+            warn!(
+                "Request failed ({}), retrying in {}s ({}/{})",
+                problem,
+                delay.as_secs(),
+                attempt,
+                self.retries
+            );
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
     pub fn host(&self) -> &str {
         &self.host
     }
 
+    #[allow(dead_code)]
     pub fn extensions_local_dir(&self) -> Option<&str> {
         self.extensions_local_dir.as_deref()
     }
 }
+
+/// Base URL of the raw files of a GitHub repository, e.g.
+/// `https://raw.githubusercontent.com/owner/repo` for `https://github.com/owner/repo.git`
+fn github_raw_base(repository: &str) -> Option<String> {
+    let url = url::Url::parse(repository).ok()?;
+    if !matches!(url.host_str(), Some("github.com" | "www.github.com")) {
+        return None;
+    }
+    let mut segments = url.path_segments()?.filter(|segment| !segment.is_empty());
+    let owner = segments.next()?;
+    let repo = segments.next()?.trim_end_matches(".git");
+    Some(format!(
+        "https://raw.githubusercontent.com/{}/{}",
+        owner, repo
+    ))
+}
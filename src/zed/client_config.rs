@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Environment variable Zed reads its server URL from, overriding the `server_url` setting
+pub const SERVER_URL_ENV: &str = "ZED_SERVER_URL";
+
+/// Setting of Zed's settings.json naming the server it talks to
+const SERVER_URL_SETTING: &str = "server_url";
+
+/// Requests Zed makes to its server URL: the extensions API is reached at the server URL
+/// itself once it isn't zed.dev, releases under `/api/releases` and `/releases`. `{os}`
+/// and `{arch}` stand for the client's platform.
+const CLIENT_ROUTES: [(&str, &str); 4] = [
+    (
+        "index",
+        "/extensions?max_schema_version=1&include_native=false",
+    ),
+    (
+        "updates",
+        "/extensions/updates?min_schema_version=0&max_schema_version=1&min_wasm_api_version=0.0.0&max_wasm_api_version=999.999.999&ids=",
+    ),
+    (
+        "latest release",
+        "/api/releases/latest?asset=zed&os={os}&arch={arch}",
+    ),
+    (
+        "release asset",
+        "/releases/stable/latest/asset?asset=zed&os={os}&arch={arch}",
+    ),
+];
+
+/// What points a Zed installation at a mirror, and the requests it makes there afterwards
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientConfig {
+    pub server_url: String,
+    /// Entries for Zed's settings.json
+    pub settings: BTreeMap<String, String>,
+    /// Environment variables doing the same as the settings
+    pub env: BTreeMap<String, String>,
+    /// Requests Zed will make, relative to `server_url`
+    pub routes: Vec<ClientRoute>,
+}
+
+/// A request Zed makes to its server
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientRoute {
+    pub name: String,
+    /// Path and query, with `{os}` and `{arch}` placeholders
+    pub path: String,
+}
+
+impl ClientConfig {
+    /// The configuration pointing Zed at the mirror reached at `server_url`
+    pub fn new(server_url: &str) -> Self {
+        let server_url = server_url.trim_end_matches('/').to_string();
+        Self {
+            settings: BTreeMap::from([(SERVER_URL_SETTING.to_string(), server_url.clone())]),
+            env: BTreeMap::from([(SERVER_URL_ENV.to_string(), server_url.clone())]),
+            routes: CLIENT_ROUTES
+                .iter()
+                .map(|(name, path)| ClientRoute {
+                    name: name.to_string(),
+                    path: path.to_string(),
+                })
+                .collect(),
+            server_url,
+        }
+    }
+}
+
+impl ClientRoute {
+    /// URL of the request a client on `os` and `arch` makes to `server_url`
+    pub fn url(&self, server_url: &str, os: &str, arch: &str) -> String {
+        let path = self.path.replace("{os}", os).replace("{arch}", arch);
+        format!("{}{}", server_url.trim_end_matches('/'), path)
+    }
+}
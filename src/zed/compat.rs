@@ -0,0 +1,85 @@
+use anyhow::{Result, anyhow};
+use semver::Version as SemverVersion;
+
+use super::{Extension, compare_versions, parse_version};
+
+/// Highest extension schema version understood by any Zed release
+const MAX_SCHEMA_VERSION: i32 = 1;
+
+/// Oldest extension WASM API version Zed is able to load
+const MIN_WASM_API_VERSION: &str = "0.0.1";
+
+/// First Zed release to ship each extension WASM API version, oldest first
+const WASM_API_RELEASES: &[(&str, &str)] = &[
+    ("0.131.0", "0.0.1"),
+    ("0.135.0", "0.0.4"),
+    ("0.143.0", "0.0.6"),
+    ("0.149.0", "0.1.0"),
+    ("0.162.0", "0.2.0"),
+    ("0.178.0", "0.3.0"),
+    ("0.186.0", "0.4.0"),
+    ("0.192.0", "0.5.0"),
+    ("0.196.0", "0.6.0"),
+];
+
+/// Extension API constraints a specific Zed release is able to load
+#[derive(Debug, Clone)]
+pub struct ExtensionCompatibility {
+    pub zed_version: SemverVersion,
+    pub max_schema_version: i32,
+    pub min_wasm_api_version: SemverVersion,
+    pub max_wasm_api_version: SemverVersion,
+}
+
+impl ExtensionCompatibility {
+    /// Resolves the schema and WASM API range supported by the given Zed version
+    pub fn for_zed_version(zed_version: &str) -> Result<Self> {
+        let zed_version = SemverVersion::parse(zed_version.trim_start_matches('v'))
+            .map_err(|e| anyhow!("Invalid Zed version '{}': {}", zed_version, e))?;
+
+        let max_wasm_api_version = WASM_API_RELEASES
+            .iter()
+            .filter(|(since, _)| {
+                SemverVersion::parse(since).is_ok_and(|since| zed_version >= since)
+            })
+            .map(|(_, wasm)| *wasm)
+            .next_back()
+            .ok_or_else(|| {
+                anyhow!(
+                    "Zed {} predates extension support (first supported release is {})",
+                    zed_version,
+                    WASM_API_RELEASES[0].0
+                )
+            })?;
+
+        Ok(Self {
+            zed_version,
+            max_schema_version: MAX_SCHEMA_VERSION,
+            min_wasm_api_version: SemverVersion::parse(MIN_WASM_API_VERSION)?,
+            max_wasm_api_version: SemverVersion::parse(max_wasm_api_version)?,
+        })
+    }
+
+    /// Check whether an extension version can be loaded by this Zed release
+    pub fn is_compatible(&self, extension: &Extension) -> bool {
+        if extension.schema_version > self.max_schema_version {
+            return false;
+        }
+
+        match &extension.wasm_api_version {
+            // Extensions without a WASM component only depend on the schema version
+            None => true,
+            Some(wasm_api_version) => parse_version(wasm_api_version).is_some_and(|version| {
+                version >= self.min_wasm_api_version && version <= self.max_wasm_api_version
+            }),
+        }
+    }
+
+    /// Pick the newest version from a version listing that this Zed release can load
+    pub fn newest_compatible<'a>(&self, versions: &'a [Extension]) -> Option<&'a Extension> {
+        versions
+            .iter()
+            .filter(|ext| self.is_compatible(ext))
+            .max_by(|v1, v2| compare_versions(&v1.version, &v2.version))
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// DNS-SD service type zedex mirrors are announced under
+pub const SERVICE_TYPE: &str = "_zedex._tcp.local.";
+
+/// A zedex mirror announced on the local network
+#[derive(Debug, Clone)]
+pub struct DiscoveredMirror {
+    /// Instance name, `zedex on <host>`
+    pub name: String,
+    pub url: String,
+    pub version: Option<String>,
+}
+
+/// A running mDNS announcement, withdrawn when dropped
+pub struct Announcement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+/// Announce a mirror listening on `port` over mDNS. Binding to an unspecified address
+/// announces every interface address.
+pub fn announce(host: &str, port: u16, https: bool) -> Result<Announcement> {
+    let hostname = local_hostname();
+    let instance = format!("zedex on {}", hostname);
+    let properties = [
+        ("version", env!("CARGO_PKG_VERSION")),
+        ("scheme", if https { "https" } else { "http" }),
+    ];
+
+    let address: Option<IpAddr> = host.parse().ok();
+    if address.is_some_and(|address| address.is_loopback()) {
+        warn!(
+            "Announcing a mirror bound to {}, other machines won't be able to reach it",
+            host
+        );
+    }
+    let mut service = match address.filter(|address| !address.is_unspecified()) {
+        Some(address) => ServiceInfo::new(
+            SERVICE_TYPE,
+            &instance,
+            &format!("{}.local.", hostname),
+            address,
+            port,
+            &properties[..],
+        ),
+        None => ServiceInfo::new(
+            SERVICE_TYPE,
+            &instance,
+            &format!("{}.local.", hostname),
+            "",
+            port,
+            &properties[..],
+        )
+        .map(ServiceInfo::enable_addr_auto),
+    }
+    .context("Failed to build the mDNS service record")?;
+    service.set_requires_probe(true);
+
+    let daemon = ServiceDaemon::new().context("Failed to start the mDNS responder")?;
+    let fullname = service.get_fullname().to_string();
+    daemon
+        .register(service)
+        .context("Failed to announce the mirror over mDNS")?;
+    info!("Announcing mirror over mDNS as \"{}\"", instance);
+
+    Ok(Announcement { daemon, fullname })
+}
+
+impl Drop for Announcement {
+    fn drop(&mut self) {
+        if let Err(e) = self.daemon.unregister(&self.fullname) {
+            debug!("Failed to withdraw the mDNS announcement: {}", e);
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Browse the local network for announced mirrors for `timeout`
+pub async fn discover(timeout: Duration) -> Result<Vec<DiscoveredMirror>> {
+    tokio::task::spawn_blocking(move || browse(timeout)).await?
+}
+
+fn browse(timeout: Duration) -> Result<Vec<DiscoveredMirror>> {
+    let daemon = ServiceDaemon::new().context("Failed to start the mDNS browser")?;
+    let events = daemon
+        .browse(SERVICE_TYPE)
+        .context("Failed to browse for mirrors")?;
+
+    let mut mirrors = BTreeMap::new();
+    let deadline = Instant::now() + timeout;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match events.recv_timeout(remaining) {
+            Ok(ServiceEvent::ServiceResolved(service)) => {
+                let mut addresses: Vec<IpAddr> = service.get_addresses().iter().copied().collect();
+                // Prefer IPv4 addresses, they need no zone index in a URL
+                addresses.sort_by_key(|address| (address.is_ipv6(), *address));
+                let Some(address) = addresses.first() else {
+                    continue;
+                };
+
+                let scheme = service.get_property_val_str("scheme").unwrap_or("http");
+                let host = match address {
+                    IpAddr::V4(address) => address.to_string(),
+                    IpAddr::V6(address) => format!("[{}]", address),
+                };
+                let name = service
+                    .get_fullname()
+                    .trim_end_matches(SERVICE_TYPE)
+                    .trim_end_matches('.')
+                    .to_string();
+                mirrors.insert(
+                    name.clone(),
+                    DiscoveredMirror {
+                        url: format!("{}://{}:{}", scheme, host, service.get_port()),
+                        version: service.get_property_val_str("version").map(str::to_string),
+                        name,
+                    },
+                );
+            }
+            Ok(event) => debug!("mDNS event: {:?}", event),
+            Err(_) => break,
+        }
+    }
+
+    let _ = daemon.shutdown();
+    Ok(mirrors.into_values().collect())
+}
+
+/// Short name of this machine, used for the announced host and instance names
+fn local_hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| {
+            name.trim()
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "zedex".to_string())
+}
@@ -1,42 +1,114 @@
-use anyhow::Result;
-use futures_util::future;
-use indicatif::{ProgressBar, ProgressStyle};
-use log::{debug, error, info};
-use std::collections::{HashMap, HashSet};
+use anyhow::{Result, bail};
+use futures_util::{StreamExt, future, stream};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use std::io::IsTerminal;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Semaphore;
 
-use crate::zed::{Client, Extension, ExtensionVersionTracker, WrappedExtensions};
+use crate::zed::{
+    ArchiveScanner, CacheQuota, Client, Extension, ExtensionCompatibility, ExtensionVersionTracker,
+    QuarantineRegistry, SyncProgress, Version, WrappedExtensions, archive_name, check_archive,
+    compare_versions, disk_full, extensions_utils, partial_path, recorded_size, release_asset_name,
+    snapshot_index, strip_signature, write_atomic, write_error, write_partial,
+};
 
 /// Options for downloading extensions
-#[derive(Clone, Copy)]
+#[derive(Clone, Default)]
 pub struct DownloadOptions {
     pub async_mode: bool,
     pub all_versions: bool,
     pub rate_limit: u64,
+    /// Only download versions loadable by this Zed release
+    pub compatibility: Option<ExtensionCompatibility>,
+    /// Extensions frozen at an exact version, keyed by extension id
+    pub pins: HashMap<String, String>,
+    /// Scan hook run against every newly downloaded archive
+    pub scanner: Option<ArchiveScanner>,
+    /// Size limit of the output directory
+    pub quota: Option<CacheQuota>,
+    /// Status file tracking the run
+    pub progress: Option<Arc<SyncProgress>>,
+    /// Also mirror the README and icon of each extension from its repository
+    pub assets: bool,
 }
 
-impl Default for DownloadOptions {
-    fn default() -> Self {
+/// Number of rounds in which failed capability listings of the index are fetched again
+const INDEX_RETRY_ROUNDS: u32 = 3;
+
+/// Delay before the first index retry round, doubled for every following round
+const INDEX_RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+
+/// Number of capability listings of the index fetched at once
+const INDEX_FETCH_CONCURRENCY: usize = 4;
+
+/// Name of the file, relative to the output directory, queueing downloads that failed
+pub const RETRY_QUEUE_FILE: &str = "retry_queue.json";
+
+/// A download that failed during a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadFailure {
+    pub id: String,
+    /// Version being fetched, when the failure is specific to one version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub error: String,
+    /// Number of runs or retry rounds this download has failed in
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl DownloadFailure {
+    fn new(id: &str, version: Option<&str>, error: impl std::fmt::Display) -> Self {
         Self {
-            async_mode: false,
-            all_versions: false,
-            rate_limit: 0,
+            id: id.to_string(),
+            version: version.map(str::to_string),
+            error: error.to_string(),
+            attempts: 1,
         }
     }
 }
 
+/// Versions downloaded and downloads that failed during a run
+#[derive(Debug, Default)]
+pub struct DownloadOutcome {
+    pub tracker: ExtensionVersionTracker,
+    pub failures: Vec<DownloadFailure>,
+}
+
+impl DownloadOutcome {
+    fn merge(&mut self, other: DownloadOutcome) {
+        self.tracker.merge(other.tracker);
+        self.failures.extend(other.failures);
+    }
+}
+
+/// Extensions listed in a freshly fetched index
+#[derive(Debug, Default)]
+pub struct IndexOutcome {
+    pub extensions: Vec<Extension>,
+    /// Errors of the capabilities whose listings could not be fetched, keyed by capability
+    pub failed_capabilities: BTreeMap<String, String>,
+}
+
 /// Downloads extensions with given options
 pub async fn download_extensions(
     extensions: Vec<Extension>,
     client: Client,
     output_dir: impl AsRef<Path>,
-    mut version_tracker: ExtensionVersionTracker,
+    version_tracker: ExtensionVersionTracker,
     options: DownloadOptions,
-) -> Result<ExtensionVersionTracker> {
+) -> Result<DownloadOutcome> {
+    let mut outcome = DownloadOutcome {
+        tracker: version_tracker.clone(),
+        failures: Vec::new(),
+    };
     let output_dir = output_dir.as_ref().to_path_buf();
 
     info!(
@@ -59,8 +131,7 @@ pub async fn download_extensions(
                 extension.clone(),
                 client.clone(),
                 output_dir.clone(),
-                options.all_versions,
-                options.rate_limit,
+                options.clone(),
                 version_tracker.clone(),
             )
         });
@@ -68,11 +139,9 @@ pub async fn download_extensions(
         // Wait for all downloads to complete (fully parallel)
         let results = future::join_all(futures).await;
 
-        // Merge all trackers
+        // Merge all trackers and failures
         for result in results {
-            if let Ok(tracker) = result {
-                version_tracker.merge(tracker);
-            }
+            outcome.merge(result);
         }
     } else {
         // Throttled mode - default safe behavior
@@ -90,20 +159,28 @@ pub async fn download_extensions(
             let ext_output_dir = output_dir.clone();
             let semaphore = semaphore.clone();
             let extension_clone = extension.clone();
-            let all_versions = options.all_versions;
-            let rate_limit = options.rate_limit;
+            let options = options.clone();
             let tracker = version_tracker.clone();
 
             let handle = tokio::spawn(async move {
                 // Acquire a permit from the semaphore (this limits concurrency)
                 let _permit = semaphore.acquire().await.unwrap();
 
+                // Nothing more can be stored once the disk or the quota filled up
+                if disk_full().is_some()
+                    || options
+                        .quota
+                        .as_ref()
+                        .is_some_and(|q| q.exceeded().is_some())
+                {
+                    return DownloadOutcome::default();
+                }
+
                 download_extension(
                     extension_clone,
                     ext_client,
                     ext_output_dir,
-                    all_versions,
-                    rate_limit,
+                    options,
                     tracker,
                 )
                 .await
@@ -114,50 +191,140 @@ pub async fn download_extensions(
 
         // Wait for all downloads to complete
         for handle in handles {
-            if let Ok(Ok(tracker)) = handle.await {
-                version_tracker.merge(tracker);
+            match handle.await {
+                Ok(result) => outcome.merge(result),
+                Err(e) => error!("Download task failed: {}", e),
             }
         }
     }
 
-    Ok(version_tracker)
+    if let Some(quota) = &options.quota {
+        quota.log_summary();
+        if let Some(report) = quota.exceeded() {
+            bail!("{}. The sync was stopped", report);
+        }
+    }
+
+    if let Some(reason) = disk_full() {
+        bail!(
+            "{}. The sync was stopped; free up space in {:?} (or move the cache to a larger volume) and run it again",
+            reason,
+            output_dir
+        );
+    }
+
+    Ok(outcome)
 }
 
-/// Downloads a single extension (and its versions if requested)
+/// Downloads a single extension (and its versions if requested), collecting failures
 async fn download_extension(
     extension: Extension,
     client: Client,
     output_dir: impl AsRef<Path>,
-    all_versions: bool,
-    rate_limit: u64,
+    options: DownloadOptions,
+    version_tracker: ExtensionVersionTracker,
+) -> DownloadOutcome {
+    let id = extension.id.clone();
+    let mut failures = Vec::new();
+    let progress = options.progress.clone();
+    if let Some(progress) = &progress {
+        progress.start_item(&id);
+    }
+
+    let outcome = match try_download_extension(
+        extension,
+        client,
+        output_dir,
+        options,
+        version_tracker.clone(),
+        &mut failures,
+    )
+    .await
+    {
+        Ok(tracker) => DownloadOutcome { tracker, failures },
+        Err(e) => {
+            error!("Failed to download extension {}: {}", id, e);
+            failures.push(DownloadFailure::new(&id, None, e));
+            DownloadOutcome {
+                tracker: version_tracker,
+                failures,
+            }
+        }
+    };
+
+    if let Some(progress) = &progress {
+        progress.finish_item(&id, outcome.failures.is_empty());
+    }
+    outcome
+}
+
+async fn try_download_extension(
+    extension: Extension,
+    client: Client,
+    output_dir: impl AsRef<Path>,
+    options: DownloadOptions,
     mut version_tracker: ExtensionVersionTracker,
+    failures: &mut Vec<DownloadFailure>,
 ) -> Result<ExtensionVersionTracker> {
     let output_dir = output_dir.as_ref().to_path_buf();
     let id = extension.id.clone();
+    let rate_limit = options.rate_limit;
+    let quarantine = load_quarantine(&output_dir);
+
+    // Pinned extensions are only ever fetched at their pinned version
+    if let Some(pinned) = options.pins.get(&id) {
+        info!("Extension {} is pinned at version {}", id, pinned);
+        download_extension_version_by_id(
+            &id,
+            pinned,
+            client,
+            &output_dir,
+            options.scanner.as_ref(),
+            options.quota.as_ref(),
+        )
+        .await?;
+        version_tracker.extensions.insert(id, pinned.clone());
+        return Ok(version_tracker);
+    }
 
     // Create extension-specific directory
     let ext_dir = output_dir.join(&id);
-    if !ext_dir.exists() {
-        if let Err(e) = fs::create_dir_all(&ext_dir) {
-            error!("Failed to create directory {:?}: {}", ext_dir, e);
-            return Ok(version_tracker);
-        }
+    if !ext_dir.exists()
+        && let Err(e) = fs::create_dir_all(&ext_dir)
+    {
+        error!("Failed to create directory {:?}: {}", ext_dir, e);
+        failures.push(DownloadFailure::new(&id, None, e));
+        return Ok(version_tracker);
     }
 
-    if all_versions {
+    if options.all_versions {
         // Fetch all versions of this extension
         let versions = client.get_extension_versions(&id).await?;
 
         // Save versions metadata
         let versions_file = ext_dir.join("versions.json");
-        let versions_json = serde_json::to_string_pretty(&WrappedExtensions {
+        let versions_json = WrappedExtensions {
             data: versions.clone(),
-        })?;
-        fs::write(&versions_file, versions_json)?;
+        }
+        .into_sorted_json()?;
+        write_atomic(&versions_file, versions_json)?;
 
-        // Download each version
-        for version in versions.iter() {
-            let file_path = ext_dir.join(format!("{}-{}.tgz", id, version.version));
+        // Download each version the target Zed release can load
+        for version in versions.iter().filter(|v| {
+            options
+                .compatibility
+                .as_ref()
+                .is_none_or(|compat| compat.is_compatible(v))
+        }) {
+            let file_path = ext_dir.join(archive_name(&id, Some(&version.version)));
+
+            if quarantine.is_quarantined(&id, &version.version) {
+                debug!(
+                    "Extension {} version {} is quarantined, skipping",
+                    id, version.version
+                );
+                continue;
+            }
 
             // Skip if already downloaded
             if file_path.exists() {
@@ -173,28 +340,27 @@ async fn download_extension(
             info!("Downloading extension: {} version {}", id, version.version);
 
             // Create a progress bar for this download
-            let pb = Arc::new(ProgressBar::new(0));
-            pb.set_style(ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                .unwrap()
-                .progress_chars("#>-"));
-
-            let pb_clone = pb.clone();
+            let (pb, on_progress) = download_progress(&file_path, &id);
             match client
-                .download_extension_version_with_progress(
-                    &id,
-                    &version.version,
-                    move |downloaded, total| {
-                        pb_clone.set_length(total);
-                        pb_clone.set_position(downloaded);
-                    },
-                )
+                .download_extension_version_with_progress(&id, &version.version, on_progress)
                 .await
             {
                 Ok(bytes) => {
                     pb.finish_with_message(format!("Downloaded {} v{}", id, version.version));
-                    match std::fs::write(&file_path, bytes) {
-                        Ok(_) => {
+                    if let Some(progress) = &options.progress {
+                        progress.add_bytes(bytes.len() as u64);
+                    }
+                    match store_archive(
+                        &file_path,
+                        bytes,
+                        options.scanner.as_ref(),
+                        options.quota.as_ref(),
+                        &id,
+                        &version.version,
+                    )
+                    .await
+                    {
+                        Ok(true) => {
                             info!(
                                 "Successfully downloaded extension: {} version {} to {:?}",
                                 id, version.version, file_path
@@ -202,7 +368,11 @@ async fn download_extension(
                             // Update version tracker
                             version_tracker.update_extension(version);
                         }
-                        Err(e) => error!("Failed to write extension file {}: {}", id, e),
+                        Ok(false) => {}
+                        Err(e) => {
+                            error!("Failed to write extension file {}: {}", id, e);
+                            failures.push(DownloadFailure::new(&id, Some(&version.version), e));
+                        }
                     }
                 }
                 Err(e) => {
@@ -210,6 +380,7 @@ async fn download_extension(
                         "Failed to download {} v{}",
                         id, version.version
                     ));
+                    failures.push(DownloadFailure::new(&id, Some(&version.version), &e));
                     if let Some(err) = e.downcast_ref::<reqwest::Error>() {
                         error!(
                             "Failed to download extension {} version {}: {}",
@@ -230,8 +401,34 @@ async fn download_extension(
             }
         }
     } else {
+        // Resolve the newest version the target Zed release can load
+        let extension = match &options.compatibility {
+            Some(compat) => {
+                let versions = client.get_extension_versions(&id).await?;
+                match compat.newest_compatible(&versions) {
+                    Some(compatible) => compatible.clone(),
+                    None => {
+                        warn!(
+                            "No version of extension {} is compatible with Zed {}, skipping",
+                            id, compat.zed_version
+                        );
+                        return Ok(version_tracker);
+                    }
+                }
+            }
+            None => extension,
+        };
+
+        if quarantine.is_quarantined(&id, &extension.version) {
+            warn!(
+                "Extension {} version {} is quarantined, skipping",
+                id, extension.version
+            );
+            return Ok(version_tracker);
+        }
+
         // Download only the latest version
-        let file_path = ext_dir.join(format!("{}.tgz", id));
+        let file_path = ext_dir.join(archive_name(&id, None));
 
         // Skip if already downloaded and version hasn't changed
         if file_path.exists() && !version_tracker.has_newer_version(&extension) {
@@ -245,28 +442,27 @@ async fn download_extension(
         info!("Downloading extension: {}", id);
 
         // Create a progress bar for this download
-        let pb = Arc::new(ProgressBar::new(0));
-        pb.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"));
-
-        let pb_clone = pb.clone();
+        let (pb, on_progress) = download_progress(&file_path, &id);
         match client
-            .download_extension_version_with_progress(
-                &id,
-                &extension.version,
-                move |downloaded, total| {
-                    pb_clone.set_length(total);
-                    pb_clone.set_position(downloaded);
-                },
-            )
+            .download_extension_version_with_progress(&id, &extension.version, on_progress)
             .await
         {
             Ok(bytes) => {
                 pb.finish_with_message(format!("Downloaded {}", id));
-                match std::fs::write(&file_path, bytes) {
-                    Ok(_) => {
+                if let Some(progress) = &options.progress {
+                    progress.add_bytes(bytes.len() as u64);
+                }
+                match store_archive(
+                    &file_path,
+                    bytes,
+                    options.scanner.as_ref(),
+                    options.quota.as_ref(),
+                    &id,
+                    &extension.version,
+                )
+                .await
+                {
+                    Ok(true) => {
                         info!(
                             "Successfully downloaded extension: {} to {:?}",
                             id, file_path
@@ -274,11 +470,16 @@ async fn download_extension(
                         // Update version tracker
                         version_tracker.update_extension(&extension);
                     }
-                    Err(e) => error!("Failed to write extension file {}: {}", id, e),
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!("Failed to write extension file {}: {}", id, e);
+                        failures.push(DownloadFailure::new(&id, Some(&extension.version), e));
+                    }
                 }
             }
             Err(e) => {
                 pb.finish_with_message(format!("Failed to download {}", id));
+                failures.push(DownloadFailure::new(&id, Some(&extension.version), &e));
                 if let Some(err) = e.downcast_ref::<reqwest::Error>() {
                     error!("Failed to download extension {}: {}", id, err);
                 } else {
@@ -297,6 +498,8 @@ pub async fn download_extension_by_id(
     client: Client,
     output_dir: impl AsRef<Path>,
     extensions: &[Extension],
+    scanner: Option<&ArchiveScanner>,
+    quota: Option<&CacheQuota>,
 ) -> Result<()> {
     let output_dir = output_dir.as_ref().to_path_buf();
 
@@ -304,6 +507,14 @@ pub async fn download_extension_by_id(
     let extension = extensions.iter().find(|e| e.id == id);
 
     if let Some(extension) = extension {
+        if load_quarantine(&output_dir).is_quarantined(id, &extension.version) {
+            warn!(
+                "Extension {} version {} is quarantined, skipping",
+                id, extension.version
+            );
+            return Ok(());
+        }
+
         info!(
             "Downloading extension: {} (version {})",
             id, extension.version
@@ -311,41 +522,31 @@ pub async fn download_extension_by_id(
 
         // Create extension-specific directory
         let ext_dir = output_dir.join(id);
-        if !ext_dir.exists() {
-            if let Err(e) = fs::create_dir_all(&ext_dir) {
-                error!("Failed to create directory {:?}: {}", ext_dir, e);
-                return Ok(());
-            }
+        if !ext_dir.exists()
+            && let Err(e) = fs::create_dir_all(&ext_dir)
+        {
+            error!("Failed to create directory {:?}: {}", ext_dir, e);
+            return Ok(());
         }
 
-        // Create a progress bar for this download
-        let pb = Arc::new(ProgressBar::new(0));
-        pb.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"));
+        let file_path = ext_dir.join(archive_name(id, None));
 
-        let pb_clone = pb.clone();
-        let file_path = ext_dir.join(format!("{}.tgz", id));
+        // Create a progress bar for this download
+        let (pb, on_progress) = download_progress(&file_path, id);
 
         match client
-            .download_extension_version_with_progress(
-                id,
-                &extension.version,
-                move |downloaded, total| {
-                    pb_clone.set_length(total);
-                    pb_clone.set_position(downloaded);
-                },
-            )
+            .download_extension_version_with_progress(id, &extension.version, on_progress)
             .await
         {
             Ok(bytes) => {
                 pb.finish_with_message(format!("Downloaded {}", id));
-                match std::fs::write(&file_path, bytes) {
-                    Ok(_) => info!(
+                match store_archive(&file_path, bytes, scanner, quota, id, &extension.version).await
+                {
+                    Ok(true) => info!(
                         "Successfully downloaded extension: {} to {:?}",
                         id, file_path
                     ),
+                    Ok(false) => {}
                     Err(e) => error!("Failed to write extension file {}: {}", id, e),
                 }
             }
@@ -365,153 +566,483 @@ pub async fn download_extension_by_id(
     Ok(())
 }
 
+/// Downloads a specific version of an extension by ID, resolving it through the versions API
+pub async fn download_extension_version_by_id(
+    id: &str,
+    version: &str,
+    client: Client,
+    output_dir: impl AsRef<Path>,
+    scanner: Option<&ArchiveScanner>,
+    quota: Option<&CacheQuota>,
+) -> Result<()> {
+    let output_dir = output_dir.as_ref().to_path_buf();
+
+    // Resolve the requested version against the upstream version listing
+    let versions = client.get_extension_versions(id).await?;
+    let extension = versions
+        .iter()
+        .find(|e| e.version == version)
+        .ok_or_else(|| anyhow::anyhow!("Version {} of extension {} not found", version, id))?;
+
+    if load_quarantine(&output_dir).is_quarantined(id, version) {
+        warn!(
+            "Extension {} version {} is quarantined, skipping",
+            id, version
+        );
+        return Ok(());
+    }
+
+    info!(
+        "Downloading extension: {} (version {})",
+        id, extension.version
+    );
+
+    // Create extension-specific directory
+    let ext_dir = output_dir.join(id);
+    fs::create_dir_all(&ext_dir)?;
+
+    // Save versions metadata so the server can resolve versioned archives
+    let versions_file = ext_dir.join("versions.json");
+    let versions_json = WrappedExtensions {
+        data: versions.clone(),
+    }
+    .into_sorted_json()?;
+    write_atomic(&versions_file, versions_json)?;
+
+    let file_path = ext_dir.join(archive_name(id, Some(&extension.version)));
+    if file_path.exists() {
+        info!(
+            "Extension {} version {} already downloaded, skipping",
+            id, extension.version
+        );
+        return Ok(());
+    }
+
+    let partial = partial_path(&file_path);
+    let size =
+        download_partial_archive(&client, &file_path, &partial, id, &extension.version).await?;
+    if let Some(quota) = quota
+        && let Err(e) = quota.reserve(&output_dir, &file_path, size)
+    {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+
+    // Scanned before it is put in place, so an unscanned archive is never served
+    let clean = match scanner {
+        Some(scanner) => {
+            scanner
+                .scan(&partial, &file_path, id, &extension.version)
+                .await?
+        }
+        None => true,
+    };
+    if clean {
+        fs::rename(&partial, &file_path).map_err(|e| write_error(e, &file_path))?;
+        info!(
+            "Successfully downloaded extension: {} version {} to {:?}",
+            id, extension.version, file_path
+        );
+    }
+
+    Ok(())
+}
+
+/// Download an archive into its `.part` file, which is kept when the download is
+/// interrupted so a later attempt (e.g. the next pull-through request after a restart)
+/// continues where it stopped. A resumed archive that turns out corrupt is downloaded
+/// again from scratch. Returns the archive's size.
+async fn download_partial_archive(
+    client: &Client,
+    file_path: &Path,
+    partial: &Path,
+    id: &str,
+    version: &str,
+) -> Result<u64> {
+    loop {
+        let resumed = partial.exists();
+        let (pb, on_progress) = download_progress(file_path, id);
+        let size = match client
+            .download_extension_version_resumable(id, version, partial, on_progress)
+            .await
+        {
+            Ok(size) => {
+                pb.finish_with_message(format!("Downloaded {} v{}", id, version));
+                size
+            }
+            Err(e) => {
+                pb.finish_with_message(format!("Failed to download {} v{}", id, version));
+                return Err(e);
+            }
+        };
+
+        let Err(e) = check_archive(partial) else {
+            return Ok(size);
+        };
+        let _ = fs::remove_file(partial);
+        if !resumed {
+            bail!(
+                "Downloaded archive of {} version {} is corrupt: {}",
+                id,
+                version,
+                e
+            );
+        }
+        warn!(
+            "Resumed archive of {} version {} is corrupt ({}), downloading it again",
+            id, version, e
+        );
+    }
+}
+
+/// Progress bar for an archive download and the callback updating it. Without a
+/// Content-Length, the size recorded for the file (or the extension's latest archive) in
+/// the directory's integrity manifest serves as an estimate; without either, a spinner
+/// shows the transferred bytes and throughput. Bars are drawn on stderr, and only while
+/// stdout is a terminal so piped output isn't interleaved with them.
+fn download_progress(
+    file_path: &Path,
+    id: &str,
+) -> (Arc<ProgressBar>, impl Fn(u64, u64) + 'static) {
+    let estimate = file_path.parent().and_then(|dir| {
+        let name = file_path.file_name()?.to_str()?;
+        recorded_size(dir, name).or_else(|| recorded_size(dir, &archive_name(id, None)))
+    });
+
+    let target = if std::io::stdout().is_terminal() {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    };
+    let pb = Arc::new(ProgressBar::with_draw_target(Some(0), target));
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+        .unwrap()
+        .progress_chars("#>-"));
+
+    let bar = pb.clone();
+    let on_progress = move |downloaded: u64, total: u64| {
+        match (total, estimate) {
+            (0, Some(estimate)) => bar.set_length(estimate.max(downloaded)),
+            (0, None) => {
+                if bar.length().is_some() {
+                    bar.set_style(
+                        ProgressStyle::default_spinner()
+                            .template(
+                                "{spinner:.green} [{elapsed_precise}] {bytes} ({bytes_per_sec})",
+                            )
+                            .unwrap(),
+                    );
+                    bar.unset_length();
+                }
+            }
+            (total, _) => bar.set_length(total),
+        }
+        bar.set_position(downloaded);
+    };
+    (pb, on_progress)
+}
+
+/// Writes a downloaded archive to disk, within the cache quota, running the scan hook
+/// against it before it is put in place. Returns `false` when the archive failed the scan
+/// and was quarantined.
+async fn store_archive(
+    file_path: &Path,
+    bytes: Vec<u8>,
+    scanner: Option<&ArchiveScanner>,
+    quota: Option<&CacheQuota>,
+    id: &str,
+    version: &str,
+) -> Result<bool> {
+    if let Some(quota) = quota
+        && let Some(cache_dir) = file_path.parent().and_then(Path::parent)
+    {
+        quota.reserve(cache_dir, file_path, bytes.len() as u64)?;
+    }
+    let Some(scanner) = scanner else {
+        write_atomic(file_path, bytes)?;
+        return Ok(true);
+    };
+
+    let partial = write_partial(file_path, bytes)?;
+    let clean = scanner.scan(&partial, file_path, id, version).await?;
+    if clean {
+        fs::rename(&partial, file_path).map_err(|e| write_error(e, file_path))?;
+    }
+    Ok(clean)
+}
+
+/// Quarantine metadata of the output directory, so quarantined versions aren't fetched again
+fn load_quarantine(output_dir: &Path) -> QuarantineRegistry {
+    QuarantineRegistry::load(output_dir).unwrap_or_else(|e| {
+        error!("Failed to load quarantine metadata: {}", e);
+        QuarantineRegistry::default()
+    })
+}
+
 /// Downloads an extension index based on provided filter criteria and saves it to a file
 pub async fn download_extension_index(
     client: &Client,
     root_dir: impl AsRef<Path>,
     provides: &[String],
-) -> Result<Vec<Extension>> {
+) -> Result<IndexOutcome> {
     let root_dir = root_dir.as_ref();
     let mut map: HashMap<String, Extension> = HashMap::new();
 
     // Fetch and merge extension lists, deduplicating by id
-    if provides.is_empty() {
+    let caps: Vec<String> = if provides.is_empty() {
         // Initial fetch to discover all provides capabilities
         let initial_exts = client.get_extensions_index(None).await?;
-        // Insert initial extensions
-        for ext in initial_exts.iter() {
-            map.insert(ext.id.clone(), ext.clone());
-        }
-        // Collect unique provides capabilities
         let mut caps = HashSet::new();
         for ext in initial_exts {
-            for cap in &ext.provides {
-                caps.insert(cap.clone());
-            }
-        }
-        // Fetch and merge by each capability
-        for cap in caps {
-            let exts = client.get_extensions_index(Some(cap.as_str())).await?;
-            for ext in exts {
-                map.insert(ext.id.clone(), ext);
-            }
+            caps.extend(ext.provides.iter().cloned());
+            merge_listed_extension(&mut map, ext);
         }
+        caps.into_iter().collect()
     } else {
         // Fetch only for specified provides
-        for prov in provides {
-            let exts = client.get_extensions_index(Some(prov.as_str())).await?;
-            for ext in exts {
+        provides.to_vec()
+    };
+    let mut caps = caps;
+    caps.sort();
+    caps.dedup();
+
+    // A failed capability doesn't abort the run, only the failed ones are fetched again
+    let mut failed = fetch_capabilities(client, caps, &mut map).await;
+    let mut delay = INDEX_RETRY_BASE_DELAY;
+    for round in 1..=INDEX_RETRY_ROUNDS {
+        if failed.is_empty() {
+            break;
+        }
+        warn!(
+            "Retrying {} failed capability listings in {}s (round {}/{})",
+            failed.len(),
+            delay.as_secs(),
+            round,
+            INDEX_RETRY_ROUNDS
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        failed = fetch_capabilities(client, failed.into_keys().collect(), &mut map).await;
+    }
+
+    let extension_path = root_dir.join("extensions.json");
+    if !failed.is_empty() {
+        // Keep what the previous index listed for the capabilities that couldn't be fetched
+        let previous = fs::read_to_string(&extension_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<WrappedExtensions>(&content).ok())
+            .map(|wrapped| wrapped.data)
+            .unwrap_or_default();
+        let mut kept = 0;
+        for ext in previous {
+            if !map.contains_key(&ext.id) && ext.provides.iter().any(|cap| failed.contains_key(cap))
+            {
                 map.insert(ext.id.clone(), ext);
+                kept += 1;
             }
         }
+        warn!(
+            "Could not fetch the extensions providing {}, kept {} of them from the previous index",
+            failed.keys().cloned().collect::<Vec<_>>().join(", "),
+            kept
+        );
     }
 
     let mut extensions: Vec<Extension> = map.into_values().collect();
-    // Sort extensions by download count (highest first)
-    extensions.sort_by(|a, b| b.download_count.cmp(&a.download_count));
+    extensions_utils::sort_by_downloads(&mut extensions);
     info!("Found {} extensions", extensions.len());
 
     // Save extensions to file
     std::fs::create_dir_all(root_dir)?;
-    let extension_path = root_dir.join("extensions.json");
-    let wrapped = WrappedExtensions {
+    let json = WrappedExtensions {
         data: extensions.clone(),
-    };
-    let json = serde_json::to_string_pretty(&wrapped)?;
-    std::fs::write(&extension_path, json)?;
+    }
+    .into_sorted_json()?;
+    snapshot_index(root_dir)?;
+    write_atomic(&extension_path, json)?;
     info!("Saved extension index to {:?}", extension_path);
 
-    Ok(extensions)
+    Ok(IndexOutcome {
+        extensions,
+        failed_capabilities: failed,
+    })
 }
 
-// Downloads the latest Zed release for supported platforms
-pub async fn download_zed_release(client: &Client, root_dir: impl AsRef<Path>) {
-    let platforms = [
-        // TODO: Add windows when windows support is implemented
-        ("zed", "linux", "x86_64"),
-        ("zed-remote-server", "linux", "x86_64"),
-        ("zed", "linux", "aarch64"),
-        ("zed-remote-server", "linux", "aarch64"),
-        ("zed", "macos", "x86_64"),
-        ("zed-remote-server", "macos", "x86_64"),
-        ("zed", "macos", "aarch64"),
-    ];
-
-    for (asset, os, arch) in platforms {
-        let url = format!(
-            "{}/api/releases/latest?asset={}&os={}&arch={}",
-            client.host(),
-            asset,
-            os,
-            arch
-        );
-        info!("Downloading Zed release from {}", url);
-        // response from server would be {"version":"0.187.8","url":"https://zed.dev/api/releases/stable/0.187.8/zed-linux-x86_64.tar.gz?update=1"}
-        let response = client.http_client.get(&url).send().await;
-
-        match response {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    let release: serde_json::Value = resp.json().await.unwrap();
-                    let version: &str = release["version"].as_str().unwrap_or("unknown");
-                    let download_url: &str = release["url"].as_str().unwrap_or("");
-                    let releases_path = root_dir.as_ref().join("releases");
-
-                    info!("Latest Zed version: {}", version);
-                    info!("Download URL: {}", download_url);
-
-                    // Create output directory if it doesn't exist
-                    let output_dir = root_dir.as_ref().join("releases").join(version);
-
-                    if !releases_path.exists() {
-                        std::fs::create_dir_all(&releases_path).unwrap();
-                    }
-                    let cache_file = releases_path.join(format!("{}-{}-{}.json", asset, os, arch));
-                    let cache_content = serde_json::to_string(&release).unwrap();
-                    std::fs::write(&cache_file, cache_content).unwrap();
-                    info!("Zed release cache saved to {:?}", cache_file);
-
-                    std::fs::create_dir_all(&output_dir).unwrap();
-
-                    // Download the file
-                    let file_path = output_dir.join(format!("{}-{}-{}.tar.gz", asset, os, arch));
-                    let download_result = client.http_client.get(download_url).send().await;
-                    match download_result {
-                        Ok(resp) => {
-                            let bytes_result = resp.bytes().await;
-                            match bytes_result {
-                                Ok(bytes) => {
-                                    use std::io::Write;
-                                    match std::fs::File::create(&file_path) {
-                                        Ok(mut file) => {
-                                            if let Err(e) = file.write_all(&bytes) {
-                                                error!(
-                                                    "Failed to write Zed release to file: {}",
-                                                    e
-                                                );
-                                            } else {
-                                                info!("Zed release downloaded to {:?}", file_path);
-                                            }
-                                        }
-                                        Err(e) => {
-                                            error!("Failed to create file for Zed release: {}", e);
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Failed to read bytes from Zed release response: {}", e);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to download Zed release: {}", e);
-                        }
-                    }
-                } else {
-                    error!("Failed to fetch latest Zed release: {}", resp.status());
+/// Fetch the extensions providing each capability into `map`, a few capabilities at a
+/// time, returning the errors of the capabilities that failed
+async fn fetch_capabilities(
+    client: &Client,
+    caps: Vec<String>,
+    map: &mut HashMap<String, Extension>,
+) -> BTreeMap<String, String> {
+    let mut listings = stream::iter(caps)
+        .map(|cap| async move {
+            let listing = client.get_extensions_index(Some(cap.as_str())).await;
+            (cap, listing)
+        })
+        .buffer_unordered(INDEX_FETCH_CONCURRENCY);
+
+    let mut failed = BTreeMap::new();
+    while let Some((cap, listing)) = listings.next().await {
+        match listing {
+            Ok(exts) => {
+                for ext in exts {
+                    merge_listed_extension(map, ext);
                 }
             }
-            Err(e) => error!("Error fetching latest Zed release: {}", e),
+            Err(e) => {
+                warn!("Failed to fetch extensions providing {}: {}", cap, e);
+                failed.insert(cap, e.to_string());
+            }
+        }
+    }
+    failed
+}
+
+/// Add an extension from a listing to the merged index. Listings fetched moments apart can
+/// disagree on an extension being published; the newest version wins, then the latest
+/// publication date, and the conflict is logged.
+fn merge_listed_extension(map: &mut HashMap<String, Extension>, ext: Extension) {
+    let mut entry = match map.entry(ext.id.clone()) {
+        Entry::Vacant(entry) => {
+            entry.insert(ext);
+            return;
+        }
+        Entry::Occupied(entry) => entry,
+    };
+
+    let known = entry.get();
+    if known.version == ext.version && known.published_at == ext.published_at {
+        // Same release, the later listing has the fresher download count
+        entry.insert(ext);
+        return;
+    }
+
+    let newer = compare_versions(&ext.version, &known.version)
+        .then_with(|| ext.published_at.cmp(&known.published_at))
+        .is_gt();
+    let (kept, dropped) = if newer { (&ext, known) } else { (known, &ext) };
+    warn!(
+        "Listings disagree on {}: keeping version {} (published {}) over {} (published {})",
+        ext.id,
+        kept.version,
+        kept.published_at.as_deref().unwrap_or("unknown"),
+        dropped.version,
+        dropped.published_at.as_deref().unwrap_or("unknown")
+    );
+    if newer {
+        entry.insert(ext);
+    }
+}
+
+/// Assets of a Zed release that can be mirrored, as (asset, os, arch)
+// TODO: Add windows when windows support is implemented
+const RELEASE_ASSETS: [(&str, &str, &str); 8] = [
+    ("zed", "linux", "x86_64"),
+    ("zed-remote-server", "linux", "x86_64"),
+    ("zed", "linux", "aarch64"),
+    ("zed-remote-server", "linux", "aarch64"),
+    ("zed", "macos", "x86_64"),
+    ("zed-remote-server", "macos", "x86_64"),
+    ("zed", "macos", "aarch64"),
+    ("zed-remote-server", "macos", "aarch64"),
+];
+
+/// Download the latest release of one asset into `releases/{version}/` and record it as
+/// the platform's latest version
+async fn download_latest_release(
+    client: &Client,
+    root_dir: &Path,
+    asset: &str,
+    os: &str,
+    arch: &str,
+) -> Result<()> {
+    let release = client.get_latest_release(None, os, arch, asset).await?;
+    info!("Latest Zed version: {}", release.version);
+    info!("Download URL: {}", release.url);
+
+    let releases_path = root_dir.join("releases");
+    fs::create_dir_all(&releases_path)?;
+    let cache_file = releases_path.join(format!("{}-{}-{}.json", asset, os, arch));
+    // A signed download URL would be expired by the time the file is served
+    let cached = Version {
+        url: strip_signature(&release.url),
+        version: release.version.clone(),
+    };
+    write_atomic(&cache_file, serde_json::to_string(&cached)?)?;
+    info!("Zed release cache saved to {:?}", cache_file);
+
+    let output_dir = releases_path.join(&release.version);
+    fs::create_dir_all(&output_dir)?;
+    let file_path = output_dir.join(release_asset_name(asset, os, arch));
+    let bytes = client
+        .send(client.http_client.get(&release.url))
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    write_atomic(&file_path, &bytes)?;
+    info!("Zed release downloaded to {:?}", file_path);
+    Ok(())
+}
+
+/// The platform zedex runs on, as os-arch
+pub fn current_platform() -> String {
+    format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// Release assets of the given os-arch platforms, or of every supported platform when none
+/// are given
+pub fn release_assets(
+    platforms: &[String],
+) -> Result<Vec<(&'static str, &'static str, &'static str)>> {
+    let platform_of = |os: &str, arch: &str| format!("{}-{}", os, arch);
+    if let Some(unknown) = platforms.iter().find(|platform| {
+        !RELEASE_ASSETS
+            .iter()
+            .any(|(_, os, arch)| platform_of(os, arch) == **platform)
+    }) {
+        let mut known: Vec<String> = RELEASE_ASSETS
+            .iter()
+            .map(|(_, os, arch)| platform_of(os, arch))
+            .collect();
+        known.dedup();
+        bail!(
+            "Zed releases aren't mirrored for {}, supported platforms are {}",
+            unknown,
+            known.join(", ")
+        );
+    }
+
+    Ok(RELEASE_ASSETS
+        .into_iter()
+        .filter(|(_, os, arch)| platforms.is_empty() || platforms.contains(&platform_of(os, arch)))
+        .collect())
+}
+
+// Downloads the latest Zed release for the given assets, returning the number of
+// assets that failed
+pub async fn download_zed_release(
+    client: &Client,
+    root_dir: impl AsRef<Path>,
+    platforms: &[(&str, &str, &str)],
+) -> usize {
+    let mut failed = 0;
+    for (index, (asset, os, arch)) in platforms.iter().enumerate() {
+        if let Some(reason) = disk_full() {
+            error!("{}, not downloading further Zed releases", reason);
+            return failed + platforms.len() - index;
+        }
+
+        info!("Fetching the latest Zed {} for {}-{}", asset, os, arch);
+        if let Err(e) = download_latest_release(client, root_dir.as_ref(), asset, os, arch).await {
+            error!(
+                "Failed to download Zed {} for {}-{}: {:#}",
+                asset, os, arch, e
+            );
+            failed += 1;
         }
     }
+    failed
 }
@@ -1,5 +1,6 @@
 use thiserror::Error;
 
+#[allow(dead_code)]
 #[derive(Debug, Error)]
 pub enum ZedError {
     #[error("HTTP request failed: {0}")]
@@ -13,4 +14,18 @@ pub enum ZedError {
 
     #[error("Invalid URL: {0}")]
     UrlParseError(#[from] url::ParseError),
+
+    #[error("Zed release {release} has no {asset} for {os}-{arch}")]
+    ReleaseNotFound {
+        release: String,
+        asset: String,
+        os: String,
+        arch: String,
+    },
+
+    #[error("{url} answered {status}")]
+    UnexpectedStatus {
+        url: String,
+        status: reqwest::StatusCode,
+    },
 }
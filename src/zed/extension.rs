@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+
+use super::compare_versions;
 
 /// Represents a Zed extension with its metadata
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,10 +29,11 @@ pub struct Extension {
 /// Tracker for extension versions
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ExtensionVersionTracker {
-    pub extensions: HashMap<String, String>, // Maps extension id to latest version
+    pub extensions: BTreeMap<String, String>, // Maps extension id to latest version
 }
 
 /// Collection of extension versions
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ExtensionVersions {
     pub versions: Vec<Extension>,
@@ -40,7 +43,7 @@ impl ExtensionVersionTracker {
     /// Create a new empty version tracker
     pub fn new() -> Self {
         Self {
-            extensions: HashMap::new(),
+            extensions: BTreeMap::new(),
         }
     }
 
@@ -82,73 +85,657 @@ pub struct WrappedExtensions {
     pub data: Extensions,
 }
 
+impl WrappedExtensions {
+    /// Pretty-printed JSON with the entries ordered by id, newest version first, so
+    /// writing unchanged metadata again gives an identical file and syncs diff cleanly
+    pub fn into_sorted_json(mut self) -> serde_json::Result<String> {
+        for extension in &mut self.data {
+            extension.provides.sort();
+            extension.provides.dedup();
+        }
+        self.data.sort_by(|a, b| {
+            a.id.cmp(&b.id)
+                .then_with(|| compare_versions(&b.version, &a.version))
+        });
+        serde_json::to_string_pretty(&self)
+    }
+}
+
 /// Functions for working with Extensions without implementing directly on Vec
 pub mod extensions_utils {
-    use super::Extensions;
+    use super::{Extension, Extensions};
+    use crate::zed::parse_version;
+    use chrono::{DateTime, FixedOffset};
     use log::debug;
+    use semver::Version as SemverVersion;
+    use std::cmp::Ordering;
+    use std::collections::HashMap;
 
-    /// Filter a collection of extensions by various criteria
-    ///
-    /// # Arguments
-    /// * `extensions` - The collection of extensions to filter
-    /// * `filter` - Optional text to search in name, id, and description
-    /// * `max_schema_version` - Optional maximum schema version
-    /// * `provides` - Optional capability that extensions must provide
-    pub fn filter_extensions(
-        extensions: &Extensions,
-        filter: Option<&str>,
+    /// Criteria to filter a collection of extensions by, built up from the listing, download
+    /// and update check parameters. Unset criteria match everything.
+    #[derive(Debug, Clone, Default)]
+    pub struct ExtensionQuery {
+        text: Option<String>,
+        min_schema_version: Option<i32>,
         max_schema_version: Option<i32>,
-        provides: Option<&str>,
-    ) -> Extensions {
-        debug!(
-            "Filtering extensions with criteria: filter={:?}, max_schema_version={:?}, provides={:?}",
-            filter, max_schema_version, provides
-        );
-
-        let filtered: Extensions = extensions
+        min_wasm_api_version: Option<SemverVersion>,
+        max_wasm_api_version: Option<SemverVersion>,
+        provides: Option<String>,
+        ids: Vec<String>,
+    }
+
+    impl ExtensionQuery {
+        /// The schema and WASM API version bounds a client sends with a download or update
+        /// check; unparseable bounds are ignored
+        pub fn from_version_bounds(params: &HashMap<String, String>) -> Self {
+            let schema = |key: &str| params.get(key).and_then(|v| v.parse::<i32>().ok());
+            let wasm = |key: &str| params.get(key).and_then(|v| parse_version(v));
+            Self::default()
+                .schema_versions(schema("min_schema_version"), schema("max_schema_version"))
+                .wasm_api_versions(wasm("min_wasm_api_version"), wasm("max_wasm_api_version"))
+        }
+
+        /// Text to search for in name, id and description, ignoring case
+        pub fn text(mut self, text: Option<&str>) -> Self {
+            self.text = text.filter(|text| !text.is_empty()).map(str::to_lowercase);
+            self
+        }
+
+        pub fn schema_versions(mut self, min: Option<i32>, max: Option<i32>) -> Self {
+            self.min_schema_version = min;
+            self.max_schema_version = max;
+            self
+        }
+
+        /// WASM API version bounds, compared as semver; extensions without a WASM component
+        /// always match and, once a bound is set, those with an unparseable version never do
+        pub fn wasm_api_versions(
+            mut self,
+            min: Option<SemverVersion>,
+            max: Option<SemverVersion>,
+        ) -> Self {
+            self.min_wasm_api_version = min;
+            self.max_wasm_api_version = max;
+            self
+        }
+
+        /// Capability extensions must provide
+        pub fn provides(mut self, capability: Option<&str>) -> Self {
+            self.provides = capability
+                .filter(|capability| !capability.is_empty())
+                .map(str::to_string);
+            self
+        }
+
+        /// Ids to keep, all when empty
+        pub fn ids<'a>(mut self, ids: impl IntoIterator<Item = &'a str>) -> Self {
+            self.ids = ids.into_iter().map(str::to_string).collect();
+            self
+        }
+
+        /// Whether any schema or WASM API version bound is set
+        pub fn has_version_bounds(&self) -> bool {
+            self.min_schema_version.is_some()
+                || self.max_schema_version.is_some()
+                || self.min_wasm_api_version.is_some()
+                || self.max_wasm_api_version.is_some()
+        }
+
+        /// Check a single extension against every criterion
+        pub fn matches(&self, ext: &Extension) -> bool {
+            if self
+                .min_schema_version
+                .is_some_and(|min| ext.schema_version < min)
+                || self
+                    .max_schema_version
+                    .is_some_and(|max| ext.schema_version > max)
+            {
+                return false;
+            }
+
+            if let Some(text) = &self.text
+                && ![&ext.name, &ext.id, &ext.description]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(text))
+            {
+                return false;
+            }
+
+            if let Some(capability) = &self.provides
+                && !ext.provides_capability(capability)
+            {
+                return false;
+            }
+
+            if !self.ids.is_empty() && !self.ids.contains(&ext.id) {
+                return false;
+            }
+
+            if self.min_wasm_api_version.is_none() && self.max_wasm_api_version.is_none() {
+                return true;
+            }
+            match &ext.wasm_api_version {
+                None => true,
+                Some(version) => parse_version(version).is_some_and(|version| {
+                    self.min_wasm_api_version
+                        .as_ref()
+                        .is_none_or(|min| version >= *min)
+                        && self
+                            .max_wasm_api_version
+                            .as_ref()
+                            .is_none_or(|max| version <= *max)
+                }),
+            }
+        }
+
+        /// The extensions matching every criterion, in their original order
+        pub fn apply(&self, extensions: &[Extension]) -> Extensions {
+            debug!("Filtering extensions with {:?}", self);
+            let filtered: Extensions = extensions
+                .iter()
+                .filter(|ext| self.matches(ext))
+                .cloned()
+                .collect();
+            debug!(
+                "Filtered extensions from {} to {}",
+                extensions.len(),
+                filtered.len()
+            );
+            filtered
+        }
+    }
+
+    /// Order extensions the way zed.dev lists them, most downloaded first
+    pub fn sort_by_downloads(extensions: &mut [Extension]) {
+        extensions.sort_by(|a, b| {
+            b.download_count
+                .cmp(&a.download_count)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+    }
+
+    /// Orders the index can be listed in
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub enum ExtensionSort {
+        #[default]
+        Downloads,
+        /// By `published_at`, extensions without one last
+        Recent,
+        /// By name regardless of case
+        Name,
+    }
+
+    impl ExtensionSort {
+        pub fn parse(value: &str) -> Option<Self> {
+            match value {
+                "downloads" => Some(Self::Downloads),
+                "recent" => Some(Self::Recent),
+                "name" => Some(Self::Name),
+                _ => None,
+            }
+        }
+
+        /// Most downloaded and most recent first, names from A to Z
+        pub fn descending_by_default(self) -> bool {
+            self != Self::Name
+        }
+
+        /// Order extensions, ties by id either way
+        pub fn apply(self, extensions: &mut [Extension], descending: bool) {
+            extensions.sort_by(|a, b| {
+                let ordering = match self {
+                    Self::Downloads => a.download_count.cmp(&b.download_count),
+                    Self::Recent => match (published_at(a), published_at(b)) {
+                        (Some(a), Some(b)) => a.cmp(&b),
+                        // Undated entries stay last in either order
+                        (Some(_), None) => return Ordering::Less,
+                        (None, Some(_)) => return Ordering::Greater,
+                        (None, None) => Ordering::Equal,
+                    },
+                    Self::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                };
+                let ordering = if descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                };
+                ordering.then_with(|| a.id.cmp(&b.id))
+            });
+        }
+    }
+
+    fn published_at(ext: &Extension) -> Option<DateTime<FixedOffset>> {
+        ext.published_at
+            .as_deref()
+            .and_then(|published_at| DateTime::parse_from_rfc3339(published_at).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Extension;
+    use super::extensions_utils::{ExtensionQuery, ExtensionSort};
+    use std::collections::HashMap;
+
+    fn extension(id: &str, schema_version: i32, wasm_api_version: Option<&str>) -> Extension {
+        Extension {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+            authors: Vec::new(),
+            repository: None,
+            schema_version,
+            wasm_api_version: wasm_api_version.map(str::to_string),
+            published_at: None,
+            download_count: 0,
+            provides: Vec::new(),
+        }
+    }
+
+    fn ids(extensions: &[Extension]) -> Vec<&str> {
+        extensions.iter().map(|ext| ext.id.as_str()).collect()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
             .iter()
-            .filter(|ext| {
-                // Filter by max schema version if provided
-                if let Some(max_version) = max_schema_version {
-                    if ext.schema_version > max_version {
-                        return false;
-                    }
-                }
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
 
-                // Filter by text search if provided
-                if let Some(search_text) = filter {
-                    if !search_text.is_empty()
-                        && !ext
-                            .name
-                            .to_lowercase()
-                            .contains(&search_text.to_lowercase())
-                        && !ext.id.to_lowercase().contains(&search_text.to_lowercase())
-                        && !ext
-                            .description
-                            .to_lowercase()
-                            .contains(&search_text.to_lowercase())
-                    {
-                        return false;
-                    }
-                }
+    #[test]
+    fn query_builder_table() {
+        let extensions = vec![
+            extension("alpha", 0, None),
+            extension("beta", 1, Some("0.1.0")),
+            extension("gamma", 1, Some("0.2.0")),
+            extension("delta", 2, Some("0.10.0")),
+        ];
+        let cases: Vec<(&str, ExtensionQuery, Vec<&str>)> = vec![
+            (
+                "default",
+                ExtensionQuery::default(),
+                vec!["alpha", "beta", "gamma", "delta"],
+            ),
+            (
+                "max schema",
+                ExtensionQuery::default().schema_versions(None, Some(1)),
+                vec!["alpha", "beta", "gamma"],
+            ),
+            (
+                "schema range",
+                ExtensionQuery::default().schema_versions(Some(1), Some(1)),
+                vec!["beta", "gamma"],
+            ),
+            (
+                "min wasm",
+                ExtensionQuery::default().wasm_api_versions(Some("0.2.0".parse().unwrap()), None),
+                vec!["alpha", "gamma", "delta"],
+            ),
+            (
+                "ids",
+                ExtensionQuery::default().ids(["delta", "alpha", "missing"]),
+                vec!["alpha", "delta"],
+            ),
+            (
+                "text",
+                ExtensionQuery::default().text(Some("AMM")),
+                vec!["gamma"],
+            ),
+            (
+                "empty text",
+                ExtensionQuery::default().text(Some("")),
+                vec!["alpha", "beta", "gamma", "delta"],
+            ),
+            (
+                "ids and schema",
+                ExtensionQuery::default()
+                    .ids(["alpha", "delta"])
+                    .schema_versions(Some(1), None),
+                vec!["delta"],
+            ),
+        ];
+
+        for (name, query, expected) in cases {
+            assert_eq!(ids(&query.apply(&extensions)), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn version_bounds_from_params() {
+        let cases = [
+            (params(&[]), false),
+            (params(&[("max_schema_version", "1")]), true),
+            (params(&[("min_wasm_api_version", "0.1.0")]), true),
+            (params(&[("max_schema_version", "one")]), false),
+            (params(&[("max_wasm_api_version", "bogus")]), false),
+            (params(&[("filter", "alpha"), ("ids", "alpha")]), false),
+        ];
+        for (params, bounded) in cases {
+            assert_eq!(
+                ExtensionQuery::from_version_bounds(&params).has_version_bounds(),
+                bounded,
+                "{params:?}"
+            );
+        }
+
+        let extensions = vec![
+            extension("old", 0, Some("0.0.6")),
+            extension("new", 1, Some("0.2.0")),
+        ];
+        let query = ExtensionQuery::from_version_bounds(&params(&[
+            ("min_schema_version", "0"),
+            ("max_schema_version", "1"),
+            ("min_wasm_api_version", "0.1.0"),
+            ("max_wasm_api_version", "0.2.0"),
+        ]));
+        assert_eq!(ids(&query.apply(&extensions)), vec!["new"]);
+    }
+
+    fn listed(id: &str, name: &str, downloads: i32, published_at: Option<&str>) -> Extension {
+        Extension {
+            name: name.to_string(),
+            download_count: downloads,
+            published_at: published_at.map(str::to_string),
+            ..extension(id, 1, None)
+        }
+    }
+
+    #[test]
+    fn sort_matrix() {
+        let extensions = vec![
+            listed("a", "zeta", 5, Some("2024-03-01T00:00:00Z")),
+            listed("b", "Alpha", 20, None),
+            listed("c", "beta", 5, Some("2023-01-01T00:00:00+02:00")),
+            listed("d", "Gamma", 1, Some("2024-03-01T00:00:00Z")),
+            listed("e", "delta", 0, Some("not a date")),
+        ];
+        let cases = [
+            (ExtensionSort::Downloads, true, ["b", "a", "c", "d", "e"]),
+            (ExtensionSort::Downloads, false, ["e", "d", "a", "c", "b"]),
+            // Undated and unparseable dates stay last either way, ties go by id
+            (ExtensionSort::Recent, true, ["a", "d", "c", "b", "e"]),
+            (ExtensionSort::Recent, false, ["c", "a", "d", "b", "e"]),
+            (ExtensionSort::Name, false, ["b", "c", "e", "d", "a"]),
+            (ExtensionSort::Name, true, ["a", "d", "e", "c", "b"]),
+        ];
+        for (sort, descending, expected) in cases {
+            let mut sorted = extensions.clone();
+            sort.apply(&mut sorted, descending);
+            assert_eq!(ids(&sorted), expected, "{sort:?} descending={descending}");
+        }
+    }
 
-                // Filter by provides capability if provided
-                if let Some(capability) = provides {
-                    if !capability.is_empty() && !ext.provides_capability(capability) {
-                        return false;
+    #[test]
+    fn sort_parse_and_defaults() {
+        assert_eq!(ExtensionSort::parse("recent"), Some(ExtensionSort::Recent));
+        assert_eq!(ExtensionSort::parse("Recent"), None);
+        assert!(ExtensionSort::Downloads.descending_by_default());
+        assert!(ExtensionSort::Recent.descending_by_default());
+        assert!(!ExtensionSort::Name.descending_by_default());
+    }
+
+    #[test]
+    fn text_provides_and_ids_filters() {
+        let extensions = vec![
+            Extension {
+                description: "Syntax highlighting for TOML".to_string(),
+                provides: vec!["languages".to_string(), "grammars".to_string()],
+                ..extension("toml", 1, None)
+            },
+            Extension {
+                provides: vec!["themes".to_string()],
+                ..extension("catppuccin", 1, None)
+            },
+            Extension {
+                provides: vec!["language-servers".to_string()],
+                ..extension("tombi", 1, None)
+            },
+        ];
+        let cases: Vec<(&str, ExtensionQuery, Vec<&str>)> = vec![
+            (
+                "text in id",
+                ExtensionQuery::default().text(Some("tom")),
+                vec!["toml", "tombi"],
+            ),
+            (
+                "text in name ignores case",
+                ExtensionQuery::default().text(Some("cAtP")),
+                vec!["catppuccin"],
+            ),
+            (
+                "text in description",
+                ExtensionQuery::default().text(Some("highlighting")),
+                vec!["toml"],
+            ),
+            (
+                "text without match",
+                ExtensionQuery::default().text(Some("rust")),
+                vec![],
+            ),
+            (
+                "provides is an exact capability",
+                ExtensionQuery::default().provides(Some("language")),
+                vec![],
+            ),
+            (
+                "provides",
+                ExtensionQuery::default().provides(Some("languages")),
+                vec!["toml"],
+            ),
+            (
+                "empty provides",
+                ExtensionQuery::default().provides(Some("")),
+                vec!["toml", "catppuccin", "tombi"],
+            ),
+            (
+                "ids are exact",
+                ExtensionQuery::default().ids(["tom", "tombi"]),
+                vec!["tombi"],
+            ),
+            (
+                "text, provides and ids together",
+                ExtensionQuery::default()
+                    .text(Some("tom"))
+                    .provides(Some("language-servers"))
+                    .ids(["toml", "tombi"]),
+                vec!["tombi"],
+            ),
+        ];
+        for (name, query, expected) in cases {
+            assert_eq!(ids(&query.apply(&extensions)), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn wasm_api_version_bounds() {
+        let extensions = vec![
+            extension("none", 1, None),
+            extension("min", 1, Some("0.1.0")),
+            extension("pre", 1, Some("0.2.0-alpha")),
+            extension("mid", 1, Some("0.2.0")),
+            extension("max", 1, Some("0.10.0")),
+            extension("bogus", 1, Some("bogus")),
+        ];
+        let bounds = |min: Option<&str>, max: Option<&str>| {
+            ExtensionQuery::default().wasm_api_versions(
+                min.map(|min| min.parse().unwrap()),
+                max.map(|max| max.parse().unwrap()),
+            )
+        };
+        let cases = [
+            // Without a bound nothing is parsed, so unparseable versions pass
+            (
+                bounds(None, None),
+                vec!["none", "min", "pre", "mid", "max", "bogus"],
+            ),
+            // Bounds are inclusive and compared numerically, not as strings
+            (
+                bounds(Some("0.1.0"), Some("0.10.0")),
+                vec!["none", "min", "pre", "mid", "max"],
+            ),
+            (bounds(Some("0.10.0"), None), vec!["none", "max"]),
+            (bounds(None, Some("0.1.0")), vec!["none", "min"]),
+            (bounds(Some("0.2.0"), Some("0.2.0")), vec!["none", "mid"]),
+            // A prerelease sorts before its release
+            (
+                bounds(None, Some("0.2.0-alpha")),
+                vec!["none", "min", "pre"],
+            ),
+            (bounds(Some("0.2.0"), None), vec!["none", "mid", "max"]),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(ids(&query.apply(&extensions)), expected, "{query:?}");
+        }
+    }
+
+    #[test]
+    fn wasm_api_version_bounds_from_loose_params() {
+        let query = ExtensionQuery::from_version_bounds(&params(&[
+            ("min_wasm_api_version", "0.2"),
+            ("max_wasm_api_version", "v0.10"),
+        ]));
+        let extensions = vec![
+            extension("below", 1, Some("0.1.9")),
+            extension("low", 1, Some("0.2.0")),
+            extension("high", 1, Some("0.10.0")),
+            extension("above", 1, Some("0.10.1")),
+        ];
+        assert_eq!(ids(&query.apply(&extensions)), vec!["low", "high"]);
+    }
+
+    fn providing(id: &str, schema: i32, wasm: Option<&str>, provides: &[&str]) -> Extension {
+        Extension {
+            provides: provides.iter().map(|p| p.to_string()).collect(),
+            ..extension(id, schema, wasm)
+        }
+    }
+
+    fn combination_fixture() -> Vec<Extension> {
+        vec![
+            providing("theme-old", 0, None, &["themes"]),
+            providing("theme-new", 2, None, &["themes", "icon-themes"]),
+            providing("lang-wasm-old", 1, Some("0.0.6"), &["languages"]),
+            providing(
+                "lang-wasm-new",
+                1,
+                Some("0.2.0"),
+                &["languages", "grammars"],
+            ),
+            providing("lsp", 2, Some("0.10.0"), &["language-servers"]),
+            providing("broken", 1, Some("x"), &["languages"]),
+        ]
+    }
+
+    #[test]
+    fn schema_wasm_ids_and_provides_combinations() {
+        let extensions = combination_fixture();
+        let version = |v: &str| Some(v.parse().unwrap());
+        let cases: Vec<(&str, ExtensionQuery, Vec<&str>)> = vec![
+            (
+                "schema and wasm",
+                ExtensionQuery::default()
+                    .schema_versions(Some(1), Some(1))
+                    .wasm_api_versions(version("0.1.0"), None),
+                vec!["lang-wasm-new"],
+            ),
+            (
+                "schema and provides",
+                ExtensionQuery::default()
+                    .schema_versions(None, Some(1))
+                    .provides(Some("themes")),
+                vec!["theme-old"],
+            ),
+            (
+                "wasm and provides",
+                ExtensionQuery::default()
+                    .wasm_api_versions(None, version("0.2.0"))
+                    .provides(Some("languages")),
+                vec!["lang-wasm-old", "lang-wasm-new"],
+            ),
+            (
+                "wasm and ids keep extensions without wasm",
+                ExtensionQuery::default()
+                    .wasm_api_versions(version("0.2.0"), None)
+                    .ids(["theme-new", "lang-wasm-old", "lsp"]),
+                vec!["theme-new", "lsp"],
+            ),
+            (
+                "ids and provides",
+                ExtensionQuery::default()
+                    .ids(["theme-new", "lsp"])
+                    .provides(Some("icon-themes")),
+                vec!["theme-new"],
+            ),
+            (
+                "all four",
+                ExtensionQuery::default()
+                    .schema_versions(Some(1), Some(2))
+                    .wasm_api_versions(version("0.1.0"), version("0.10.0"))
+                    .ids(["lang-wasm-new", "lsp", "broken", "theme-old"])
+                    .provides(Some("languages")),
+                vec!["lang-wasm-new"],
+            ),
+            (
+                "all four excluding everything",
+                ExtensionQuery::default()
+                    .schema_versions(Some(2), None)
+                    .wasm_api_versions(None, version("0.2.0"))
+                    .ids(["lsp"])
+                    .provides(Some("language-servers")),
+                vec![],
+            ),
+        ];
+        for (name, query, expected) in cases {
+            assert_eq!(ids(&query.apply(&extensions)), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn combined_query_is_the_intersection_of_its_criteria() {
+        let extensions = combination_fixture();
+        let schemas = [None, Some((Some(1), None)), Some((None, Some(1)))];
+        let wasms = [
+            None,
+            Some((Some("0.1.0".parse().unwrap()), None)),
+            Some((None, Some("0.2.0".parse().unwrap()))),
+        ];
+        let id_sets: [&[&str]; 2] = [&[], &["theme-new", "lang-wasm-new", "lsp", "broken"]];
+        let capabilities = [None, Some("languages"), Some("themes")];
+
+        for schema in &schemas {
+            for wasm in &wasms {
+                for id_set in id_sets {
+                    for capability in capabilities {
+                        let mut parts = vec![
+                            ExtensionQuery::default().ids(id_set.iter().copied()),
+                            ExtensionQuery::default().provides(capability),
+                        ];
+                        let mut query = ExtensionQuery::default()
+                            .ids(id_set.iter().copied())
+                            .provides(capability);
+                        if let Some((min, max)) = schema {
+                            parts.push(ExtensionQuery::default().schema_versions(*min, *max));
+                            query = query.schema_versions(*min, *max);
+                        }
+                        if let Some((min, max)) = wasm {
+                            parts.push(
+                                ExtensionQuery::default()
+                                    .wasm_api_versions(min.clone(), max.clone()),
+                            );
+                            query = query.wasm_api_versions(min.clone(), max.clone());
+                        }
+
+                        let expected: Vec<&str> = extensions
+                            .iter()
+                            .filter(|ext| parts.iter().all(|part| part.matches(ext)))
+                            .map(|ext| ext.id.as_str())
+                            .collect();
+                        assert_eq!(ids(&query.apply(&extensions)), expected, "{query:?}");
                     }
                 }
-
-                true
-            })
-            .cloned()
-            .collect();
-
-        debug!(
-            "Filtered extensions from {} to {}",
-            extensions.len(),
-            filtered.len()
-        );
-        filtered
+            }
+        }
     }
 }
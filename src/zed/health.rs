@@ -1,11 +1,15 @@
-use actix_web::{HttpResponse, Responder};
+use actix_web::{HttpResponse, Responder, web};
 use log::debug;
 use once_cell::sync::OnceCell;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use super::disk_full;
+use super::history::load_sync_history;
+use super::server::{DriftReport, ServerConfig, ServerState, VerifyReport};
+
 /// Health check response structure
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 pub struct HealthResponse {
     /// Status of the service
     status: String,
@@ -19,6 +23,15 @@ pub struct HealthResponse {
     uptime: u64,
     /// Number of extensions loaded
     extensions_loaded: u64,
+    /// Unix time the mirror was last synced successfully
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_sync: Option<u64>,
+    /// Result of the last upstream drift check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_drift: Option<DriftReport>,
+    /// Result of the background archive verification
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verification: Option<VerifyReport>,
 }
 
 /// Server uptime tracking
@@ -44,7 +57,7 @@ fn get_start_time() -> u64 {
 }
 
 /// Health check handler that returns service status in JSON format
-pub async fn health_check() -> impl Responder {
+pub async fn health_check(state: web::Data<ServerState>) -> impl Responder {
     debug!("Health check requested");
 
     // Get current time
@@ -63,9 +76,51 @@ pub async fn health_check() -> impl Responder {
         version: env!("CARGO_PKG_VERSION").to_string(),
         timestamp: now,
         extensions_loaded: get_extensions_loaded_count(),
+        last_sync: last_sync(&state.config),
         uptime,
+        upstream_drift: state.drift_report(),
+        verification: state.verification_report(),
     };
 
+    // Surface upstream drift without failing the health check
+    if let Some(drift) = &health.upstream_drift
+        && drift.stale_extensions > 0
+    {
+        health.reason = format!("Service is running ({})", drift.summary());
+    }
+
+    // Corrupt archives affect only the clients downloading them
+    if let Some(verification) = &health.verification
+        && !verification.corrupt.is_empty()
+    {
+        health.status = "DEGRADED".to_string();
+        health.reason = verification.summary();
+    }
+
+    // A stale mirror still serves, but its users miss new extensions and versions
+    if let Some(max_age) = state.config.max_sync_age {
+        match health.last_sync {
+            Some(last_sync) if now.saturating_sub(last_sync) <= max_age.as_secs() => {}
+            Some(last_sync) => {
+                health.status = "DEGRADED".to_string();
+                health.reason = format!(
+                    "Mirror last synced {} days ago",
+                    now.saturating_sub(last_sync) / (24 * 60 * 60)
+                );
+            }
+            None => {
+                health.status = "DEGRADED".to_string();
+                health.reason = "No successful sync recorded".to_string();
+            }
+        }
+    }
+
+    // Content can still be served from a full disk, but nothing new can be stored
+    if let Some(reason) = disk_full() {
+        health.status = "DEGRADED".to_string();
+        health.reason = reason;
+    }
+
     // Check for loaded extensions
     if health.extensions_loaded == 0 {
         health.status = "ERROR".to_string();
@@ -73,13 +128,36 @@ pub async fn health_check() -> impl Responder {
     }
 
     // Return JSON response
-    if health.status == "OK" {
+    if health.status != "ERROR" {
         HttpResponse::Ok().json(health)
     } else {
         HttpResponse::InternalServerError().json(health)
     }
 }
 
+/// When the mirror was last synced: the end of the last successful run in the sync
+/// history, else the last time the version tracker was written
+fn last_sync(config: &ServerConfig) -> Option<u64> {
+    let recorded = config
+        .root_dir
+        .as_deref()
+        .and_then(|root_dir| load_sync_history(root_dir).ok())
+        .and_then(|records| {
+            records
+                .iter()
+                .rev()
+                .find(|record| record.succeeded())
+                .map(|record| record.finished_at.max(0) as u64)
+        });
+    recorded.or_else(|| {
+        std::fs::metadata(config.extensions_dir.join("version_tracker.json"))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs())
+    })
+}
+
 pub fn get_extensions_loaded_count() -> u64 {
     let dir =
         std::env::var("ZED_EXTENSIONS_LOCAL_DIR").unwrap_or_else(|_| ".zedex-cache".to_string());
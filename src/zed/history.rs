@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use super::progress::ProgressStatus;
+
+/// Name of the file, relative to the root directory, with one record per sync run
+pub const SYNC_HISTORY_FILE: &str = "sync-history.jsonl";
+
+/// Outcome of one `get all-extensions` or `sync` run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncRecord {
+    /// Command that ran, e.g. `sync`
+    pub operation: String,
+    /// Where the run mirrored from, upstream or a peer URL
+    pub source: String,
+    /// Who or what started the run, e.g. `manual` or `cron`
+    pub triggered_by: String,
+    /// Unix times the run started and ended
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// `succeeded`, `partial` or `failed`
+    pub outcome: String,
+    /// Items mirrored or found up to date
+    pub completed: usize,
+    /// Items that still failed when the run ended
+    pub failed: usize,
+    /// Bytes transferred
+    pub bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SyncRecord {
+    /// Record of a finished run from its progress
+    pub fn new(status: &ProgressStatus, source: &str, triggered_by: &str, outcome: &str) -> Self {
+        Self {
+            operation: status.operation.clone(),
+            source: source.to_string(),
+            triggered_by: triggered_by.to_string(),
+            started_at: status.started_at,
+            finished_at: chrono::Utc::now().timestamp(),
+            outcome: outcome.to_string(),
+            completed: status.completed_total,
+            failed: status.failed,
+            bytes: status.bytes,
+            error: status.error.clone(),
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.outcome == "succeeded"
+    }
+}
+
+/// Append a run to the history in `root_dir`
+pub fn append_sync_record(root_dir: &Path, record: &SyncRecord) -> Result<()> {
+    let path = root_dir.join(SYNC_HISTORY_FILE);
+    fs::create_dir_all(root_dir)?;
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to append to {:?}", path))
+}
+
+/// Runs recorded in `root_dir`, oldest first. Lines that can't be parsed are skipped.
+pub fn load_sync_history(root_dir: &Path) -> Result<Vec<SyncRecord>> {
+    let path = root_dir.join(SYNC_HISTORY_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    Ok(content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(number, line)| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                warn!("Skipping line {} of {:?}: {}", number + 1, path, e);
+                None
+            }
+        })
+        .collect())
+}
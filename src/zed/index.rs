@@ -0,0 +1,193 @@
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use semver::Version as SemverVersion;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use super::{Extension, Extensions, parse_version};
+
+/// Modification time of each index whose problems were last reported, so a server
+/// re-reading an unchanged index doesn't log the same report on every request
+static REPORTED: Lazy<Mutex<HashMap<PathBuf, SystemTime>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A problem with a single entry of an extensions index
+#[derive(Debug, Clone)]
+pub struct IndexIssue {
+    /// Position of the entry in the index
+    pub position: usize,
+    pub id: Option<String>,
+    pub problem: String,
+    /// Whether the entry was left out of the loaded index
+    pub dropped: bool,
+}
+
+/// Load an extensions index (`{"data": [...]}`), validating and normalizing every entry.
+/// Entries with missing fields, versions without a number or duplicate ids are dropped and
+/// reported; in strict mode any such entry fails the load instead. Other non-semver versions
+/// are kept and ordered by [`parse_version`].
+pub fn load_index(path: &Path, strict: bool) -> Result<Extensions> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    parse_index(&content, path, strict)
+}
+
+/// Validate an extensions index read from `source`, see [`load_index`]
+pub fn parse_index(content: &str, source: &Path, strict: bool) -> Result<Extensions> {
+    let mut wrapped: serde_json::Value =
+        serde_json::from_str(content).with_context(|| format!("{:?} is not valid JSON", source))?;
+    let Some(entries) = wrapped.get_mut("data").and_then(|data| data.as_array_mut()) else {
+        bail!("{:?} has no \"data\" list of extensions", source);
+    };
+
+    let (extensions, issues) = validate_entries(std::mem::take(entries));
+    if issues.is_empty() {
+        return Ok(extensions);
+    }
+
+    let dropped = issues.iter().filter(|issue| issue.dropped).count();
+    if strict && dropped > 0 {
+        let details: Vec<String> = issues.iter().map(describe).collect();
+        bail!(
+            "{:?} has {} invalid entries:\n  {}",
+            source,
+            dropped,
+            details.join("\n  ")
+        );
+    }
+
+    if should_report(source) {
+        for issue in &issues {
+            warn!("{:?}: {}", source, describe(issue));
+        }
+        info!(
+            "Loaded {} extensions from {:?}, dropped {} invalid entries",
+            extensions.len(),
+            source,
+            dropped
+        );
+    }
+    Ok(extensions)
+}
+
+fn validate_entries(entries: Vec<serde_json::Value>) -> (Extensions, Vec<IndexIssue>) {
+    let mut issues = Vec::new();
+    let mut extensions: Extensions = Vec::new();
+    // Index in `extensions` and position in the index of the entry kept for each id
+    let mut positions: HashMap<String, (usize, usize)> = HashMap::new();
+
+    for (position, entry) in entries.into_iter().enumerate() {
+        let id = entry
+            .get("id")
+            .and_then(|id| id.as_str())
+            .map(str::to_string);
+        let mut issue = |problem: String, dropped: bool| {
+            issues.push(IndexIssue {
+                position,
+                id: id.clone(),
+                problem,
+                dropped,
+            })
+        };
+
+        let mut extension = match serde_json::from_value::<Extension>(entry) {
+            Ok(extension) => extension,
+            Err(e) => {
+                issue(e.to_string(), true);
+                continue;
+            }
+        };
+
+        if extension.id.trim().is_empty() {
+            issue("empty id".to_string(), true);
+            continue;
+        }
+
+        let normalized = extension.version.trim().trim_start_matches('v');
+        let Some(version) = parse_version(normalized) else {
+            issue(
+                format!("version '{}' has no version number", extension.version),
+                true,
+            );
+            continue;
+        };
+        if SemverVersion::parse(normalized).is_err() {
+            issue(
+                format!(
+                    "version '{}' is not semver, ordered as {}",
+                    extension.version, version
+                ),
+                false,
+            );
+        }
+        if normalized != extension.version {
+            issue(
+                format!(
+                    "version '{}' normalized to '{}'",
+                    extension.version, normalized
+                ),
+                false,
+            );
+            extension.version = normalized.to_string();
+        }
+
+        // Duplicates keep the entry with the highest version
+        match positions.get(&extension.id).copied() {
+            Some((index, kept_position)) => {
+                let kept = &mut extensions[index];
+                let replace = parse_version(&kept.version).is_some_and(|kept| version > kept);
+                let (dropped_position, dropped_version, kept_version) = if replace {
+                    (
+                        kept_position,
+                        kept.version.clone(),
+                        extension.version.clone(),
+                    )
+                } else {
+                    (position, extension.version.clone(), kept.version.clone())
+                };
+                issues.push(IndexIssue {
+                    position: dropped_position,
+                    id: Some(extension.id.clone()),
+                    problem: format!(
+                        "duplicate id at version {}, version {} is kept",
+                        dropped_version, kept_version
+                    ),
+                    dropped: true,
+                });
+                if replace {
+                    *kept = extension;
+                    positions.insert(kept.id.clone(), (index, position));
+                }
+            }
+            None => {
+                positions.insert(extension.id.clone(), (extensions.len(), position));
+                extensions.push(extension);
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.position);
+    (extensions, issues)
+}
+
+fn describe(issue: &IndexIssue) -> String {
+    format!(
+        "entry {} ({}): {}{}",
+        issue.position,
+        issue.id.as_deref().unwrap_or("no id"),
+        issue.problem,
+        if issue.dropped { ", dropped" } else { "" }
+    )
+}
+
+/// Whether the problems of an index haven't been reported since it last changed
+fn should_report(source: &Path) -> bool {
+    let Ok(modified) = fs::metadata(source).and_then(|metadata| metadata.modified()) else {
+        return true;
+    };
+    let mut reported = REPORTED.lock().unwrap_or_else(|e| e.into_inner());
+    reported.insert(source.to_path_buf(), modified) != Some(modified)
+}
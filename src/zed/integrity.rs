@@ -0,0 +1,296 @@
+use anyhow::{Context, Result};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::manifest::is_mirrored_file;
+use super::{ChecksumAlgorithm, QUARANTINE_DIR, write_atomic};
+
+/// Name of the integrity manifest written to every mirrored directory
+pub const INTEGRITY_FILE: &str = "INTEGRITY.json";
+
+/// When the last verification that found no problems started, relative to the extensions
+/// directory
+pub const INTEGRITY_VERIFIED_FILE: &str = "integrity_verified.json";
+
+/// Version of the integrity manifest format
+const INTEGRITY_FORMAT: u32 = 1;
+
+/// Digests of the mirrored files of a single directory, see the README for the format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityManifest {
+    pub format: u32,
+    pub algorithm: ChecksumAlgorithm,
+    /// Digests keyed by file name
+    pub files: BTreeMap<String, IntegrityEntry>,
+}
+
+/// Size and hex-encoded digest of a file in an integrity manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityEntry {
+    pub size: u64,
+    pub digest: String,
+}
+
+impl IntegrityManifest {
+    /// Hash the mirrored files directly inside `dir`
+    pub fn build(dir: &Path, algorithm: ChecksumAlgorithm) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for entry in fs::read_dir(dir)?.flatten() {
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() || !is_mirrored_file(&name) {
+                continue;
+            }
+
+            let digest = algorithm
+                .cached_hash(&entry.path(), &metadata)
+                .with_context(|| format!("Failed to hash {:?}", entry.path()))?;
+            files.insert(
+                name,
+                IntegrityEntry {
+                    size: metadata.len(),
+                    digest,
+                },
+            );
+        }
+
+        Ok(Self {
+            format: INTEGRITY_FORMAT,
+            algorithm,
+            files,
+        })
+    }
+
+    fn load(dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(dir.join(INTEGRITY_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LastVerified {
+    /// Unix timestamp
+    verified_at: u64,
+}
+
+/// Size of a file as recorded in its directory's integrity manifest
+pub fn recorded_size(dir: &Path, name: &str) -> Option<u64> {
+    IntegrityManifest::load(dir)?
+        .files
+        .get(name)
+        .map(|entry| entry.size)
+}
+
+/// Write or refresh the integrity manifest of every mirrored directory below `root`,
+/// returning how many were (re)written
+pub fn write_integrity_manifests(root: &Path, algorithm: ChecksumAlgorithm) -> Result<usize> {
+    let mut written = 0;
+    for dir in mirrored_dirs(root) {
+        let manifest = IntegrityManifest::build(&dir, algorithm)?;
+        if manifest.files.is_empty() || IntegrityManifest::load(&dir).as_ref() == Some(&manifest) {
+            continue;
+        }
+        write_atomic(
+            &dir.join(INTEGRITY_FILE),
+            serde_json::to_string_pretty(&manifest)?,
+        )?;
+        debug!("Wrote integrity manifest of {:?}", dir);
+        written += 1;
+    }
+
+    if written > 0 {
+        info!(
+            "Updated {} {} integrity manifests in {:?}",
+            written,
+            algorithm.name(),
+            root
+        );
+    }
+    Ok(written)
+}
+
+/// Check every directory below `root` against its integrity manifest, returning one
+/// problem per mismatching, missing or unlisted file. Files are hashed on `jobs` threads,
+/// all cores when `None`. With `incremental`, files not modified since the last
+/// verification that found no problems are only checked for their size.
+pub fn verify_integrity_manifests(
+    root: &Path,
+    jobs: Option<usize>,
+    incremental: bool,
+) -> Result<Vec<String>> {
+    let started = SystemTime::now();
+    let since = if incremental {
+        let since = last_verified(root);
+        if since.is_none() {
+            info!(
+                "No earlier verification of {:?} recorded, checking every file",
+                root
+            );
+        }
+        since
+    } else {
+        None
+    };
+
+    let mut problems = Vec::new();
+    let mut pending = Vec::new();
+    let mut unchanged = 0;
+    for dir in mirrored_dirs(root) {
+        let Some(expected) = IntegrityManifest::load(&dir) else {
+            if dir.join(INTEGRITY_FILE).exists() {
+                problems.push(format!("{:?}: unreadable {}", dir, INTEGRITY_FILE));
+            }
+            continue;
+        };
+        if expected.format != INTEGRITY_FORMAT {
+            warn!(
+                "Skipping {:?}, unsupported integrity manifest format {}",
+                dir, expected.format
+            );
+            continue;
+        }
+
+        let present = mirrored_files(&dir);
+        for name in present.keys() {
+            if !expected.files.contains_key(name) {
+                problems.push(format!("{}: not listed", dir.join(name).display()));
+            }
+        }
+        for (name, entry) in expected.files {
+            let path = dir.join(&name);
+            match present.get(&name) {
+                None => problems.push(format!("{}: missing", path.display())),
+                Some(metadata) if metadata.len() != entry.size => problems.push(format!(
+                    "{}: size {} does not match the recorded {}",
+                    path.display(),
+                    metadata.len(),
+                    entry.size
+                )),
+                Some(metadata)
+                    if since.is_some_and(|since| {
+                        metadata.modified().is_ok_and(|modified| modified < since)
+                    }) =>
+                {
+                    unchanged += 1
+                }
+                Some(_) => pending.push((path, expected.algorithm, entry)),
+            }
+        }
+    }
+    if unchanged > 0 {
+        info!(
+            "Skipping {} files unchanged since the last verification",
+            unchanged
+        );
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()?;
+    info!(
+        "Hashing {} files on {} threads",
+        pending.len(),
+        pool.current_num_threads()
+    );
+    let target = if std::io::stdout().is_terminal() {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    };
+    let progress = ProgressBar::with_draw_target(Some(pending.len() as u64), target);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files ({eta})")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    let mismatches: Vec<String> = pool.install(|| {
+        pending
+            .par_iter()
+            .filter_map(|(path, algorithm, entry)| {
+                let problem = match algorithm.hash_file(path) {
+                    Ok(digest) if digest == entry.digest => None,
+                    Ok(digest) => Some(format!(
+                        "{}: {} digest {} does not match the recorded {}",
+                        path.display(),
+                        algorithm.name(),
+                        digest,
+                        entry.digest
+                    )),
+                    Err(e) => Some(format!("{}: unreadable: {:#}", path.display(), e)),
+                };
+                progress.inc(1);
+                problem
+            })
+            .collect()
+    });
+    progress.finish_and_clear();
+    problems.extend(mismatches);
+
+    // Files found corrupt may predate the next run's cutoff, so only a clean run counts
+    if problems.is_empty() {
+        let verified_at = started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        write_atomic(
+            &root.join(INTEGRITY_VERIFIED_FILE),
+            serde_json::to_string_pretty(&LastVerified { verified_at })?,
+        )?;
+    }
+    Ok(problems)
+}
+
+/// Start of the last verification of `root` that found no problems
+fn last_verified(root: &Path) -> Option<SystemTime> {
+    let content = fs::read_to_string(root.join(INTEGRITY_VERIFIED_FILE)).ok()?;
+    let last: LastVerified = serde_json::from_str(&content).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(last.verified_at))
+}
+
+/// Metadata of the mirrored files directly inside `dir`, keyed by file name
+fn mirrored_files(dir: &Path) -> BTreeMap<String, fs::Metadata> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let metadata = entry.metadata().ok()?;
+            (metadata.is_file() && is_mirrored_file(&name)).then_some((name, metadata))
+        })
+        .collect()
+}
+
+/// `root` and every directory below it, leaving out hidden and quarantined ones
+fn mirrored_dirs(root: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf()];
+    let mut index = 0;
+    while index < dirs.len() {
+        let dir = dirs[index].clone();
+        index += 1;
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if name.starts_with('.') || (dir == root && name == QUARANTINE_DIR) {
+                continue;
+            }
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    dirs
+}
@@ -0,0 +1,256 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{ExtensionVersionTracker, QUARANTINE_DIR, load_index};
+
+/// Extension of extension archives
+const ARCHIVE_EXTENSION: &str = "tgz";
+
+/// Extension of release assets, whatever their actual format
+const RELEASE_ASSET_EXTENSION: &str = "tar.gz";
+
+/// File name of an extension archive: `{id}-{version}.tgz`, or `{id}.tgz` for the latest
+/// mirrored version
+pub fn archive_name(id: &str, version: Option<&str>) -> String {
+    match version {
+        Some(version) => format!("{}-{}.{}", id, version, ARCHIVE_EXTENSION),
+        None => format!("{}.{}", id, ARCHIVE_EXTENSION),
+    }
+}
+
+/// Where an extension archive is stored, `{extensions_dir}/{id}/{archive_name}`
+pub fn archive_path(extensions_dir: &Path, id: &str, version: Option<&str>) -> PathBuf {
+    extensions_dir.join(id).join(archive_name(id, version))
+}
+
+/// An extension archive as stored, under its canonical name or one written by older
+/// versions of zedex: `.tar.gz` in the extension's directory, or `{id}.tar.gz` in the
+/// extensions directory for the latest version
+pub fn find_archive(extensions_dir: &Path, id: &str, version: Option<&str>) -> Option<PathBuf> {
+    let canonical = archive_path(extensions_dir, id, version);
+    let legacy = canonical.with_extension(RELEASE_ASSET_EXTENSION);
+    let mut candidates = vec![canonical, legacy];
+    if version.is_none() {
+        candidates.push(extensions_dir.join(format!("{}.{}", id, RELEASE_ASSET_EXTENSION)));
+    }
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+/// Extension id and version of an archive path relative to an extensions directory, the
+/// version being `None` for the latest-version archive. `None` for other files.
+pub fn archive_version(relative: &Path) -> Option<(String, Option<String>)> {
+    let mut components = relative.iter().map(|component| component.to_str());
+    let (dir, file) = match (components.next()??, components.next(), components.next()) {
+        (file, None, None) => (None, file),
+        (dir, Some(file), None) => (Some(dir), file?),
+        _ => return None,
+    };
+    let stem = file
+        .strip_suffix(&format!(".{}", ARCHIVE_EXTENSION))
+        .or_else(|| file.strip_suffix(&format!(".{}", RELEASE_ASSET_EXTENSION)))?;
+    match dir {
+        // Only the latest version was kept at the top of the extensions directory
+        None => Some((stem.to_string(), None)),
+        Some(id) if stem == id => Some((id.to_string(), None)),
+        Some(id) => {
+            let version = stem.strip_prefix(id)?.strip_prefix('-')?;
+            Some((id.to_string(), Some(version.to_string())))
+        }
+    }
+}
+
+/// Version of the `{id}.tgz` archive according to the version tracker
+pub fn tracked_version(extensions_dir: &Path, id: &str) -> Option<String> {
+    let content = fs::read_to_string(extensions_dir.join("version_tracker.json")).ok()?;
+    serde_json::from_str::<ExtensionVersionTracker>(&content)
+        .ok()?
+        .extensions
+        .remove(id)
+}
+
+/// File name of a mirrored release asset, `{asset}-{os}-{arch}.tar.gz`
+pub fn release_asset_name(asset: &str, os: &str, arch: &str) -> String {
+    format!("{}-{}-{}.{}", asset, os, arch, RELEASE_ASSET_EXTENSION)
+}
+
+/// Release file names requested by older Zed builds and install scripts, with the
+/// `{asset}-{os}-{arch}` names they are mirrored under, tried in order. `Zed.dmg` predates
+/// per-architecture disk images; the Intel build runs on either kind of Mac.
+const RELEASE_FILE_ALIASES: [(&str, &[&str]); 3] = [
+    ("Zed.dmg", &["zed-macos-x86_64", "zed-macos-aarch64"]),
+    ("Zed-aarch64.dmg", &["zed-macos-aarch64"]),
+    ("Zed-x86_64.dmg", &["zed-macos-x86_64"]),
+];
+
+/// Other spellings of the operating systems and architectures in release file names
+const PLATFORM_ALIASES: [(&str, &str); 5] = [
+    ("darwin", "macos"),
+    ("mac", "macos"),
+    ("arm64", "aarch64"),
+    ("amd64", "x86_64"),
+    ("x64", "x86_64"),
+];
+
+/// Mirrored files that can serve a release download named `filename`. `zedex release
+/// download` stores assets as `{version}/{asset}-{os}-{arch}.tar.gz` while Zed requests the
+/// remote server as `...-{os}-{arch}.gz`, older mirrors kept remote servers as
+/// `zed-remote-server/zed-remote-server-{version}-{os}-{arch}.gz`, and older clients ask
+/// for names like `Zed.dmg` or `zed-darwin-arm64.tar.gz`.
+pub fn release_file_candidates(releases_dir: &Path, version: &str, filename: &str) -> Vec<PathBuf> {
+    let mut candidates = vec![releases_dir.join(version).join(filename)];
+    for stem in release_stems(version, filename) {
+        candidates.push(
+            releases_dir
+                .join(version)
+                .join(format!("{}.{}", stem, RELEASE_ASSET_EXTENSION)),
+        );
+        candidates.push(releases_dir.join(version).join(format!("{}.gz", stem)));
+        for asset in ["zed-remote-server", "zed"] {
+            if let Some(platform) = stem.strip_prefix(&format!("{asset}-")) {
+                candidates.push(
+                    releases_dir
+                        .join(asset)
+                        .join(format!("{asset}-{version}-{platform}.gz")),
+                );
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    candidates.retain(|path| seen.insert(path.clone()));
+    candidates
+}
+
+/// Names a release file may be mirrored under, without extension: the requested name
+/// itself, then its alias or the name with the version left out and the platform spelled
+/// the way zedex stores it
+fn release_stems(version: &str, filename: &str) -> Vec<String> {
+    if let Some((_, stems)) = RELEASE_FILE_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(filename))
+    {
+        return stems.iter().map(|stem| stem.to_string()).collect();
+    }
+
+    let stem = release_stem(filename);
+    let mut stems = vec![stem.to_string()];
+    let unversioned = stem.replacen(&format!("-{}-", version), "-", 1);
+    for asset in ["zed-remote-server", "zed"] {
+        let Some((os, arch)) = unversioned
+            .strip_prefix(&format!("{asset}-"))
+            .and_then(|platform| platform.split_once('-'))
+        else {
+            continue;
+        };
+        stems.push(format!(
+            "{}-{}-{}",
+            asset,
+            canonical_platform(os),
+            canonical_platform(arch)
+        ));
+        break;
+    }
+    stems
+}
+
+fn canonical_platform(name: &str) -> &str {
+    PLATFORM_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, canonical)| canonical)
+}
+
+/// A release file name without its `.tar.gz`, `.gz` or `.dmg` extension
+fn release_stem(filename: &str) -> &str {
+    filename
+        .strip_suffix(".tar.gz")
+        .or_else(|| filename.strip_suffix(".gz"))
+        .or_else(|| filename.strip_suffix(".dmg"))
+        .unwrap_or(filename)
+}
+
+/// Files stored under names of older versions of zedex, with the canonical path each
+/// belongs at
+pub fn legacy_files(extensions_dir: &Path, releases_dir: Option<&Path>) -> Vec<(PathBuf, PathBuf)> {
+    let indexed: HashSet<String> = load_index(&extensions_dir.join("extensions.json"), false)
+        .map(|index| index.into_iter().map(|extension| extension.id).collect())
+        .unwrap_or_default();
+
+    let mut moves = Vec::new();
+    for entry in read_dir(extensions_dir) {
+        let path = entry.path();
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if path.is_file() {
+            // Old structure: the latest archive at the top of the extensions directory
+            if let Some(id) = name.strip_suffix(".tar.gz")
+                && indexed.contains(id)
+            {
+                moves.push((path, archive_path(extensions_dir, id, None)));
+            }
+        } else if path.is_dir()
+            && name != QUARANTINE_DIR
+            && !name.starts_with('.')
+            && Some(path.as_path()) != releases_dir
+        {
+            for file in read_dir(&path) {
+                let Some(file_name) = file.file_name().to_str().map(str::to_string) else {
+                    continue;
+                };
+                if let Some(stem) = file_name.strip_suffix(".tar.gz")
+                    && (stem == name || stem.starts_with(&format!("{}-", name)))
+                {
+                    moves.push((
+                        file.path(),
+                        path.join(format!("{}.{}", stem, ARCHIVE_EXTENSION)),
+                    ));
+                }
+            }
+        }
+    }
+
+    let Some(releases_dir) = releases_dir else {
+        return moves;
+    };
+    for entry in read_dir(releases_dir) {
+        let path = entry.path();
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if !path.is_dir() {
+            continue;
+        }
+        for file in read_dir(&path) {
+            let Some(file_name) = file.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if file_name.ends_with(".tar.gz") || !file_name.ends_with(".gz") {
+                continue;
+            }
+            let stem = release_stem(&file_name);
+            let target = if ["zed-remote-server", "zed"].contains(&name.as_str()) {
+                // `{asset}/{asset}-{version}-{os}-{arch}.gz`, the version being what
+                // precedes the platform
+                let Some(rest) = stem.strip_prefix(&format!("{}-", name)) else {
+                    continue;
+                };
+                let parts: Vec<&str> = rest.rsplitn(3, '-').collect();
+                let [arch, os, version] = parts[..] else {
+                    continue;
+                };
+                releases_dir
+                    .join(version)
+                    .join(release_asset_name(&name, os, arch))
+            } else {
+                path.join(format!("{}.{}", stem, RELEASE_ASSET_EXTENSION))
+            };
+            moves.push((file.path(), target));
+        }
+    }
+    moves
+}
+
+fn read_dir(dir: &Path) -> impl Iterator<Item = fs::DirEntry> {
+    fs::read_dir(dir).into_iter().flatten().flatten()
+}
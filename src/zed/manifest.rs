@@ -0,0 +1,111 @@
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use super::{
+    AUDIT_LOG_FILE, INDEX_REVISIONS_FILE, INTEGRITY_FILE, INTEGRITY_VERIFIED_FILE, QUARANTINE_DIR,
+    RETRY_QUEUE_FILE, SHA256SUMS_FILE, TRANSFER_STATS_FILE, VERIFIED_CHECKSUMS_FILE, cached_sha256,
+    sha256_bytes,
+};
+
+/// Local state files that are never replicated
+const LOCAL_STATE_FILES: [&str; 6] = [
+    INDEX_REVISIONS_FILE,
+    INTEGRITY_VERIFIED_FILE,
+    TRANSFER_STATS_FILE,
+    RETRY_QUEUE_FILE,
+    VERIFIED_CHECKSUMS_FILE,
+    AUDIT_LOG_FILE,
+];
+
+/// File extensions of mirrored content listed in the manifest
+const MANIFEST_EXTENSIONS: [&str; 8] = ["tgz", "gz", "json", "asc", "minisig", "md", "png", "svg"];
+
+/// A mirrored file in the checksum manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Checksums of every mirrored file in an extensions directory, keyed by `/`-separated
+/// path relative to it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChecksumManifest {
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+impl ChecksumManifest {
+    /// Walk an extensions directory and hash its mirrored files. Quarantined archives,
+    /// hidden files and runtime state are left out.
+    pub fn build(extensions_dir: &Path) -> Result<Self> {
+        let mut manifest = Self::default();
+        manifest.add_dir(extensions_dir, "")?;
+        Ok(manifest)
+    }
+
+    fn add_dir(&mut self, dir: &Path, prefix: &str) -> Result<()> {
+        for entry in fs::read_dir(dir)?.flatten() {
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if name.starts_with('.') || (prefix.is_empty() && name == QUARANTINE_DIR) {
+                continue;
+            }
+
+            let relative = format!("{}{}", prefix, name);
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+
+            if metadata.is_dir() {
+                self.add_dir(&path, &format!("{}/", relative))?;
+                continue;
+            }
+
+            if !is_mirrored_file(&name) || LOCAL_STATE_FILES.contains(&relative.as_str()) {
+                continue;
+            }
+
+            match cached_sha256(&path, &metadata) {
+                Ok(sha256) => {
+                    self.files.insert(
+                        relative,
+                        ManifestEntry {
+                            size: metadata.len(),
+                            sha256,
+                        },
+                    );
+                }
+                Err(e) => warn!("Failed to hash {:?}, leaving it out: {}", path, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Canonical serialization that entity tags and signatures are computed over
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    /// Entity tag identifying this exact set of files and checksums
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", sha256_bytes(&self.canonical_bytes()))
+    }
+}
+
+/// Whether a file holds mirrored content rather than state local to this mirror. Integrity
+/// manifests are local too, every mirror writes its own with its configured algorithm.
+pub(super) fn is_mirrored_file(name: &str) -> bool {
+    let mirrored = name == SHA256SUMS_FILE
+        || Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| MANIFEST_EXTENSIONS.contains(&ext));
+    mirrored && name != INTEGRITY_FILE && !LOCAL_STATE_FILES.contains(&name)
+}
@@ -1,19 +1,90 @@
+mod archive;
+mod assets;
+mod budget;
+mod bundle;
+mod checksum;
 mod client;
+mod client_config;
+mod compat;
+mod discovery;
 mod downloader;
 mod error;
 mod extension;
 mod health;
+mod history;
+mod index;
+mod integrity;
+mod layout;
+mod manifest;
+mod progress;
+mod quarantine;
+mod quota;
+mod request_id;
+mod revisions;
+mod scan;
 mod server;
+mod signing;
+mod snapshots;
+mod storage;
+mod transfer;
+mod upstream;
+mod urls;
 mod version;
+mod webhook;
 
+pub use archive::{check_archive, declared_dependencies, inspect_archive};
+pub use assets::{ExtensionAssets, README_FILE, icon_content_type, mirror_extension_assets};
+pub use budget::{RequestBudget, log_request_usage, request_budget_spent, set_request_budget};
+pub use bundle::{BundleCompression, open_bundle, pack_bundle};
+pub use checksum::{ChecksumAlgorithm, cached_sha256, sha256_bytes, sha256_file};
 pub use client::Client;
+pub use client_config::{ClientConfig, SERVER_URL_ENV};
+pub use compat::ExtensionCompatibility;
+pub use discovery::{announce, discover};
 pub use downloader::{
-    DownloadOptions, download_extension_by_id, download_extension_index, download_extensions,
-    download_zed_release,
+    DownloadFailure, DownloadOptions, RETRY_QUEUE_FILE, current_platform, download_extension_by_id,
+    download_extension_index, download_extension_version_by_id, download_extensions,
+    download_zed_release, release_assets,
 };
-pub use error::ZedError;
 pub use extension::extensions_utils;
 pub use extension::{Extension, ExtensionVersionTracker, Extensions, WrappedExtensions};
-pub use health::health_check;
-pub use server::{LocalServer, ServerConfig};
-pub use version::Version;
+pub use history::{SyncRecord, append_sync_record, load_sync_history};
+pub use index::{load_index, parse_index};
+pub use integrity::{
+    INTEGRITY_FILE, INTEGRITY_VERIFIED_FILE, recorded_size, verify_integrity_manifests,
+    write_integrity_manifests,
+};
+pub use layout::{
+    archive_name, archive_path, archive_version, find_archive, legacy_files, release_asset_name,
+    tracked_version,
+};
+pub use manifest::{ChecksumManifest, ManifestEntry};
+pub use progress::SyncProgress;
+pub use quarantine::{QUARANTINE_DIR, QuarantineRegistry};
+pub use quota::{CacheQuota, EvictionPolicy, parse_size};
+pub use request_id::{REQUEST_ID_HEADER, current_request_id, in_request, request_id_or_new};
+pub use revisions::{INDEX_REVISIONS_FILE, IndexHistory};
+pub use scan::ArchiveScanner;
+pub use server::{
+    AUDIT_LOG_FILE, CorsPolicy, IndexChannel, LocalServer, ProxyCache, ProxyRules, ServerConfig,
+    ServerTimeouts, TlsConfig, UpstreamLimit, UpstreamSchema, VERIFIED_CHECKSUMS_FILE,
+    VirtualHosts,
+};
+pub use signing::{
+    SHA256SUMS_FILE, SignatureTool, SignatureVerifier, Signer, sign_metadata,
+    write_release_checksums,
+};
+pub use snapshots::{list_index_snapshots, rollback_index, snapshot_index};
+pub use storage::{
+    disk_full, is_disk_full, partial_path, write_atomic, write_error, write_partial,
+};
+pub use transfer::{
+    ServedVersion, TRANSFER_STATS_FILE, TransferCounters, TransferStats, format_bytes,
+};
+pub use upstream::{
+    RequestOptions, SshTunnel, TunnelGuard, UpstreamCredential, set_request_options,
+    set_upstream_credentials, set_upstream_proxy,
+};
+pub use urls::{SignedUrls, signed_urls, strip_signature};
+pub use version::{Version, compare_versions, parse_version};
+pub use webhook::{Webhook, WebhookEvent, WebhookFormat, Webhooks};
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use log::error;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::write_atomic;
+
+/// Minimum time between two writes of the status file for items being started or bytes
+/// being counted; finished items and phases are always written
+const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Progress of a long-running `get all-extensions` or `sync`, as written to a status file
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressStatus {
+    /// Command being run, e.g. `sync`
+    pub operation: String,
+    /// `running`, `finished` or `failed`
+    pub state: String,
+    /// Current pass over the items, e.g. `download` or `retry 1/3`
+    pub phase: String,
+    /// Unix time the run started
+    pub started_at: i64,
+    /// Unix time of the last update
+    pub updated_at: i64,
+    /// Items of the current phase
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    /// Items completed in all phases of the run
+    pub completed_total: usize,
+    /// Bytes transferred since the run started
+    pub bytes: u64,
+    /// Items being worked on right now
+    pub current: BTreeSet<String>,
+    /// Estimated seconds until the current phase is done
+    pub eta_secs: Option<u64>,
+    /// Why the run failed
+    pub error: Option<String>,
+}
+
+/// Progress of a run, shared by its download tasks and mirrored to a status file if one
+/// was requested
+pub struct SyncProgress {
+    path: Option<PathBuf>,
+    status: Mutex<(ProgressStatus, Option<Instant>)>,
+    phase_started: Mutex<Instant>,
+}
+
+impl SyncProgress {
+    /// Track the progress of `operation`, writing it to the status file at `path` if given
+    pub fn new(path: Option<&Path>, operation: &str) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        let progress = Self {
+            path: path.map(Path::to_path_buf),
+            status: Mutex::new((
+                ProgressStatus {
+                    operation: operation.to_string(),
+                    state: "running".to_string(),
+                    phase: "starting".to_string(),
+                    started_at: now,
+                    updated_at: now,
+                    total: 0,
+                    completed: 0,
+                    failed: 0,
+                    completed_total: 0,
+                    bytes: 0,
+                    current: BTreeSet::new(),
+                    eta_secs: None,
+                    error: None,
+                },
+                None,
+            )),
+            phase_started: Mutex::new(Instant::now()),
+        };
+        progress.update(true, |_| {});
+        progress
+    }
+
+    /// Start a pass over `total` items, resetting the item counters
+    pub fn start_phase(&self, phase: &str, total: usize) {
+        *self.phase_started.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+        self.update(true, |status| {
+            status.phase = phase.to_string();
+            status.total = total;
+            status.completed = 0;
+            status.failed = 0;
+            status.current.clear();
+        });
+    }
+
+    /// Mark an item as being worked on
+    pub fn start_item(&self, item: &str) {
+        self.update(false, |status| {
+            status.current.insert(item.to_string());
+        });
+    }
+
+    /// Mark an item as done
+    pub fn finish_item(&self, item: &str, succeeded: bool) {
+        self.update(true, |status| {
+            status.current.remove(item);
+            if succeeded {
+                status.completed += 1;
+                status.completed_total += 1;
+            } else {
+                status.failed += 1;
+            }
+        });
+    }
+
+    /// Count transferred bytes
+    pub fn add_bytes(&self, bytes: u64) {
+        self.update(false, |status| status.bytes += bytes);
+    }
+
+    /// Record the outcome of the run
+    pub fn finish(&self, result: &Result<()>) {
+        self.update(true, |status| {
+            status.current.clear();
+            status.eta_secs = None;
+            match result {
+                Ok(()) => status.state = "finished".to_string(),
+                Err(e) => {
+                    status.state = "failed".to_string();
+                    status.error = Some(format!("{:#}", e));
+                }
+            }
+        });
+    }
+
+    /// The progress as it stands
+    pub fn status(&self) -> ProgressStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+            .clone()
+    }
+
+    fn update(&self, force: bool, change: impl FnOnce(&mut ProgressStatus)) {
+        let elapsed = self
+            .phase_started
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .elapsed();
+        let mut guard = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        let (status, last_write) = &mut *guard;
+        change(status);
+        status.updated_at = chrono::Utc::now().timestamp();
+
+        let done = status.completed + status.failed;
+        status.eta_secs = (done > 0 && status.state == "running").then(|| {
+            let remaining = status.total.saturating_sub(done) as f64;
+            (elapsed.as_secs_f64() / done as f64 * remaining).round() as u64
+        });
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        if !force && last_write.is_some_and(|at| at.elapsed() < WRITE_INTERVAL) {
+            return;
+        }
+        *last_write = Some(Instant::now());
+        match serde_json::to_string_pretty(status) {
+            Ok(json) => {
+                if let Err(e) = write_atomic(path, json) {
+                    error!("Failed to write status file {:?}: {:#}", path, e);
+                }
+            }
+            Err(e) => error!("Failed to serialize progress status: {}", e),
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{Context, Result, anyhow, bail};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{archive_name, tracked_version, write_atomic};
+
+/// Name of the directory, relative to the extensions directory, holding quarantined archives
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// Name of the metadata file inside the quarantine directory
+const QUARANTINE_FILE: &str = "quarantine.json";
+
+/// Serializes read-modify-write cycles of the metadata file within this process
+static REGISTRY_LOCK: Mutex<()> = Mutex::new(());
+
+/// Whether an artifact may be served
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactState {
+    Active,
+    Quarantined,
+}
+
+/// Quarantine metadata for a single extension version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub state: ArtifactState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Unix timestamp of the last state change
+    pub updated_at: u64,
+    /// Archive file names moved into the quarantine directory
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+/// Quarantine states of the extension versions in an extensions directory, keyed by `id@version`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuarantineRegistry {
+    #[serde(skip)]
+    extensions_dir: PathBuf,
+    pub artifacts: BTreeMap<String, QuarantineEntry>,
+}
+
+impl QuarantineRegistry {
+    /// The quarantine metadata file of an extensions directory
+    pub fn metadata_file(extensions_dir: &Path) -> PathBuf {
+        extensions_dir.join(QUARANTINE_DIR).join(QUARANTINE_FILE)
+    }
+
+    /// Load the quarantine metadata of an extensions directory, empty if none was written yet
+    pub fn load(extensions_dir: &Path) -> Result<Self> {
+        let path = Self::metadata_file(extensions_dir);
+        let mut registry = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read quarantine metadata {:?}", path))?;
+            serde_json::from_str::<Self>(&content)
+                .with_context(|| format!("Failed to parse quarantine metadata {:?}", path))?
+        } else {
+            Self::default()
+        };
+        registry.extensions_dir = extensions_dir.to_path_buf();
+        Ok(registry)
+    }
+
+    /// Load the metadata, apply a change and write it back while holding the registry lock
+    pub fn update<T>(extensions_dir: &Path, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let _guard = REGISTRY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut registry = Self::load(extensions_dir)?;
+        let result = f(&mut registry)?;
+        registry.save()?;
+        Ok(result)
+    }
+
+    fn save(&self) -> Result<()> {
+        let dir = self.extensions_dir.join(QUARANTINE_DIR);
+        fs::create_dir_all(&dir)?;
+        write_atomic(
+            &dir.join(QUARANTINE_FILE),
+            serde_json::to_string_pretty(self)?,
+        )
+    }
+
+    /// Check whether a specific extension version is quarantined
+    pub fn is_quarantined(&self, id: &str, version: &str) -> bool {
+        self.artifacts
+            .get(&artifact_key(id, version))
+            .is_some_and(|entry| entry.state == ArtifactState::Quarantined)
+    }
+
+    /// Check whether any version of an extension is quarantined
+    pub fn has_quarantined(&self, id: &str) -> bool {
+        self.quarantined().any(|(key, _)| {
+            key.rsplit_once('@')
+                .is_some_and(|(entry_id, _)| entry_id == id)
+        })
+    }
+
+    /// Check whether an archive file of an extension belongs to a quarantined version
+    pub fn covers_archive(&self, id: &str, file_name: &str) -> bool {
+        self.quarantined().any(|(key, entry)| {
+            key.rsplit_once('@').is_some_and(|(entry_id, version)| {
+                entry_id == id
+                    && (entry.files.iter().any(|file| file == file_name)
+                        || file_name == archive_name(id, Some(version)))
+            })
+        })
+    }
+
+    /// Quarantined artifacts, keyed by `id@version`
+    pub fn quarantined(&self) -> impl Iterator<Item = (&String, &QuarantineEntry)> {
+        self.artifacts
+            .iter()
+            .filter(|(_, entry)| entry.state == ArtifactState::Quarantined)
+    }
+
+    /// Pull an extension version out of serving, moving its archives into the quarantine
+    /// directory. Versions without local archives are still recorded so they aren't proxied.
+    pub fn quarantine(
+        &mut self,
+        id: &str,
+        version: &str,
+        reason: Option<String>,
+    ) -> Result<Vec<PathBuf>> {
+        validate_id(id)?;
+        let ext_dir = self.extensions_dir.join(id);
+
+        let mut archives = vec![ext_dir.join(archive_name(id, Some(version)))];
+        if tracked_version(&self.extensions_dir, id).as_deref() == Some(version) {
+            archives.push(ext_dir.join(archive_name(id, None)));
+        }
+
+        let mut moved = Vec::new();
+        for archive in archives.into_iter().filter(|path| path.exists()) {
+            moved.push(self.quarantine_archive(&archive, id, version, reason.clone())?);
+        }
+
+        if moved.is_empty() {
+            self.mark(id, version, ArtifactState::Quarantined, reason);
+        }
+        Ok(moved)
+    }
+
+    /// Move a single archive into the quarantine directory and record it under the given version
+    pub fn quarantine_archive(
+        &mut self,
+        archive: &Path,
+        id: &str,
+        version: &str,
+        reason: Option<String>,
+    ) -> Result<PathBuf> {
+        self.quarantine_staged(archive, archive, id, version, reason)
+    }
+
+    /// Move a file into the quarantine directory under the name of the archive it would be
+    /// served as, e.g. a download's `.part` file that was never put in place
+    pub fn quarantine_staged(
+        &mut self,
+        staged: &Path,
+        archive: &Path,
+        id: &str,
+        version: &str,
+        reason: Option<String>,
+    ) -> Result<PathBuf> {
+        validate_id(id)?;
+        let file_name = archive
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Archive path {:?} has no file name", archive))?
+            .to_string();
+
+        let target_dir = self.extensions_dir.join(QUARANTINE_DIR).join(id);
+        fs::create_dir_all(&target_dir)?;
+        let target = target_dir.join(&file_name);
+        fs::rename(staged, &target)?;
+        debug!("Moved {:?} to {:?}", staged, target);
+
+        let entry = self.mark(id, version, ArtifactState::Quarantined, reason);
+        if !entry.files.contains(&file_name) {
+            entry.files.push(file_name);
+        }
+        Ok(target)
+    }
+
+    /// Restore a quarantined extension version, moving its archives back into serving
+    pub fn release(&mut self, id: &str, version: &str) -> Result<Vec<PathBuf>> {
+        validate_id(id)?;
+        let key = artifact_key(id, version);
+        let entry = match self.artifacts.get(&key) {
+            Some(entry) if entry.state == ArtifactState::Quarantined => entry.clone(),
+            _ => bail!("{} is not quarantined", key),
+        };
+
+        let source_dir = self.extensions_dir.join(QUARANTINE_DIR).join(id);
+        let target_dir = self.extensions_dir.join(id);
+        fs::create_dir_all(&target_dir)?;
+
+        // Check every archive up front so a release never leaves the entry half restored
+        for file_name in &entry.files {
+            let source = source_dir.join(file_name);
+            let target = target_dir.join(file_name);
+            if !source.exists() {
+                bail!("Quarantined archive {:?} is missing", source);
+            }
+            if target.exists() {
+                bail!(
+                    "Cannot restore {:?}: {:?} already exists",
+                    file_name,
+                    target
+                );
+            }
+        }
+
+        let mut restored = Vec::new();
+        for file_name in &entry.files {
+            let source = source_dir.join(file_name);
+            let target = target_dir.join(file_name);
+            fs::rename(&source, &target)?;
+            info!("Restored {:?} to {:?}", source, target);
+            restored.push(target);
+        }
+
+        let entry = self.mark(id, version, ArtifactState::Active, None);
+        entry.files.clear();
+        Ok(restored)
+    }
+
+    fn mark(
+        &mut self,
+        id: &str,
+        version: &str,
+        state: ArtifactState,
+        reason: Option<String>,
+    ) -> &mut QuarantineEntry {
+        let entry = self
+            .artifacts
+            .entry(artifact_key(id, version))
+            .or_insert_with(|| QuarantineEntry {
+                state,
+                reason: None,
+                updated_at: now(),
+                files: Vec::new(),
+            });
+        entry.state = state;
+        entry.reason = reason;
+        entry.updated_at = now();
+        entry
+    }
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+fn artifact_key(id: &str, version: &str) -> String {
+    format!("{}@{}", id, version)
+}
+
+/// Reject ids that would escape the extensions directory
+fn validate_id(id: &str) -> Result<()> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        bail!("Invalid extension id '{}'", id);
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use anyhow::{Result, bail};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use super::{
+    ExtensionVersionTracker, QUARANTINE_DIR, TRANSFER_STATS_FILE, TransferCounters, format_bytes,
+};
+
+/// What to do when a download would push the cache over its size limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvictionPolicy {
+    /// Stop the sync and report how far over the limit it would go
+    #[default]
+    Stop,
+    /// Delete the versioned archives that were served least recently to make room
+    LeastRecentlyServed,
+}
+
+/// Size limit for an extensions directory, shared by every download of a sync
+#[derive(Clone)]
+pub struct CacheQuota {
+    max_bytes: u64,
+    policy: EvictionPolicy,
+    state: Arc<Mutex<QuotaState>>,
+}
+
+#[derive(Default)]
+struct QuotaState {
+    /// Bytes used by the cache, measured on first use and kept up to date afterwards
+    used: Option<u64>,
+    /// Archives written during this run, which are never evicted
+    written: HashSet<PathBuf>,
+    evicted: usize,
+    evicted_bytes: u64,
+    /// Report of the download that could not fit, once the sync has to stop
+    exceeded: Option<String>,
+}
+
+/// A versioned archive that may be evicted
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    last_served: u64,
+    modified: u64,
+}
+
+impl CacheQuota {
+    pub fn new(max_bytes: u64, policy: EvictionPolicy) -> Self {
+        Self {
+            max_bytes,
+            policy,
+            state: Arc::new(Mutex::new(QuotaState::default())),
+        }
+    }
+
+    /// A quota with the same limit and policy that measures the cache anew and has
+    /// written nothing yet, for downloads that aren't part of one run, like the server's
+    /// pull-through. Downloads finishing at the same moment may overshoot the limit by
+    /// each other's archives.
+    pub fn fresh(&self) -> Self {
+        Self::new(self.max_bytes, self.policy)
+    }
+
+    /// Make room for a file of `size` bytes about to be written to `target` in the cache,
+    /// evicting archives if the policy allows. Fails once the limit cannot be kept.
+    pub fn reserve(&self, cache_dir: &Path, target: &Path, size: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(report) = &state.exceeded {
+            bail!("{}", report);
+        }
+
+        let mut used = match state.used {
+            Some(used) => used,
+            None => dir_size(cache_dir),
+        };
+
+        if used + size > self.max_bytes && self.policy == EvictionPolicy::LeastRecentlyServed {
+            for candidate in eviction_candidates(cache_dir, &state.written) {
+                if used + size <= self.max_bytes {
+                    break;
+                }
+                match fs::remove_file(&candidate.path) {
+                    Ok(()) => {
+                        info!(
+                            "Evicted {:?} ({}) to stay within the cache quota",
+                            candidate.path,
+                            format_bytes(candidate.size)
+                        );
+                        used = used.saturating_sub(candidate.size);
+                        state.evicted += 1;
+                        state.evicted_bytes += candidate.size;
+                    }
+                    Err(e) => warn!("Failed to evict {:?}: {}", candidate.path, e),
+                }
+            }
+        }
+
+        if used + size > self.max_bytes {
+            let report = format!(
+                "Cache quota of {} reached: {} used, {} more needed for {:?}. Raise --max-cache-size{}",
+                format_bytes(self.max_bytes),
+                format_bytes(used),
+                format_bytes(size),
+                target,
+                match self.policy {
+                    EvictionPolicy::Stop =>
+                        " or set eviction = \"least-recently-served\" under [cache]",
+                    EvictionPolicy::LeastRecentlyServed => ", nothing else can be evicted",
+                }
+            );
+            state.used = Some(used);
+            state.exceeded = Some(report.clone());
+            bail!("{}", report);
+        }
+
+        state.used = Some(used + size);
+        state.written.insert(target.to_path_buf());
+        Ok(())
+    }
+
+    /// Report of the download that stopped the sync, if the quota was exceeded
+    pub fn exceeded(&self) -> Option<String> {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .exceeded
+            .clone()
+    }
+
+    /// Log how much was evicted during the run
+    pub fn log_summary(&self) {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.evicted > 0 {
+            info!(
+                "Evicted {} archives ({}) to stay within the cache quota of {}",
+                state.evicted,
+                format_bytes(state.evicted_bytes),
+                format_bytes(self.max_bytes)
+            );
+        }
+    }
+}
+
+/// Parse a size such as `200GB`, `512 MiB` or a plain byte count
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = match number.parse() {
+        Ok(number) => number,
+        Err(_) => bail!("Invalid size '{}'", size),
+    };
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => bail!(
+            "Invalid size unit in '{}', expected B, KB, MB, GB or TB",
+            size
+        ),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Total size of the regular files below a directory, without downloads in progress,
+/// whose size is reserved once they finish
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(_) if entry.path().extension().is_some_and(|ext| ext == "part") => 0,
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Versioned archives that may be evicted, least recently served first. The latest
+/// archive of each extension and the version it holds are never evicted.
+fn eviction_candidates(cache_dir: &Path, written: &HashSet<PathBuf>) -> Vec<Candidate> {
+    let last_served = TransferCounters::load(&cache_dir.join(TRANSFER_STATS_FILE))
+        .map(|counters| counters.last_served)
+        .unwrap_or_default();
+    let latest = fs::read_to_string(cache_dir.join("version_tracker.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<ExtensionVersionTracker>(&content).ok())
+        .map(|tracker| tracker.extensions)
+        .unwrap_or_default();
+
+    let mut candidates = Vec::new();
+    for entry in fs::read_dir(cache_dir).into_iter().flatten().flatten() {
+        let Some(id) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if id.starts_with('.') || id == QUARANTINE_DIR || id == "releases" {
+            continue;
+        }
+
+        for file in fs::read_dir(entry.path()).into_iter().flatten().flatten() {
+            let path = file.path();
+            let Some(version) = file
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix(&format!("{}-", id)))
+                .and_then(|name| name.strip_suffix(".tgz"))
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            if latest.get(&id) == Some(&version) || written.contains(&path) {
+                continue;
+            }
+            let Ok(metadata) = file.metadata() else {
+                continue;
+            };
+
+            candidates.push(Candidate {
+                size: metadata.len(),
+                last_served: last_served
+                    .get(&format!("{}@{}", id, version))
+                    .copied()
+                    .unwrap_or_default(),
+                modified: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|age| age.as_secs())
+                    .unwrap_or_default(),
+                path,
+            });
+        }
+    }
+
+    candidates.sort_by_key(|candidate| (candidate.last_served, candidate.modified));
+    candidates
+}
@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::task::futures::TaskLocalFuture;
+
+/// Header carrying the ID of a request, taken from clients, answered with and passed on to
+/// upstream
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request ID taken from a client, longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Requests given an ID so far, so IDs made in the same nanosecond still differ
+static ISSUED: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// ID of the request the current task is serving, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Run `f` and then `future` as part of the request `id`, so their log lines and upstream
+/// requests carry it
+pub fn in_request<F: Future>(id: String, f: impl FnOnce() -> F) -> TaskLocalFuture<String, F> {
+    let future = REQUEST_ID.sync_scope(id.clone(), f);
+    REQUEST_ID.scope(id, future)
+}
+
+/// The request ID a client sent if it is fit for logs and headers, otherwise a new one
+pub fn request_id_or_new(incoming: Option<&str>) -> String {
+    let usable = |id: &&str| {
+        !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+    };
+    match incoming.filter(usable) {
+        Some(id) => id.to_string(),
+        None => {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            let seed = format!(
+                "{}:{}:{}",
+                std::process::id(),
+                nanos,
+                ISSUED.fetch_add(1, Ordering::Relaxed)
+            );
+            blake3::hash(seed.as_bytes()).to_hex()[..16].to_string()
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{Extension, Extensions, sha256_bytes, write_atomic};
+
+/// Name of the file, relative to the extensions directory, holding index revisions
+pub const INDEX_REVISIONS_FILE: &str = "index_revisions.json";
+
+/// Number of past revisions whose entity tags are still accepted as a `since` marker
+const MAX_REVISIONS: usize = 200;
+
+/// When each entry of the served index last changed, and the revisions it went through
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexRevisions {
+    /// Entity tags of past revisions with the Unix time they were first served
+    pub revisions: Vec<(String, u64)>,
+    /// Checksum and Unix time of the last change of each entry, keyed by extension id
+    pub entries: BTreeMap<String, (String, u64)>,
+    /// Unix time each extension was removed from the index, keyed by extension id
+    pub removed: BTreeMap<String, u64>,
+}
+
+/// Changes to the index since a client's marker
+pub struct IndexChanges {
+    /// Entity tag of the current revision
+    pub revision: String,
+    /// Whether the marker was unknown and `changed` holds the whole index
+    pub full: bool,
+    pub changed: Extensions,
+    pub removed: Vec<String>,
+}
+
+/// Index revisions of a served extensions directory, updated as the index is served
+pub struct IndexHistory {
+    path: PathBuf,
+    revisions: Mutex<IndexRevisions>,
+}
+
+impl IndexHistory {
+    /// Create a history backed by the revisions file in the given extensions directory
+    pub fn new(extensions_dir: &Path) -> Self {
+        let path = extensions_dir.join(INDEX_REVISIONS_FILE);
+        let revisions = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            revisions: Mutex::new(revisions),
+        }
+    }
+
+    /// Record the index as it is served now, returning the entity tag of its revision
+    pub fn observe(&self, extensions: &[Extension]) -> String {
+        let revision = revision_tag(extensions);
+        let now = chrono::Utc::now().timestamp() as u64;
+        let mut revisions = self.revisions.lock().unwrap_or_else(|e| e.into_inner());
+        if revisions
+            .revisions
+            .last()
+            .is_some_and(|(tag, _)| *tag == revision)
+        {
+            return revision;
+        }
+
+        let mut current = BTreeMap::new();
+        for extension in extensions {
+            let checksum = entry_checksum(extension);
+            let changed_at = match revisions.entries.get(&extension.id) {
+                Some((known, changed_at)) if *known == checksum => *changed_at,
+                _ => now,
+            };
+            current.insert(extension.id.clone(), (checksum, changed_at));
+        }
+        let previous = std::mem::replace(&mut revisions.entries, current);
+        for id in previous.into_keys() {
+            if !revisions.entries.contains_key(&id) {
+                revisions.removed.insert(id, now);
+            }
+        }
+        let IndexRevisions {
+            entries, removed, ..
+        } = &mut *revisions;
+        removed.retain(|id, _| !entries.contains_key(id));
+
+        revisions.revisions.push((revision.clone(), now));
+        let excess = revisions.revisions.len().saturating_sub(MAX_REVISIONS);
+        revisions.revisions.drain(..excess);
+
+        match serde_json::to_string_pretty(&*revisions) {
+            Ok(json) => {
+                if let Err(e) = write_atomic(&self.path, json) {
+                    error!(
+                        "Failed to persist index revisions to {:?}: {}",
+                        self.path, e
+                    );
+                }
+            }
+            Err(e) => error!("Failed to serialize index revisions: {}", e),
+        }
+        revision
+    }
+
+    /// Entries of the served index changed since `since`, a Unix timestamp or the entity
+    /// tag of a past revision
+    pub fn changes_since(&self, extensions: Extensions, since: Option<&str>) -> IndexChanges {
+        let revision = self.observe(&extensions);
+        let revisions = self.revisions.lock().unwrap_or_else(|e| e.into_inner());
+
+        let since = since.map(|since| since.trim().trim_matches('"'));
+        let marker = match since {
+            Some(since) if since == revision.trim_matches('"') => {
+                return IndexChanges {
+                    revision,
+                    full: false,
+                    changed: Vec::new(),
+                    removed: Vec::new(),
+                };
+            }
+            Some(since) => since.parse::<u64>().ok().or_else(|| {
+                revisions
+                    .revisions
+                    .iter()
+                    .find(|(tag, _)| tag.trim_matches('"') == since)
+                    .map(|(_, timestamp)| *timestamp)
+            }),
+            None => None,
+        };
+
+        let Some(marker) = marker else {
+            return IndexChanges {
+                revision,
+                full: true,
+                changed: extensions,
+                removed: Vec::new(),
+            };
+        };
+
+        // Changes within the marker's second are included, clients may see them twice
+        let changed = extensions
+            .into_iter()
+            .filter(|extension| {
+                revisions
+                    .entries
+                    .get(&extension.id)
+                    .is_none_or(|(_, changed_at)| *changed_at >= marker)
+            })
+            .collect();
+        let removed = revisions
+            .removed
+            .iter()
+            .filter(|(_, removed_at)| **removed_at >= marker)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        IndexChanges {
+            revision,
+            full: false,
+            changed,
+            removed,
+        }
+    }
+}
+
+/// Entity tag identifying the exact content of a served index
+fn revision_tag(extensions: &[Extension]) -> String {
+    let json = serde_json::to_vec(extensions).unwrap_or_default();
+    format!("\"{}\"", &sha256_bytes(&json)[..16])
+}
+
+fn entry_checksum(extension: &Extension) -> String {
+    sha256_bytes(&serde_json::to_vec(extension).unwrap_or_default())
+}
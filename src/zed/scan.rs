@@ -0,0 +1,125 @@
+use anyhow::{Context, Result, anyhow, bail};
+use log::{debug, error, info, warn};
+use std::env;
+use std::fs;
+use std::path::Path;
+use tokio::process::Command;
+
+use super::QuarantineRegistry;
+
+/// Placeholder replaced with the archive path in the scan command
+const PATH_PLACEHOLDER: &str = "{path}";
+
+/// Runs an external scan command (e.g. `clamscan {path}`) against downloaded archives
+/// before they are served, and moves archives that fail the scan into the quarantine
+/// directory.
+#[derive(Debug, Clone)]
+pub struct ArchiveScanner {
+    command: String,
+}
+
+impl ArchiveScanner {
+    /// A scanner running `command`, whose program must exist so a missing scanner is
+    /// noticed at startup rather than on the first download
+    pub fn new(command: impl Into<String>) -> Result<Self> {
+        let command = command.into();
+        let program = command
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("Scan command is empty"))?;
+        if !program_exists(program) {
+            bail!("Scan command '{}' not found", program);
+        }
+        Ok(Self { command })
+    }
+
+    /// The scan command as configured
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Scan a downloaded archive waiting at `staged` before it is moved to `archive` and
+    /// served. Archives failing the scan, or that couldn't be scanned at all, are moved to
+    /// the quarantine directory under `archive`'s name. Returns `true` when the archive is
+    /// clean and may be put in place.
+    pub async fn scan(
+        &self,
+        staged: &Path,
+        archive: &Path,
+        id: &str,
+        version: &str,
+    ) -> Result<bool> {
+        let reason = match self.run(staged).await {
+            Ok(None) => {
+                debug!("Scan passed for {:?}", archive);
+                return Ok(true);
+            }
+            Ok(Some(reason)) => reason,
+            Err(e) => {
+                error!("Could not scan {:?}: {:#}", archive, e);
+                format!("Scan could not run: {:#}", e)
+            }
+        };
+
+        // Archives live in `{extensions_dir}/{id}/`, next to the quarantine directory
+        let quarantined = archive
+            .parent()
+            .and_then(Path::parent)
+            .ok_or_else(|| anyhow!("Archive path {:?} has no extensions directory", archive))
+            .and_then(|extensions_dir| {
+                QuarantineRegistry::update(extensions_dir, |registry| {
+                    registry.quarantine_staged(staged, archive, id, version, Some(reason))
+                })
+            });
+        match quarantined {
+            Ok(quarantined) => {
+                info!("Quarantined {:?} to {:?}", archive, quarantined);
+                Ok(false)
+            }
+            Err(e) => {
+                // Never leave an unscanned archive behind to be put in place later
+                let _ = fs::remove_file(staged);
+                Err(e)
+            }
+        }
+    }
+
+    /// Run the scan command on `path`, returning why the scan failed, if it did
+    async fn run(&self, path: &Path) -> Result<Option<String>> {
+        let mut args: Vec<String> = self
+            .command
+            .split_whitespace()
+            .map(|arg| arg.replace(PATH_PLACEHOLDER, &path.to_string_lossy()))
+            .collect();
+        if !self.command.contains(PATH_PLACEHOLDER) {
+            args.push(path.to_string_lossy().to_string());
+        }
+
+        debug!("Scanning {:?} with: {}", path, args.join(" "));
+        let output = Command::new(&args[0])
+            .args(&args[1..])
+            .output()
+            .await
+            .with_context(|| format!("Failed to run scan command '{}'", args[0]))?;
+
+        if output.status.success() {
+            return Ok(None);
+        }
+        warn!(
+            "Scan failed for {:?} ({}): {}",
+            path,
+            output.status,
+            String::from_utf8_lossy(&output.stdout).trim()
+        );
+        Ok(Some(format!("Scan failed ({})", output.status)))
+    }
+}
+
+/// Whether `program` is a file, given as a path or found in `PATH`
+fn program_exists(program: &str) -> bool {
+    if program.contains(std::path::MAIN_SEPARATOR) || program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
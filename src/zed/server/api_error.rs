@@ -0,0 +1,91 @@
+use actix_web::http::{StatusCode, header};
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+use thiserror::Error;
+
+/// An error answered to a client, with its status code and a JSON body
+/// `{"error": message, "status": code}`. Internal errors carry a message meant for the
+/// client; their cause is logged where they happen instead of being sent.
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// The resource existed but was withdrawn, such as a yanked version
+    #[error("{0}")]
+    Gone(String),
+    /// A failure of this mirror, such as an unreadable file
+    #[error("{0}")]
+    Internal(String),
+    /// Upstream couldn't be reached or sent something unusable
+    #[error("{0}")]
+    BadGateway(String),
+    /// Upstream didn't answer in time
+    #[error("{0}")]
+    GatewayTimeout(String),
+    /// Upstream answered with an error status, which is passed on
+    #[error("{message}")]
+    Upstream { status: StatusCode, message: String },
+    /// The mirror is too busy, come back after `retry_after_secs`
+    #[error("{message}")]
+    Unavailable {
+        message: String,
+        retry_after_secs: u64,
+    },
+}
+
+impl ApiError {
+    /// The error for a failed upstream request, by whether it timed out, got an error
+    /// status or never got an answer
+    pub fn upstream(e: &reqwest::Error) -> Self {
+        let message = format!("Upstream request failed: {}", e);
+        match e.status() {
+            _ if e.is_timeout() => Self::GatewayTimeout(message),
+            Some(status) => Self::Upstream {
+                status: StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY),
+                message,
+            },
+            None => Self::BadGateway(message),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Gone(_) => StatusCode::GONE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            Self::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::Upstream { status, .. } => *status,
+            Self::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let mut response = HttpResponse::build(status);
+        if let Self::Unavailable {
+            retry_after_secs, ..
+        } = self
+        {
+            response.insert_header((header::RETRY_AFTER, retry_after_secs.to_string()));
+        }
+        response.json(json!({
+            "error": self.to_string(),
+            "status": status.as_u16(),
+        }))
+    }
+}
+
+impl From<ApiError> for HttpResponse {
+    fn from(error: ApiError) -> Self {
+        error.error_response()
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use actix_web::web;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use log::info;
+
+use crate::zed::{current_request_id, in_request};
+
+use super::upstream_limit::UpstreamBusy;
+
+/// An upstream response, buffered so it can be handed to every coalesced request. The
+/// body is shared, not copied, between them.
+#[derive(Clone)]
+pub struct UpstreamResponse {
+    pub status: reqwest::StatusCode,
+    pub headers: reqwest::header::HeaderMap,
+    pub body: web::Bytes,
+}
+
+/// Why a coalesced upstream fetch failed
+#[derive(Clone)]
+pub enum FetchError {
+    /// No upstream slot became free in time
+    Busy(UpstreamBusy),
+    Failed(Arc<reqwest::Error>),
+}
+
+/// Upstream downloads of a server, coalesced by URL
+pub type UpstreamFetches = Coalescer<Result<UpstreamResponse, FetchError>>;
+
+/// A fetch in progress and the ID of the request that started it
+struct InFlight<T> {
+    fetch: Shared<BoxFuture<'static, T>>,
+    leader: Option<String>,
+}
+
+/// Runs one fetch per key at a time, handing its result to every caller that asked for the
+/// same key meanwhile. Kept per server (and tenant), so only requests served from the same
+/// directories share a fetch.
+///
+/// Results are held in memory until the last caller has them, so for release archives
+/// that is one buffered copy per URL, however many clients wait for it. Only the leader's
+/// request ID reaches upstream; the others log the ID they joined.
+pub struct Coalescer<T> {
+    in_flight: Mutex<HashMap<String, InFlight<T>>>,
+}
+
+impl<T> Default for Coalescer<T> {
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Coalescer<T> {
+    /// The result of the fetch in progress for `key`, or of a new one made by `start`
+    pub async fn run<F>(&self, key: &str, start: impl FnOnce() -> F) -> T
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let fetch = {
+            let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+            match in_flight.get(key) {
+                Some(InFlight { fetch, leader }) => {
+                    info!(
+                        "Joining in-flight upstream request {} for {}",
+                        leader.as_deref().unwrap_or("without an ID"),
+                        key
+                    );
+                    fetch.clone()
+                }
+                None => {
+                    // Keep the leader's ID even when a follower ends up polling the fetch
+                    let leader = current_request_id();
+                    let fetch = match leader.clone() {
+                        Some(id) => in_request(id, start).boxed(),
+                        None => start().boxed(),
+                    }
+                    .shared();
+                    in_flight.insert(
+                        key.to_string(),
+                        InFlight {
+                            fetch: fetch.clone(),
+                            leader,
+                        },
+                    );
+                    fetch
+                }
+            }
+        };
+
+        let result = fetch.clone().await;
+        // Whoever finishes first retires the entry, so later requests fetch afresh
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if in_flight
+            .get(key)
+            .is_some_and(|current| current.fetch.ptr_eq(&fetch))
+        {
+            in_flight.remove(key);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coalescer;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// A fetch counting its starts, finishing after a moment with `result`
+    fn counted(
+        starts: &Arc<AtomicUsize>,
+        result: Result<u32, String>,
+    ) -> impl FnOnce() -> std::pin::Pin<Box<dyn Future<Output = Result<u32, String>> + Send>> {
+        let starts = starts.clone();
+        move || {
+            starts.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                result
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_fetches_of_one_key_share_a_request() {
+        let coalescer = Coalescer::default();
+        let starts = Arc::new(AtomicUsize::new(0));
+
+        let (a, b, c) = tokio::join!(
+            coalescer.run("https://upstream/a", counted(&starts, Ok(1))),
+            coalescer.run("https://upstream/a", counted(&starts, Ok(2))),
+            coalescer.run("https://upstream/a", counted(&starts, Ok(3))),
+        );
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        assert_eq!((a, b, c), (Ok(1), Ok(1), Ok(1)));
+    }
+
+    #[tokio::test]
+    async fn errors_reach_every_follower() {
+        let coalescer = Coalescer::default();
+        let starts = Arc::new(AtomicUsize::new(0));
+
+        let (a, b) = tokio::join!(
+            coalescer.run("key", counted(&starts, Err("refused".to_string()))),
+            coalescer.run("key", counted(&starts, Ok(2))),
+        );
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        assert_eq!(a, Err("refused".to_string()));
+        assert_eq!(b, Err("refused".to_string()));
+    }
+
+    #[tokio::test]
+    async fn other_keys_and_later_fetches_start_anew() {
+        let coalescer = Coalescer::default();
+        let starts = Arc::new(AtomicUsize::new(0));
+
+        let (a, b) = tokio::join!(
+            coalescer.run("a", counted(&starts, Ok(1))),
+            coalescer.run("b", counted(&starts, Ok(2))),
+        );
+        assert_eq!((a, b), (Ok(1), Ok(2)));
+        // The finished fetch was retired, so asking again fetches afresh
+        assert_eq!(coalescer.run("a", counted(&starts, Ok(3))).await, Ok(3));
+        assert_eq!(starts.load(Ordering::SeqCst), 3);
+    }
+}
@@ -1,13 +1,199 @@
-use std::path::PathBuf;
+use actix_web::http::Method;
+use ipnet::IpNet;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::zed::{ArchiveScanner, CacheQuota, Signer, Webhooks};
+
+use super::upstream_limit::UpstreamLimit;
 
 #[derive(Clone)]
 pub struct ServerConfig {
     pub port: u16,
     pub host: String,
     pub extensions_dir: PathBuf,
+    /// Further extensions directories looked up in order after `extensions_dir`, which
+    /// alone is written to, e.g. a shared mirror on NFS under local overrides
+    pub fallback_extensions_dirs: Vec<PathBuf>,
     pub releases_dir: Option<PathBuf>,
+    /// Root directory holding the sync history
+    pub root_dir: Option<PathBuf>,
     pub proxy_mode: bool,
+    /// Download archives missing from the cache from upstream on first request and keep them
+    pub pull_through: bool,
+    /// Scan hook run against archives pulled from upstream
+    pub scanner: Option<ArchiveScanner>,
+    /// Size limit of the extensions directory, kept by pull-through downloads
+    pub cache_quota: Option<CacheQuota>,
+    /// URL clients reach the mirror at, used in generated links
     pub domain: Option<String>,
+    /// Reverse proxies whose forwarded headers decide the scheme and host of generated
+    /// links when no domain is set
+    pub trusted_proxies: Vec<IpNet>,
+    /// How often to compare the local cache against upstream
+    pub drift_check_interval: Option<Duration>,
+    /// How many archives to re-hash per minute in the background
+    pub verify_per_minute: Option<usize>,
+    /// Announce the mirror on the local network over mDNS
+    pub announce: bool,
+    /// Extensions frozen at an exact version, keyed by extension id
+    pub pins: HashMap<String, String>,
+    /// Versions withdrawn from serving, keyed by extension id
+    pub yanked: HashMap<String, HashSet<String>>,
+    /// Render HTML index pages for /releases and /extensions-archive
+    pub browsable_indexes: bool,
+    /// Broadcast index changes and new releases on /events
+    pub events: bool,
+    /// Fail requests on an extensions.json with invalid entries instead of dropping them
+    pub strict_index: bool,
+    /// Which upstream API paths may be proxied in proxy mode
+    pub proxy_rules: ProxyRules,
+    /// How long proxied API responses are reused
+    pub proxy_cache: ProxyCache,
+    /// Relay proxied responses that don't match their expected schema instead of failing
+    /// them; they are never cached or persisted
+    pub relay_schema_mismatches: bool,
+    /// Bound on concurrent upstream fetches, shared with the tenants
+    pub upstream_limit: UpstreamLimit,
+    /// Independent roots served under their own path prefix, keyed by prefix
+    pub tenants: BTreeMap<String, ServerConfig>,
+    /// How long idle keep-alive connections stay open (actix default when unset, zero disables)
+    pub keep_alive: Option<Duration>,
+    /// Maximum number of pending connections queued by the listener
+    pub backlog: u32,
+    /// Maximum number of concurrent connections per worker
+    pub max_connections: usize,
+    /// Number of worker threads (one per CPU core when unset)
+    pub workers: Option<usize>,
+    /// Certificate and key to serve HTTPS with, which also enables HTTP/2
+    pub tls: Option<TlsConfig>,
+    /// Port also serving plaintext HTTP next to the TLS port
+    pub http_port: Option<u16>,
+    /// Redirect requests on `http_port` to HTTPS instead of serving them
+    pub redirect_http: bool,
+    /// Limits on slow and stalled clients
+    pub timeouts: ServerTimeouts,
+    /// Key the checksum manifest served to peers is signed with
+    pub signer: Option<Signer>,
+    /// Bearer token required by the `/admin` endpoints, which are disabled without one
+    pub admin_token: Option<String>,
+    /// URLs notified when the drift check finds the mirror behind upstream
+    pub webhooks: Webhooks,
+    /// Views of the index limited to some extensions, keyed by name
+    pub channels: BTreeMap<String, IndexChannel>,
+    /// Channel served to clients that don't select one, the whole index when unset
+    pub default_channel: Option<String>,
+    /// Age of the last successful sync at which the mirror is reported stale
+    pub max_sync_age: Option<Duration>,
+    /// Origins allowed to call the server from a browser, CORS is off when unset
+    pub cors: Option<CorsPolicy>,
+    /// Hostnames of Zed's API and website, each served only its own routes
+    pub virtual_hosts: VirtualHosts,
+}
+
+/// A view of the index listing only some extensions, e.g. those approved for the whole
+/// fleet while pilots get everything. Clients select it with `?channel=` or by connecting
+/// to its port.
+#[derive(Clone, Debug, Default)]
+pub struct IndexChannel {
+    /// Extension id patterns listed, matched like [`ProxyRules`] (all when empty)
+    pub allow: Vec<String>,
+    /// Extension id patterns left out, winning over `allow`
+    pub deny: Vec<String>,
+    /// Port serving the channel by default
+    pub port: Option<u16>,
+}
+
+impl IndexChannel {
+    /// Check whether the channel lists an extension
+    pub fn lists(&self, id: &str) -> bool {
+        !self.deny.iter().any(|pattern| glob_match(pattern, id))
+            && (self.allow.is_empty() || self.allow.iter().any(|pattern| glob_match(pattern, id)))
+    }
+}
+
+/// Cross-origin requests answered with CORS headers
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+    /// Allowed origins as `scheme://host[:port]`, or `*` for any
+    pub origins: Vec<String>,
+    pub methods: Vec<Method>,
+    /// How long browsers may cache a preflight answer
+    pub max_age: Option<Duration>,
+}
+
+/// Hostnames Zed reaches the extensions API and the website with. With both pointed at
+/// one mirror, e.g. by `zedex dns-setup`, requests for the API host get only the
+/// extensions API and requests for the website only releases and `/api`, as upstream.
+/// Requests for other hosts get every route.
+#[derive(Clone, Debug)]
+pub struct VirtualHosts {
+    pub api: Vec<String>,
+    pub web: Vec<String>,
+}
+
+impl Default for VirtualHosts {
+    fn default() -> Self {
+        Self {
+            api: vec!["api.zed.dev".to_string()],
+            web: vec!["zed.dev".to_string()],
+        }
+    }
+}
+
+/// PEM files used to terminate TLS
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// Allow and deny patterns for upstream API paths (e.g. `extensions/*`), where `*`
+/// matches any run of characters, `/` included, so `**` is the same as `*`. Patterns match
+/// whole paths. Deny rules win, also over the path with its trailing `/` dropped; when
+/// allow rules are given, a path must match one of them.
+#[derive(Clone, Debug, Default)]
+pub struct ProxyRules {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+/// TTLs of proxied API responses per upstream path pattern, matched like [`ProxyRules`]
+/// with the longest matching pattern winning. Paths without a pattern aren't cached.
+#[derive(Clone, Debug)]
+pub struct ProxyCache {
+    pub ttls: Vec<(String, Duration)>,
+    /// Keep responses on disk so they survive restarts
+    pub persist: bool,
+    /// Maximum number of responses held in memory
+    pub max_entries: usize,
+    /// How long past its TTL a response is still served when upstream fails
+    pub stale_if_error: Duration,
+}
+
+/// Timeouts protecting the workers from slow and stalled clients, e.g. on flaky Wi-Fi
+#[derive(Clone, Debug, Default)]
+pub struct ServerTimeouts {
+    /// How long a client may take to send the request headers (actix default when unset)
+    pub request_header: Option<Duration>,
+    /// How long sent data may go unacknowledged before the connection is dropped
+    pub stall: Option<Duration>,
+    /// How long a request may take until its response is fully sent, per request path
+    /// pattern matched like [`ProxyRules`], with the longest matching pattern winning
+    pub routes: Vec<(String, Duration)>,
+}
+
+impl Default for ProxyCache {
+    fn default() -> Self {
+        Self {
+            ttls: Vec::new(),
+            persist: false,
+            max_entries: 1024,
+            stale_if_error: Duration::from_secs(86400),
+        }
+    }
 }
 
 impl Default for ServerConfig {
@@ -17,9 +203,308 @@ impl Default for ServerConfig {
             port: 2654,
             host: "127.0.0.1".to_string(),
             extensions_dir: root_dir.clone(),
+            fallback_extensions_dirs: Vec::new(),
             releases_dir: Some(root_dir.join("releases")),
+            root_dir: Some(root_dir),
             proxy_mode: false,
+            pull_through: false,
+            scanner: None,
+            cache_quota: None,
             domain: None,
+            trusted_proxies: Vec::new(),
+            drift_check_interval: None,
+            verify_per_minute: None,
+            announce: false,
+            pins: HashMap::new(),
+            yanked: HashMap::new(),
+            browsable_indexes: false,
+            events: false,
+            strict_index: false,
+            proxy_rules: ProxyRules::default(),
+            proxy_cache: ProxyCache::default(),
+            relay_schema_mismatches: false,
+            upstream_limit: UpstreamLimit::default(),
+            tenants: BTreeMap::new(),
+            keep_alive: None,
+            backlog: 2048,
+            max_connections: 25_000,
+            workers: None,
+            tls: None,
+            http_port: None,
+            redirect_http: false,
+            timeouts: ServerTimeouts::default(),
+            signer: None,
+            admin_token: None,
+            webhooks: Webhooks::default(),
+            channels: BTreeMap::new(),
+            default_channel: None,
+            max_sync_age: None,
+            cors: None,
+            virtual_hosts: VirtualHosts::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The extensions directories in lookup order
+    pub fn extensions_dirs(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.extensions_dir.as_path())
+            .chain(self.fallback_extensions_dirs.iter().map(PathBuf::as_path))
+    }
+
+    /// Check whether a specific extension version has been yanked
+    pub fn is_yanked(&self, id: &str, version: &str) -> bool {
+        self.yanked
+            .get(id)
+            .is_some_and(|versions| versions.contains(version))
+    }
+
+    /// Check whether a request for an upstream API path (without the `api/` prefix)
+    /// may be proxied
+    pub fn proxy_allowed(&self, path: &str) -> bool {
+        self.proxy_mode && self.proxy_rules.permits(path)
+    }
+
+    /// Check whether an archive missing for an upstream API path may be pulled into the cache
+    pub fn pull_allowed(&self, path: &str) -> bool {
+        self.pull_through && self.proxy_rules.permits(path)
+    }
+
+    /// The configuration as served, after the config file and command line are combined
+    pub fn effective(&self) -> Value {
+        let yanked: BTreeMap<&String, Vec<&String>> = self
+            .yanked
+            .iter()
+            .map(|(id, versions)| {
+                let mut versions: Vec<&String> = versions.iter().collect();
+                versions.sort();
+                (id, versions)
+            })
+            .collect();
+        let tenants: BTreeMap<&String, Value> = self
+            .tenants
+            .iter()
+            .map(|(name, tenant)| (name, tenant.effective()))
+            .collect();
+        let cors = self.cors.as_ref().map(|cors| {
+            json!({
+                "allowed_origins": cors.origins,
+                "allowed_methods": cors.methods.iter().map(Method::as_str).collect::<Vec<_>>(),
+                "max_age_secs": cors.max_age.map(|d| d.as_secs()),
+            })
+        });
+
+        json!({
+            "host": self.host,
+            "port": self.port,
+            "domain": self.domain,
+            "trusted_proxies": self
+                .trusted_proxies
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            "extensions_dir": self.extensions_dir,
+            "fallback_extensions_dirs": self.fallback_extensions_dirs,
+            "releases_dir": self.releases_dir,
+            "root_dir": self.root_dir,
+            "proxy_mode": self.proxy_mode,
+            "pull_through": self.pull_through,
+            "scan_command": self.scanner.as_ref().map(ArchiveScanner::command),
+            "drift_check_interval_secs": self.drift_check_interval.map(|d| d.as_secs()),
+            "verify_per_minute": self.verify_per_minute,
+            "announce": self.announce,
+            "browsable_indexes": self.browsable_indexes,
+            "events": self.events,
+            "strict_index": self.strict_index,
+            "pins": self.pins.iter().collect::<BTreeMap<_, _>>(),
+            "yanked": yanked,
+            "proxy_rules": { "allow": self.proxy_rules.allow, "deny": self.proxy_rules.deny },
+            "proxy_cache": {
+                "ttl_secs": self
+                    .proxy_cache
+                    .ttls
+                    .iter()
+                    .map(|(pattern, ttl)| (pattern, ttl.as_secs()))
+                    .collect::<BTreeMap<_, _>>(),
+                "persist": self.proxy_cache.persist,
+                "max_entries": self.proxy_cache.max_entries,
+                "stale_if_error_secs": self.proxy_cache.stale_if_error.as_secs(),
+            },
+            "relay_schema_mismatches": self.relay_schema_mismatches,
+            "upstream_limit": {
+                "max_concurrent_fetches": self.upstream_limit.max(),
+                "queue_timeout_secs": self.upstream_limit.queue_timeout().as_secs(),
+            },
+            "keep_alive_secs": self.keep_alive.map(|d| d.as_secs()),
+            "backlog": self.backlog,
+            "max_connections": self.max_connections,
+            "workers": self.workers,
+            "tls": self.tls.as_ref().map(|tls| json!({
+                "cert_path": tls.cert_path,
+                "key_path": tls.key_path,
+            })),
+            "http_port": self.http_port,
+            "redirect_http": self.redirect_http,
+            "timeouts": {
+                "request_header_secs": self.timeouts.request_header.map(|d| d.as_secs()),
+                "stall_secs": self.timeouts.stall.map(|d| d.as_secs()),
+                "route_secs": self
+                    .timeouts
+                    .routes
+                    .iter()
+                    .map(|(pattern, timeout)| (pattern, timeout.as_secs()))
+                    .collect::<BTreeMap<_, _>>(),
+            },
+            "signing": self.signer.as_ref().map(|signer| json!({
+                "tool": signer.tool,
+                "key": signer.key,
+            })),
+            "admin_endpoints": self.admin_token.is_some(),
+            "webhooks": self.webhooks.hooks.iter().map(|hook| json!({
+                "format": hook.format,
+                "events": hook.events,
+            })).collect::<Vec<_>>(),
+            "channels": self.channels.iter().map(|(name, channel)| (name, json!({
+                "allow": channel.allow,
+                "deny": channel.deny,
+                "port": channel.port,
+            }))).collect::<BTreeMap<_, _>>(),
+            "default_channel": self.default_channel,
+            "max_sync_age_secs": self.max_sync_age.map(|age| age.as_secs()),
+            "cors": cors,
+            "virtual_hosts": {
+                "api": self.virtual_hosts.api,
+                "web": self.virtual_hosts.web,
+            },
+            "tenants": tenants,
+        })
+    }
+}
+
+impl ProxyRules {
+    /// Check a path, relative to the upstream API root, against the rules
+    pub fn permits(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        let path = path.split('?').next().unwrap_or(path);
+
+        // `telemetry/` must not get around a deny rule for `telemetry`
+        let trimmed = path.trim_end_matches('/');
+        if self
+            .deny
+            .iter()
+            .any(|pattern| glob_match(pattern, path) || glob_match(pattern, trimmed))
+        {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|pattern| glob_match(pattern, path))
+    }
+}
+
+/// Match a path against a pattern where `*` matches any run of characters
+pub(super) fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches('/');
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard, the pattern must match exactly
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProxyRules, glob_match};
+
+    fn rules(allow: &[&str], deny: &[&str]) -> ProxyRules {
+        ProxyRules {
+            allow: allow.iter().map(|p| p.to_string()).collect(),
+            deny: deny.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let cases = [
+            ("extensions", "extensions", true),
+            ("/extensions", "extensions", true),
+            // Patterns are anchored at both ends
+            ("extensions", "extensions/updates", false),
+            ("updates", "extensions/updates", false),
+            ("extensions/*", "extensions/updates", true),
+            ("extensions/*", "extensions/", true),
+            ("extensions/*", "extensions", false),
+            ("extensions/*", "api/extensions/x", false),
+            // `*` crosses `/`, `**` is the same
+            ("extensions/*", "extensions/html/download", true),
+            ("extensions/**", "extensions/html/download", true),
+            ("extensions/*/download", "extensions/html/download", true),
+            (
+                "extensions/*/download",
+                "extensions/html/0.1.0/download",
+                true,
+            ),
+            ("extensions/*/download", "extensions/html/download/x", false),
+            ("*/download", "extensions/html/download", true),
+            ("*", "", true),
+            ("a*a", "a", false),
+            ("a*a", "aa", true),
+            ("*x*x", "x", false),
+            // A trailing `/` is part of the path
+            ("extensions/", "extensions/", true),
+            ("extensions/", "extensions", false),
+            ("extensions", "extensions/", false),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(glob_match(pattern, path), expected, "{pattern} ~ {path}");
+        }
+    }
+
+    #[test]
+    fn proxy_rules() {
+        let cases = [
+            (rules(&[], &[]), "anything/at/all", true),
+            (rules(&["extensions*"], &[]), "extensions/updates", true),
+            (rules(&["extensions*"], &[]), "releases/stable", false),
+            (rules(&[], &["telemetry/*"]), "telemetry/events", false),
+            (rules(&[], &["telemetry/*"]), "extensions", true),
+            // Deny wins over allow
+            (
+                rules(&["*"], &["extensions/*/download"]),
+                "extensions/html/download",
+                false,
+            ),
+            (
+                rules(&["extensions/*"], &["extensions/secret"]),
+                "extensions/html",
+                true,
+            ),
+            // Leading slashes and queries are ignored
+            (rules(&[], &["telemetry/*"]), "/telemetry/events", false),
+            (
+                rules(&[], &["extensions/updates"]),
+                "extensions/updates?ids=a",
+                false,
+            ),
+            // Trailing slashes don't get around a deny rule, but aren't allowed for free
+            (rules(&[], &["telemetry"]), "telemetry/", false),
+            (rules(&[], &["telemetry"]), "telemetry//", false),
+            (rules(&["extensions"], &[]), "extensions/", false),
+        ];
+        for (rules, path, expected) in cases {
+            assert_eq!(rules.permits(path), expected, "{rules:?} {path}");
         }
     }
 }
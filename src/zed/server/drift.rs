@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use serde::Serialize;
+
+use crate::zed::{Client, Version, parse_version};
+
+use super::config::ServerConfig;
+use super::layers::merged_index;
+use super::state::ServerState;
+
+/// Zed platform whose latest release is compared against the mirror
+const DRIFT_REFERENCE_PLATFORM: (&str, &str, &str) = ("zed", "macos", "aarch64");
+
+/// Snapshot of how far the local cache lags behind upstream
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DriftReport {
+    /// Unix timestamp of the last completed upstream check
+    pub last_checked: Option<u64>,
+    /// Number of mirrored extensions with a newer version upstream
+    pub stale_extensions: usize,
+    /// Extension ids with a newer version upstream
+    pub stale_extension_ids: Vec<String>,
+    /// Number of upstream extensions missing from the local index
+    pub missing_extensions: usize,
+    /// Latest Zed release advertised upstream
+    pub upstream_zed_version: Option<String>,
+    /// Latest Zed release available in the mirror
+    pub mirrored_zed_version: Option<String>,
+    /// Error from the last check, if it failed
+    pub last_error: Option<String>,
+}
+
+impl DriftReport {
+    /// Whether upstream has newer extensions or a newer Zed release than the mirror
+    pub fn is_behind(&self) -> bool {
+        self.stale_extensions > 0
+            || matches!(
+                (&self.upstream_zed_version, &self.mirrored_zed_version),
+                (Some(upstream), Some(mirrored)) if upstream != mirrored
+            )
+    }
+
+    /// Human readable one-line summary of the drift
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{} extensions stale", self.stale_extensions);
+        if let (Some(upstream), Some(mirrored)) =
+            (&self.upstream_zed_version, &self.mirrored_zed_version)
+            && upstream != mirrored
+        {
+            summary.push_str(&format!(
+                ", latest Zed {} vs mirrored {}",
+                upstream, mirrored
+            ));
+        }
+        summary
+    }
+}
+
+/// Periodically compares the local cache against upstream and records the drift
+pub async fn run_drift_checker(state: ServerState, interval: Duration) {
+    let client = Client::with_http_client(state.http_client.clone());
+    let mut ticker = tokio::time::interval(interval);
+    // Drift last sent to the webhooks, so an unchanged lag isn't reported on every check
+    let mut notified: Option<(Vec<String>, Option<String>)> = None;
+
+    loop {
+        ticker.tick().await;
+        debug!("Checking upstream for newer extensions and releases");
+
+        let report = check_drift(&client, &state.config).await;
+        if let Some(err) = &report.last_error {
+            warn!("Upstream drift check failed: {}", err);
+        } else {
+            info!("Upstream drift check: {}", report.summary());
+            let drift = report.is_behind().then(|| {
+                (
+                    report.stale_extension_ids.clone(),
+                    report.upstream_zed_version.clone(),
+                )
+            });
+            if drift.is_some() && drift != notified {
+                state.config.webhooks.drift_detected(&report).await;
+            }
+            notified = drift;
+        }
+
+        if let Ok(mut drift) = state.drift.write() {
+            *drift = report;
+        }
+    }
+}
+
+async fn check_drift(client: &Client, config: &ServerConfig) -> DriftReport {
+    let mut report = DriftReport {
+        last_checked: Some(chrono::Utc::now().timestamp() as u64),
+        mirrored_zed_version: config
+            .releases_dir
+            .as_deref()
+            .and_then(mirrored_zed_version),
+        ..Default::default()
+    };
+
+    let local = load_local_versions(config);
+    match client.get_extensions_index(None).await {
+        Ok(upstream) => {
+            for ext in upstream {
+                match local.get(&ext.id) {
+                    Some(local_version) if is_newer(&ext.version, local_version) => {
+                        report.stale_extension_ids.push(ext.id);
+                    }
+                    Some(_) => {}
+                    None => report.missing_extensions += 1,
+                }
+            }
+            report.stale_extension_ids.sort();
+            report.stale_extensions = report.stale_extension_ids.len();
+        }
+        Err(e) => report.last_error = Some(format!("Failed to fetch extension index: {}", e)),
+    }
+
+    let (asset, os, arch) = DRIFT_REFERENCE_PLATFORM;
+    match client.get_latest_release(None, os, arch, asset).await {
+        Ok(release) => report.upstream_zed_version = Some(release.version),
+        Err(e) => {
+            report
+                .last_error
+                .get_or_insert(format!("Failed to fetch latest release: {}", e));
+        }
+    }
+
+    report
+}
+
+fn load_local_versions(config: &ServerConfig) -> HashMap<String, String> {
+    merged_index(config)
+        .ok()
+        .flatten()
+        .map(|extensions| {
+            extensions
+                .into_iter()
+                .map(|ext| (ext.id, ext.version))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn mirrored_zed_version(releases_dir: &Path) -> Option<String> {
+    fs::read_dir(releases_dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| {
+            entry.file_name().to_str().is_some_and(|name| {
+                name.starts_with("zed-")
+                    && !name.starts_with("zed-remote-server-")
+                    && name.ends_with(".json")
+            })
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .filter_map(|content| serde_json::from_str::<Version>(&content).ok())
+        .max()
+        .map(|version| version.version)
+}
+
+fn is_newer(upstream: &str, local: &str) -> bool {
+    match (parse_version(upstream), parse_version(local)) {
+        (Some(upstream), Some(local)) => upstream > local,
+        _ => upstream != local,
+    }
+}
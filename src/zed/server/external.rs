@@ -0,0 +1,60 @@
+use actix_web::HttpRequest;
+use actix_web::http::header;
+
+use super::config::ServerConfig;
+
+impl ServerConfig {
+    /// Scheme and host clients reach the mirror at, for absolute links in responses: the
+    /// configured domain, else the forwarded headers of a trusted proxy, else the scheme
+    /// and `Host` the request arrived with. Never the bind address.
+    pub fn external_url(&self, req: &HttpRequest) -> String {
+        if let Some(domain) = &self.domain {
+            return domain.trim_end_matches('/').to_string();
+        }
+        if self.is_trusted_proxy(req) {
+            let info = req.connection_info();
+            return format!("{}://{}", info.scheme(), info.host());
+        }
+
+        let scheme = if req.app_config().secure() {
+            "https"
+        } else {
+            "http"
+        };
+        format!("{}://{}", scheme, request_authority(req))
+    }
+
+    /// Host a request was addressed to, as forwarded by a trusted proxy or else its own
+    /// `Host`, lowercase and without the port
+    pub fn request_host(&self, req: &HttpRequest) -> String {
+        let authority = if self.is_trusted_proxy(req) {
+            req.connection_info().host().to_string()
+        } else {
+            request_authority(req).to_string()
+        };
+        let host = match authority.rsplit_once(':') {
+            Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+            _ => &authority,
+        };
+        host.trim_end_matches('.').to_ascii_lowercase()
+    }
+
+    /// Whether the request's direct peer is one of the trusted proxies
+    fn is_trusted_proxy(&self, req: &HttpRequest) -> bool {
+        req.peer_addr().is_some_and(|peer| {
+            let address = peer.ip().to_canonical();
+            self.trusted_proxies
+                .iter()
+                .any(|network| network.contains(&address))
+        })
+    }
+}
+
+/// Host the client addressed the request to, ignoring forwarded headers anyone could set
+pub fn request_authority(req: &HttpRequest) -> &str {
+    req.headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+        .unwrap_or_else(|| req.app_config().host())
+}
@@ -0,0 +1,149 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, http::header, web};
+use log::{error, info, warn};
+use serde_json::json;
+use std::collections::HashMap;
+
+use crate::zed::{list_index_snapshots, load_sync_history, rollback_index};
+
+use super::super::api_error::ApiError;
+use super::super::paths::{invalid_path, is_safe_segment};
+use super::super::state::ServerState;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/admin/index/snapshots").route(web::get().to(get_index_snapshots)))
+        .service(web::resource("/admin/sync/history").route(web::get().to(get_sync_history)))
+        .service(
+            web::resource("/admin/index/rollback/{timestamp}")
+                .route(web::post().to(rollback_index_snapshot)),
+        );
+}
+
+/// Snapshots of the served index, oldest first
+pub async fn get_index_snapshots(
+    req: HttpRequest,
+    state: web::Data<ServerState>,
+) -> impl Responder {
+    if let Some(denied) = authorize(&req, &state) {
+        return denied;
+    }
+
+    let extensions_dir = state.config.extensions_dir.clone();
+    match web::block(move || list_index_snapshots(&extensions_dir)).await {
+        Ok(Ok(snapshots)) => HttpResponse::Ok().json(snapshots),
+        Ok(Err(e)) => {
+            error!("Failed to list index snapshots: {:#}", e);
+            ApiError::Internal("Failed to list index snapshots".to_string()).into()
+        }
+        Err(e) => {
+            error!("Index snapshot task failed: {}", e);
+            ApiError::Internal("Failed to list index snapshots".to_string()).into()
+        }
+    }
+}
+
+/// Serve a snapshot of the index again. The index is read from disk on every request,
+/// so clients see the rolled back index right away.
+pub async fn rollback_index_snapshot(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<ServerState>,
+) -> impl Responder {
+    if let Some(denied) = authorize(&req, &state) {
+        return denied;
+    }
+
+    let timestamp = path.into_inner();
+    if !is_safe_segment(&timestamp) {
+        return invalid_path(&timestamp);
+    }
+    info!("Index rollback to {} requested", timestamp);
+    let extensions_dir = state.config.extensions_dir.clone();
+    let task_timestamp = timestamp.clone();
+    match web::block(move || rollback_index(&extensions_dir, &task_timestamp)).await {
+        Ok(Ok(())) => HttpResponse::Ok().body(format!("Rolled the index back to {}", timestamp)),
+        Ok(Err(e)) => {
+            error!("Index rollback to {} failed: {:#}", timestamp, e);
+            ApiError::BadRequest(format!("{:#}", e)).into()
+        }
+        Err(e) => {
+            error!("Index rollback task failed: {}", e);
+            ApiError::Internal("Failed to roll the index back".to_string()).into()
+        }
+    }
+}
+
+/// Recorded `get all-extensions` and `sync` runs, newest first. `limit` caps the number
+/// of runs returned.
+pub async fn get_sync_history(
+    req: HttpRequest,
+    state: web::Data<ServerState>,
+    query: web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    if let Some(denied) = authorize(&req, &state) {
+        return denied;
+    }
+    let Some(root_dir) = state.config.root_dir.clone() else {
+        return ApiError::NotFound("No root directory configured".to_string()).into();
+    };
+
+    let limit = query
+        .get("limit")
+        .and_then(|limit| limit.parse::<usize>().ok());
+    match web::block(move || load_sync_history(&root_dir)).await {
+        Ok(Ok(records)) => {
+            let last_success = records.iter().rev().find(|record| record.succeeded());
+            let runs: Vec<_> = records
+                .iter()
+                .rev()
+                .take(limit.unwrap_or(usize::MAX))
+                .collect();
+            HttpResponse::Ok().json(json!({
+                "last_success": last_success,
+                "runs": runs,
+            }))
+        }
+        Ok(Err(e)) => {
+            error!("Failed to load the sync history: {:#}", e);
+            ApiError::Internal("Failed to load the sync history".to_string()).into()
+        }
+        Err(e) => {
+            error!("Sync history task failed: {}", e);
+            ApiError::Internal("Failed to load the sync history".to_string()).into()
+        }
+    }
+}
+
+/// Check the request's bearer token against the configured admin token. The endpoints
+/// don't exist unless a token is configured.
+fn authorize(req: &HttpRequest, state: &ServerState) -> Option<HttpResponse> {
+    let Some(expected) = state.config.admin_token.as_deref() else {
+        return Some(HttpResponse::NotFound().finish());
+    };
+
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match given {
+        Some(given) if tokens_match(given, expected) => None,
+        _ => {
+            warn!("Rejected unauthorized admin request to {}", req.path());
+            Some(
+                HttpResponse::Unauthorized()
+                    .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                    .finish(),
+            )
+        }
+    }
+}
+
+/// Compare tokens without returning early on the first differing byte
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
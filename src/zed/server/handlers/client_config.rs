@@ -0,0 +1,14 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+
+use crate::zed::ClientConfig;
+
+use super::super::state::ServerState;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/client-config").to(get_client_config));
+}
+
+/// Settings and environment pointing Zed at this mirror, at the URL clients reach it with
+pub async fn get_client_config(req: HttpRequest, state: web::Data<ServerState>) -> impl Responder {
+    HttpResponse::Ok().json(ClientConfig::new(&state.config.external_url(&req)))
+}
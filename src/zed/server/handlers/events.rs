@@ -0,0 +1,39 @@
+use actix_web::http::header;
+use actix_web::{HttpResponse, Responder, web};
+use futures_util::{StreamExt, stream};
+use log::debug;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::super::state::ServerState;
+
+/// Idle time after which a comment is sent, so proxies keep the connection open
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/events").to(get_events));
+}
+
+/// Server-Sent Events stream of extension versions and releases as they are mirrored
+pub async fn get_events(state: web::Data<ServerState>) -> impl Responder {
+    debug!("Events subscriber connected");
+    let receiver = state.events.subscribe();
+
+    let greeting = stream::once(async {
+        Ok::<_, actix_web::Error>(web::Bytes::from_static(b"retry: 5000\n: connected\n\n"))
+    });
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let message = match tokio::time::timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+            Ok(Ok(event)) => event.to_sse(),
+            Ok(Err(RecvError::Lagged(skipped))) => format!(": {} events skipped\n\n", skipped),
+            Ok(Err(RecvError::Closed)) => return None,
+            Err(_) => ": keep-alive\n\n".to_string(),
+        };
+        Some((Ok(web::Bytes::from(message)), receiver))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(greeting.chain(events))
+}
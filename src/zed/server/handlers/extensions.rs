@@ -1,284 +1,874 @@
-use std::{collections::HashMap, fs};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use actix_web::{HttpResponse, Responder, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, http::header, web};
 use log::{debug, error, info, warn};
-use semver::Version as SemverVersion;
 
-use crate::zed::{WrappedExtensions, extensions_utils};
+use crate::zed::{
+    Client, Extension, QuarantineRegistry, ServedVersion, WrappedExtensions, archive_path,
+    compare_versions,
+    extensions_utils::{self, ExtensionQuery, ExtensionSort},
+};
 
+use super::super::api_error::ApiError;
+use super::super::config::IndexChannel;
+use super::super::layers::{find_layered_archive, latest_archive, merged_index, merged_versions};
+use super::super::paths::{invalid_path, is_safe_segment};
+use super::super::pull::pull_extension_version;
 use super::super::state::ServerState;
+use super::super::streaming::{ARCHIVE_CONTENT_TYPE, stream_file};
+use super::super::upstream_limit::UpstreamBusy;
+use super::super::views::ServedIndex;
 use super::proxy::{
     proxy_download_request, proxy_download_version_request, proxy_extension_versions,
     proxy_extensions_updates,
 };
 
+/// Path prefixes the extension API is served under. Older Zed releases call it under `/api`.
+const API_PREFIXES: [&str; 2] = ["", "/api"];
+
+/// Query parameter spellings used by some Zed releases, mapped to the canonical name
+const QUERY_ALIASES: [(&str, &str); 4] = [
+    ("schema_version", "max_schema_version"),
+    ("wasm_api_version", "max_wasm_api_version"),
+    ("extension_ids", "ids"),
+    ("search", "filter"),
+];
+
+/// Told to clients when the index on disk can't be read, the cause is logged
+const INDEX_UNREADABLE: &str = "The extension index can't be read";
+
+/// Page size when `page` is given without `per_page`
+const DEFAULT_PER_PAGE: usize = 50;
+
+/// Largest page of the index served at once
+const MAX_PER_PAGE: usize = 500;
+
+/// Header paginated listings report the size of the whole listing in
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+/// Capabilities with a dedicated listing, as requested by Zed's theme and language pickers
+const PICKER_CAPABILITIES: &str = "themes|icon-themes|languages";
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::resource("/extensions").to(get_extensions_index))
-        .service(web::resource("/extensions/updates").to(check_extension_updates))
-        .service(web::resource("/extensions/{id}/download").to(download_extension))
-        .service(
-            web::resource("/extensions/{id}/{version}/download")
-                .to(download_extension_with_version),
-        )
-        .service(web::resource("/extensions/{id}").to(get_extension_versions));
+    for prefix in API_PREFIXES {
+        cfg.service(web::resource(format!("{prefix}/extensions")).to(get_extensions_index))
+            .service(
+                web::resource(format!(
+                    "{prefix}/extensions/{{capability:{PICKER_CAPABILITIES}}}"
+                ))
+                .to(get_capability_index),
+            )
+            .service(
+                web::resource(format!("{prefix}/extensions/updates")).to(check_extension_updates),
+            )
+            .service(web::resource(format!("{prefix}/extensions/changes")).to(get_index_changes))
+            .service(
+                web::resource(format!("{prefix}/extensions/{{id}}/download"))
+                    .to(download_extension),
+            )
+            .service(
+                web::resource(format!("{prefix}/extensions/{{id}}/{{version}}/download"))
+                    .to(download_extension_with_version),
+            )
+            .service(
+                web::resource(format!("{prefix}/extensions/{{id}}")).to(get_extension_versions),
+            );
+    }
 }
 
-fn filter_extensions_with_params(
-    extensions: &WrappedExtensions,
-    filter: Option<&str>,
-    min_schema_version: Option<i32>,
-    max_schema_version: Option<i32>,
-    min_wasm_api_version: Option<&str>,
-    max_wasm_api_version: Option<&str>,
-    provides: Option<&str>,
-    extension_ids: Option<&[&str]>,
-) -> crate::zed::Extensions {
-    let filtered_by_standard =
-        extensions_utils::filter_extensions(&extensions.data, filter, max_schema_version, provides);
-
-    let filtered_by_min_schema = if let Some(min_version) = min_schema_version {
-        filtered_by_standard
-            .into_iter()
-            .filter(|ext| ext.schema_version >= min_version)
-            .collect()
-    } else {
-        filtered_by_standard
+/// Rewrite aliased query parameters to their canonical names, keeping the canonical
+/// value when a client sends both
+fn normalize_query(
+    query: web::Query<HashMap<String, String>>,
+) -> web::Query<HashMap<String, String>> {
+    let mut query = query.into_inner();
+    for (alias, canonical) in QUERY_ALIASES {
+        if let Some(value) = query.remove(alias) {
+            query.entry(canonical.to_string()).or_insert(value);
+        }
+    }
+    web::Query(query)
+}
+
+/// The index channel a request selects with `?channel=`, else by the port it arrived on,
+/// else the default channel. `None` serves the whole index, unknown names a 404.
+fn request_channel<'a>(
+    state: &'a ServerState,
+    req: &HttpRequest,
+    query: &HashMap<String, String>,
+) -> Result<Option<(&'a str, &'a IndexChannel)>, HttpResponse> {
+    let config = &state.config;
+    let port = req.app_config().local_addr().port();
+    let name = query.get("channel").map(String::as_str).or_else(|| {
+        config
+            .channels
+            .iter()
+            .find(|(_, channel)| channel.port == Some(port))
+            .map(|(name, _)| name.as_str())
+            .or(config.default_channel.as_deref())
+    });
+
+    let Some(name) = name else {
+        return Ok(None);
     };
+    match config.channels.get_key_value(name) {
+        Some((name, channel)) => Ok(Some((name.as_str(), channel))),
+        None => {
+            warn!("Request for unknown channel {}", name);
+            Err(ApiError::NotFound(format!("Unknown channel {}", name)).into())
+        }
+    }
+}
 
-    let filtered_by_ids = if let Some(ids) = extension_ids {
-        if !ids.is_empty() {
-            filtered_by_min_schema
-                .into_iter()
-                .filter(|ext| ids.contains(&ext.id.as_str()))
-                .collect()
-        } else {
-            filtered_by_min_schema
+/// The order asked with `sort=downloads|recent|name` and `order=asc|desc`. Without `order`,
+/// downloads and recent run descending and names ascending.
+fn request_order(query: &HashMap<String, String>) -> Result<(ExtensionSort, bool), HttpResponse> {
+    let sort = match query.get("sort") {
+        None => ExtensionSort::default(),
+        Some(value) => ExtensionSort::parse(value).ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "Unknown sort {}, expected downloads, recent or name",
+                value
+            ))
+        })?,
+    };
+    let descending = match query.get("order").map(String::as_str) {
+        None => sort.descending_by_default(),
+        Some("asc") => false,
+        Some("desc") => true,
+        Some(value) => {
+            return Err(ApiError::BadRequest(format!(
+                "Unknown order {}, expected asc or desc",
+                value
+            ))
+            .into());
         }
-    } else {
-        filtered_by_min_schema
     };
+    Ok((sort, descending))
+}
 
-    if min_wasm_api_version.is_some() || max_wasm_api_version.is_some() {
-        filtered_by_ids
-            .into_iter()
-            .filter(|ext| {
-                if ext.wasm_api_version.is_none() {
-                    return true;
-                }
+/// A page of a listing, numbered from 1
+#[derive(Debug, Clone, Copy)]
+struct Page {
+    number: usize,
+    size: usize,
+}
 
-                let ext_version = ext.wasm_api_version.as_ref().unwrap();
+/// The page asked with `page` and `per_page`, none to list everything like zed.dev
+fn request_page(query: &HashMap<String, String>) -> Result<Option<Page>, HttpResponse> {
+    let parse = |name: &str| {
+        query
+            .get(name)
+            .map(|value| {
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| {
+                        HttpResponse::from(ApiError::BadRequest(format!(
+                            "{} must be a positive number, not {}",
+                            name, value
+                        )))
+                    })
+            })
+            .transpose()
+    };
+    let number = parse("page")?;
+    let size = parse("per_page")?;
+    if number.is_none() && size.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Page {
+        number: number.unwrap_or(1),
+        size: size.unwrap_or(DEFAULT_PER_PAGE).min(MAX_PER_PAGE),
+    }))
+}
 
-                if let Some(min_version) = min_wasm_api_version {
-                    if ext_version.as_str() < min_version {
-                        return false;
-                    }
-                }
+/// Keep the extensions a channel lists, all of them without a channel
+fn retain_channel(extensions: &mut Vec<Extension>, channel: Option<(&str, &IndexChannel)>) {
+    if let Some((_, channel)) = channel {
+        extensions.retain(|ext| channel.lists(&ext.id));
+    }
+}
+
+/// Apply pins, yanked and quarantined versions to the index, replacing entries with the
+/// version that may be served or hiding them when none is available
+fn apply_version_policies(state: &ServerState, extensions: WrappedExtensions) -> WrappedExtensions {
+    let config = &state.config;
+    let quarantine = state.quarantine();
+    if config.pins.is_empty() && config.yanked.is_empty() && quarantine.quarantined().count() == 0 {
+        return extensions;
+    }
+    let withheld = |id: &str, version: &str| {
+        config.is_yanked(id, version) || quarantine.is_quarantined(id, version)
+    };
+
+    let data = extensions
+        .data
+        .into_iter()
+        .filter_map(|ext| {
+            let pinned = config.pins.get(&ext.id);
+            if pinned.is_none_or(|pinned| *pinned == ext.version)
+                && !withheld(&ext.id, &ext.version)
+            {
+                return Some(ext);
+            }
+
+            let versions = load_versions(state, &ext.id).unwrap_or_default();
+            let replacement = match pinned {
+                Some(pinned) => versions
+                    .into_iter()
+                    .find(|v| v.version == *pinned && !withheld(&v.id, &v.version)),
+                None => versions
+                    .into_iter()
+                    .filter(|v| !withheld(&v.id, &v.version))
+                    .max_by(|v1, v2| compare_versions(&v1.version, &v2.version)),
+            };
+
+            if replacement.is_none() {
+                warn!(
+                    "No servable version of {} is mirrored, hiding it from the index",
+                    ext.id
+                );
+            }
+            replacement
+        })
+        .collect();
+
+    WrappedExtensions { data }
+}
+
+/// The index as served, rebuilt only when the indexes, the quarantine metadata or the
+/// version listings of pinned and yanked extensions change
+fn served_index(state: &ServerState) -> anyhow::Result<Option<Arc<ServedIndex>>> {
+    let config = &state.config;
+    let mut sources: Vec<PathBuf> = config
+        .extensions_dirs()
+        .map(|dir| dir.join("extensions.json"))
+        .collect();
+    sources.push(QuarantineRegistry::metadata_file(&config.extensions_dir));
+    for id in config.pins.keys().chain(config.yanked.keys()) {
+        sources.extend(
+            config
+                .extensions_dirs()
+                .map(|dir| dir.join(id).join("versions.json")),
+        );
+    }
+
+    state.served_index.get_or_build(sources, || {
+        let Some(data) = merged_index(config)? else {
+            return Ok(None);
+        };
+        // Stored sorted by id for stable diffs, listed by popularity like zed.dev
+        let mut extensions = apply_version_policies(state, WrappedExtensions { data }).data;
+        extensions_utils::sort_by_downloads(&mut extensions);
+        let revision = state.index_history.observe(&extensions);
+        Ok(Some(ServedIndex {
+            extensions,
+            revision,
+        }))
+    })
+}
+
+/// Serve the newest mirrored version of an extension that satisfies the client's
+/// constraints and may be served
+async fn serve_compatible_version(
+    req: &HttpRequest,
+    state: &ServerState,
+    id: String,
+    constraints: &ExtensionQuery,
+) -> HttpResponse {
+    let quarantine = state.quarantine();
+    let latest = latest_archive(&state.config, &id);
+    let latest_version = latest.as_ref().and_then(|(_, version)| version.as_deref());
+
+    let mut candidates = load_versions(state, &id).unwrap_or_default();
+    // Without versions.json (latest-only mirrors) the index entry describes `{id}.tgz`
+    if let Some(latest_version) = latest_version
+        && !candidates.iter().any(|ext| ext.version == latest_version)
+        && let Ok(Some(index)) = merged_index(&state.config)
+    {
+        candidates.extend(index.into_iter().filter(|ext| ext.id == id));
+    }
+
+    let selected = candidates
+        .into_iter()
+        .filter(|ext| {
+            constraints.matches(ext)
+                && !state.config.is_yanked(&id, &ext.version)
+                && !quarantine.is_quarantined(&id, &ext.version)
+        })
+        .filter_map(|ext| {
+            let archive =
+                find_layered_archive(&state.config, &id, Some(&ext.version)).or_else(|| {
+                    (latest_version == Some(ext.version.as_str()))
+                        .then(|| latest.as_ref().map(|(archive, _)| archive.clone()))
+                        .flatten()
+                })?;
+            Some((ext.version, archive))
+        })
+        .max_by(|(v1, _), (v2, _)| compare_versions(v1, v2));
+
+    if let Some((version, archive)) = selected {
+        match stream_file(req, &archive, ARCHIVE_CONTENT_TYPE).await {
+            Ok(response) => {
+                info!("Serving newest compatible version {} for {}", version, id);
+                return served(response, &id, &version);
+            }
+            Err(e) => error!("Failed to read archive file {}: {}", archive.display(), e),
+        }
+    }
 
-                if let Some(max_version) = max_wasm_api_version {
-                    if ext_version.as_str() > max_version {
-                        return false;
-                    }
+    if state
+        .config
+        .pull_allowed(&format!("extensions/{}/download", id))
+    {
+        match Client::with_http_client(state.http_client.clone())
+            .get_extension_versions(&id)
+            .await
+        {
+            Ok(versions) => {
+                let newest = versions
+                    .iter()
+                    .filter(|ext| {
+                        constraints.matches(ext)
+                            && !state.config.is_yanked(&id, &ext.version)
+                            && !quarantine.is_quarantined(&id, &ext.version)
+                    })
+                    .max_by(|v1, v2| compare_versions(&v1.version, &v2.version));
+                if let Some(extension) = newest {
+                    return pull_and_serve(req, state, &id, &extension.version).await;
                 }
+            }
+            Err(e) => warn!("Failed to list upstream versions of {}: {}", id, e),
+        }
+    }
 
-                true
-            })
-            .collect()
+    if state
+        .config
+        .proxy_allowed(&format!("extensions/{}/download", id))
+    {
+        info!(
+            "No mirrored version of {} satisfies the request, proxying it",
+            id
+        );
+        proxy_download_request(id, state).await
     } else {
-        filtered_by_ids
+        warn!(
+            "No mirrored version of {} satisfies the requested schema and WASM API versions",
+            id
+        );
+        ApiError::NotFound(format!(
+            "No mirrored version of {} is compatible with the requested schema and WASM API versions",
+            id
+        ))
+        .into()
     }
 }
 
+/// Version of an extension listed in the index that isn't in the cache yet, if any
+fn pull_candidate(state: &ServerState, id: &str) -> Option<String> {
+    let index = merged_index(&state.config).ok()??;
+    let version = index.into_iter().find(|ext| ext.id == id)?.version;
+
+    let withheld =
+        state.config.is_yanked(id, &version) || state.quarantine().is_quarantined(id, &version);
+    let cached = find_layered_archive(&state.config, id, Some(&version)).is_some()
+        || latest_archive_version(state, id).as_deref() == Some(version.as_str());
+    (!withheld && !cached).then_some(version)
+}
+
+/// Name the extension version an archive response serves, for the transfer stats
+fn served(mut response: HttpResponse, id: &str, version: &str) -> HttpResponse {
+    response.extensions_mut().insert(ServedVersion {
+        id: id.to_string(),
+        version: version.to_string(),
+    });
+    response
+}
+
+/// Pull an extension version into the cache and serve it
+async fn pull_and_serve(
+    req: &HttpRequest,
+    state: &ServerState,
+    id: &str,
+    version: &str,
+) -> HttpResponse {
+    match pull_extension_version(state, id, version).await {
+        Ok(archive) => match stream_file(req, &archive, ARCHIVE_CONTENT_TYPE).await {
+            Ok(response) => {
+                info!("Serving {} version {} pulled from upstream", id, version);
+                served(response, id, version)
+            }
+            Err(e) => {
+                error!("Failed to read archive file {}: {}", archive.display(), e);
+                ApiError::Internal(format!("Failed to read the archive of {} {}", id, version))
+                    .into()
+            }
+        },
+        Err(ref e) if let Some(busy) = e.downcast_ref::<UpstreamBusy>() => busy.response(),
+        Err(e) => {
+            error!("Failed to pull {} version {}: {:#}", id, version, e);
+            ApiError::BadGateway(format!(
+                "Failed to pull extension {} version {} from upstream: {}",
+                id, version, e
+            ))
+            .into()
+        }
+    }
+}
+
+/// Version of the `{id}.tgz` archive served according to its version tracker
+fn latest_archive_version(state: &ServerState, id: &str) -> Option<String> {
+    latest_archive(&state.config, id)?.1
+}
+
+fn yanked_response(id: &str, version: &str) -> HttpResponse {
+    ApiError::Gone(format!(
+        "Extension {} version {} has been yanked from this mirror",
+        id, version
+    ))
+    .into()
+}
+
+fn quarantined_response(id: &str, version: &str) -> HttpResponse {
+    ApiError::Gone(format!(
+        "Extension {} version {} is quarantined on this mirror",
+        id, version
+    ))
+    .into()
+}
+
+/// Load the mirrored versions of an extension, if any extensions directory lists them
+fn load_versions(state: &ServerState, id: &str) -> Option<crate::zed::Extensions> {
+    merged_versions(&state.config, id)
+        .inspect_err(|e| error!("Failed to load versions of {}: {:#}", id, e))
+        .ok()
+        .flatten()
+}
+
 pub async fn get_extensions_index(
+    req: HttpRequest,
     state: web::Data<ServerState>,
     query: web::Query<HashMap<String, String>>,
 ) -> impl Responder {
-    let extensions_file = state.config.extensions_dir.join("extensions.json");
-
-    match fs::read_to_string(&extensions_file) {
-        Ok(content) => match serde_json::from_str::<WrappedExtensions>(&content) {
-            Ok(extensions) => {
-                let filter = query.get("filter").map(|s| s.as_str());
-                let max_schema_version = query
-                    .get("max_schema_version")
-                    .and_then(|v| v.parse::<i32>().ok());
-                let provides = query.get("provides").map(|s| s.as_str());
-
-                debug!(
-                    "Filtering extensions: filter={:?}, max_schema_version={:?}, provides={:?}",
-                    filter, max_schema_version, provides
-                );
+    serve_index(&req, &state, normalize_query(query), None)
+}
+
+/// Extensions providing one capability, e.g. `/extensions/themes`, served from the views
+/// cached per capability
+pub async fn get_capability_index(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<ServerState>,
+    query: web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    let capability = path.into_inner();
+    serve_index(&req, &state, normalize_query(query), Some(&capability))
+}
 
-                let filtered_extensions = filter_extensions_with_params(
-                    &extensions,
-                    filter,
-                    None,
+/// Serve the index filtered by the query, or by `capability` in place of `provides`
+fn serve_index(
+    req: &HttpRequest,
+    state: &ServerState,
+    query: web::Query<HashMap<String, String>>,
+    capability: Option<&str>,
+) -> HttpResponse {
+    let channel = match request_channel(state, req, &query) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+    let order = match request_order(&query) {
+        Ok(order) => order,
+        Err(response) => return response,
+    };
+    let page = match request_page(&query) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    match served_index(state) {
+        Ok(Some(index)) => {
+            let extensions = &index.extensions;
+            let revision = index.revision.as_str();
+            let filter = query.get("filter").map(|s| s.as_str());
+            let max_schema_version = query
+                .get("max_schema_version")
+                .and_then(|v| v.parse::<i32>().ok());
+            let provides = capability.or(query.get("provides").map(|s| s.as_str()));
+
+            debug!(
+                "Filtering extensions: filter={:?}, max_schema_version={:?}, provides={:?}, page={:?}",
+                filter, max_schema_version, provides, page
+            );
+
+            // Pages are cut from the listing on each request rather than cached
+            if filter.is_none() && page.is_none() {
+                return serve_index_view(
+                    state,
+                    extensions,
+                    revision,
                     max_schema_version,
-                    None,
-                    None,
                     provides,
-                    None,
-                );
-
-                info!(
-                    "Serving {} filtered extensions from index",
-                    filtered_extensions.len()
+                    channel,
+                    order,
                 );
-
-                let wrapped = WrappedExtensions {
-                    data: filtered_extensions,
-                };
-                HttpResponse::Ok().json(wrapped)
             }
-            Err(e) => {
-                error!("Error parsing extensions.json: {}", e);
-                HttpResponse::InternalServerError()
-                    .body(format!("Error parsing extensions file: {}", e))
+
+            let mut filtered_extensions = ExtensionQuery::default()
+                .text(filter)
+                .schema_versions(None, max_schema_version)
+                .provides(provides)
+                .apply(extensions);
+            retain_channel(&mut filtered_extensions, channel);
+            sort_listing(&mut filtered_extensions, order);
+
+            let total = filtered_extensions.len();
+            let mut response = HttpResponse::Ok();
+            response.insert_header((header::ETAG, revision));
+            if let Some(page) = page {
+                filtered_extensions = filtered_extensions
+                    .into_iter()
+                    .skip((page.number - 1).saturating_mul(page.size))
+                    .take(page.size)
+                    .collect();
+                response.insert_header((TOTAL_COUNT_HEADER, total));
             }
-        },
+
+            info!(
+                "Serving {} of {} filtered extensions from index",
+                filtered_extensions.len(),
+                total
+            );
+
+            let wrapped = WrappedExtensions {
+                data: filtered_extensions,
+            };
+            response.json(wrapped)
+        }
+        Err(e) => {
+            error!("Error loading extensions.json: {:#}", e);
+            ApiError::Internal(INDEX_UNREADABLE.to_string()).into()
+        }
+        Ok(None) => {
+            error!("No extensions.json in the extensions directories");
+            ApiError::NotFound("Extensions file not found".to_string()).into()
+        }
+    }
+}
+
+/// Reorder a listing, which comes most downloaded first
+fn sort_listing(extensions: &mut [Extension], (sort, descending): (ExtensionSort, bool)) {
+    if (sort, descending) != (ExtensionSort::Downloads, true) {
+        sort.apply(extensions, descending);
+    }
+}
+
+/// Serve the index filtered by schema version and capability only, from the views cached
+/// for its revision
+fn serve_index_view(
+    state: &ServerState,
+    extensions: &[Extension],
+    revision: &str,
+    max_schema_version: Option<i32>,
+    provides: Option<&str>,
+    channel: Option<(&str, &IndexChannel)>,
+    order: (ExtensionSort, bool),
+) -> HttpResponse {
+    let key = (
+        max_schema_version,
+        provides.map(str::to_string),
+        channel.map(|(name, _)| name.to_string()),
+        order,
+    );
+    let view = state.index_views.get_or_build(revision, key, || {
+        let mut data = ExtensionQuery::default()
+            .schema_versions(None, max_schema_version)
+            .provides(provides)
+            .apply(extensions);
+        retain_channel(&mut data, channel);
+        sort_listing(&mut data, order);
+        let count = data.len();
+        let body = serde_json::to_vec(&WrappedExtensions { data })?;
+        Ok((count, body.into()))
+    });
+
+    match view {
+        Ok((count, body)) => {
+            info!("Serving {} filtered extensions from index", count);
+            HttpResponse::Ok()
+                .insert_header((header::ETAG, revision))
+                .content_type("application/json")
+                .body(body)
+        }
+        Err(e) => {
+            error!("Failed to serialize the index: {}", e);
+            ApiError::Internal("Failed to serialize the extension index".to_string()).into()
+        }
+    }
+}
+
+/// Entries of the index added or changed since `since`, a Unix timestamp or the `ETag`
+/// of an earlier index or changes response. Unknown markers get the whole index with
+/// `"full": true`.
+pub async fn get_index_changes(
+    req: HttpRequest,
+    state: web::Data<ServerState>,
+    query: web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    let channel = match request_channel(&state, &req, &query) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+    let data = match merged_index(&state.config) {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            error!("No extensions.json in the extensions directories");
+            return ApiError::NotFound("Extensions index unavailable".to_string()).into();
+        }
         Err(e) => {
-            error!("Error reading extensions.json: {}", e);
-            HttpResponse::NotFound().body(format!("Extensions file not found: {}", e))
+            error!("Error loading extensions.json: {:#}", e);
+            return ApiError::Internal(INDEX_UNREADABLE.to_string()).into();
         }
+    };
+    let extensions = apply_version_policies(&state, WrappedExtensions { data });
+
+    // Clients that know the current revision don't need the changes body at all
+    let known = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    let since = query.get("since").map(String::as_str).or(known);
+    let mut changes = state.index_history.changes_since(extensions.data, since);
+    if let Some((_, channel)) = channel {
+        changes.changed.retain(|ext| channel.lists(&ext.id));
+        changes.removed.retain(|id| channel.lists(id));
+    }
+    if known.is_some_and(|known| known.split(',').any(|tag| tag.trim() == changes.revision)) {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, changes.revision))
+            .finish();
     }
+
+    debug!(
+        "Serving {} changed and {} removed extensions since {:?}",
+        changes.changed.len(),
+        changes.removed.len(),
+        since
+    );
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, changes.revision.clone()))
+        .json(serde_json::json!({
+            "revision": changes.revision,
+            "full": changes.full,
+            "data": changes.changed,
+            "removed": changes.removed,
+        }))
 }
 
 pub async fn download_extension(
+    req: HttpRequest,
     path: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
     state: web::Data<ServerState>,
 ) -> impl Responder {
     let id = path.into_inner();
-    let ext_dir = state.config.extensions_dir.join(&id);
+    if !is_safe_segment(&id) {
+        return invalid_path(&id);
+    }
 
-    let latest_file_path = ext_dir.join(format!("{}.tgz", id));
-    debug!(
-        "Checking for latest version: {}",
-        latest_file_path.display()
-    );
+    if let Some(pinned) = state.config.pins.get(&id) {
+        info!("Serving pinned version {} for {}", pinned, id);
+        return serve_extension_version(&req, &state, id.clone(), pinned.clone()).await;
+    }
 
-    if let Ok(bytes) = fs::read(&latest_file_path) {
+    let constraints = ExtensionQuery::from_version_bounds(&normalize_query(query));
+    if constraints.has_version_bounds() {
+        return serve_compatible_version(&req, &state, id, &constraints).await;
+    }
+
+    if state
+        .config
+        .pull_allowed(&format!("extensions/{}/download", id))
+        && let Some(version) = pull_candidate(&state, &id)
+    {
+        return pull_and_serve(&req, &state, &id, &version).await;
+    }
+
+    // Also finds archives stored under names of older versions, like `{id}.tar.gz` at
+    // the top of the extensions directory
+    let latest = latest_archive(&state.config, &id);
+    debug!("Latest archive for {}: {:?}", id, latest);
+
+    let quarantine = state.quarantine();
+    let withheld = |version: &str| {
+        state.config.is_yanked(&id, version) || quarantine.is_quarantined(&id, version)
+    };
+    let withheld_latest = (state.config.yanked.contains_key(&id)
+        || quarantine.has_quarantined(&id))
+        && latest
+            .as_ref()
+            .and_then(|(_, version)| version.as_deref())
+            .is_some_and(withheld);
+
+    if withheld_latest {
+        debug!(
+            "Latest archive for {} is yanked or quarantined, skipping it",
+            id
+        );
+    } else if let Some((latest_file_path, latest_version)) = &latest
+        && let Ok(response) = stream_file(&req, latest_file_path, ARCHIVE_CONTENT_TYPE).await
+    {
         info!("Serving latest version for {}", id);
-        return HttpResponse::Ok()
-            .content_type("application/gzip")
-            .body(bytes);
+        return match latest_version {
+            Some(version) => served(response, &id, version),
+            None => response,
+        };
     }
 
-    if ext_dir.exists() {
-        let versions_file = ext_dir.join("versions.json");
+    if let Some(versions) = load_versions(&state, &id) {
+        debug!("Looking for highest available version of {}", id);
 
-        if versions_file.exists() {
-            debug!(
-                "Looking for highest available version in {}",
-                versions_file.display()
+        let highest_version = versions
+            .iter()
+            .filter_map(|ext| {
+                let version = &ext.version;
+                let archive_path = find_layered_archive(&state.config, &id, Some(version))?;
+
+                (!withheld(version)).then(|| (version.clone(), archive_path))
+            })
+            .max_by(|(v1, _), (v2, _)| compare_versions(v1, v2));
+
+        if let Some((version_str, file_path)) = highest_version {
+            info!(
+                "Serving highest downloaded version {} for {}",
+                version_str, id
             );
 
-            if let Ok(content) = fs::read_to_string(&versions_file) {
-                if let Ok(versions) = serde_json::from_str::<WrappedExtensions>(&content) {
-                    let highest_version = versions
-                        .data
-                        .iter()
-                        .filter_map(|ext| {
-                            let version = &ext.version;
-                            let archive_path = ext_dir.join(format!("{}-{}.tgz", id, version));
-
-                            if archive_path.exists() {
-                                SemverVersion::parse(version)
-                                    .map(|v| (v, version.clone(), archive_path))
-                                    .or_else(|e| {
-                                        warn!("Invalid version '{}' for {}: {}", version, id, e);
-                                        Err(e)
-                                    })
-                                    .ok()
-                            } else {
-                                None
-                            }
-                        })
-                        .max_by(|(v1, _, _), (v2, _, _)| v1.cmp(v2));
-
-                    if let Some((_, version_str, file_path)) = highest_version {
-                        info!(
-                            "Serving highest downloaded version {} for {}",
-                            version_str, id
-                        );
-
-                        if let Ok(bytes) = fs::read(&file_path) {
-                            return HttpResponse::Ok()
-                                .content_type("application/gzip")
-                                .body(bytes);
-                        } else {
-                            error!("Failed to read archive file: {}", file_path.display());
-                        }
-                    } else {
-                        debug!("No downloaded versions found for {}", id);
-                    }
-                } else {
-                    error!("Failed to parse versions.json for {}", id);
-                }
+            if let Ok(response) = stream_file(&req, &file_path, ARCHIVE_CONTENT_TYPE).await {
+                return served(response, &id, &version_str);
             } else {
-                error!("Failed to read versions.json for {}", id);
+                error!("Failed to read archive file: {}", file_path.display());
             }
+        } else {
+            debug!("No downloaded versions found for {}", id);
         }
     }
 
-    let old_path = state.config.extensions_dir.join(format!("{}.tar.gz", id));
-    debug!("Checking old structure: {}", old_path.display());
-
-    if let Ok(bytes) = fs::read(&old_path) {
-        info!("Serving extension from old structure for {}", id);
-        return HttpResponse::Ok()
-            .content_type("application/gzip")
-            .body(bytes);
+    if withheld_latest {
+        warn!(
+            "Only yanked or quarantined versions of {} are available",
+            id
+        );
+        return ApiError::Gone(format!(
+            "All mirrored versions of extension {} have been yanked or quarantined",
+            id
+        ))
+        .into();
     }
 
-    if state.config.proxy_mode {
+    if state
+        .config
+        .proxy_allowed(&format!("extensions/{}/download", id))
+    {
         error!("Extension not found locally for {}, proxying request", id);
-        proxy_download_request(id).await
+        proxy_download_request(id, &state).await
     } else {
         error!(
             "Extension not found locally for {} and proxy mode is off",
             id
         );
-        HttpResponse::NotFound().body(format!("Extension archive not found for id: {}", id))
+        ApiError::NotFound(format!("Extension archive not found for id: {}", id)).into()
     }
 }
 
 pub async fn download_extension_with_version(
+    req: HttpRequest,
     path: web::Path<(String, String)>,
     state: web::Data<ServerState>,
 ) -> impl Responder {
     let (id, version) = path.into_inner();
+    if !is_safe_segment(&id) || !is_safe_segment(&version) {
+        return invalid_path(&format!("{}/{}", id, version));
+    }
     debug!("Requested extension {} with version {}", id, version);
 
-    let ext_dir = state.config.extensions_dir.join(&id);
-    let versioned_file_path = ext_dir.join(format!("{}-{}.tgz", id, version));
+    if let Some(pinned) = state.config.pins.get(&id)
+        && *pinned != version
+    {
+        warn!(
+            "Rejecting download of {} version {}: pinned at {}",
+            id, version, pinned
+        );
+        return ApiError::NotFound(format!("Extension {} is pinned at version {}", id, pinned))
+            .into();
+    }
+
+    serve_extension_version(&req, &state, id, version).await
+}
+
+async fn serve_extension_version(
+    req: &HttpRequest,
+    state: &ServerState,
+    id: String,
+    version: String,
+) -> HttpResponse {
+    if state.config.is_yanked(&id, &version) {
+        warn!("Rejecting download of yanked {} version {}", id, version);
+        return yanked_response(&id, &version);
+    }
+    if state.quarantine().is_quarantined(&id, &version) {
+        warn!(
+            "Rejecting download of quarantined {} version {}",
+            id, version
+        );
+        return quarantined_response(&id, &version);
+    }
+
+    // Latest-only mirrors keep the version as `{id}.tgz`
+    let versioned_file_path = find_layered_archive(&state.config, &id, Some(&version))
+        .or_else(|| {
+            latest_archive(&state.config, &id)
+                .filter(|(_, latest)| latest.as_deref() == Some(version.as_str()))
+                .map(|(archive, _)| archive)
+        })
+        .unwrap_or_else(|| archive_path(&state.config.extensions_dir, &id, Some(&version)));
 
     debug!(
         "Looking for versioned extension at {:?}",
         versioned_file_path
     );
-    match fs::read(&versioned_file_path) {
-        Ok(bytes) => {
+    match stream_file(req, &versioned_file_path, ARCHIVE_CONTENT_TYPE).await {
+        Ok(response) => {
             info!(
                 "Successfully served extension archive: {} version {}",
                 id, version
             );
-            HttpResponse::Ok()
-                .content_type("application/gzip")
-                .body(bytes)
+            served(response, &id, &version)
         }
         Err(_) => {
-            if state.config.proxy_mode {
+            if state
+                .config
+                .pull_allowed(&format!("extensions/{}/{}/download", id, version))
+            {
+                pull_and_serve(req, state, &id, &version).await
+            } else if state
+                .config
+                .proxy_allowed(&format!("extensions/{}/{}/download", id, version))
+            {
                 error!(
                     "Extension version file not found, proxying: {} version {}",
                     id, version
                 );
-                proxy_download_version_request(id, version).await
+                proxy_download_version_request(id, version, state).await
             } else {
                 error!(
                     "Extension version file not found: {} version {}",
                     id, version
                 );
-                HttpResponse::NotFound()
-                    .body(format!("Extension version archive not found: {}", version))
+                ApiError::NotFound(format!("Extension version archive not found: {}", version))
+                    .into()
             }
         }
     }
@@ -289,61 +879,58 @@ pub async fn get_extension_versions(
     state: web::Data<ServerState>,
 ) -> impl Responder {
     let id = path.into_inner();
-    let ext_dir = state.config.extensions_dir.join(&id);
-    let versions_file = ext_dir.join("versions.json");
-
+    if !is_safe_segment(&id) {
+        return invalid_path(&id);
+    }
     debug!("Attempting to serve versions for extension id: {}", id);
 
-    if versions_file.exists() {
-        match fs::read_to_string(&versions_file) {
-            Ok(content) => match serde_json::from_str::<WrappedExtensions>(&content) {
-                Ok(extensions) => {
-                    info!(
-                        "Successfully served {} versions for extension: {}",
-                        extensions.data.len(),
-                        id
-                    );
-                    HttpResponse::Ok().json(extensions)
-                }
-                Err(e) => {
-                    error!("Error parsing versions.json for {}: {}", id, e);
-                    HttpResponse::InternalServerError()
-                        .body(format!("Error parsing versions file: {}", e))
-                }
-            },
-            Err(e) => {
-                error!("Error reading versions.json for {}: {}", id, e);
-                HttpResponse::InternalServerError()
-                    .body(format!("Error reading versions file: {}", e))
+    match merged_versions(&state.config, &id) {
+        Ok(Some(data)) => {
+            let mut extensions = WrappedExtensions { data };
+            if let Some(pinned) = state.config.pins.get(&id) {
+                extensions.data.retain(|ext| ext.version == *pinned);
             }
+            let quarantine = state.quarantine();
+            extensions.data.retain(|ext| {
+                !state.config.is_yanked(&id, &ext.version)
+                    && !quarantine.is_quarantined(&id, &ext.version)
+            });
+
+            info!(
+                "Successfully served {} versions for extension: {}",
+                extensions.data.len(),
+                id
+            );
+            HttpResponse::Ok().json(extensions)
+        }
+        Err(e) => {
+            error!("Error loading versions.json for {}: {:#}", id, e);
+            ApiError::Internal(format!("The versions of {} can't be read", id)).into()
+        }
+        Ok(None) if state.config.proxy_allowed(&format!("extensions/{}", id)) => {
+            info!(
+                "Extension versions file not found for {}. Proxying request in proxy mode.",
+                id
+            );
+            proxy_extension_versions(id, &state).await
+        }
+        Ok(None) => {
+            error!("Extension versions file not found for {}", id);
+            ApiError::NotFound(format!("Extension versions not found for: {}", id)).into()
         }
-    } else if state.config.proxy_mode {
-        info!(
-            "Extension versions file not found for {}. Proxying request in proxy mode.",
-            id
-        );
-        proxy_extension_versions(id).await
-    } else {
-        error!(
-            "Extension versions file not found for {}: {:?}",
-            id, versions_file
-        );
-        HttpResponse::NotFound().body(format!("Extension versions not found for: {}", id))
     }
 }
 
 pub async fn check_extension_updates(
+    req: HttpRequest,
     state: web::Data<ServerState>,
     query: web::Query<HashMap<String, String>>,
 ) -> impl Responder {
-    let min_schema_version = query
-        .get("min_schema_version")
-        .and_then(|v| v.parse::<i32>().ok());
-    let max_schema_version = query
-        .get("max_schema_version")
-        .and_then(|v| v.parse::<i32>().ok());
-    let min_wasm_api_version = query.get("min_wasm_api_version").map(|s| s.as_str());
-    let max_wasm_api_version = query.get("max_wasm_api_version").map(|s| s.as_str());
+    let query = normalize_query(query);
+    let channel = match request_channel(&state, &req, &query) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
     let ids_param = query.get("ids").cloned().unwrap_or_default();
 
     let extension_ids: Vec<&str> = if !ids_param.is_empty() {
@@ -357,59 +944,37 @@ pub async fn check_extension_updates(
         return HttpResponse::Ok().json(WrappedExtensions { data: Vec::new() });
     }
 
-    debug!(
-        "Extension update check: min_schema={:?}, max_schema={:?}, min_wasm_api={:?}, max_wasm_api={:?}, ids={:?}",
-        min_schema_version,
-        max_schema_version,
-        min_wasm_api_version,
-        max_wasm_api_version,
-        extension_ids
-    );
-
-    let extensions_file = state.config.extensions_dir.join("extensions.json");
+    let update_query = ExtensionQuery::from_version_bounds(&query).ids(extension_ids);
+    debug!("Extension update check: {:?}", update_query);
 
-    match fs::read_to_string(&extensions_file) {
-        Ok(content) => match serde_json::from_str::<WrappedExtensions>(&content) {
-            Ok(extensions) => {
-                let filtered_extensions = filter_extensions_with_params(
-                    &extensions,
-                    None,
-                    min_schema_version,
-                    max_schema_version,
-                    min_wasm_api_version,
-                    max_wasm_api_version,
-                    None,
-                    if extension_ids.is_empty() {
-                        None
-                    } else {
-                        Some(&extension_ids)
-                    },
-                );
+    match merged_index(&state.config) {
+        Ok(Some(data)) => {
+            let extensions = apply_version_policies(&state, WrappedExtensions { data });
+            let mut filtered_extensions = update_query.apply(&extensions.data);
+            retain_channel(&mut filtered_extensions, channel);
 
-                info!(
-                    "Serving {} updated extensions from index",
-                    filtered_extensions.len()
-                );
+            info!(
+                "Serving {} updated extensions from index",
+                filtered_extensions.len()
+            );
 
-                let wrapped = WrappedExtensions {
-                    data: filtered_extensions,
-                };
-                HttpResponse::Ok().json(wrapped)
-            }
-            Err(e) => {
-                error!("Error parsing extensions.json: {}", e);
-                HttpResponse::InternalServerError()
-                    .body(format!("Error parsing extensions file: {}", e))
-            }
-        },
+            let wrapped = WrappedExtensions {
+                data: filtered_extensions,
+            };
+            HttpResponse::Ok().json(wrapped)
+        }
         Err(e) => {
-            error!("Error reading extensions.json: {}", e);
+            error!("Error loading extensions.json: {:#}", e);
+            ApiError::Internal(INDEX_UNREADABLE.to_string()).into()
+        }
+        Ok(None) => {
+            error!("No extensions.json in the extensions directories");
 
-            if state.config.proxy_mode {
-                return proxy_extensions_updates(query).await;
+            if state.config.proxy_allowed("extensions/updates") {
+                return proxy_extensions_updates(query, &state).await;
             }
 
-            HttpResponse::NotFound().body(format!("Extensions file not found: {}", e))
+            ApiError::NotFound("Extensions file not found".to_string()).into()
         }
     }
 }
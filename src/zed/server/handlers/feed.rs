@@ -0,0 +1,204 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::debug;
+
+use crate::zed::{ExtensionVersionTracker, QUARANTINE_DIR};
+
+use super::super::state::ServerState;
+
+/// Maximum number of entries included in the feed
+const MAX_FEED_ENTRIES: usize = 100;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/feed.xml").to(get_feed));
+}
+
+/// A single mirrored artifact shown in the feed
+struct FeedEntry {
+    title: String,
+    id: String,
+    link: String,
+    updated: DateTime<Utc>,
+}
+
+/// Atom feed of newly mirrored extension versions and Zed releases
+pub async fn get_feed(req: HttpRequest, state: web::Data<ServerState>) -> impl Responder {
+    let base_url = state.config.external_url(&req);
+
+    let mut entries = extension_entries(&state.config.extensions_dir, &base_url);
+    if let Some(releases_dir) = &state.config.releases_dir {
+        entries.extend(release_entries(releases_dir, &base_url));
+    }
+
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.updated));
+    entries.truncate(MAX_FEED_ENTRIES);
+    debug!("Serving feed with {} entries", entries.len());
+
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(render_feed(&base_url, &entries))
+}
+
+fn extension_entries(extensions_dir: &Path, base_url: &str) -> Vec<FeedEntry> {
+    let tracker: ExtensionVersionTracker =
+        fs::read_to_string(extensions_dir.join("version_tracker.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+    let Ok(dirs) = fs::read_dir(extensions_dir) else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+    for dir in dirs.flatten() {
+        let path = dir.path();
+        let Some(id) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !path.is_dir() || id == QUARANTINE_DIR || id == "releases" {
+            continue;
+        }
+
+        let Ok(files) = fs::read_dir(&path) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let Some(file_name) = file.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let Some(stem) = file_name.strip_suffix(".tgz") else {
+                continue;
+            };
+
+            let version = if stem == id {
+                tracker.extensions.get(id).cloned()
+            } else {
+                stem.strip_prefix(&format!("{}-", id)).map(str::to_string)
+            };
+            let Some(updated) = modified_time(&file.path()) else {
+                continue;
+            };
+
+            let title = match &version {
+                Some(version) => format!("Extension {} {} mirrored", id, version),
+                None => format!("Extension {} mirrored", id),
+            };
+            entries.push(FeedEntry {
+                title,
+                id: format!(
+                    "zedex:extension:{}:{}",
+                    id,
+                    version.as_deref().unwrap_or(stem)
+                ),
+                link: match &version {
+                    Some(version) => format!("{}/extensions/{}/{}/download", base_url, id, version),
+                    None => format!("{}/extensions/{}/download", base_url, id),
+                },
+                updated,
+            });
+        }
+    }
+
+    entries
+}
+
+fn release_entries(releases_dir: &Path, base_url: &str) -> Vec<FeedEntry> {
+    let Ok(dirs) = fs::read_dir(releases_dir) else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+    for dir in dirs.flatten() {
+        let path = dir.path();
+        let Some(version) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !path.is_dir() {
+            continue;
+        }
+
+        let Ok(files) = fs::read_dir(&path) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let Some(file_name) = file.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let Some(updated) = modified_time(&file.path()) else {
+                continue;
+            };
+
+            entries.push(FeedEntry {
+                title: format!("Zed release {} mirrored ({})", version, file_name),
+                id: format!("zedex:release:{}:{}", version, file_name),
+                link: format!("{}/releases/{}/{}", base_url, version, file_name),
+                updated,
+            });
+        }
+    }
+
+    entries
+}
+
+fn modified_time(path: &Path) -> Option<DateTime<Utc>> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .map(|time: SystemTime| time.into())
+}
+
+fn render_feed(base_url: &str, entries: &[FeedEntry]) -> String {
+    let updated = entries
+        .first()
+        .map(|entry| entry.updated)
+        .unwrap_or_else(Utc::now);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str("  <title>zedex mirror updates</title>\n");
+    xml.push_str(&format!("  <id>{}/feed.xml</id>\n", escape_xml(base_url)));
+    xml.push_str(&format!(
+        "  <link rel=\"self\" href=\"{}/feed.xml\"/>\n",
+        escape_xml(base_url)
+    ));
+    xml.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        updated.to_rfc3339_opts(SecondsFormat::Secs, true)
+    ));
+
+    for entry in entries {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape_xml(&entry.title)
+        ));
+        xml.push_str(&format!("    <id>{}</id>\n", escape_xml(&entry.id)));
+        xml.push_str(&format!(
+            "    <link href=\"{}\"/>\n",
+            escape_xml(&entry.link)
+        ));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            entry.updated.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use actix_files::Directory;
+use actix_web::{HttpRequest, HttpResponse, dev::ServiceResponse};
+use chrono::{DateTime, Local};
+use log::warn;
+use semver::Version as SemverVersion;
+
+use crate::zed::{QUARANTINE_DIR, cached_sha256, format_bytes};
+
+/// Renders an HTML index page for a mirrored directory listing name, version,
+/// size, SHA-256 checksum and modification date of each entry
+pub fn render_listing(dir: &Directory, req: &HttpRequest) -> Result<ServiceResponse, io::Error> {
+    let title = format!("Index of {}", req.path());
+    let base = req.path().trim_end_matches('/');
+    let dir_name = dir
+        .path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let at_root = dir.path == dir.base;
+
+    let mut entries: Vec<_> = fs::read_dir(&dir.path)?
+        .filter(|entry| dir.is_visible(entry))
+        .flatten()
+        .filter(|entry| !(at_root && entry.file_name() == QUARANTINE_DIR))
+        .collect();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut rows = String::new();
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let modified = metadata
+            .modified()
+            .map(|time| {
+                DateTime::<Local>::from(time)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default();
+
+        if metadata.is_dir() {
+            rows.push_str(&format!(
+                "<tr><td><a href=\"{base}/{href}/\">{name}/</a></td><td></td><td></td><td></td><td>{modified}</td></tr>\n",
+                href = escape_html(&name),
+                name = escape_html(&name),
+            ));
+            continue;
+        }
+
+        let version = archive_version(&dir_name, &name).unwrap_or_default();
+        let checksum = cached_checksum(&entry.path(), &metadata).unwrap_or_default();
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{base}/{href}\">{name}</a></td><td>{version}</td><td>{size}</td><td><code>{checksum}</code></td><td>{modified}</td></tr>\n",
+            href = escape_html(&name),
+            name = escape_html(&name),
+            version = escape_html(&version),
+            size = format_bytes(metadata.len()),
+        ));
+    }
+
+    let parent = if at_root {
+        String::new()
+    } else {
+        "<tr><td><a href=\"../\">../</a></td><td></td><td></td><td></td><td></td></tr>\n"
+            .to_string()
+    };
+
+    let body = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+         td,th{{padding:2px 12px;text-align:left}}code{{font-size:smaller}}</style>\n</head>\n\
+         <body>\n<h1>{title}</h1>\n<table>\n\
+         <tr><th>Name</th><th>Version</th><th>Size</th><th>SHA-256</th><th>Modified</th></tr>\n\
+         {parent}{rows}</table>\n</body>\n</html>\n",
+        title = escape_html(&title),
+    );
+
+    Ok(ServiceResponse::new(
+        req.clone(),
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body),
+    ))
+}
+
+/// Derive the version of a file from `{id}-{version}.tgz` naming or a version-named directory
+fn archive_version(dir_name: &str, file_name: &str) -> Option<String> {
+    if let Some(version) = file_name
+        .strip_prefix(&format!("{}-", dir_name))
+        .and_then(|rest| rest.strip_suffix(".tgz"))
+    {
+        return Some(version.to_string());
+    }
+
+    SemverVersion::parse(dir_name)
+        .ok()
+        .map(|_| dir_name.to_string())
+}
+
+fn cached_checksum(path: &Path, metadata: &fs::Metadata) -> Option<String> {
+    cached_sha256(path, metadata)
+        .map_err(|e| warn!("Failed to compute checksum for {:?}: {}", path, e))
+        .ok()
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
@@ -0,0 +1,115 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, http::header, web};
+use log::{debug, error};
+
+use crate::zed::ChecksumManifest;
+
+use super::super::api_error::ApiError;
+use super::super::state::ServerState;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/checksums.json").to(get_checksum_manifest))
+        .service(web::resource("/checksums.json.sig").to(get_manifest_signature));
+}
+
+/// Checksums of every mirrored file, used by peers to skip archives they already have.
+/// Responds with 304 when the peer's `If-None-Match` matches the current manifest.
+pub async fn get_checksum_manifest(
+    req: HttpRequest,
+    state: web::Data<ServerState>,
+) -> impl Responder {
+    let manifest = match build_manifest(&state).await {
+        Ok(manifest) => manifest,
+        Err(response) => return response,
+    };
+
+    let etag = manifest.etag();
+    let unchanged = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+
+    if unchanged {
+        debug!("Checksum manifest unchanged ({})", etag);
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish();
+    }
+
+    debug!(
+        "Serving checksum manifest with {} files",
+        manifest.files.len()
+    );
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .json(manifest)
+}
+
+/// Detached signature of the current checksum manifest, taken over its canonical JSON.
+/// The `ETag` names the manifest revision that was signed.
+pub async fn get_manifest_signature(state: web::Data<ServerState>) -> impl Responder {
+    let Some(signer) = state.config.signer.clone() else {
+        return ApiError::NotFound("This mirror does not sign its manifest".to_string()).into();
+    };
+    let manifest = match build_manifest(&state).await {
+        Ok(manifest) => manifest,
+        Err(response) => return response,
+    };
+
+    let etag = manifest.etag();
+    let cached = state
+        .manifest_signature
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .filter(|(signed, _)| *signed == etag)
+        .map(|(_, signature)| signature.clone());
+
+    let signature = match cached {
+        Some(signature) => signature,
+        None => {
+            let bytes = manifest.canonical_bytes();
+            match web::block(move || signer.sign_bytes(&bytes)).await {
+                Ok(Ok(signature)) => {
+                    *state
+                        .manifest_signature
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner()) =
+                        Some((etag.clone(), signature.clone()));
+                    signature
+                }
+                Ok(Err(e)) => {
+                    error!("Failed to sign checksum manifest: {:#}", e);
+                    return ApiError::Internal("Failed to sign the checksum manifest".to_string())
+                        .into();
+                }
+                Err(e) => {
+                    error!("Manifest signing task failed: {}", e);
+                    return ApiError::Internal("Failed to sign the checksum manifest".to_string())
+                        .into();
+                }
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .content_type("application/octet-stream")
+        .body(signature)
+}
+
+async fn build_manifest(state: &ServerState) -> Result<ChecksumManifest, HttpResponse> {
+    let failed = || ApiError::Internal("Failed to build the checksum manifest".to_string()).into();
+    let extensions_dir = state.config.extensions_dir.clone();
+    match web::block(move || ChecksumManifest::build(&extensions_dir)).await {
+        Ok(Ok(manifest)) => Ok(manifest),
+        Ok(Err(e)) => {
+            error!("Failed to build checksum manifest: {}", e);
+            Err(failed())
+        }
+        Err(e) => {
+            error!("Checksum manifest task failed: {}", e);
+            Err(failed())
+        }
+    }
+}
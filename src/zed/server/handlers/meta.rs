@@ -0,0 +1,233 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use log::{debug, error};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::zed::{Extension, ExtensionAssets, README_FILE, cached_sha256, icon_content_type};
+
+use super::super::api_error::ApiError;
+use super::super::layers::{merged_index, merged_versions};
+use super::super::paths::{invalid_path, is_safe_segment};
+use super::super::state::ServerState;
+use super::super::streaming::stream_file;
+use super::super::verify::last_verified;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/extensions/{id}/meta").to(get_extension_meta))
+        .service(web::resource("/extensions/{id}/readme").to(get_extension_readme))
+        .service(web::resource("/extensions/{id}/icon").to(get_extension_icon));
+}
+
+/// Content type mirrored READMEs are served with
+const README_CONTENT_TYPE: &str = "text/markdown; charset=utf-8";
+
+/// Everything the mirror knows about an extension
+#[derive(Serialize)]
+struct ExtensionMeta {
+    id: String,
+    /// Entry of the mirrored upstream index
+    upstream: Option<Extension>,
+    /// Version the extension is pinned to
+    pinned: Option<String>,
+    /// Archives on disk, newest version first
+    files: Vec<MirroredFile>,
+    /// README and icon mirrored from the repository
+    assets: Option<ExtensionAssets>,
+}
+
+/// An archive of the extension in the cache
+#[derive(Serialize)]
+struct MirroredFile {
+    file: String,
+    /// Version of the archive, `None` for the unversioned latest archive
+    version: Option<String>,
+    size: u64,
+    sha256: String,
+    /// Unix timestamp the background verifier last found the archive intact
+    last_verified: Option<u64>,
+    yanked: bool,
+    quarantined: bool,
+    /// Upstream metadata of this version, from the mirrored version listing
+    metadata: Option<Extension>,
+}
+
+/// Upstream metadata of an extension merged with what is mirrored locally
+pub async fn get_extension_meta(
+    path: web::Path<String>,
+    state: web::Data<ServerState>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if !is_safe_segment(&id) {
+        return invalid_path(&id);
+    }
+    debug!("Metadata requested for extension {}", id);
+
+    let task_state = state.clone();
+    let task_id = id.clone();
+    match web::block(move || build_meta(&task_state, &task_id)).await {
+        Ok(Some(meta)) => HttpResponse::Ok().json(meta),
+        Ok(None) => ApiError::NotFound(format!("Extension not found: {}", id)).into(),
+        Err(e) => {
+            error!("Metadata task for {} failed: {}", id, e);
+            ApiError::Internal(format!("Failed to gather the metadata of {}", id)).into()
+        }
+    }
+}
+
+fn build_meta(state: &ServerState, id: &str) -> Option<ExtensionMeta> {
+    let upstream = merged_index(&state.config)
+        .ok()
+        .flatten()
+        .and_then(|index| index.into_iter().find(|ext| ext.id == id));
+
+    let mirrored = state
+        .config
+        .extensions_dirs()
+        .any(|extensions_dir| extensions_dir.join(id).is_dir());
+    if upstream.is_none() && !mirrored {
+        return None;
+    }
+
+    let mut version_metadata: BTreeMap<String, Extension> = merged_versions(&state.config, id)
+        .ok()
+        .flatten()
+        .map(|versions| {
+            versions
+                .into_iter()
+                .map(|ext| (ext.version.clone(), ext))
+                .collect()
+        })
+        .unwrap_or_default();
+    let quarantine = state.quarantine();
+
+    // An archive in several extensions directories is served from the first one
+    let mut seen = HashSet::new();
+    let mut files: Vec<MirroredFile> = Vec::new();
+    for extensions_dir in state.config.extensions_dirs() {
+        let verified = last_verified(extensions_dir);
+        files.extend(
+            fs::read_dir(extensions_dir.join(id))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|entry| seen.insert(entry.file_name()))
+                .filter_map(|entry| {
+                    let file = entry.file_name().to_str()?.to_string();
+                    let version = archive_version(id, &file)?;
+                    let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+                    let sha256 = match cached_sha256(&entry.path(), &metadata) {
+                        Ok(sha256) => sha256,
+                        Err(e) => {
+                            error!("Failed to hash {:?}: {:#}", entry.path(), e);
+                            return None;
+                        }
+                    };
+
+                    Some(MirroredFile {
+                        last_verified: verified.get(&format!("{}/{}", id, file)).copied(),
+                        yanked: version
+                            .as_deref()
+                            .is_some_and(|v| state.config.is_yanked(id, v)),
+                        quarantined: version
+                            .as_deref()
+                            .is_some_and(|v| quarantine.is_quarantined(id, v)),
+                        metadata: version.as_deref().and_then(|v| version_metadata.remove(v)),
+                        size: metadata.len(),
+                        sha256,
+                        version,
+                        file,
+                    })
+                }),
+        );
+    }
+    files.sort_by(|a, b| compare_versions(b.version.as_deref(), a.version.as_deref()));
+
+    Some(ExtensionMeta {
+        id: id.to_string(),
+        upstream,
+        pinned: state.config.pins.get(id).cloned(),
+        files,
+        assets: mirrored_assets(state, id).map(|(_, assets)| assets),
+    })
+}
+
+/// README of an extension, as mirrored from its repository
+pub async fn get_extension_readme(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let id = path.into_inner();
+    if !is_safe_segment(&id) {
+        return invalid_path(&id);
+    }
+    match mirrored_assets(&state, &id) {
+        Some((ext_dir, assets)) if assets.readme => {
+            serve_asset(&req, &ext_dir.join(README_FILE), README_CONTENT_TYPE).await
+        }
+        _ => ApiError::NotFound(format!("No README mirrored for {}", id)).into(),
+    }
+}
+
+/// Icon of an extension, as mirrored from its repository
+pub async fn get_extension_icon(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let id = path.into_inner();
+    if !is_safe_segment(&id) {
+        return invalid_path(&id);
+    }
+    match mirrored_assets(&state, &id).and_then(|(ext_dir, assets)| Some((ext_dir, assets.icon?))) {
+        Some((ext_dir, icon)) => {
+            serve_asset(&req, &ext_dir.join(&icon), icon_content_type(&icon)).await
+        }
+        None => ApiError::NotFound(format!("No icon mirrored for {}", id)).into(),
+    }
+}
+
+/// The directory of an extension holding its mirrored assets, first in lookup order, and
+/// the record of them
+fn mirrored_assets(state: &ServerState, id: &str) -> Option<(PathBuf, ExtensionAssets)> {
+    state.config.extensions_dirs().find_map(|extensions_dir| {
+        let ext_dir = extensions_dir.join(id);
+        ExtensionAssets::load(&ext_dir).map(|assets| (ext_dir, assets))
+    })
+}
+
+async fn serve_asset(req: &HttpRequest, path: &Path, content_type: &str) -> HttpResponse {
+    match stream_file(req, path, content_type).await {
+        Ok(response) => response,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            ApiError::NotFound("Asset not found".to_string()).into()
+        }
+        Err(e) => {
+            error!("Error reading asset {}: {}", path.display(), e);
+            ApiError::Internal("Failed to read the asset".to_string()).into()
+        }
+    }
+}
+
+/// Version of an extension archive name, `Some(None)` for the unversioned `<id>.tgz`
+fn archive_version(id: &str, file: &str) -> Option<Option<String>> {
+    let stem = file.strip_suffix(".tgz")?;
+    if stem == id {
+        return Some(None);
+    }
+    let version = stem.strip_prefix(id)?.strip_prefix('-')?;
+    Some(Some(version.to_string()))
+}
+
+/// Order versions by semver where possible, with the unversioned archive first
+fn compare_versions(a: Option<&str>, b: Option<&str>) -> std::cmp::Ordering {
+    match (a, b) {
+        (None, None) => std::cmp::Ordering::Equal,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (Some(a), Some(b)) => crate::zed::compare_versions(a, b),
+    }
+}
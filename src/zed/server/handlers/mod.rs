@@ -1,3 +1,11 @@
+pub mod admin;
+pub mod client_config;
+pub mod events;
 pub mod extensions;
+pub mod feed;
+pub mod listing;
+pub mod manifest;
+pub mod meta;
 pub mod proxy;
 pub mod releases;
+pub mod stats;
@@ -1,12 +1,132 @@
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
 
-use actix_web::{HttpResponse, Responder, http, web};
-use log::{debug, error, trace, warn};
+use actix_web::{HttpRequest, HttpResponse, Responder, http, web};
+use log::{debug, error, info, trace, warn};
 
+use crate::zed::{WrappedExtensions, upstream, write_atomic};
+
+use super::super::api_error::ApiError;
+use super::super::coalesce::{FetchError, UpstreamResponse};
+use super::super::paths::{invalid_path, is_safe_segment, safe_join};
+use super::super::response_cache::CachedResponse;
 use super::super::state::ServerState;
-use super::releases::serve_release_file;
+use super::super::upstream_schema::UpstreamSchema;
+use super::releases::{release_file_candidates, serve_release_file};
+
+/// Fetch an upstream URL, sharing a single request among all concurrent callers asking
+/// for the same URL, see [`Coalescer`](super::super::coalesce::Coalescer). Only the shared
+/// request takes an upstream slot.
+async fn fetch_coalesced(url: &str, state: &ServerState) -> Result<UpstreamResponse, FetchError> {
+    let owned = url.to_string();
+    let limit = state.config.upstream_limit.clone();
+    let client = state.http_client.clone();
+    state
+        .upstream_fetches
+        .run(url, move || async move {
+            let _permit = limit.acquire().await.map_err(FetchError::Busy)?;
+            let failed = |e: reqwest::Error| FetchError::Failed(Arc::new(e));
+            let response = upstream::authorize(client.get(&owned), &owned)
+                .send()
+                .await
+                .map_err(failed)?;
+            Ok(UpstreamResponse {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: response.bytes().await.map_err(failed)?,
+            })
+        })
+        .await
+}
+
+/// Relay a coalesced upstream download with its status and headers
+async fn proxy_artifact(url: &str, state: &ServerState) -> HttpResponse {
+    match fetch_coalesced(url, state).await {
+        Ok(response) => {
+            let mut builder = HttpResponse::build(response.status);
+
+            for (key, value) in response.headers.iter() {
+                if let Ok(header_value) = http::header::HeaderValue::from_bytes(value.as_bytes()) {
+                    builder.append_header((key.clone(), header_value));
+                }
+            }
+
+            builder.body(response.body)
+        }
+        Err(FetchError::Busy(busy)) => busy.response(),
+        Err(FetchError::Failed(e)) => {
+            error!("Failed to proxy download of {}: {}", url, e);
+            ApiError::upstream(&e).into()
+        }
+    }
+}
+
+/// Query parameters as they are forwarded upstream
+fn query_string(query: &HashMap<String, String>) -> String {
+    query
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Check a successful upstream response against its schema. Returns whether the body may
+/// be cached or persisted, or the 502 to answer with when mismatches aren't relayed.
+fn check_schema(
+    state: &ServerState,
+    schema: UpstreamSchema,
+    path: &str,
+    body: &[u8],
+) -> Result<bool, HttpResponse> {
+    let Err(mismatch) = schema.validate(body) else {
+        return Ok(true);
+    };
+    warn!("{} for {}", mismatch, path);
+    state.schema_mismatches.record(path, &mismatch);
+    if state.config.relay_schema_mismatches {
+        Ok(false)
+    } else {
+        Err(ApiError::BadGateway(mismatch.to_string()).into())
+    }
+}
+
+/// Serve an upstream API response from the proxy cache
+fn cached_response(path: &str, cached: CachedResponse) -> HttpResponse {
+    debug!("Serving cached upstream response for {}", path);
+    HttpResponse::build(http::StatusCode::from_u16(cached.status).unwrap_or(http::StatusCode::OK))
+        .content_type(cached.content_type)
+        .insert_header(("X-Cache", "HIT"))
+        .body(cached.body)
+}
+
+/// Answer a failed upstream request with the cached response past its TTL, while within
+/// the stale-if-error window, so clients keep working through upstream outages
+fn or_stale(state: &ServerState, path: &str, query: &str, response: HttpResponse) -> HttpResponse {
+    if !response.status().is_server_error() {
+        return response;
+    }
+    let Some(cached) = state.response_cache.get_stale(path, query) else {
+        return response;
+    };
+    let age = (chrono::Utc::now().timestamp() as u64).saturating_sub(cached.stored_at);
+    warn!(
+        "Upstream request for {} failed with {}, serving the cached response from {} seconds ago",
+        path,
+        response.status(),
+        age
+    );
+    HttpResponse::build(http::StatusCode::from_u16(cached.status).unwrap_or(http::StatusCode::OK))
+        .content_type(cached.content_type)
+        .insert_header(("X-Cache", "STALE"))
+        .insert_header((http::header::WARNING, "110 - \"Response is Stale\""))
+        .insert_header((http::header::AGE, age))
+        .body(cached.body)
+}
 
 pub async fn proxy_api_request(
+    req: HttpRequest,
     path: web::Path<String>,
     query: web::Query<HashMap<String, String>>,
     state: web::Data<ServerState>,
@@ -20,7 +140,7 @@ pub async fn proxy_api_request(
 
     if !state.config.proxy_mode {
         warn!("Rejecting proxy request in local mode: {}", path_str);
-        return HttpResponse::NotFound().body(format!("API path not found locally: {}", path_str));
+        return ApiError::NotFound(format!("API path not found locally: {}", path_str)).into();
     }
 
     if path_str.starts_with("api/releases/stable/") || path_str.starts_with("releases/stable/") {
@@ -30,171 +150,242 @@ pub async fn proxy_api_request(
         if parts.len() >= 4 {
             let version = parts[2];
             let filename = parts[3];
+            if !is_safe_segment(version) || !is_safe_segment(filename) {
+                return invalid_path(&path_str);
+            }
 
-            if let Some(releases_dir) = &state.config.releases_dir {
-                let zed_path = releases_dir.join("zed").join(format!(
-                    "zed-{}-{}.gz",
-                    version,
-                    filename.replace(".tar.gz", "")
-                ));
-                if zed_path.exists() {
-                    return serve_release_file(&zed_path);
-                }
-
-                let remote_server_path = releases_dir.join("zed-remote-server").join(format!(
-                    "zed-remote-server-{}-{}.gz",
-                    version,
-                    filename.replace(".tar.gz", "")
-                ));
-                if remote_server_path.exists() {
-                    return serve_release_file(&remote_server_path);
-                }
+            if let Some(releases_dir) = &state.config.releases_dir
+                && let Some(file_path) = release_file_candidates(releases_dir, version, filename)
+                    .into_iter()
+                    .find(|path| path.exists())
+            {
+                return serve_release_file(&req, &file_path).await;
             }
         }
     }
 
-    if state.config.releases_dir.is_some()
+    if let Some(releases_dir) = &state.config.releases_dir
         && path_str.starts_with("releases/")
         && path_str != "releases/latest"
     {
         let clean_path = path_str.split('?').next().unwrap_or(&path_str);
-        let file_path = state
-            .config
-            .releases_dir
-            .as_ref()
-            .unwrap()
-            .join(clean_path.trim_start_matches("releases/"));
+        let Some(file_path) = safe_join(releases_dir, clean_path.trim_start_matches("releases/"))
+        else {
+            return invalid_path(&path_str);
+        };
         debug!("Attempting to serve release file from: {:?}", file_path);
 
         if file_path.exists() {
-            return serve_release_file(&file_path);
+            return serve_release_file(&req, &file_path).await;
         } else {
             debug!("Release file not found locally: {:?}", file_path);
         }
     }
 
-    let client = match reqwest::Client::builder().user_agent("zedex").build() {
-        Ok(client) => client,
-        Err(e) => {
-            error!("Error creating HTTP client: {}", e);
-            return HttpResponse::InternalServerError()
-                .body(format!("Error creating HTTP client: {}", e));
-        }
-    };
-    let mut url = format!("https://zed.dev/api/{}", path_str);
+    if !state.config.proxy_rules.permits(&path_str) {
+        warn!("Proxy rules deny request for API path: {}", path_str);
+        return ApiError::Forbidden(format!(
+            "Proxying of API path {} is not allowed on this mirror",
+            path_str
+        ))
+        .into();
+    }
+
+    let query_string = query_string(&query);
+    if let Some(cached) = state.response_cache.get(&path_str, &query_string) {
+        return cached_response(&path_str, cached);
+    }
 
-    if !query.is_empty() {
+    let response = fetch_api_response(&state, &path_str, &query_string).await;
+    or_stale(&state, &path_str, &query_string, response)
+}
+
+/// Forward an API request upstream, caching the response when its path is cached
+async fn fetch_api_response(
+    state: &ServerState,
+    path_str: &str,
+    query_string: &str,
+) -> HttpResponse {
+    let mut url = format!("https://zed.dev/api/{}", path_str);
+    if !query_string.is_empty() {
         url.push('?');
-        let query_string = query
-            .iter()
-            .map(|(k, v)| format!("{}={}", k, v))
-            .collect::<Vec<_>>()
-            .join("&");
-        url.push_str(&query_string);
+        url.push_str(query_string);
     }
 
     debug!("Proxying request to: {}", url);
 
-    match client.get(&url).send().await {
+    // Release archives are large and requested by many clients at once after a release
+    match fetch_coalesced(&url, state).await {
         Ok(response) => {
-            let status = response.status();
-            debug!("Proxy response status: {}", status);
+            debug!("Proxy response status: {}", response.status);
 
             let content_type = response
-                .headers()
+                .headers
                 .get(http::header::CONTENT_TYPE)
                 .and_then(|h| h.to_str().ok())
                 .unwrap_or("application/json")
                 .to_string();
 
-            let body = response.bytes().await.unwrap_or_default();
-
             debug!("Response content type: {}", content_type);
-            debug!("Response size: {} bytes", body.len());
+            debug!("Response size: {} bytes", response.body.len());
+            let cacheable =
+                match UpstreamSchema::for_path(path_str).filter(|_| response.status.is_success()) {
+                    Some(schema) => match check_schema(state, schema, path_str, &response.body) {
+                        Ok(valid) => valid,
+                        Err(rejected) => return rejected,
+                    },
+                    None => true,
+                };
+            if cacheable {
+                state.response_cache.put(
+                    path_str,
+                    query_string,
+                    response.status.as_u16(),
+                    &content_type,
+                    response.body.clone(),
+                );
+            }
 
             HttpResponse::build(
-                http::StatusCode::from_u16(status.as_u16()).unwrap_or(http::StatusCode::OK),
+                http::StatusCode::from_u16(response.status.as_u16())
+                    .unwrap_or(http::StatusCode::OK),
             )
             .content_type(content_type)
-            .body(body)
+            .body(response.body)
         }
-        Err(e) => {
+        Err(FetchError::Busy(busy)) => busy.response(),
+        Err(FetchError::Failed(e)) => {
             error!("Error proxying request: {}", e);
-            HttpResponse::InternalServerError().body(format!("Error proxying request: {}", e))
+            ApiError::upstream(&e).into()
         }
     }
 }
 
-pub async fn proxy_extensions_updates(query: web::Query<HashMap<String, String>>) -> HttpResponse {
+pub async fn proxy_extensions_updates(
+    query: web::Query<HashMap<String, String>>,
+    state: &ServerState,
+) -> HttpResponse {
     debug!("Proxying extension updates request to api.zed.dev");
 
-    let client = match reqwest::Client::builder().user_agent("zedex").build() {
-        Ok(client) => client,
-        Err(e) => {
-            error!("Error creating HTTP client: {}", e);
-            return HttpResponse::InternalServerError()
-                .body(format!("Error creating HTTP client: {}", e));
-        }
-    };
+    let query_string = query_string(&query);
+    if let Some(cached) = state
+        .response_cache
+        .get("extensions/updates", &query_string)
+    {
+        return cached_response("extensions/updates", cached);
+    }
 
-    let mut url = "https://api.zed.dev/extensions/updates".to_string();
+    let response = fetch_extensions_updates(&query_string, state).await;
+    or_stale(state, "extensions/updates", &query_string, response)
+}
 
-    if !query.is_empty() {
+async fn fetch_extensions_updates(query_string: &str, state: &ServerState) -> HttpResponse {
+    let mut url = "https://api.zed.dev/extensions/updates".to_string();
+    if !query_string.is_empty() {
         url.push('?');
-        let query_string = query
-            .iter()
-            .map(|(k, v)| format!("{}={}", k, v))
-            .collect::<Vec<_>>()
-            .join("&");
-        url.push_str(&query_string);
+        url.push_str(query_string);
     }
 
     debug!("Proxying extension updates to: {}", url);
 
-    match client.get(&url).send().await {
+    let _permit = match state.config.upstream_limit.acquire().await {
+        Ok(permit) => permit,
+        Err(busy) => return busy.response(),
+    };
+    match upstream::authorize(state.http_client.get(&url), &url)
+        .send()
+        .await
+    {
         Ok(response) => match response.error_for_status() {
             Ok(response) => match response.bytes().await {
-                Ok(bytes) => HttpResponse::Ok()
-                    .content_type("application/json")
-                    .body(bytes),
+                Ok(bytes) => {
+                    match check_schema(state, UpstreamSchema::Index, "extensions/updates", &bytes) {
+                        Ok(true) => state.response_cache.put(
+                            "extensions/updates",
+                            query_string,
+                            200,
+                            "application/json",
+                            bytes.clone(),
+                        ),
+                        Ok(false) => {}
+                        Err(rejected) => return rejected,
+                    }
+                    HttpResponse::Ok()
+                        .content_type("application/json")
+                        .body(bytes)
+                }
                 Err(e) => {
                     error!("Error reading proxied response: {}", e);
-                    HttpResponse::InternalServerError()
-                        .body(format!("Error reading proxied response: {}", e))
+                    ApiError::upstream(&e).into()
                 }
             },
             Err(e) => {
                 error!("Error from proxied server: {}", e);
-                match e.status() {
-                    Some(status) => HttpResponse::build(
-                        http::StatusCode::from_u16(status.as_u16())
-                            .unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR),
-                    )
-                    .body(format!("Error from zed.dev: {}", e)),
-                    None => HttpResponse::InternalServerError()
-                        .body(format!("Error from zed.dev: {}", e)),
-                }
+                ApiError::upstream(&e).into()
             }
         },
         Err(e) => {
             error!("Error proxying request: {}", e);
-            HttpResponse::InternalServerError().body(format!("Error proxying request: {}", e))
+            ApiError::upstream(&e).into()
         }
     }
 }
 
-pub async fn proxy_extension_versions(extension_id: String) -> HttpResponse {
-    let url = format!("https://api.zed.dev/extensions/{}", extension_id);
+pub async fn proxy_extension_versions(extension_id: String, state: &ServerState) -> HttpResponse {
+    let path = format!("extensions/{}", extension_id);
+    if let Some(cached) = state.response_cache.get(&path, "") {
+        return cached_response(&path, cached);
+    }
+
+    let response = fetch_extension_versions(&extension_id, &path, state).await;
+    or_stale(state, &path, "", response)
+}
+
+async fn fetch_extension_versions(
+    extension_id: &str,
+    path: &str,
+    state: &ServerState,
+) -> HttpResponse {
+    let url = format!("https://api.zed.dev/{}", path);
     debug!("Proxying extension versions request to: {}", url);
 
-    let client = reqwest::Client::new();
-    match client.get(&url).send().await {
+    let _permit = match state.config.upstream_limit.acquire().await {
+        Ok(permit) => permit,
+        Err(busy) => return busy.response(),
+    };
+    match upstream::authorize(state.http_client.get(&url), &url)
+        .send()
+        .await
+    {
         Ok(resp) => {
             let status = resp.status();
             let headers = resp.headers().clone();
 
             match resp.bytes().await {
                 Ok(bytes) => {
+                    let valid = if status.is_success() {
+                        match check_schema(state, UpstreamSchema::Versions, path, &bytes) {
+                            Ok(valid) => valid,
+                            Err(rejected) => return rejected,
+                        }
+                    } else {
+                        false
+                    };
+                    if valid {
+                        persist_versions(extension_id, &bytes, &state.config.extensions_dir);
+                        let content_type = headers
+                            .get(reqwest::header::CONTENT_TYPE)
+                            .and_then(|h| h.to_str().ok())
+                            .unwrap_or("application/json");
+                        state.response_cache.put(
+                            path,
+                            "",
+                            status.as_u16(),
+                            content_type,
+                            bytes.clone(),
+                        );
+                    }
+
                     let mut builder = HttpResponse::build(status);
 
                     for (key, value) in headers.iter() {
@@ -209,137 +400,140 @@ pub async fn proxy_extension_versions(extension_id: String) -> HttpResponse {
                 }
                 Err(e) => {
                     error!("Failed to get response body from proxy request: {}", e);
-                    HttpResponse::InternalServerError().body(format!("Proxy error: {}", e))
+                    ApiError::upstream(&e).into()
                 }
             }
         }
         Err(e) => {
             error!("Failed to proxy extension versions request: {}", e);
-            HttpResponse::InternalServerError().body(format!("Proxy error: {}", e))
+            ApiError::upstream(&e).into()
+        }
+    }
+}
+
+/// Store a proxied version listing as the extension's versions.json so later
+/// lookups and highest-version fallbacks can be served locally
+fn persist_versions(extension_id: &str, body: &[u8], extensions_dir: &Path) {
+    if !is_safe_segment(extension_id) {
+        warn!("Not caching versions for suspicious id: {}", extension_id);
+        return;
+    }
+
+    let versions = match serde_json::from_slice::<WrappedExtensions>(body) {
+        Ok(versions) => versions,
+        Err(e) => {
+            warn!(
+                "Not caching versions for {}: unexpected upstream response: {}",
+                extension_id, e
+            );
+            return;
         }
+    };
+
+    let count = versions.data.len();
+    let ext_dir = extensions_dir.join(extension_id);
+    let result = fs::create_dir_all(&ext_dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| {
+            let json = versions.into_sorted_json()?;
+            write_atomic(&ext_dir.join("versions.json"), json)
+        });
+
+    match result {
+        Ok(_) => info!("Cached {} upstream versions for {}", count, extension_id),
+        Err(e) => error!("Failed to cache versions for {}: {:#}", extension_id, e),
     }
 }
 
-pub async fn proxy_download_request(extension_id: String) -> HttpResponse {
+pub async fn proxy_download_request(extension_id: String, state: &ServerState) -> HttpResponse {
     let url = format!(
         "https://api.zed.dev/extensions/{}/download?min_schema_version=0&max_schema_version=100&min_wasm_api_version=0.0.0&max_wasm_api_version=100.0.0",
         extension_id
     );
     debug!("Proxying extension download request to: {}", url);
-
-    let client = reqwest::Client::new();
-    match client.get(&url).send().await {
-        Ok(resp) => {
-            let status = resp.status();
-            let headers = resp.headers().clone();
-
-            match resp.bytes().await {
-                Ok(bytes) => {
-                    let mut builder = HttpResponse::build(status);
-
-                    for (key, value) in headers.iter() {
-                        if let Ok(header_value) =
-                            http::header::HeaderValue::from_bytes(value.as_bytes())
-                        {
-                            builder.append_header((key.clone(), header_value));
-                        }
-                    }
-
-                    builder.body(bytes)
-                }
-                Err(e) => {
-                    error!("Failed to get response body from proxy request: {}", e);
-                    HttpResponse::InternalServerError().body(format!("Proxy error: {}", e))
-                }
-            }
-        }
-        Err(e) => {
-            error!("Failed to proxy extension download request: {}", e);
-            HttpResponse::InternalServerError().body(format!("Proxy error: {}", e))
-        }
-    }
+    proxy_artifact(&url, state).await
 }
 
-pub async fn proxy_download_version_request(extension_id: String, version: String) -> HttpResponse {
+pub async fn proxy_download_version_request(
+    extension_id: String,
+    version: String,
+    state: &ServerState,
+) -> HttpResponse {
     let url = format!(
         "https://api.zed.dev/extensions/{}/{}/download",
         extension_id, version
     );
     debug!("Proxying versioned extension download request to: {}", url);
-
-    let client = reqwest::Client::new();
-    match client.get(&url).send().await {
-        Ok(resp) => {
-            let status = resp.status();
-            let headers = resp.headers().clone();
-
-            match resp.bytes().await {
-                Ok(bytes) => {
-                    let mut builder = HttpResponse::build(status);
-
-                    for (key, value) in headers.iter() {
-                        if let Ok(header_value) =
-                            http::header::HeaderValue::from_bytes(value.as_bytes())
-                        {
-                            builder.append_header((key.clone(), header_value));
-                        }
-                    }
-
-                    builder.body(bytes)
-                }
-                Err(e) => {
-                    error!("Failed to get response body from proxy request: {}", e);
-                    HttpResponse::InternalServerError().body(format!("Proxy error: {}", e))
-                }
-            }
-        }
-        Err(e) => {
-            error!("Failed to proxy extension version download request: {}", e);
-            HttpResponse::InternalServerError().body(format!("Proxy error: {}", e))
-        }
-    }
+    proxy_artifact(&url, state).await
 }
 
-pub async fn proxy_version_request(os: String, arch: String, asset: String) -> HttpResponse {
+pub async fn proxy_version_request(
+    os: String,
+    arch: String,
+    asset: String,
+    state: &ServerState,
+) -> HttpResponse {
     debug!(
         "Proxying version request for {}-{}-{} to zed.dev",
         asset, os, arch
     );
 
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://zed.dev/api/releases/latest?asset={}&os={}&arch={}",
-        asset, os, arch
-    );
+    let query_string = format!("asset={}&os={}&arch={}", asset, os, arch);
+    if let Some(cached) = state.response_cache.get("releases/latest", &query_string) {
+        return cached_response("releases/latest", cached);
+    }
 
-    match client.get(&url).send().await {
+    let response = fetch_latest_version(&query_string, state).await;
+    or_stale(state, "releases/latest", &query_string, response)
+}
+
+async fn fetch_latest_version(query_string: &str, state: &ServerState) -> HttpResponse {
+    let _permit = match state.config.upstream_limit.acquire().await {
+        Ok(permit) => permit,
+        Err(busy) => return busy.response(),
+    };
+    let url = format!("https://zed.dev/api/releases/latest?{}", query_string);
+
+    match upstream::authorize(state.http_client.get(&url), &url)
+        .send()
+        .await
+    {
         Ok(response) => match response.error_for_status() {
             Ok(response) => match response.bytes().await {
-                Ok(bytes) => HttpResponse::Ok()
-                    .content_type("application/json")
-                    .body(bytes),
+                Ok(bytes) => {
+                    match check_schema(
+                        state,
+                        UpstreamSchema::LatestRelease,
+                        "releases/latest",
+                        &bytes,
+                    ) {
+                        Ok(true) => state.response_cache.put(
+                            "releases/latest",
+                            query_string,
+                            200,
+                            "application/json",
+                            bytes.clone(),
+                        ),
+                        Ok(false) => {}
+                        Err(rejected) => return rejected,
+                    }
+                    HttpResponse::Ok()
+                        .content_type("application/json")
+                        .body(bytes)
+                }
                 Err(e) => {
                     error!("Error reading proxied response: {}", e);
-                    HttpResponse::InternalServerError()
-                        .body(format!("Error reading proxied response: {}", e))
+                    ApiError::upstream(&e).into()
                 }
             },
             Err(e) => {
                 error!("Error from proxied server: {}", e);
-                match e.status() {
-                    Some(status) => HttpResponse::build(
-                        http::StatusCode::from_u16(status.as_u16())
-                            .unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR),
-                    )
-                    .body(format!("Error from zed.dev: {}", e)),
-                    None => HttpResponse::InternalServerError()
-                        .body(format!("Error from zed.dev: {}", e)),
-                }
+                ApiError::upstream(&e).into()
             }
         },
         Err(e) => {
             error!("Error proxying request: {}", e);
-            HttpResponse::InternalServerError().body(format!("Error proxying request: {}", e))
+            ApiError::upstream(&e).into()
         }
     }
 }
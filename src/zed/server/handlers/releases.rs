@@ -1,28 +1,50 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use actix_files::Files;
-use actix_web::{HttpResponse, Responder, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
 use log::{debug, error, info, warn};
 
-use crate::zed::Version;
+use crate::zed::{Version, layout, strip_signature};
 
+use super::super::api_error::ApiError;
+use super::super::paths::{invalid_path, is_safe_segment, safe_join};
 use super::super::state::ServerState;
+use super::super::streaming::stream_file;
+use super::listing::render_listing;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/api/releases/latest").to(get_latest_version))
         .service(web::resource("/api/releases/{channel}/latest").to(get_latest_version))
+        .service(web::resource("/api/releases/{channel}/{version}/asset").to(get_release_asset))
         .service(
             web::resource("/api/releases/{channel}/{version}/{filename}").to(serve_release_api),
-        );
+        )
+        // Route family used by newer Zed releases
+        .service(web::resource("/releases/{channel}/latest").to(get_latest_version))
+        .service(web::resource("/releases/{channel}/latest/asset").to(get_latest_version))
+        // Asset of an exact version, as requested for the remote server when SSH-ing into a host
+        .service(web::resource("/releases/{channel}/{version}/asset").to(get_release_asset));
 }
 
-pub fn configure_static_assets(cfg: &mut web::ServiceConfig, releases_dir: PathBuf) {
-    cfg.service(Files::new("/releases", releases_dir));
+pub fn configure_static_assets(cfg: &mut web::ServiceConfig, releases_dir: PathBuf, browse: bool) {
+    let files = Files::new("/releases", releases_dir);
+    if browse {
+        cfg.service(
+            files
+                .show_files_listing()
+                .redirect_to_slash_directory()
+                .files_listing_renderer(render_listing),
+        );
+    } else {
+        cfg.service(files);
+    }
 }
 
 pub async fn get_latest_version(
+    req: HttpRequest,
     path: Option<web::Path<String>>,
     state: web::Data<ServerState>,
     query: web::Query<HashMap<String, String>>,
@@ -48,7 +70,11 @@ pub async fn get_latest_version(
     }
 
     if let Some(releases_dir) = &state.config.releases_dir {
-        let platform_version_file = releases_dir.join(format!("{asset}-{os}-{arch}.json"));
+        let Some(platform_version_file) =
+            safe_join(releases_dir, &format!("{asset}-{os}-{arch}.json"))
+        else {
+            return invalid_path(&format!("{asset}-{os}-{arch}"));
+        };
         info!(
             "Looking for platform-specific version file: {:?}",
             platform_version_file
@@ -59,37 +85,95 @@ pub async fn get_latest_version(
                 "Found platform-specific version file: {:?}",
                 platform_version_file
             );
-            return read_version_file(
-                platform_version_file,
-                state.config.domain.as_ref().map(|x| x.as_str()),
-            );
+            return read_version_file(platform_version_file, &state.config.external_url(&req));
         }
 
-        if state.config.proxy_mode {
-            return super::proxy::proxy_version_request(os, arch, asset).await;
+        if state.config.proxy_allowed("releases/latest") {
+            return super::proxy::proxy_version_request(os, arch, asset, &state).await;
         }
 
-        HttpResponse::NotFound()
-            .content_type("text/plain")
-            .body(format!(
-                "Version file not found for asset {} on platform {}-{}",
-                asset, os, arch
-            ))
+        ApiError::NotFound(format!(
+            "Version file not found for asset {} on platform {}-{}",
+            asset, os, arch
+        ))
+        .into()
     } else {
-        HttpResponse::NotFound()
-            .content_type("text/plain")
-            .body("Releases directory not configured")
+        ApiError::NotFound("Releases directory not configured".to_string()).into()
     }
 }
 
-pub fn read_version_file(file_path: PathBuf, domain: Option<&str>) -> HttpResponse {
+/// Download location of an asset of an exact Zed version, e.g. the `zed-remote-server`
+/// matching the client's version, pointing at this mirror
+pub async fn get_release_asset(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    state: web::Data<ServerState>,
+    query: web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    let (channel, version) = path.into_inner();
+    if version == "latest" {
+        return get_latest_version(req.clone(), Some(web::Path::from(channel)), state, query)
+            .await
+            .respond_to(&req)
+            .map_into_boxed_body();
+    }
+
+    let param = |name: &str| query.get(name).map(String::as_str).unwrap_or_default();
+    let (asset, os, arch) = (param("asset"), param("os"), param("arch"));
+    info!(
+        "Release asset request for channel={channel}, version={version}, asset={asset}, os={os}, arch={arch}"
+    );
+    if ![channel.as_str(), &version, asset, os, arch]
+        .into_iter()
+        .all(is_safe_segment)
+    {
+        return invalid_path(&format!("{channel}/{version}/{asset}-{os}-{arch}"));
+    }
+
+    let filename = format!("{asset}-{os}-{arch}.gz");
+    let mirrored = state
+        .config
+        .releases_dir
+        .as_ref()
+        .is_some_and(|releases_dir| {
+            release_file_candidates(releases_dir, &version, &filename)
+                .iter()
+                .any(|path| path.exists())
+        });
+    if !mirrored {
+        warn!("No mirrored {} for Zed {}", filename, version);
+        return ApiError::NotFound(format!(
+            "{} {} for {}-{} is not mirrored",
+            asset, version, os, arch
+        ))
+        .into();
+    }
+
+    let base_url = state.config.external_url(&req);
+    HttpResponse::Ok().json(Version {
+        url: format!("{base_url}/api/releases/{channel}/{version}/{filename}"),
+        version,
+    })
+}
+
+/// Mirrored files that can serve a release download named `filename`, see
+/// [`layout::release_file_candidates`], none if the requested names are unsafe
+pub fn release_file_candidates(releases_dir: &Path, version: &str, filename: &str) -> Vec<PathBuf> {
+    if !is_safe_segment(version) || !is_safe_segment(filename) {
+        return Vec::new();
+    }
+    layout::release_file_candidates(releases_dir, version, filename)
+}
+
+/// Serve a mirrored version file with its zed.dev download link pointed at `base_url`
+pub fn read_version_file(file_path: PathBuf, base_url: &str) -> HttpResponse {
     debug!("Reading version file: {:?}", file_path);
     match fs::read_to_string(&file_path) {
         Ok(content) => match serde_json::from_str::<Version>(&content) {
             Ok(mut version) => {
-                if let Some(domain) = domain {
-                    version.url = version.url.replace("https://zed.dev", domain);
-                }
+                // Files written before signatures were stripped on download
+                version.url = strip_signature(&version.url);
+                version.url = version.url.replace("https://zed.dev", base_url);
 
                 info!("Successfully read version file: {:?}", file_path);
                 HttpResponse::Ok()
@@ -102,42 +186,47 @@ pub fn read_version_file(file_path: PathBuf, domain: Option<&str>) -> HttpRespon
                     file_path.display(),
                     e
                 );
-                HttpResponse::InternalServerError()
-                    .body(format!("Error parsing version file: {}", e))
+                ApiError::Internal("The release version file is corrupt".to_string()).into()
             }
         },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            ApiError::NotFound("Release version file not found".to_string()).into()
+        }
         Err(e) => {
             error!("Failed to read version file {}: {}", file_path.display(), e);
-            HttpResponse::InternalServerError().body(format!("Error reading version file: {}", e))
+            ApiError::Internal("Failed to read the release version file".to_string()).into()
         }
     }
 }
 
-pub fn serve_release_file(file_path: &PathBuf) -> HttpResponse {
-    match fs::read(file_path) {
-        Ok(bytes) => {
-            let content_type = match file_path.extension().and_then(|e| e.to_str()) {
-                Some("dmg") => "application/x-apple-diskimage",
-                Some("zip") => "application/zip",
-                Some("exe") => "application/vnd.microsoft.portable-executable",
-                Some("AppImage") => "application/x-executable",
-                Some("json") => "application/json",
-                Some("gz") => "application/gzip",
-                Some("tar") => "application/x-tar",
-                _ => "application/octet-stream",
-            };
-
+pub async fn serve_release_file(req: &HttpRequest, file_path: &Path) -> HttpResponse {
+    let content_type = match file_path.extension().and_then(|e| e.to_str()) {
+        Some("dmg") => "application/x-apple-diskimage",
+        Some("zip") => "application/zip",
+        Some("exe") => "application/vnd.microsoft.portable-executable",
+        Some("AppImage") => "application/x-executable",
+        Some("json") => "application/json",
+        Some("gz") => "application/gzip",
+        Some("tar") => "application/x-tar",
+        _ => "application/octet-stream",
+    };
+    match stream_file(req, file_path, content_type).await {
+        Ok(response) => {
             info!("Serving release file with content type: {}", content_type);
-            HttpResponse::Ok().content_type(content_type).body(bytes)
+            response
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            ApiError::NotFound("Release file not found".to_string()).into()
         }
         Err(e) => {
-            error!("Error reading release file: {}", e);
-            HttpResponse::InternalServerError().body(format!("Error reading release file: {}", e))
+            error!("Error reading release file {}: {}", file_path.display(), e);
+            ApiError::Internal("Failed to read the release file".to_string()).into()
         }
     }
 }
 
 pub async fn serve_release_api(
+    req: HttpRequest,
     path: web::Path<(String, String, String)>,
     state: web::Data<ServerState>,
 ) -> impl Responder {
@@ -149,19 +238,22 @@ pub async fn serve_release_api(
     );
 
     if let Some(releases_dir) = &state.config.releases_dir {
-        let file_path = releases_dir.join(format!("{version}/{asset}"));
+        let candidates = release_file_candidates(releases_dir, &version, &asset);
+        if candidates.is_empty() {
+            return invalid_path(&format!("{channel}/{version}/{asset}"));
+        }
 
-        info!("Looking for release file at: {:?}", file_path);
+        info!("Looking for release file at: {:?}", candidates);
 
-        if file_path.exists() {
-            return serve_release_file(&file_path);
-        } else {
-            warn!("Release file not found: {:?}", file_path);
+        match candidates.iter().find(|path| path.exists()) {
+            Some(file_path) => return serve_release_file(&req, file_path).await,
+            None => warn!("Release file not found: {:?}", candidates),
         }
     }
 
-    HttpResponse::NotFound().body(format!(
+    ApiError::NotFound(format!(
         "Release file not found for {} {} {}",
         channel, version, asset
     ))
+    .into()
 }
@@ -0,0 +1,44 @@
+use actix_web::{HttpResponse, Responder, web};
+use log::debug;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::zed::TransferCounters;
+
+use super::super::drift::DriftReport;
+use super::super::layers::merged_index;
+use super::super::state::ServerState;
+use super::super::upstream_schema::{MismatchCount, UpstreamSchema};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/stats").to(get_stats));
+}
+
+/// Mirror statistics response structure
+#[derive(Serialize)]
+struct StatsResponse {
+    /// Number of extensions in the served index
+    extensions_indexed: usize,
+    /// Result of the last upstream drift check
+    upstream_drift: Option<DriftReport>,
+    /// Bytes served per endpoint, extension and day
+    transfer: TransferCounters,
+    /// Proxied upstream responses that didn't match their expected schema
+    upstream_schema_mismatches: BTreeMap<UpstreamSchema, MismatchCount>,
+}
+
+pub async fn get_stats(state: web::Data<ServerState>) -> impl Responder {
+    debug!("Stats requested");
+
+    let extensions_indexed = merged_index(&state.config)
+        .ok()
+        .flatten()
+        .map_or(0, |extensions| extensions.len());
+
+    HttpResponse::Ok().json(StatsResponse {
+        extensions_indexed,
+        upstream_drift: state.drift_report(),
+        transfer: state.transfer.snapshot(),
+        upstream_schema_mismatches: state.schema_mismatches.snapshot(),
+    })
+}
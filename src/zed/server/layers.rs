@@ -0,0 +1,85 @@
+use anyhow::{Context, Result};
+use log::warn;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::zed::{
+    ExtensionVersionTracker, Extensions, WrappedExtensions, find_archive, load_index,
+};
+
+use super::config::ServerConfig;
+
+/// The index merged across the extensions directories, the first directory listing an
+/// extension providing its entry. `None` when no directory has an `extensions.json`.
+pub fn merged_index(config: &ServerConfig) -> Result<Option<Extensions>> {
+    let mut merged: Option<Extensions> = None;
+    let mut seen = HashSet::new();
+    for dir in config.extensions_dirs() {
+        let index_file = dir.join("extensions.json");
+        if !index_file.is_file() {
+            continue;
+        }
+        let index = load_index(&index_file, config.strict_index)?;
+        merged
+            .get_or_insert_with(Vec::new)
+            .extend(index.into_iter().filter(|ext| seen.insert(ext.id.clone())));
+    }
+    if let Some(merged) = &mut merged {
+        merged.sort_by(|a, b| a.id.cmp(&b.id));
+    }
+    Ok(merged)
+}
+
+/// The versions of an extension merged across the extensions directories, the first
+/// directory listing a version providing its entry. `None` when no directory has a
+/// `versions.json` for it.
+pub fn merged_versions(config: &ServerConfig, id: &str) -> Result<Option<Extensions>> {
+    let mut merged: Option<Extensions> = None;
+    let mut seen = HashSet::new();
+    for dir in config.extensions_dirs() {
+        let versions_file = dir.join(id).join("versions.json");
+        if !versions_file.is_file() {
+            continue;
+        }
+        let content = fs::read_to_string(&versions_file)
+            .with_context(|| format!("Cannot read {:?}", versions_file))?;
+        let versions = serde_json::from_str::<WrappedExtensions>(&content)
+            .with_context(|| format!("Cannot parse {:?}", versions_file))?;
+        merged.get_or_insert_with(Vec::new).extend(
+            versions
+                .data
+                .into_iter()
+                .filter(|ext| seen.insert(ext.version.clone())),
+        );
+    }
+    Ok(merged)
+}
+
+/// An extension archive from the first extensions directory holding it
+pub fn find_layered_archive(
+    config: &ServerConfig,
+    id: &str,
+    version: Option<&str>,
+) -> Option<PathBuf> {
+    config
+        .extensions_dirs()
+        .find_map(|dir| find_archive(dir, id, version))
+}
+
+/// The latest-version archive of an extension from the first extensions directory holding
+/// one, with its version according to that directory's version tracker
+pub fn latest_archive(config: &ServerConfig, id: &str) -> Option<(PathBuf, Option<String>)> {
+    config.extensions_dirs().find_map(|dir| {
+        let archive = find_archive(dir, id, None)?;
+        let version = fs::read_to_string(dir.join("version_tracker.json"))
+            .ok()
+            .and_then(|content| {
+                serde_json::from_str::<ExtensionVersionTracker>(&content)
+                    .inspect_err(|e| warn!("Invalid version tracker in {:?}: {}", dir, e))
+                    .ok()
+            })
+            .and_then(|mut tracker| tracker.extensions.remove(id));
+        Some((archive, version))
+    })
+}
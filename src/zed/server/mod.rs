@@ -1,17 +1,68 @@
+mod api_error;
+mod coalesce;
 mod config;
+mod drift;
+mod external;
 mod handlers;
+mod layers;
+mod paths;
+mod pull;
+mod response_cache;
 mod state;
+mod streaming;
+mod timeouts;
+mod tls;
+mod upstream_limit;
+mod upstream_schema;
+mod verify;
+mod views;
+mod virtual_hosts;
+mod watcher;
 
-pub use config::ServerConfig;
+pub use config::{
+    CorsPolicy, IndexChannel, ProxyCache, ProxyRules, ServerConfig, ServerTimeouts, TlsConfig,
+    VirtualHosts,
+};
+pub use drift::DriftReport;
+pub use state::ServerState;
+pub use upstream_limit::UpstreamLimit;
+pub use upstream_schema::UpstreamSchema;
+pub use verify::{AUDIT_LOG_FILE, VERIFIED_CHECKSUMS_FILE, VerifyReport};
 
-use super::health;
+use super::{
+    QUARANTINE_DIR, REQUEST_ID_HEADER, ServedVersion, announce, archive_version, health,
+    in_request, load_index, request_id_or_new, tracked_version, upstream,
+};
+use actix_cors::Cors;
 use actix_files::Files;
-use actix_web::{App, HttpServer, middleware::Logger, web};
-use anyhow::Result;
-use handlers::{extensions, proxy, releases};
-use log::{info, warn};
-use state::ServerState;
+use actix_web::{
+    App, HttpResponse, HttpServer,
+    body::{BodySize, MessageBody},
+    dev::{HttpServiceFactory, Service, ServiceRequest},
+    http::header,
+    middleware::{Condition, Logger},
+    web,
+};
+use anyhow::{Context, Result, bail};
+use api_error::ApiError;
+use futures_util::future::{self, Either};
+use handlers::{
+    admin, client_config, events, extensions, feed, listing, manifest, meta, proxy, releases, stats,
+};
+use log::{debug, info, warn};
 use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use timeouts::DeadlineBody;
+
+const HEALTH_CHECK_PATH: &str = "/health";
+
+/// actix's default access log line, followed by the request ID
+const ACCESS_LOG_FORMAT: &str =
+    r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#;
+
+/// How often transfer counters are written to disk
+const TRANSFER_STATS_PERSIST_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct LocalServer {
     config: ServerConfig,
@@ -23,58 +74,527 @@ impl LocalServer {
     }
 
     pub async fn run(&self) -> Result<()> {
-        const HEALTH_CHECK_PATH: &str = "/health";
-
         health::init();
         log_server_banner(&self.config, HEALTH_CHECK_PATH)?;
 
-        let server_state = web::Data::new(ServerState::new(self.config.clone()));
+        // Report invalid index entries up front, and refuse to start on them in strict mode
+        for config in std::iter::once(&self.config).chain(self.config.tenants.values()) {
+            for extensions_dir in config.extensions_dirs() {
+                let index_file = extensions_dir.join("extensions.json");
+                if index_file.exists() {
+                    load_index(&index_file, config.strict_index)?;
+                }
+            }
+        }
+
+        let http_client = upstream::http_client()?;
+        let server_state =
+            web::Data::new(ServerState::new(self.config.clone(), http_client.clone()));
+        spawn_background_tasks(&server_state);
 
-        HttpServer::new(move || {
-            let state = server_state.clone();
-            let config = state.config();
+        let tenant_states: Vec<(String, web::Data<ServerState>)> = self
+            .config
+            .tenants
+            .iter()
+            .map(|(prefix, config)| {
+                info!(
+                    "Serving tenant /{} from {:?}",
+                    prefix, config.extensions_dir
+                );
+                let state = web::Data::new(ServerState::new(config.clone(), http_client.clone()));
+                spawn_background_tasks(&state);
+                (prefix.clone(), state)
+            })
+            .collect();
 
+        let all_states: Vec<web::Data<ServerState>> = std::iter::once(server_state.clone())
+            .chain(tenant_states.iter().map(|(_, state)| state.clone()))
+            .collect();
+
+        // Plaintext requests are redirected to the TLS port, when both are served
+        let redirect_port = (self.config.tls.is_some()
+            && self.config.http_port.is_some()
+            && self.config.redirect_http)
+            .then_some(self.config.port);
+
+        let timeouts = self.config.timeouts.clone();
+        let cors = self.config.cors.clone();
+
+        let mut server = HttpServer::new(move || {
+            let timeouts = timeouts.clone();
             let mut app = App::new()
-                .app_data(state.clone())
-                .wrap(Logger::default())
-                .service(web::resource(HEALTH_CHECK_PATH).to(health::health_check))
-                .configure(extensions::configure)
-                .configure(releases::configure);
-
-            if let Some(releases_dir) = config.releases_dir.clone() {
-                if releases_dir.exists() {
-                    app = app.configure({
-                        let dir = releases_dir.clone();
-                        move |cfg| releases::configure_static_assets(cfg, dir.clone())
-                    });
+                .wrap_fn(move |req, srv| {
+                    let path = req.path().to_string();
+                    let timeout = timeouts.for_route(&path);
+                    let response = srv.call(req);
+                    async move {
+                        let Some(timeout) = timeout else {
+                            return Ok(response.await?.map_into_boxed_body());
+                        };
+                        let deadline = tokio::time::Instant::now() + timeout;
+                        match tokio::time::timeout_at(deadline, response).await {
+                            Ok(response) => Ok(response?
+                                .map_body(|_, body| DeadlineBody::new(body, deadline, path))
+                                .map_into_boxed_body()),
+                            Err(_) => {
+                                warn!("Request for {} timed out after {:?}", path, timeout);
+                                Err(ApiError::GatewayTimeout("Request timed out".to_string())
+                                    .into())
+                            }
+                        }
+                    }
+                })
+                .wrap_fn(move |req, srv| match https_location(&req, redirect_port) {
+                    Some(location) => {
+                        let response = HttpResponse::PermanentRedirect()
+                            .insert_header((header::LOCATION, location))
+                            .finish();
+                        Either::Left(future::ok(
+                            req.into_response(response).map_into_right_body(),
+                        ))
+                    }
+                    None => {
+                        let response = srv.call(req);
+                        Either::Right(async move { Ok(response.await?.map_into_left_body()) })
+                    }
+                })
+                .wrap(Condition::new(
+                    cors.is_some(),
+                    cors.as_ref().map(cors_middleware).unwrap_or_default(),
+                ))
+                .wrap_fn(|req, srv| {
+                    let id = request_id_or_new(
+                        req.headers()
+                            .get(REQUEST_ID_HEADER)
+                            .and_then(|value| value.to_str().ok()),
+                    );
+                    let header = header::HeaderValue::from_str(&id).ok();
+                    let response = in_request(id, || srv.call(req));
+                    async move {
+                        let mut response = response.await?;
+                        if let Some(header) = header {
+                            response
+                                .headers_mut()
+                                .insert(header::HeaderName::from_static(REQUEST_ID_HEADER), header);
+                        }
+                        Ok(response)
+                    }
+                })
+                .wrap(Logger::new(ACCESS_LOG_FORMAT));
+
+            // Tenants get the full route set under their own prefix and state
+            for (prefix, state) in &tenant_states {
+                app = app.service(served_root(&format!("/{}", prefix), state.clone()));
+            }
+
+            app.service(served_root("", server_state.clone()))
+        })
+        .backlog(self.config.backlog)
+        .max_connections(self.config.max_connections);
+
+        if let Some(keep_alive) = self.config.keep_alive {
+            server = server.keep_alive(keep_alive);
+        }
+        if let Some(workers) = self.config.workers {
+            server = server.workers(workers);
+        }
+
+        if let Some(request_header) = self.config.timeouts.request_header {
+            server = server.client_request_timeout(request_header);
+        }
+
+        let tls = self
+            .config
+            .tls
+            .as_ref()
+            .map(tls::rustls_config)
+            .transpose()?;
+        // Channel ports are served like the main port
+        let channel_ports: Vec<(u16, _)> = self
+            .config
+            .channels
+            .values()
+            .filter_map(|channel| channel.port)
+            .map(|port| (port, tls.clone()))
+            .collect();
+        let ports = std::iter::once((self.config.port, tls))
+            .chain(self.config.http_port.map(|port| (port, None)))
+            .chain(channel_ports);
+        for (port, tls) in ports {
+            let host = self.config.host.as_str();
+            server = match (self.config.timeouts.stall, tls) {
+                (Some(stall), tls) => {
+                    for listener in
+                        timeouts::bind_listeners(host, port, self.config.backlog, stall)?
+                    {
+                        server = match &tls {
+                            Some(tls) => server.listen_rustls_0_23(listener, tls.clone())?,
+                            None => server.listen(listener)?,
+                        };
+                    }
+                    server
                 }
+                (None, Some(tls)) => server
+                    .bind_rustls_0_23((host, port), tls)
+                    .with_context(|| format!("Cannot listen on {}:{}", host, port))?,
+                (None, None) => server
+                    .bind((host, port))
+                    .with_context(|| format!("Cannot listen on {}:{}", host, port))?,
+            };
+        }
+        // Held while serving so the announcement is withdrawn on shutdown
+        let _announcement = if self.config.announce {
+            Some(announce(
+                &self.config.host,
+                self.config.port,
+                self.config.tls.is_some(),
+            )?)
+        } else {
+            None
+        };
+
+        server.run().await?;
+
+        for state in all_states {
+            state.transfer.persist();
+        }
+
+        Ok(())
+    }
+
+    /// Validate the configuration without serving: the served directories and indexes of
+    /// every tenant, the TLS files and the listen address, which is bound and released
+    /// again. Prints the effective configuration as JSON.
+    pub fn check(&self) -> Result<()> {
+        for config in std::iter::once(&self.config).chain(self.config.tenants.values()) {
+            for extensions_dir in config.extensions_dirs() {
+                if !extensions_dir.is_dir() {
+                    bail!("Extensions directory {:?} does not exist", extensions_dir);
+                }
+            }
+            if let Some(releases_dir) = &config.releases_dir
+                && !releases_dir.is_dir()
+            {
+                warn!("Releases directory {:?} does not exist yet", releases_dir);
+            }
+            let mut indexed = false;
+            for extensions_dir in config.extensions_dirs() {
+                let index_file = extensions_dir.join("extensions.json");
+                if index_file.exists() {
+                    load_index(&index_file, config.strict_index)?;
+                    indexed = true;
+                }
+            }
+            if !indexed {
+                warn!("No extensions.json in {:?} yet", config.extensions_dir);
             }
+        }
 
-            app = app.service(web::resource("/api/{path:.*}").to(proxy::proxy_api_request));
-            app = app.service(Files::new(
-                "/extensions-archive",
-                config.extensions_dir.clone(),
-            ));
+        if let Some(tls_config) = &self.config.tls {
+            tls::rustls_config(tls_config)?;
+        }
 
-            app
-        })
-        .bind((self.config.host.as_str(), self.config.port))?
-        .run()
-        .await?;
+        let address = (self.config.host.as_str(), self.config.port);
+        std::net::TcpListener::bind(address).with_context(|| {
+            format!("Cannot listen on {}:{}", self.config.host, self.config.port)
+        })?;
+        if let Some(http_port) = self.config.http_port {
+            std::net::TcpListener::bind((self.config.host.as_str(), http_port))
+                .with_context(|| format!("Cannot listen on {}:{}", self.config.host, http_port))?;
+        }
+        for port in self
+            .config
+            .channels
+            .values()
+            .filter_map(|channel| channel.port)
+        {
+            std::net::TcpListener::bind((self.config.host.as_str(), port))
+                .with_context(|| format!("Cannot listen on {}:{}", self.config.host, port))?;
+        }
 
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&self.config.effective())?
+        );
+        info!("Configuration is valid");
         Ok(())
     }
 }
 
+/// Scope serving a single root directory under a path prefix, recording the
+/// bytes it serves
+fn served_root(prefix: &str, state: web::Data<ServerState>) -> impl HttpServiceFactory + use<> {
+    let transfer = state.transfer.clone();
+    let routing_state = state.clone();
+
+    web::scope(prefix)
+        .app_data(state.clone())
+        .wrap_fn(move |req, srv| {
+            let transfer = transfer.clone();
+            let response = srv.call(req);
+            async move {
+                let response = response.await?;
+                let bytes = match response.response().body().size() {
+                    BodySize::Sized(bytes) => bytes,
+                    _ => 0,
+                };
+                let request = response.request();
+                let endpoint = request
+                    .match_pattern()
+                    .unwrap_or_else(|| "unmatched".to_string());
+                // Handlers name the version they served, the route may not
+                let served = response
+                    .response()
+                    .extensions()
+                    .get::<ServedVersion>()
+                    .cloned();
+                let params = request.match_info();
+                let (id, version) = match &served {
+                    Some(served) => (Some(served.id.as_str()), Some(served.version.as_str())),
+                    None => (params.get("id"), params.get("version")),
+                };
+                transfer.record(&endpoint, id, version, bytes);
+                Ok(response)
+            }
+        })
+        .wrap_fn(move |req, srv| {
+            let config = routing_state.config();
+            let host = config.request_host(req.request());
+            // Tenant prefixes are already matched, the rest is the route within the root
+            let path = req.match_info().unprocessed();
+            let refused =
+                (!config.virtual_hosts.routes(&host, path)).then(|| format!("{}{}", host, path));
+            match refused {
+                Some(route) => {
+                    debug!("Not routing {}, it belongs to the other Zed host", route);
+                    let response = HttpResponse::from(ApiError::NotFound(format!(
+                        "{} is not served on this host",
+                        req.path()
+                    )));
+                    Either::Left(future::ok(
+                        req.into_response(response).map_into_right_body(),
+                    ))
+                }
+                None => {
+                    let response = srv.call(req);
+                    Either::Right(async move { Ok(response.await?.map_into_left_body()) })
+                }
+            }
+        })
+        .configure(move |cfg| configure_routes(cfg, &state))
+}
+
+/// Answer preflights and add CORS headers for the allowed origins, exposing the headers a
+/// dashboard may read
+fn cors_middleware(policy: &CorsPolicy) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(policy.methods.clone())
+        .allowed_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::HeaderName::from_static(REQUEST_ID_HEADER),
+        ])
+        .expose_headers([
+            header::HeaderName::from_static(REQUEST_ID_HEADER),
+            header::ETAG,
+            header::HeaderName::from_static("x-cache"),
+            header::HeaderName::from_static("x-total-count"),
+        ])
+        .max_age(policy.max_age.map(|max_age| max_age.as_secs() as usize));
+    for origin in &policy.origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    cors
+}
+
+/// Where to redirect a request that arrived on the plaintext listener, `None` for requests
+/// served as they are. Health checks are answered on both listeners.
+fn https_location(req: &ServiceRequest, https_port: Option<u16>) -> Option<String> {
+    let https_port = https_port?;
+    if req.app_config().secure() || req.path() == HEALTH_CHECK_PATH {
+        return None;
+    }
+
+    let authority = external::request_authority(req.request());
+    let host = match authority.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => authority,
+    };
+    let authority = if https_port == 443 {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, https_port)
+    };
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    Some(format!("https://{}{}", authority, path))
+}
+
+/// Start the periodic tasks configured for a served root
+fn spawn_background_tasks(state: &web::Data<ServerState>) {
+    let transfer = state.transfer.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(TRANSFER_STATS_PERSIST_INTERVAL);
+        loop {
+            ticker.tick().await;
+            transfer.persist();
+        }
+    });
+
+    if let Some(interval) = state.config.drift_check_interval {
+        info!(
+            "Checking upstream for drift every {} seconds",
+            interval.as_secs()
+        );
+        tokio::spawn(drift::run_drift_checker(state.get_ref().clone(), interval));
+    }
+
+    if let Some(per_minute) = state.config.verify_per_minute.filter(|n| *n > 0) {
+        info!(
+            "Verifying {} archives per minute in the background",
+            per_minute
+        );
+        tokio::spawn(verify::run_verifier(state.get_ref().clone(), per_minute));
+    }
+
+    if state.config.events {
+        tokio::spawn(watcher::run_event_watcher(state.get_ref().clone()));
+    }
+}
+
+/// Register every route served for a single root directory
+fn configure_routes(cfg: &mut web::ServiceConfig, state: &web::Data<ServerState>) {
+    let config = state.config();
+
+    cfg.service(web::resource(HEALTH_CHECK_PATH).to(health::health_check))
+        .configure(admin::configure)
+        .configure(client_config::configure)
+        .configure(stats::configure)
+        .configure(feed::configure)
+        .configure(manifest::configure)
+        .configure(meta::configure)
+        .configure(extensions::configure)
+        .configure(releases::configure);
+
+    if config.events {
+        cfg.configure(events::configure);
+    }
+
+    if let Some(releases_dir) = config.releases_dir.clone()
+        && releases_dir.exists()
+    {
+        releases::configure_static_assets(cfg, releases_dir, config.browsable_indexes);
+    }
+
+    cfg.service(web::resource("/api/{path:.*}").to(proxy::proxy_api_request));
+    // Files missing from an extensions directory are looked up in the next one
+    let mut fallback: Option<Files> = None;
+    for extensions_dir in config.fallback_extensions_dirs.iter().rev() {
+        let files = archive_files(&config, extensions_dir);
+        fallback = Some(match fallback {
+            Some(next) => files.default_handler(next),
+            None => files,
+        });
+    }
+    let mut files = archive_files(&config, &config.extensions_dir);
+    if let Some(next) = fallback {
+        files = files.default_handler(next);
+    }
+    cfg.service(files);
+}
+
+/// Static files of `/extensions-archive` served from one extensions directory
+fn archive_files(config: &ServerConfig, extensions_dir: &Path) -> Files {
+    let policy = config.clone();
+    let dir = extensions_dir.to_path_buf();
+    let files = Files::new("/extensions-archive", extensions_dir)
+        .path_filter(move |path, _| servable_archive(&policy, &dir, path));
+    if config.browsable_indexes {
+        files
+            .show_files_listing()
+            .redirect_to_slash_directory()
+            .files_listing_renderer(listing::render_listing)
+    } else {
+        files
+    }
+}
+
+/// Whether a file of `/extensions-archive` may be served. Quarantined archives never are;
+/// yanked versions and, for pinned extensions, versions other than the pin are withheld
+/// like on the download routes.
+fn servable_archive(config: &ServerConfig, extensions_dir: &Path, path: &Path) -> bool {
+    if path.starts_with(QUARANTINE_DIR) {
+        return false;
+    }
+    let Some((id, version)) = archive_version(path) else {
+        return true;
+    };
+    let version = version.or_else(|| tracked_version(extensions_dir, &id));
+    let pin = config.pins.get(&id);
+    match version {
+        Some(version) => !config.is_yanked(&id, &version) && pin.is_none_or(|pin| *pin == version),
+        // A latest archive of unknown version can't be told apart from the pinned one
+        None => pin.is_none(),
+    }
+}
+
 fn log_server_banner(config: &ServerConfig, health_path: &str) -> Result<()> {
+    let scheme = if config.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
     info!(
         "Starting local Zed extension server on {}:{}",
         config.host, config.port
     );
+    if config.tls.is_some() {
+        info!("TLS enabled, HTTP/2 is negotiated over ALPN");
+    }
+    if let Some(http_port) = config.http_port {
+        if config.redirect_http {
+            info!(
+                "Redirecting plaintext requests on {}:{} to HTTPS",
+                config.host, http_port
+            );
+        } else {
+            info!(
+                "Also serving plaintext HTTP on {}:{}",
+                config.host, http_port
+            );
+        }
+    }
     info!("Serving extensions from {:?}", config.extensions_dir);
+    for extensions_dir in &config.fallback_extensions_dirs {
+        info!("Falling back to extensions in {:?}", extensions_dir);
+    }
+    for (name, channel) in &config.channels {
+        match channel.port {
+            Some(port) => info!(
+                "Serving the {} channel with ?channel={} and on {}:{}",
+                name, name, config.host, port
+            ),
+            None => info!("Serving the {} channel with ?channel={}", name, name),
+        }
+    }
+    if let Some(default_channel) = &config.default_channel {
+        info!(
+            "Clients that don't select a channel get {}",
+            default_channel
+        );
+    }
+    if config.pull_through {
+        info!("Pull-through enabled, missing archives are downloaded from upstream on request");
+    }
     info!(
-        "Health check available at http://{}:{}{}",
-        config.host, config.port, health_path
+        "Health check available at {}://{}:{}{}",
+        scheme, config.host, config.port, health_path
     );
 
     if let Some(releases_dir) = &config.releases_dir {
@@ -133,3 +653,87 @@ fn log_server_banner(config: &ServerConfig, health_path: &str) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::https_location;
+    use actix_web::http::header;
+    use actix_web::test::TestRequest;
+
+    fn location(host: Option<&str>, uri: &str, https_port: Option<u16>) -> Option<String> {
+        let mut req = TestRequest::with_uri(uri);
+        if let Some(host) = host {
+            req = req.insert_header((header::HOST, host));
+        }
+        https_location(&req.to_srv_request(), https_port)
+    }
+
+    #[test]
+    fn https_redirect_targets() {
+        let cases = [
+            (
+                Some("mirror.example:2654"),
+                Some(8443),
+                "https://mirror.example:8443/extensions?filter=a",
+            ),
+            (
+                Some("mirror.example"),
+                Some(8443),
+                "https://mirror.example:8443/extensions?filter=a",
+            ),
+            (
+                Some("mirror.example:2654"),
+                Some(443),
+                "https://mirror.example/extensions?filter=a",
+            ),
+            (
+                Some("mirror.example"),
+                Some(443),
+                "https://mirror.example/extensions?filter=a",
+            ),
+            (
+                Some("[::1]:2654"),
+                Some(8443),
+                "https://[::1]:8443/extensions?filter=a",
+            ),
+            (
+                Some("[::1]"),
+                Some(8443),
+                "https://[::1]:8443/extensions?filter=a",
+            ),
+            (
+                Some("[2001:db8::1]:80"),
+                Some(443),
+                "https://[2001:db8::1]/extensions?filter=a",
+            ),
+            (
+                Some("[2001:db8::1]"),
+                Some(443),
+                "https://[2001:db8::1]/extensions?filter=a",
+            ),
+            // Without a Host header, the listener's own host name is used
+            (
+                None,
+                Some(8443),
+                "https://localhost:8443/extensions?filter=a",
+            ),
+        ];
+        for (host, https_port, expected) in cases {
+            assert_eq!(
+                location(host, "/extensions?filter=a", https_port).as_deref(),
+                Some(expected),
+                "{host:?} {https_port:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn requests_served_without_redirect() {
+        assert_eq!(location(Some("mirror.example"), "/extensions", None), None);
+        assert_eq!(location(Some("mirror.example"), "/health", Some(443)), None);
+        assert_eq!(
+            location(Some("mirror.example"), "/", Some(443)).as_deref(),
+            Some("https://mirror.example/")
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use actix_web::HttpResponse;
+use log::warn;
+use std::path::{Path, PathBuf};
+
+use super::api_error::ApiError;
+
+/// Percent-encoded dots, separators and NUL that must never reach the filesystem, even if
+/// something downstream decodes them again
+const ENCODED_SEQUENCES: [&str; 4] = ["%2e", "%2f", "%5c", "%00"];
+
+/// Check a single path segment taken from a request (an extension id, version, asset
+/// name...) before it is joined into a filesystem path. Rejects empty and hidden names,
+/// `.` and `..`, separators, drive prefixes, NUL and their percent-encodings.
+pub fn is_safe_segment(segment: &str) -> bool {
+    let lower = segment.to_ascii_lowercase();
+    !segment.is_empty()
+        && !segment.starts_with('.')
+        && !segment.contains(['/', '\\', ':', '\0'])
+        && !ENCODED_SEQUENCES
+            .iter()
+            .any(|sequence| lower.contains(sequence))
+}
+
+/// Join a `/`-separated relative path from a request onto `base`, or `None` if any of its
+/// segments is unsafe, see [`is_safe_segment`]
+pub fn safe_join(base: &Path, relative: &str) -> Option<PathBuf> {
+    relative
+        .split('/')
+        .try_fold(base.to_path_buf(), |path, segment| {
+            is_safe_segment(segment).then(|| path.join(segment))
+        })
+}
+
+/// Response for a request whose path segments were rejected
+pub fn invalid_path(requested: &str) -> HttpResponse {
+    warn!("Rejecting request with an unsafe path: {}", requested);
+    ApiError::BadRequest("Invalid path".to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_safe_segment, safe_join};
+    use std::path::Path;
+
+    #[test]
+    fn accepts_ordinary_segments() {
+        for segment in [
+            "foo",
+            "foo-bar_1",
+            "0.2.0",
+            "zed-linux-x86_64.json",
+            "v1.0.0+b.1",
+        ] {
+            assert!(is_safe_segment(segment), "{segment}");
+        }
+    }
+
+    #[test]
+    fn rejects_traversal_and_separators() {
+        for segment in [
+            "",
+            ".",
+            "..",
+            ".hidden",
+            "%2e%2e",
+            "%2E%2E",
+            "%2e.",
+            "foo%2fbar",
+            "foo%2Fbar",
+            "foo%5cbar",
+            "foo%00",
+            "foo/bar",
+            "..\\..\\etc",
+            "foo\\bar",
+            "foo\0bar",
+            "/etc",
+            "C:",
+            "C:\\Windows",
+        ] {
+            assert!(!is_safe_segment(segment), "{segment:?}");
+        }
+    }
+
+    #[test]
+    fn safe_join_stays_under_base() {
+        let base = Path::new("/srv/zedex");
+        assert_eq!(
+            safe_join(base, "releases/stable/zed.tar.gz"),
+            Some(base.join("releases").join("stable").join("zed.tar.gz"))
+        );
+        for relative in [
+            "",
+            "/etc/passwd",
+            "releases//zed",
+            "releases/",
+            "../secret",
+            "releases/../../secret",
+            "releases/%2e%2e/secret",
+            "releases/..\\secret",
+            "releases/zed\0.json",
+            "C:/Windows",
+        ] {
+            assert_eq!(safe_join(base, relative), None, "{relative:?}");
+        }
+    }
+}
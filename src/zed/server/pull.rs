@@ -0,0 +1,77 @@
+use anyhow::{Result, bail};
+use log::info;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::zed::{CacheQuota, Client, archive_path, download_extension_version_by_id};
+
+use super::state::ServerState;
+
+/// Per-archive locks, so concurrent requests for the same version download it once
+#[derive(Default)]
+pub struct PullLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl PullLocks {
+    fn lock_for(&self, key: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(locks.entry(key.to_string()).or_default())
+    }
+
+    /// Drop locks no request is holding or waiting for
+    fn prune(&self) {
+        let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+    }
+}
+
+/// Download an extension version from upstream into the served cache, returning the path
+/// of its archive. Requests arriving while the same version is being pulled wait for that
+/// download instead of starting their own.
+pub async fn pull_extension_version(
+    state: &ServerState,
+    id: &str,
+    version: &str,
+) -> Result<PathBuf> {
+    let extensions_dir = &state.config.extensions_dir;
+    let archive = archive_path(extensions_dir, id, Some(version));
+
+    let lock = state.pulls.lock_for(&format!("{}@{}", id, version));
+    let guard = lock.lock().await;
+    let result = if archive.exists() {
+        Ok(())
+    } else {
+        info!("Pulling {} version {} from upstream", id, version);
+        match state.config.upstream_limit.acquire().await {
+            Ok(_permit) => {
+                // Measured anew for every pull, as the server outlives any one run
+                let quota = state.config.cache_quota.as_ref().map(CacheQuota::fresh);
+                download_extension_version_by_id(
+                    id,
+                    version,
+                    Client::with_http_client(state.http_client.clone()),
+                    extensions_dir,
+                    state.config.scanner.as_ref(),
+                    quota.as_ref(),
+                )
+                .await
+            }
+            Err(busy) => Err(busy.into()),
+        }
+    };
+    drop(guard);
+    drop(lock);
+    state.pulls.prune();
+
+    result?;
+    if !archive.exists() {
+        bail!(
+            "{} version {} was not stored, it may be quarantined",
+            id,
+            version
+        );
+    }
+    Ok(archive)
+}
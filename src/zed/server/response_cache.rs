@@ -0,0 +1,215 @@
+use actix_web::web::Bytes;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::zed::{SignedUrls, sha256_bytes, signed_urls, write_atomic};
+
+use super::config::{ProxyCache, glob_match};
+
+/// Name of the directory, relative to the extensions directory, holding persisted
+/// responses. Hidden so it is neither served nor synced by peers.
+pub const PROXY_CACHE_DIR: &str = ".proxy_cache";
+
+/// Larger responses are never cached, they are artifacts rather than API answers
+const MAX_CACHED_BODY: usize = 4 * 1024 * 1024;
+
+/// Responses with signed download URLs are only served while the signatures stay valid
+/// for this long, so clients have time to use them
+const MIN_SIGNATURE_VALIDITY: Duration = Duration::from_secs(60);
+
+/// A successful upstream API response kept for reuse
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub content_type: String,
+    /// Unix time the response was received
+    pub stored_at: u64,
+    #[serde(skip)]
+    pub body: Bytes,
+    /// Unix time the first signed URL in the body expires
+    #[serde(skip)]
+    pub expires_at: Option<u64>,
+}
+
+/// TTL cache of idempotent upstream GETs made in proxy mode, keyed by upstream path and
+/// query. Optionally persisted so a restart doesn't send every client upstream again.
+pub struct ResponseCache {
+    config: ProxyCache,
+    dir: Option<PathBuf>,
+    entries: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl ResponseCache {
+    pub fn new(config: &ProxyCache, extensions_dir: &Path) -> Self {
+        Self {
+            dir: config.persist.then(|| extensions_dir.join(PROXY_CACHE_DIR)),
+            config: config.clone(),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long responses for an upstream API path are kept, from the most specific
+    /// matching pattern. `None` when the path isn't cached.
+    pub fn ttl(&self, path: &str) -> Option<Duration> {
+        let path = path.trim_start_matches('/');
+        let path = path.split('?').next().unwrap_or(path);
+        self.config
+            .ttls
+            .iter()
+            .filter(|(pattern, _)| glob_match(pattern, path))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, ttl)| *ttl)
+            .filter(|ttl| !ttl.is_zero())
+    }
+
+    /// A fresh cached response for a path and query, checking persisted responses when
+    /// none is in memory
+    pub fn get(&self, path: &str, query: &str) -> Option<CachedResponse> {
+        let ttl = self.ttl(path)?;
+        self.lookup(
+            &cache_key(path, query),
+            ttl,
+            ttl + self.config.stale_if_error,
+        )
+    }
+
+    /// A cached response past its TTL but within the stale-if-error window, to answer with
+    /// when upstream fails
+    pub fn get_stale(&self, path: &str, query: &str) -> Option<CachedResponse> {
+        let ttl = self.ttl(path)?;
+        if self.config.stale_if_error.is_zero() {
+            return None;
+        }
+        let max_age = ttl + self.config.stale_if_error;
+        self.lookup(&cache_key(path, query), max_age, max_age)
+    }
+
+    /// A cached response younger than `max_age`. Responses up to `keep_for` old stay in
+    /// memory for stale-if-error.
+    fn lookup(&self, key: &str, max_age: Duration, keep_for: Duration) -> Option<CachedResponse> {
+        let usable = |response: &CachedResponse, max_age: Duration| {
+            now().saturating_sub(response.stored_at) < max_age.as_secs()
+                && response
+                    .expires_at
+                    .is_none_or(|at| now() + MIN_SIGNATURE_VALIDITY.as_secs() < at)
+        };
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(response) = entries.get(key) {
+            if usable(response, max_age) {
+                return Some(response.clone());
+            }
+            if !usable(response, keep_for) {
+                entries.remove(key);
+            }
+        }
+
+        let response = self
+            .load(key)
+            .filter(|response| usable(response, max_age))?;
+        debug!("Loaded cached response for {} from disk", key);
+        entries.insert(key.to_string(), response.clone());
+        Some(response)
+    }
+
+    /// Keep a successful response for a cached path
+    pub fn put(&self, path: &str, query: &str, status: u16, content_type: &str, body: Bytes) {
+        if self.ttl(path).is_none() || !(200..300).contains(&status) || body.len() > MAX_CACHED_BODY
+        {
+            return;
+        }
+        let key = cache_key(path, query);
+        let Some(expires_at) = signature_expiry(&body) else {
+            debug!("Not caching {}, it has signed URLs of unknown expiry", key);
+            return;
+        };
+        let response = CachedResponse {
+            status,
+            content_type: content_type.to_string(),
+            stored_at: now(),
+            body,
+            expires_at,
+        };
+        self.persist(&key, &response);
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.config.max_entries && !entries.contains_key(&key) {
+            // Make room by dropping the oldest response
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, response)| response.stored_at)
+                .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, response);
+    }
+
+    fn load(&self, key: &str) -> Option<CachedResponse> {
+        let (meta_path, body_path) = self.paths(key)?;
+        let meta = fs::read(meta_path).ok()?;
+        let mut response: CachedResponse = serde_json::from_slice(&meta).ok()?;
+        response.body = fs::read(body_path).ok()?.into();
+        response.expires_at = signature_expiry(&response.body)?;
+        Some(response)
+    }
+
+    fn persist(&self, key: &str, response: &CachedResponse) {
+        let Some((meta_path, body_path)) = self.paths(key) else {
+            return;
+        };
+        let result = serde_json::to_vec(response)
+            .map_err(anyhow::Error::from)
+            .and_then(|meta| {
+                if let Some(dir) = &self.dir {
+                    fs::create_dir_all(dir)?;
+                }
+                write_atomic(&body_path, &response.body)?;
+                write_atomic(&meta_path, meta)
+            });
+        if let Err(e) = result {
+            warn!("Failed to persist cached response for {}: {:#}", key, e);
+        }
+    }
+
+    fn paths(&self, key: &str) -> Option<(PathBuf, PathBuf)> {
+        let dir = self.dir.as_ref()?;
+        let name = &sha256_bytes(key.as_bytes())[..32];
+        Some((
+            dir.join(format!("{}.json", name)),
+            dir.join(format!("{}.body", name)),
+        ))
+    }
+}
+
+/// Key of a path and query, independent of the order of the query parameters
+fn cache_key(path: &str, query: &str) -> String {
+    let path = path.trim_start_matches('/');
+    let mut params: Vec<&str> = query.split('&').filter(|p| !p.is_empty()).collect();
+    params.sort_unstable();
+    if params.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, params.join("&"))
+    }
+}
+
+/// When the signed URLs of a response expire, `Some(None)` when it has none and `None` when
+/// it can't be told, making the response uncacheable
+fn signature_expiry(body: &[u8]) -> Option<Option<u64>> {
+    match signed_urls(body) {
+        SignedUrls::None => Some(None),
+        SignedUrls::ExpireAt(at) => Some(Some(at)),
+        SignedUrls::Unknown => None,
+    }
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
@@ -1,20 +1,98 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 
+use tokio::sync::broadcast;
+
+use log::error;
+
+use crate::zed::{IndexHistory, QuarantineRegistry, TransferStats};
+
+use super::coalesce::UpstreamFetches;
 use super::config::ServerConfig;
+use super::drift::DriftReport;
+use super::pull::PullLocks;
+use super::response_cache::ResponseCache;
+use super::upstream_schema::SchemaMismatches;
+use super::verify::VerifyReport;
+use super::views::{IndexViews, ServedIndexCache};
+use super::watcher::{EVENTS_CAPACITY, MirrorEvent};
+
+/// Entity tag of a checksum manifest and its detached signature
+pub type ManifestSignature = (String, Vec<u8>);
 
 #[derive(Clone)]
 pub struct ServerState {
     pub config: Arc<ServerConfig>,
+    pub drift: Arc<RwLock<DriftReport>>,
+    pub transfer: Arc<TransferStats>,
+    pub index_history: Arc<IndexHistory>,
+    pub verification: Arc<RwLock<VerifyReport>>,
+    /// Signature of the last signed checksum manifest
+    pub manifest_signature: Arc<Mutex<Option<ManifestSignature>>>,
+    pub pulls: Arc<PullLocks>,
+    pub index_views: Arc<IndexViews>,
+    pub served_index: Arc<ServedIndexCache>,
+    pub response_cache: Arc<ResponseCache>,
+    /// Proxied upstream responses that didn't match their schema
+    pub schema_mismatches: Arc<SchemaMismatches>,
+    /// Changes to the mirror, for `/events` subscribers
+    pub events: broadcast::Sender<MirrorEvent>,
+    /// Client for upstream requests, built once so its connections are pooled
+    pub http_client: reqwest::Client,
+    /// Upstream downloads in progress, shared by concurrent requests for the same URL
+    pub upstream_fetches: Arc<UpstreamFetches>,
 }
 
 impl ServerState {
-    pub fn new(config: ServerConfig) -> Self {
+    pub fn new(config: ServerConfig, http_client: reqwest::Client) -> Self {
         Self {
+            http_client,
+            transfer: Arc::new(TransferStats::new(&config.extensions_dir)),
+            index_history: Arc::new(IndexHistory::new(&config.extensions_dir)),
+            response_cache: Arc::new(ResponseCache::new(
+                &config.proxy_cache,
+                &config.extensions_dir,
+            )),
             config: Arc::new(config),
+            drift: Arc::new(RwLock::new(DriftReport::default())),
+            verification: Arc::new(RwLock::new(VerifyReport::default())),
+            manifest_signature: Arc::new(Mutex::new(None)),
+            pulls: Arc::new(PullLocks::default()),
+            index_views: Arc::new(IndexViews::default()),
+            served_index: Arc::new(ServedIndexCache::default()),
+            schema_mismatches: Arc::new(SchemaMismatches::default()),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            upstream_fetches: Arc::new(UpstreamFetches::default()),
         }
     }
 
     pub fn config(&self) -> Arc<ServerConfig> {
         Arc::clone(&self.config)
     }
+
+    /// Current upstream drift report, if a check has completed
+    pub fn drift_report(&self) -> Option<DriftReport> {
+        self.drift
+            .read()
+            .ok()
+            .filter(|drift| drift.last_checked.is_some())
+            .map(|drift| drift.clone())
+    }
+
+    /// Current archive verification report, if a batch has been verified
+    pub fn verification_report(&self) -> Option<VerifyReport> {
+        self.verification
+            .read()
+            .ok()
+            .filter(|report| report.last_sweep.is_some())
+            .map(|report| report.clone())
+    }
+
+    /// Quarantine metadata of the served extensions directory, re-read on each call so
+    /// `zedex quarantine` takes effect without a restart
+    pub fn quarantine(&self) -> QuarantineRegistry {
+        QuarantineRegistry::load(&self.config.extensions_dir).unwrap_or_else(|e| {
+            error!("Failed to load quarantine metadata: {}", e);
+            QuarantineRegistry::default()
+        })
+    }
 }
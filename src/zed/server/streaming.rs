@@ -0,0 +1,238 @@
+use actix_web::body::SizedStream;
+use actix_web::http::header::{self, HttpDate};
+use actix_web::{HttpRequest, HttpResponse};
+use http_range::{HttpRange, HttpRangeParseError};
+use std::io::{self, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+/// Size of the chunks files are read and sent in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Content type of extension archives
+pub const ARCHIVE_CONTENT_TYPE: &str = "application/gzip";
+
+/// Respond with a file read from disk chunk by chunk as the client receives it, so
+/// concurrent downloads of large release assets don't each hold the whole file in memory.
+/// The length is sent up front and counted in the transfer statistics. A file replaced
+/// while it is streamed is served as it was when opened.
+///
+/// A single byte range is answered with 206 Partial Content, so interrupted downloads can
+/// resume. Requests for several ranges, and any range of an empty file, get the whole file.
+pub async fn stream_file(
+    req: &HttpRequest,
+    path: &Path,
+    content_type: &str,
+) -> io::Result<HttpResponse> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    let size = metadata.len();
+    // Whole seconds, as dates are sent and compared in headers
+    let last_modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|elapsed| HttpDate::from(UNIX_EPOCH + Duration::from_secs(elapsed.as_secs())));
+
+    let range = match requested_range(req, size, last_modified) {
+        Ok(range) => range,
+        Err(response) => return Ok(response),
+    };
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type)
+        .insert_header((header::ACCEPT_RANGES, "bytes"));
+    if let Some(last_modified) = last_modified {
+        response.insert_header((header::LAST_MODIFIED, last_modified));
+    }
+
+    let length = match range {
+        Some(range) => {
+            file.seek(SeekFrom::Start(range.start)).await?;
+            response
+                .status(actix_web::http::StatusCode::PARTIAL_CONTENT)
+                .insert_header((
+                    header::CONTENT_RANGE,
+                    format!(
+                        "bytes {}-{}/{}",
+                        range.start,
+                        range.start + range.length - 1,
+                        size
+                    ),
+                ));
+            range.length
+        }
+        None => size,
+    };
+
+    Ok(response.body(SizedStream::new(
+        length,
+        ReaderStream::with_capacity(file.take(length), CHUNK_SIZE),
+    )))
+}
+
+/// The byte range a request asks for, `None` for the whole file, or the 416 response to a
+/// range starting past the end of the file. Range headers that can't be parsed are
+/// ignored as RFC 9110 asks, and several ranges aren't worth a multipart body, both get
+/// the whole file. Returned ranges are never empty.
+fn requested_range(
+    req: &HttpRequest,
+    size: u64,
+    last_modified: Option<HttpDate>,
+) -> Result<Option<HttpRange>, HttpResponse> {
+    let Some(range) = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return Ok(None);
+    };
+
+    // Resuming a download of a file that changed since would splice two different files,
+    // such a download starts over. Only dates are validators, no entity tags are sent.
+    if let Some(if_range) = req.headers().get(header::IF_RANGE) {
+        let unchanged = if_range
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse::<HttpDate>().ok())
+            .is_some_and(|date| Some(date) == last_modified);
+        if !unchanged {
+            return Ok(None);
+        }
+    }
+
+    match HttpRange::parse(range, size) {
+        // A suffix range of an empty file parses as zero bytes at 0
+        Ok(ranges) => Ok(match ranges[..] {
+            [range] if range.length > 0 => Some(range),
+            _ => None,
+        }),
+        Err(HttpRangeParseError::InvalidRange) => Ok(None),
+        Err(HttpRangeParseError::NoOverlap) => Err(HttpResponse::RangeNotSatisfiable()
+            .insert_header((header::CONTENT_RANGE, format!("bytes */{}", size)))
+            .finish()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::requested_range;
+    use actix_web::http::header::HttpDate;
+    use actix_web::http::{StatusCode, header};
+    use actix_web::test::TestRequest;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// What a request is answered with: the range served, `None` for the whole file, or
+    /// the status and Content-Range of an error
+    type Outcome = Result<Option<(u64, u64)>, (StatusCode, String)>;
+
+    fn outcome(
+        headers: &[(header::HeaderName, &str)],
+        size: u64,
+        modified: Option<HttpDate>,
+    ) -> Outcome {
+        let mut req = TestRequest::default();
+        for (name, value) in headers {
+            req = req.insert_header((name.clone(), *value));
+        }
+        match requested_range(&req.to_http_request(), size, modified) {
+            Ok(range) => Ok(range.map(|range| (range.start, range.length))),
+            Err(response) => Err((
+                response.status(),
+                response
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string(),
+            )),
+        }
+    }
+
+    #[test]
+    fn range_selection() {
+        let not_satisfiable =
+            |size: u64| Err((StatusCode::RANGE_NOT_SATISFIABLE, format!("bytes */{size}")));
+        let cases: Vec<(&str, Option<&str>, u64, Outcome)> = vec![
+            ("no header", None, 100, Ok(None)),
+            ("full file", Some("bytes=0-99"), 100, Ok(Some((0, 100)))),
+            ("first bytes", Some("bytes=0-9"), 100, Ok(Some((0, 10)))),
+            ("suffix", Some("bytes=-10"), 100, Ok(Some((90, 10)))),
+            (
+                "suffix longer than file",
+                Some("bytes=-500"),
+                100,
+                Ok(Some((0, 100))),
+            ),
+            ("open ended", Some("bytes=40-"), 100, Ok(Some((40, 60)))),
+            (
+                "end past eof",
+                Some("bytes=90-200"),
+                100,
+                Ok(Some((90, 10))),
+            ),
+            (
+                "start past eof",
+                Some("bytes=100-"),
+                100,
+                not_satisfiable(100),
+            ),
+            (
+                "start far past eof",
+                Some("bytes=500-600"),
+                100,
+                not_satisfiable(100),
+            ),
+            ("reversed", Some("bytes=5-2"), 100, Ok(None)),
+            ("bad syntax", Some("bytes=a-b"), 100, Ok(None)),
+            ("other unit", Some("items=0-9"), 100, Ok(None)),
+            ("several ranges", Some("bytes=0-9,20-29"), 100, Ok(None)),
+            ("suffix of empty file", Some("bytes=-1"), 0, Ok(None)),
+            (
+                "start of empty file",
+                Some("bytes=0-"),
+                0,
+                not_satisfiable(0),
+            ),
+        ];
+        for (name, range, size, expected) in cases {
+            let headers: Vec<_> = range
+                .map(|range| (header::RANGE, range))
+                .into_iter()
+                .collect();
+            assert_eq!(outcome(&headers, size, None), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn if_range_dates() {
+        let modified = HttpDate::from(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let stale = HttpDate::from(UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        let cases = [
+            (
+                "matching date",
+                modified.to_string(),
+                Some(modified),
+                Ok(Some((10, 90))),
+            ),
+            ("stale date", stale.to_string(), Some(modified), Ok(None)),
+            ("unknown modification", modified.to_string(), None, Ok(None)),
+            (
+                "entity tag",
+                "\"abc\"".to_string(),
+                Some(modified),
+                Ok(None),
+            ),
+        ];
+        for (name, if_range, last_modified, expected) in cases {
+            let headers = [
+                (header::RANGE, "bytes=10-"),
+                (header::IF_RANGE, if_range.as_str()),
+            ];
+            assert_eq!(outcome(&headers, 100, last_modified), expected, "{name}");
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use anyhow::{Context, Result};
+use log::warn;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::config::{ServerTimeouts, glob_match};
+
+impl ServerTimeouts {
+    /// How long a request for `path` may take from start to the last byte of its
+    /// response, from the most specific matching pattern
+    pub fn for_route(&self, path: &str) -> Option<Duration> {
+        let path = path.trim_start_matches('/');
+        self.routes
+            .iter()
+            .filter(|(pattern, _)| glob_match(pattern, path))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, timeout)| *timeout)
+            .filter(|timeout| !timeout.is_zero())
+    }
+}
+
+/// Response body that fails once its route's deadline has passed, which makes actix drop
+/// the connection of a client downloading too slowly
+pub struct DeadlineBody<B> {
+    body: B,
+    deadline: Instant,
+    path: String,
+}
+
+impl<B> DeadlineBody<B> {
+    pub fn new(body: B, deadline: Instant, path: String) -> Self {
+        Self {
+            body,
+            deadline,
+            path,
+        }
+    }
+}
+
+impl<B> MessageBody for DeadlineBody<B>
+where
+    B: MessageBody + Unpin,
+{
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        if Instant::now() >= self.deadline {
+            warn!(
+                "Response to {} did not finish in time, closing the connection",
+                self.path
+            );
+            return Poll::Ready(Some(Err(Box::new(std::io::Error::from(
+                std::io::ErrorKind::TimedOut,
+            )))));
+        }
+        Pin::new(&mut self.body).poll_next(cx).map_err(|e| e.into())
+    }
+}
+
+/// Bind the listening sockets for `host` and `port` the way actix does, additionally
+/// setting a stall timeout: connections whose sent data the client hasn't acknowledged
+/// for that long are closed by the kernel. Accepted connections inherit the setting.
+pub fn bind_listeners(
+    host: &str,
+    port: u16,
+    backlog: u32,
+    stall: Duration,
+) -> Result<Vec<TcpListener>> {
+    let addresses = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Cannot resolve {}:{}", host, port))?;
+    addresses
+        .map(|address| {
+            let socket = Socket::new(
+                Domain::for_address(address),
+                Type::STREAM,
+                Some(Protocol::TCP),
+            )?;
+            #[cfg(not(windows))]
+            socket.set_reuse_address(true)?;
+            set_stall_timeout(&socket, stall)?;
+            socket.bind(&address.into())?;
+            socket.listen(backlog.min(i32::MAX as u32) as i32)?;
+            Ok(TcpListener::from(socket))
+        })
+        .collect::<std::io::Result<_>>()
+        .with_context(|| format!("Cannot listen on {}:{}", host, port))
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn set_stall_timeout(socket: &Socket, stall: Duration) -> std::io::Result<()> {
+    socket.set_tcp_user_timeout(Some(stall))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn set_stall_timeout(_socket: &Socket, _stall: Duration) -> std::io::Result<()> {
+    warn!("stall_secs is only supported on Linux, stalled connections stay open");
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use super::TlsConfig;
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use std::sync::Arc;
+
+/// Build the rustls configuration for the listener from PEM files. actix advertises
+/// both `h2` and `http/1.1` over ALPN, so clients negotiate HTTP/2 when they support it.
+pub fn rustls_config(tls: &TlsConfig) -> Result<rustls::ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(&tls.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read TLS certificate {:?}", tls.cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(&tls.key_path)
+        .with_context(|| format!("Failed to read TLS private key {:?}", tls.key_path))?;
+
+    rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")
+}
@@ -0,0 +1,83 @@
+use actix_web::HttpResponse;
+use log::{debug, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::api_error::ApiError;
+
+/// Bound on the upstream fetches the server runs at once, shared by its tenants. Fetches
+/// beyond it wait in line, for at most the queue timeout.
+#[derive(Clone, Debug, Default)]
+pub struct UpstreamLimit {
+    permits: Option<Arc<Semaphore>>,
+    max: usize,
+    queue_timeout: Duration,
+}
+
+/// A fetch gave up waiting for a free upstream slot
+#[derive(Clone, Debug, Error)]
+#[error("All {max} upstream fetch slots stayed busy for {}s", waited.as_secs())]
+pub struct UpstreamBusy {
+    max: usize,
+    waited: Duration,
+}
+
+impl UpstreamLimit {
+    /// Allow `max` concurrent fetches, unlimited when unset
+    pub fn new(max: Option<usize>, queue_timeout: Duration) -> Self {
+        let max = max.filter(|max| *max > 0);
+        Self {
+            permits: max.map(|max| Arc::new(Semaphore::new(max))),
+            max: max.unwrap_or_default(),
+            queue_timeout,
+        }
+    }
+
+    /// Maximum number of concurrent fetches, if limited
+    pub fn max(&self) -> Option<usize> {
+        self.permits.as_ref().map(|_| self.max)
+    }
+
+    pub fn queue_timeout(&self) -> Duration {
+        self.queue_timeout
+    }
+
+    /// Wait for a free upstream slot, held until the returned permit is dropped
+    pub async fn acquire(&self) -> Result<Option<OwnedSemaphorePermit>, UpstreamBusy> {
+        let Some(permits) = &self.permits else {
+            return Ok(None);
+        };
+        if permits.available_permits() == 0 {
+            debug!("Upstream fetch queued, all {} slots are busy", self.max);
+        }
+        match tokio::time::timeout(self.queue_timeout, Arc::clone(permits).acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            // The semaphore is never closed
+            Ok(Err(_)) => Ok(None),
+            Err(_) => {
+                warn!(
+                    "Upstream fetch gave up after waiting {}s for one of {} slots",
+                    self.queue_timeout.as_secs(),
+                    self.max
+                );
+                Err(UpstreamBusy {
+                    max: self.max,
+                    waited: self.queue_timeout,
+                })
+            }
+        }
+    }
+}
+
+impl UpstreamBusy {
+    /// 503 asking the client to come back once the burst has passed
+    pub fn response(&self) -> HttpResponse {
+        ApiError::Unavailable {
+            message: self.to_string(),
+            retry_after_secs: self.waited.as_secs().max(1),
+        }
+        .into()
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::zed::{Extension, Version, parse_version};
+
+/// JSON responses proxy mode relays and persists, checked before either so an upstream
+/// format change doesn't end up in clients or the cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpstreamSchema {
+    /// `{"data": [extension, ...]}` of the index and update checks
+    Index,
+    /// `{"data": [extension, ...]}` listing the versions of one extension
+    Versions,
+    /// `{"version": ..., "url": ...}` of the latest release
+    LatestRelease,
+}
+
+/// An upstream response that doesn't have the expected shape
+#[derive(Debug, Error)]
+#[error("Upstream {schema} response doesn't match the expected schema: {problem}")]
+pub struct SchemaMismatch {
+    pub schema: UpstreamSchema,
+    pub problem: String,
+}
+
+/// Mismatches seen per schema, reported on /stats
+#[derive(Debug, Default)]
+pub struct SchemaMismatches(Mutex<BTreeMap<UpstreamSchema, MismatchCount>>);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MismatchCount {
+    pub count: u64,
+    /// Upstream path and problem of the latest mismatch
+    pub last_path: String,
+    pub last_problem: String,
+    /// Unix timestamp of the latest mismatch
+    pub last_seen: u64,
+}
+
+impl fmt::Display for UpstreamSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Index => "index",
+            Self::Versions => "versions",
+            Self::LatestRelease => "latest release",
+        })
+    }
+}
+
+impl UpstreamSchema {
+    /// Schema of the response to a proxied API path (without the `api/` prefix), if it
+    /// is checked
+    pub fn for_path(path: &str) -> Option<Self> {
+        let segments: Vec<&str> = path.trim_start_matches("api/").split('/').collect();
+        match segments[..] {
+            ["extensions"] | ["extensions", "updates"] => Some(Self::Index),
+            ["extensions", _] => Some(Self::Versions),
+            ["releases", "latest"] | ["releases", _, "latest"] => Some(Self::LatestRelease),
+            _ => None,
+        }
+    }
+
+    /// Check a response body against the schema
+    pub fn validate(self, body: &[u8]) -> Result<(), SchemaMismatch> {
+        let mismatch = |problem: String| SchemaMismatch {
+            schema: self,
+            problem,
+        };
+        let value: Value =
+            serde_json::from_slice(body).map_err(|e| mismatch(format!("not valid JSON: {}", e)))?;
+
+        match self {
+            Self::Index | Self::Versions => {
+                let entries = value
+                    .get("data")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| mismatch("no \"data\" list of extensions".to_string()))?;
+                for (position, entry) in entries.iter().enumerate() {
+                    let extension = Extension::deserialize(entry)
+                        .map_err(|e| mismatch(format!("entry {}: {}", position, e)))?;
+                    if parse_version(&extension.version).is_none() {
+                        return Err(mismatch(format!(
+                            "entry {} ({}): version '{}' has no version number",
+                            position, extension.id, extension.version
+                        )));
+                    }
+                }
+            }
+            Self::LatestRelease => {
+                Version::deserialize(&value).map_err(|e| mismatch(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SchemaMismatches {
+    pub fn record(&self, path: &str, mismatch: &SchemaMismatch) {
+        let mut counts = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = counts
+            .entry(mismatch.schema)
+            .or_insert_with(|| MismatchCount {
+                count: 0,
+                last_path: String::new(),
+                last_problem: String::new(),
+                last_seen: 0,
+            });
+        entry.count += 1;
+        entry.last_path = path.to_string();
+        entry.last_problem = mismatch.problem.clone();
+        entry.last_seen = chrono::Utc::now().timestamp() as u64;
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<UpstreamSchema, MismatchCount> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
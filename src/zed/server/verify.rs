@@ -0,0 +1,230 @@
+use anyhow::Result;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::zed::{QUARANTINE_DIR, check_archive, sha256_file, write_atomic};
+
+use super::state::ServerState;
+
+/// Checksums recorded by the verifier, relative to the extensions directory
+pub const VERIFIED_CHECKSUMS_FILE: &str = "verified_checksums.json";
+
+/// Append-only log of problems found by the verifier, relative to the extensions directory
+pub const AUDIT_LOG_FILE: &str = "audit.log";
+
+/// How often a batch of archives is verified
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Results of the background archive verification
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// Unix timestamp of the last batch
+    pub last_sweep: Option<u64>,
+    /// Archives verified since the server started
+    pub verified: u64,
+    /// Problems of currently corrupt archives, keyed by path relative to the extensions directory
+    pub corrupt: BTreeMap<String, String>,
+}
+
+impl VerifyReport {
+    /// Human readable one-line summary of the corrupt archives
+    pub fn summary(&self) -> String {
+        let files: Vec<&str> = self.corrupt.keys().map(String::as_str).collect();
+        format!("{} corrupt archives: {}", files.len(), files.join(", "))
+    }
+}
+
+/// Checksum of an archive as it was last seen intact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedChecksum {
+    size: u64,
+    /// Modification time in seconds since the epoch
+    modified: u64,
+    sha256: String,
+    /// Unix timestamp the archive was last found intact
+    #[serde(default)]
+    verified_at: Option<u64>,
+}
+
+/// When each archive was last found intact by the verifier, keyed by path relative to
+/// the extensions directory
+pub fn last_verified(extensions_dir: &Path) -> BTreeMap<String, u64> {
+    let recorded: BTreeMap<String, RecordedChecksum> =
+        fs::read_to_string(extensions_dir.join(VERIFIED_CHECKSUMS_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+    recorded
+        .into_iter()
+        .filter_map(|(relative, checksum)| Some((relative, checksum.verified_at?)))
+        .collect()
+}
+
+/// Re-hash a few archives per minute, flagging archives whose contents changed without
+/// being rewritten or that no longer decompress
+pub async fn run_verifier(state: ServerState, per_minute: usize) {
+    let extensions_dir = state.config.extensions_dir.clone();
+    let checksums_file = extensions_dir.join(VERIFIED_CHECKSUMS_FILE);
+    let mut recorded: BTreeMap<String, RecordedChecksum> = fs::read_to_string(&checksums_file)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+    let mut cursor = 0;
+
+    loop {
+        ticker.tick().await;
+
+        let dir = extensions_dir.clone();
+        let batch_start = cursor;
+        let checked = tokio::task::spawn_blocking(move || {
+            let archives = list_archives(&dir);
+            let total = archives.len();
+            let batch: Vec<(String, Result<RecordedChecksum, String>)> = archives
+                .into_iter()
+                .cycle()
+                .skip(batch_start % total.max(1))
+                .take(per_minute.min(total))
+                .map(|(relative, path)| {
+                    let result = verify_file(&path).map_err(|e| format!("{:#}", e));
+                    (relative, result)
+                })
+                .collect();
+            (batch, total)
+        })
+        .await;
+
+        let Ok((batch, total)) = checked else {
+            continue;
+        };
+        cursor = if total == 0 {
+            0
+        } else {
+            (cursor + batch.len()) % total
+        };
+
+        let Ok(mut report) = state.verification.write() else {
+            continue;
+        };
+        report.last_sweep = Some(chrono::Utc::now().timestamp() as u64);
+
+        for (relative, result) in batch {
+            report.verified += 1;
+            let problem = match result {
+                Err(problem) => Some(problem),
+                Ok(checksum) => match recorded.get(&relative) {
+                    Some(known)
+                        if known.size == checksum.size
+                            && known.modified == checksum.modified
+                            && known.sha256 != checksum.sha256 =>
+                    {
+                        Some(format!(
+                            "checksum changed from {} to {} without the file being rewritten",
+                            known.sha256, checksum.sha256
+                        ))
+                    }
+                    _ => {
+                        recorded.insert(relative.clone(), checksum);
+                        None
+                    }
+                },
+            };
+
+            match problem {
+                Some(problem) => {
+                    if report.corrupt.get(&relative) != Some(&problem) {
+                        error!("Archive {} is corrupt: {}", relative, problem);
+                        audit(&extensions_dir, &relative, &problem);
+                    }
+                    report.corrupt.insert(relative, problem);
+                }
+                None => {
+                    if report.corrupt.remove(&relative).is_some() {
+                        info!("Archive {} verified intact again", relative);
+                    }
+                }
+            }
+        }
+        drop(report);
+
+        recorded.retain(|relative, _| extensions_dir.join(relative).exists());
+        if let Err(e) = serde_json::to_string_pretty(&recorded)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| write_atomic(&checksums_file, json))
+        {
+            error!("Failed to persist verified checksums: {:#}", e);
+        }
+        debug!(
+            "Verified a batch of archives, next batch starts at {}",
+            cursor
+        );
+    }
+}
+
+/// Archives below the extensions directory, keyed by `/`-separated relative path
+fn list_archives(extensions_dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut archives = Vec::new();
+    collect_archives(extensions_dir, "", &mut archives);
+    archives.sort();
+    archives
+}
+
+fn collect_archives(dir: &Path, prefix: &str, archives: &mut Vec<(String, PathBuf)>) {
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if name.starts_with('.') || (prefix.is_empty() && name == QUARANTINE_DIR) {
+            continue;
+        }
+
+        let path = entry.path();
+        let relative = format!("{}{}", prefix, name);
+        if path.is_dir() {
+            collect_archives(&path, &format!("{}/", relative), archives);
+        } else if name.ends_with(".tgz") || name.ends_with(".tar.gz") {
+            archives.push((relative, path));
+        }
+    }
+}
+
+fn verify_file(path: &Path) -> Result<RecordedChecksum> {
+    let metadata = fs::metadata(path)?;
+    let sha256 = sha256_file(path)?;
+    check_archive(path)?;
+
+    Ok(RecordedChecksum {
+        size: metadata.len(),
+        modified: metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        sha256,
+        verified_at: Some(chrono::Utc::now().timestamp() as u64),
+    })
+}
+
+/// Append a problem to the audit log
+fn audit(extensions_dir: &Path, relative: &str, problem: &str) {
+    let line = serde_json::json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "event": "corrupt_archive",
+        "path": relative,
+        "problem": problem,
+    });
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(extensions_dir.join(AUDIT_LOG_FILE))
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = result {
+        error!("Failed to write the audit log: {}", e);
+    }
+}
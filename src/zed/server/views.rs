@@ -0,0 +1,123 @@
+use crate::zed::Extensions;
+use crate::zed::extensions_utils::ExtensionSort;
+use actix_web::web::Bytes;
+use anyhow::Result;
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Views kept per index revision, further combinations are built on every request
+const MAX_VIEWS: usize = 64;
+
+/// Modification time and length of a file, `None` when it doesn't exist
+type FileStamp = Option<(SystemTime, u64)>;
+
+/// Files an index was built from, with their stamps when it was built
+type SourceStamps = Vec<(PathBuf, FileStamp)>;
+
+/// The index as served: version policies applied, most downloaded first
+pub struct ServedIndex {
+    pub extensions: Extensions,
+    /// Entity tag of the index revision
+    pub revision: String,
+}
+
+/// The served index, rebuilt only when one of the files it is made of changes, so polls
+/// don't re-read, re-validate and re-hash an unchanged index
+#[derive(Default)]
+pub struct ServedIndexCache {
+    cached: Mutex<Option<(SourceStamps, Arc<ServedIndex>)>>,
+}
+
+impl ServedIndexCache {
+    /// The index built from `sources`, built with `build` unless it was built before
+    /// from sources that are unchanged since. Missing sources count as unchanged while
+    /// they stay missing.
+    pub fn get_or_build(
+        &self,
+        sources: Vec<PathBuf>,
+        build: impl FnOnce() -> Result<Option<ServedIndex>>,
+    ) -> Result<Option<Arc<ServedIndex>>> {
+        // Taken before building, so changes made meanwhile trigger another build
+        let stamps: SourceStamps = sources
+            .into_iter()
+            .map(|path| {
+                let stamp = stamp(&path);
+                (path, stamp)
+            })
+            .collect();
+        {
+            let cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some((cached_stamps, index)) = &*cached
+                && *cached_stamps == stamps
+            {
+                return Ok(Some(index.clone()));
+            }
+        }
+
+        let index = build()?.map(Arc::new);
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        *cached = index.clone().map(|index| (stamps, index));
+        Ok(index)
+    }
+}
+
+fn stamp(path: &Path) -> FileStamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// A serialized index response and the number of extensions it lists
+pub type IndexView = (usize, Bytes);
+
+/// A view's `max_schema_version`, `provides` capability, channel and order (descending or not)
+pub type ViewKey = (
+    Option<i32>,
+    Option<String>,
+    Option<String>,
+    (ExtensionSort, bool),
+);
+
+/// Serialized views of the index per `max_schema_version` and capability, so the frequent
+/// polls from Zed clients and pickers don't re-filter and re-serialize the whole index.
+/// Views are dropped as soon as the served index revision changes.
+#[derive(Default)]
+pub struct IndexViews {
+    views: Mutex<(String, HashMap<ViewKey, IndexView>)>,
+}
+
+impl IndexViews {
+    /// The view of `revision` for `key`, built with `build` if it isn't cached
+    pub fn get_or_build(
+        &self,
+        revision: &str,
+        key: ViewKey,
+        build: impl FnOnce() -> serde_json::Result<IndexView>,
+    ) -> serde_json::Result<IndexView> {
+        {
+            let views = self.views.lock().unwrap_or_else(|e| e.into_inner());
+            if views.0 == revision
+                && let Some(view) = views.1.get(&key)
+            {
+                return Ok(view.clone());
+            }
+        }
+
+        let view = build()?;
+        let mut views = self.views.lock().unwrap_or_else(|e| e.into_inner());
+        if views.0 != revision {
+            debug!(
+                "Index revision changed to {}, dropping cached views",
+                revision
+            );
+            *views = (revision.to_string(), HashMap::new());
+        }
+        if views.1.len() < MAX_VIEWS {
+            views.1.insert(key, view.clone());
+        }
+        Ok(view)
+    }
+}
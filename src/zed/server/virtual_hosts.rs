@@ -0,0 +1,28 @@
+use super::config::VirtualHosts;
+
+/// First path segments only the extensions API host serves
+const API_SEGMENTS: [&str; 1] = ["extensions"];
+
+/// First path segments only the website serves
+const WEB_SEGMENTS: [&str; 2] = ["api", "releases"];
+
+impl VirtualHosts {
+    /// Whether a request for `host` may reach the route at `path`. Paths of the other
+    /// host are refused, e.g. `/extensions` on the website, where upstream serves pages
+    /// rather than the API. Mirror routes such as `/health` are served on every host.
+    pub fn routes(&self, host: &str, path: &str) -> bool {
+        let segment = path
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .unwrap_or_default();
+        let listed = |hosts: &[String]| hosts.iter().any(|name| name.eq_ignore_ascii_case(host));
+        if listed(&self.api) {
+            !WEB_SEGMENTS.contains(&segment)
+        } else if listed(&self.web) {
+            !API_SEGMENTS.contains(&segment)
+        } else {
+            true
+        }
+    }
+}
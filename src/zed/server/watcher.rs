@@ -0,0 +1,176 @@
+use log::{debug, error};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::zed::load_index;
+
+use super::config::ServerConfig;
+use super::state::ServerState;
+
+/// How often the served directories are scanned for changes
+const EVENTS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Events buffered for subscribers that fall behind
+pub const EVENTS_CAPACITY: usize = 256;
+
+/// A change to the mirror, broadcast to `/events` subscribers
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum MirrorEvent {
+    /// An extension was added to the index or its listed version changed
+    ExtensionVersion {
+        id: String,
+        version: String,
+        previous: Option<String>,
+    },
+    /// An extension was dropped from the index
+    ExtensionRemoved { id: String },
+    /// A release asset was mirrored
+    Release { version: String, asset: String },
+}
+
+impl MirrorEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::ExtensionVersion { .. } => "extension-version",
+            Self::ExtensionRemoved { .. } => "extension-removed",
+            Self::Release { .. } => "release",
+        }
+    }
+
+    /// The event as a Server-Sent Events message
+    pub fn to_sse(&self) -> String {
+        let data = serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string());
+        format!("event: {}\ndata: {}\n\n", self.name(), data)
+    }
+}
+
+/// What the mirror served at the last scan
+#[derive(Clone, Default)]
+struct MirrorSnapshot {
+    index_modified: Option<SystemTime>,
+    /// Listed version of every extension in the index
+    versions: BTreeMap<String, String>,
+    /// Mirrored release assets as `(version, file name)`
+    releases: BTreeSet<(String, String)>,
+}
+
+impl MirrorSnapshot {
+    /// Scan the served directories, re-reading the index only when it changed since `previous`
+    fn scan(config: &ServerConfig, previous: &MirrorSnapshot) -> MirrorSnapshot {
+        let index_file = config.extensions_dir.join("extensions.json");
+        let index_modified = fs::metadata(&index_file)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let versions = if index_modified.is_some() && index_modified == previous.index_modified {
+            previous.versions.clone()
+        } else {
+            match load_index(&index_file, false) {
+                Ok(index) => index
+                    .into_iter()
+                    .map(|extension| (extension.id, extension.version))
+                    .collect(),
+                Err(e) => {
+                    debug!("Index unavailable for events: {:#}", e);
+                    previous.versions.clone()
+                }
+            }
+        };
+
+        MirrorSnapshot {
+            index_modified,
+            versions,
+            releases: config
+                .releases_dir
+                .as_deref()
+                .map(release_assets)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Events leading from `self` to `current`
+    fn changes(&self, current: &MirrorSnapshot) -> Vec<MirrorEvent> {
+        let mut events = Vec::new();
+        for (id, version) in &current.versions {
+            let previous = self.versions.get(id);
+            if previous != Some(version) {
+                events.push(MirrorEvent::ExtensionVersion {
+                    id: id.clone(),
+                    version: version.clone(),
+                    previous: previous.cloned(),
+                });
+            }
+        }
+        for id in self.versions.keys() {
+            if !current.versions.contains_key(id) {
+                events.push(MirrorEvent::ExtensionRemoved { id: id.clone() });
+            }
+        }
+        for (version, asset) in current.releases.difference(&self.releases) {
+            events.push(MirrorEvent::Release {
+                version: version.clone(),
+                asset: asset.clone(),
+            });
+        }
+        events
+    }
+}
+
+/// Release assets in the version directories of `releases_dir`, skipping partial downloads
+fn release_assets(releases_dir: &Path) -> BTreeSet<(String, String)> {
+    let mut assets = BTreeSet::new();
+    let Ok(dirs) = fs::read_dir(releases_dir) else {
+        return assets;
+    };
+    for dir in dirs.flatten() {
+        let path = dir.path();
+        let Some(version) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Ok(files) = fs::read_dir(&path) else {
+            continue;
+        };
+        for file in files.flatten() {
+            if let Some(asset) = file.file_name().to_str()
+                && !asset.ends_with(".part")
+            {
+                assets.insert((version.to_string(), asset.to_string()));
+            }
+        }
+    }
+    assets
+}
+
+/// Scan the served directories periodically and broadcast what changed. The first scan
+/// only records the current state.
+pub async fn run_event_watcher(state: ServerState) {
+    let mut ticker = tokio::time::interval(EVENTS_POLL_INTERVAL);
+    let mut known: Option<MirrorSnapshot> = None;
+    loop {
+        ticker.tick().await;
+        let config = state.config();
+        let previous = known.clone().unwrap_or_default();
+        let current =
+            match tokio::task::spawn_blocking(move || MirrorSnapshot::scan(&config, &previous))
+                .await
+            {
+                Ok(current) => current,
+                Err(e) => {
+                    error!("Scanning for mirror events failed: {}", e);
+                    continue;
+                }
+            };
+
+        if let Some(previous) = &known {
+            for event in previous.changes(&current) {
+                debug!("Broadcasting {} event", event.name());
+                // Sending only fails while nobody is subscribed
+                let _ = state.events.send(event);
+            }
+        }
+        known = Some(current);
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::{Context, Result, bail};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{sha256_file, write_atomic};
+
+/// Name of the checksum list written to every release version directory
+pub const SHA256SUMS_FILE: &str = "SHA256SUMS";
+
+/// External tool used to create and check detached signatures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureTool {
+    /// `gpg`, with keys given as key ids and trusted keys as keyring files
+    #[default]
+    Gpg,
+    /// `minisign`, with keys given as key files
+    Minisign,
+}
+
+impl SignatureTool {
+    /// File extension of detached signatures
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gpg => "asc",
+            Self::Minisign => "minisig",
+        }
+    }
+}
+
+/// Creates detached signatures of generated metadata
+#[derive(Debug, Clone)]
+pub struct Signer {
+    pub tool: SignatureTool,
+    /// gpg key id, or path to a minisign secret key
+    pub key: String,
+}
+
+/// Checks detached signatures against a trusted key
+#[derive(Debug, Clone)]
+pub struct SignatureVerifier {
+    pub tool: SignatureTool,
+    /// gpg keyring, or minisign public key file
+    pub trusted_key: PathBuf,
+}
+
+impl Signer {
+    /// Write a detached signature next to a file, returning its path
+    pub fn sign_file(&self, path: &Path) -> Result<PathBuf> {
+        let mut signature = path.as_os_str().to_owned();
+        signature.push(format!(".{}", self.tool.extension()));
+        let signature = PathBuf::from(signature);
+
+        let mut command = match self.tool {
+            SignatureTool::Gpg => {
+                let mut command = Command::new("gpg");
+                command
+                    .args(["--batch", "--yes", "--armor", "--local-user", &self.key])
+                    .arg("--output")
+                    .arg(&signature)
+                    .arg("--detach-sign")
+                    .arg(path);
+                command
+            }
+            SignatureTool::Minisign => {
+                let mut command = Command::new("minisign");
+                command
+                    .args(["-S", "-s", &self.key, "-m"])
+                    .arg(path)
+                    .arg("-x")
+                    .arg(&signature);
+                command
+            }
+        };
+        run(&mut command).with_context(|| format!("Failed to sign {:?}", path))?;
+        debug!("Signed {:?}", path);
+        Ok(signature)
+    }
+
+    /// Detached signature of a byte buffer
+    pub fn sign_bytes(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let dir = scratch_dir()?;
+        let data = dir.path.join("data");
+        fs::write(&data, bytes)?;
+        let signature = self.sign_file(&data)?;
+        Ok(fs::read(signature)?)
+    }
+}
+
+impl SignatureVerifier {
+    /// Check a detached signature of a byte buffer
+    pub fn verify_bytes(&self, bytes: &[u8], signature: &[u8]) -> Result<()> {
+        let dir = scratch_dir()?;
+        let data = dir.path.join("data");
+        let signature_path = dir.path.join(format!("data.{}", self.tool.extension()));
+        fs::write(&data, bytes)?;
+        fs::write(&signature_path, signature)?;
+
+        let mut command = match self.tool {
+            SignatureTool::Gpg => {
+                let mut command = Command::new("gpgv");
+                command
+                    .arg("--keyring")
+                    .arg(&self.trusted_key)
+                    .arg(&signature_path)
+                    .arg(&data);
+                command
+            }
+            SignatureTool::Minisign => {
+                let mut command = Command::new("minisign");
+                command
+                    .arg("-V")
+                    .arg("-p")
+                    .arg(&self.trusted_key)
+                    .arg("-m")
+                    .arg(&data)
+                    .arg("-x")
+                    .arg(&signature_path);
+                command
+            }
+        };
+        run(&mut command).context("Signature verification failed")
+    }
+}
+
+/// Write a `SHA256SUMS` file to every release version directory below `releases_dir`
+pub fn write_release_checksums(releases_dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(releases_dir).into_iter().flatten().flatten() {
+        let dir = entry.path();
+        if !dir.is_dir() {
+            continue;
+        }
+
+        let mut files: Vec<PathBuf> = fs::read_dir(&dir)?
+            .flatten()
+            .map(|file| file.path())
+            .filter(|path| path.to_string_lossy().ends_with(".tar.gz"))
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        files.sort();
+
+        let mut sums = String::new();
+        for file in files {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            sums.push_str(&format!("{}  {}\n", sha256_file(&file)?, name));
+        }
+        write_atomic(&dir.join(SHA256SUMS_FILE), sums)?;
+    }
+    Ok(())
+}
+
+/// Sign the generated metadata of a mirror: the extension index, the latest-version
+/// JSON of every release platform and the release `SHA256SUMS` files. Files whose
+/// signature is newer than the file itself are left alone.
+pub fn sign_metadata(root: &Path, signer: &Signer) -> Result<usize> {
+    let releases_dir = root.join("releases");
+    let mut files = vec![root.join("extensions.json")];
+    for entry in fs::read_dir(&releases_dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files.push(path.join(SHA256SUMS_FILE));
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+
+    let mut signed = 0;
+    for file in files.into_iter().filter(|file| file.is_file()) {
+        let mut signature = file.as_os_str().to_owned();
+        signature.push(format!(".{}", signer.tool.extension()));
+        let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+        if let (Ok(signed_at), Ok(changed_at)) = (modified(Path::new(&signature)), modified(&file))
+            && signed_at >= changed_at
+        {
+            continue;
+        }
+        signer.sign_file(&file)?;
+        signed += 1;
+    }
+
+    if signed > 0 {
+        info!("Signed {} metadata files in {:?}", signed, root);
+    }
+    Ok(signed)
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run {}, is it installed?", program))?;
+    if !output.status.success() {
+        bail!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Temporary directory removed when dropped
+struct ScratchDir {
+    path: PathBuf,
+}
+
+fn scratch_dir() -> Result<ScratchDir> {
+    let path = std::env::temp_dir().join(format!(
+        "zedex-signing-{}-{}",
+        std::process::id(),
+        chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    ));
+    fs::create_dir_all(&path)?;
+    Ok(ScratchDir { path })
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
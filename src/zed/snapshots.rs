@@ -0,0 +1,112 @@
+use anyhow::{Context, Result, bail};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{parse_index, write_atomic};
+
+/// Name of the directory, relative to the extensions directory, holding index snapshots.
+/// Hidden so peers syncing from the mirror don't replicate it.
+pub const INDEX_SNAPSHOTS_DIR: &str = ".index_snapshots";
+
+/// Number of snapshots kept, older ones are deleted as new ones are taken
+const MAX_SNAPSHOTS: usize = 30;
+
+/// Format of snapshot timestamps, also used to name them on the command line
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// A saved copy of `extensions.json`
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexSnapshot {
+    /// UTC time the snapshot was taken, e.g. `20250101T120000Z`
+    pub timestamp: String,
+    pub size: u64,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// Save a copy of the current `extensions.json` before it is replaced. Nothing is saved when
+/// there is no index yet or it is identical to the newest snapshot.
+pub fn snapshot_index(extensions_dir: &Path) -> Result<Option<IndexSnapshot>> {
+    let index_file = extensions_dir.join("extensions.json");
+    let Ok(content) = fs::read(&index_file) else {
+        return Ok(None);
+    };
+
+    let snapshots = list_index_snapshots(extensions_dir)?;
+    if let Some(newest) = snapshots.last()
+        && fs::read(&newest.path).is_ok_and(|saved| saved == content)
+    {
+        debug!("Index unchanged since snapshot {}", newest.timestamp);
+        return Ok(None);
+    }
+
+    let timestamp = chrono::Utc::now().format(TIMESTAMP_FORMAT).to_string();
+    let path = snapshot_path(extensions_dir, &timestamp);
+    fs::create_dir_all(extensions_dir.join(INDEX_SNAPSHOTS_DIR))?;
+    write_atomic(&path, &content)
+        .with_context(|| format!("Failed to snapshot the index to {:?}", path))?;
+    info!("Saved a snapshot of the index as {}", timestamp);
+
+    let excess = (snapshots.len() + 1).saturating_sub(MAX_SNAPSHOTS);
+    for old in snapshots.iter().take(excess) {
+        if let Err(e) = fs::remove_file(&old.path) {
+            warn!("Failed to remove index snapshot {:?}: {}", old.path, e);
+        }
+    }
+
+    Ok(Some(IndexSnapshot {
+        timestamp,
+        size: content.len() as u64,
+        path,
+    }))
+}
+
+/// Snapshots of an extensions directory, oldest first
+pub fn list_index_snapshots(extensions_dir: &Path) -> Result<Vec<IndexSnapshot>> {
+    let dir = extensions_dir.join(INDEX_SNAPSHOTS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots: Vec<IndexSnapshot> = fs::read_dir(&dir)?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let timestamp = name.strip_prefix("extensions-")?.strip_suffix(".json")?;
+            chrono::NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+            Some(IndexSnapshot {
+                timestamp: timestamp.to_string(),
+                size: entry.metadata().ok()?.len(),
+                path: entry.path(),
+            })
+        })
+        .collect();
+    snapshots.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(snapshots)
+}
+
+/// Replace the served `extensions.json` with a snapshot. The index being replaced is
+/// snapshotted first, so a rollback can itself be undone.
+pub fn rollback_index(extensions_dir: &Path, timestamp: &str) -> Result<()> {
+    let path = snapshot_path(extensions_dir, timestamp);
+    if !path.exists() {
+        bail!("No index snapshot {}", timestamp);
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    parse_index(&content, &path, false)
+        .with_context(|| format!("Index snapshot {} is not a valid index", timestamp))?;
+
+    snapshot_index(extensions_dir)?;
+    write_atomic(&extensions_dir.join("extensions.json"), content)?;
+    info!("Rolled the index back to snapshot {}", timestamp);
+    Ok(())
+}
+
+fn snapshot_path(extensions_dir: &Path, timestamp: &str) -> PathBuf {
+    extensions_dir
+        .join(INDEX_SNAPSHOTS_DIR)
+        .join(format!("extensions-{}.json", timestamp))
+}
@@ -0,0 +1,70 @@
+use anyhow::{Result, anyhow};
+use log::error;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Reason of the last write that failed because the disk was full, cleared by the
+/// next successful write
+static DISK_FULL: Mutex<Option<String>> = Mutex::new(None);
+
+/// Write a file through a `.part` sibling that is renamed into place, so a failed
+/// write never leaves a truncated file behind for clients to download
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let partial = write_partial(path, contents)?;
+    fs::rename(&partial, path).map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        write_error(e, path)
+    })
+}
+
+/// Write a file's `.part` sibling only, for files checked before they are renamed into
+/// place. Returns the sibling's path.
+pub fn write_partial(path: &Path, contents: impl AsRef<[u8]>) -> Result<PathBuf> {
+    let partial = partial_path(path);
+
+    match fs::write(&partial, contents) {
+        Ok(()) => {
+            *DISK_FULL.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            Ok(partial)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(write_error(e, path))
+        }
+    }
+}
+
+/// The `.part` sibling a file is written to before it is renamed into place
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+/// Wrap a write error, recording it when the disk is full
+pub fn write_error(e: io::Error, path: &Path) -> anyhow::Error {
+    if e.kind() != ErrorKind::StorageFull {
+        return anyhow!(e).context(format!("Failed to write {:?}", path));
+    }
+
+    let reason = format!("Disk full while writing {:?}", path);
+    error!("{}", reason);
+    *DISK_FULL.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason.clone());
+    anyhow!(e).context(reason)
+}
+
+/// Why the last write failed for lack of space, if it did
+pub fn disk_full() -> Option<String> {
+    DISK_FULL.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Check whether an error was caused by a full disk
+pub fn is_disk_full(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == ErrorKind::StorageFull)
+    })
+}
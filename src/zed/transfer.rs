@@ -0,0 +1,151 @@
+use anyhow::Result;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::write_atomic;
+
+/// Name of the file, relative to the extensions directory, holding transfer counters
+pub const TRANSFER_STATS_FILE: &str = "transfer_stats.json";
+
+/// Bytes and requests served by the mirror
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransferCounters {
+    pub total_bytes: u64,
+    pub total_requests: u64,
+    /// Bytes served per route pattern
+    pub by_endpoint: BTreeMap<String, u64>,
+    /// Bytes served per extension id
+    pub by_extension: BTreeMap<String, u64>,
+    /// Bytes served per day (YYYY-MM-DD)
+    pub by_day: BTreeMap<String, u64>,
+    /// Unix time each extension version was last downloaded, keyed by `id@version`
+    pub last_served: BTreeMap<String, u64>,
+}
+
+impl TransferCounters {
+    /// Load counters from a stats file, returning empty counters if it doesn't exist
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+/// Extension version an archive response serves, attached as a response extension by the
+/// handlers so routes that don't name the version, like `/extensions/{id}/download`, still
+/// update `last_served`
+#[derive(Debug, Clone)]
+pub struct ServedVersion {
+    pub id: String,
+    pub version: String,
+}
+
+/// Transfer counters shared by the server's request handlers and persisted periodically
+pub struct TransferStats {
+    path: PathBuf,
+    counters: Mutex<TransferCounters>,
+    dirty: AtomicBool,
+}
+
+impl TransferStats {
+    /// Create stats backed by the stats file in the given extensions directory
+    pub fn new(extensions_dir: &Path) -> Self {
+        let path = extensions_dir.join(TRANSFER_STATS_FILE);
+        let counters = TransferCounters::load(&path).unwrap_or_else(|e| {
+            error!("Failed to load transfer stats from {:?}: {}", path, e);
+            TransferCounters::default()
+        });
+
+        Self {
+            path,
+            counters: Mutex::new(counters),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Record a served response
+    pub fn record(
+        &self,
+        endpoint: &str,
+        extension_id: Option<&str>,
+        version: Option<&str>,
+        bytes: u64,
+    ) {
+        let day = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+        if let Ok(mut counters) = self.counters.lock() {
+            counters.total_requests += 1;
+            counters.total_bytes += bytes;
+            *counters
+                .by_endpoint
+                .entry(endpoint.to_string())
+                .or_default() += bytes;
+            *counters.by_day.entry(day).or_default() += bytes;
+            if let Some(id) = extension_id {
+                *counters.by_extension.entry(id.to_string()).or_default() += bytes;
+                if let Some(version) = version {
+                    counters.last_served.insert(
+                        format!("{}@{}", id, version),
+                        chrono::Utc::now().timestamp() as u64,
+                    );
+                }
+            }
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Current counter values
+    pub fn snapshot(&self) -> TransferCounters {
+        self.counters
+            .lock()
+            .map(|counters| counters.clone())
+            .unwrap_or_default()
+    }
+
+    /// Write the counters to disk if they changed since the last write
+    pub fn persist(&self) {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        let result = serde_json::to_string_pretty(&self.snapshot())
+            .map_err(anyhow::Error::from)
+            .and_then(|json| write_atomic(&self.path, json));
+
+        match result {
+            Ok(_) => debug!("Persisted transfer stats to {:?}", self.path),
+            Err(e) => {
+                error!(
+                    "Failed to persist transfer stats to {:?}: {:#}",
+                    self.path, e
+                );
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Format a byte count for humans (e.g. `1.5 GB`)
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
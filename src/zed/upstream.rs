@@ -0,0 +1,209 @@
+use anyhow::{Context, Result, bail};
+use log::{debug, info, warn};
+use once_cell::sync::OnceCell;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use super::{REQUEST_ID_HEADER, current_request_id};
+
+/// Proxy every upstream request is routed through, set once at startup
+static UPSTREAM_PROXY: OnceCell<reqwest::Proxy> = OnceCell::new();
+
+/// Timeouts and retries of upstream requests, set once at startup
+static REQUEST_OPTIONS: OnceCell<RequestOptions> = OnceCell::new();
+
+/// Bearer tokens of upstreams requiring authentication, set once at startup
+static UPSTREAM_CREDENTIALS: OnceCell<Vec<UpstreamCredential>> = OnceCell::new();
+
+/// How long to wait for an ssh tunnel to start accepting connections
+const TUNNEL_STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// An `ssh -D` dynamic port forward, exposing a SOCKS5 proxy on a local port
+#[derive(Debug, Clone)]
+pub struct SshTunnel {
+    /// ssh destination, e.g. `user@jump.example.com`
+    pub destination: String,
+    pub port: Option<u16>,
+    pub identity_file: Option<PathBuf>,
+    /// Local port the SOCKS5 proxy listens on
+    pub local_port: u16,
+}
+
+/// Timeouts and retries applied to upstream requests
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    /// Time allowed for a whole request, including reading the response body
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// How often a request is retried after a connection error, a timeout or a
+    /// 429/5xx response
+    pub retries: u32,
+}
+
+/// Bearer token sent with every request to an upstream
+#[derive(Clone)]
+pub struct UpstreamCredential {
+    /// Base URL of the upstream, e.g. `https://mirror.example.com:2654`
+    pub url: String,
+    pub token: String,
+}
+
+/// A running ssh tunnel, stopped when dropped
+pub struct TunnelGuard {
+    child: Child,
+}
+
+/// Route all upstream requests through a proxy, e.g. `socks5h://127.0.0.1:1080`.
+/// Must be called before any client is created.
+pub fn set_upstream_proxy(url: &str) -> Result<()> {
+    let proxy =
+        reqwest::Proxy::all(url).with_context(|| format!("Invalid upstream proxy URL {}", url))?;
+    if UPSTREAM_PROXY.set(proxy).is_err() {
+        warn!("Upstream proxy already configured, ignoring {}", url);
+    } else {
+        info!("Routing upstream requests through {}", url);
+    }
+    Ok(())
+}
+
+/// Apply timeouts and retries to all upstream requests. Must be called before any
+/// client is created.
+pub fn set_request_options(options: RequestOptions) {
+    debug!("Upstream request options: {:?}", options);
+    if REQUEST_OPTIONS.set(options).is_err() {
+        warn!("Upstream request options already configured, ignoring them");
+    }
+}
+
+/// Authenticate requests to upstreams with bearer tokens. Must be called before any
+/// request is sent.
+pub fn set_upstream_credentials(credentials: Vec<UpstreamCredential>) {
+    for credential in &credentials {
+        info!("Authenticating requests to {} with a token", credential.url);
+    }
+    if UPSTREAM_CREDENTIALS.set(credentials).is_err() {
+        warn!("Upstream credentials already configured, ignoring them");
+    }
+}
+
+/// Token of the upstream a URL belongs to. The longest matching base URL wins, so a
+/// mirror can have its own token next to one for its host.
+pub fn token_for(url: &str) -> Option<&'static str> {
+    UPSTREAM_CREDENTIALS
+        .get()?
+        .iter()
+        .filter(|credential| {
+            let base = credential.url.trim_end_matches('/');
+            url.strip_prefix(base)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?']))
+        })
+        .max_by_key(|credential| credential.url.len())
+        .map(|credential| credential.token.as_str())
+}
+
+/// Add the bearer token of the upstream `url` belongs to, if one is configured, and the ID
+/// of the request being served, so upstream logs can be matched with the mirror's
+pub fn authorize(request: reqwest::RequestBuilder, url: &str) -> reqwest::RequestBuilder {
+    let request = match current_request_id() {
+        Some(id) => request.header(REQUEST_ID_HEADER, id),
+        None => request,
+    };
+    match token_for(url) {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// Timeouts and retries configured for upstream requests
+pub fn request_options() -> RequestOptions {
+    REQUEST_OPTIONS.get().cloned().unwrap_or_default()
+}
+
+/// Builder for HTTP clients talking to upstream, honouring the configured proxy and timeouts
+fn client_builder() -> reqwest::ClientBuilder {
+    let mut builder = reqwest::Client::builder().user_agent("zedex");
+    if let Some(proxy) = UPSTREAM_PROXY.get() {
+        builder = builder.proxy(proxy.clone());
+    }
+    if let Some(options) = REQUEST_OPTIONS.get() {
+        if let Some(timeout) = options.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(connect_timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+    }
+    builder
+}
+
+/// HTTP client for upstream requests. Build it once and share it, clones reuse its
+/// connection pool.
+pub fn http_client() -> Result<reqwest::Client> {
+    client_builder()
+        .build()
+        .context("Failed to create the upstream HTTP client")
+}
+
+impl SshTunnel {
+    /// Start the tunnel and route upstream requests through it
+    pub async fn open(&self) -> Result<TunnelGuard> {
+        let mut command = Command::new("ssh");
+        command
+            .arg("-N")
+            .arg("-D")
+            .arg(format!("{}:{}", Ipv4Addr::LOCALHOST, self.local_port))
+            .args(["-o", "ExitOnForwardFailure=yes", "-o", "BatchMode=yes"])
+            .stdin(Stdio::null());
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity_file) = &self.identity_file {
+            command.arg("-i").arg(identity_file);
+        }
+        command.arg(&self.destination);
+
+        debug!("Starting ssh tunnel: {:?}", command);
+        let child = command
+            .spawn()
+            .context("Failed to run ssh, is it installed?")?;
+        let mut guard = TunnelGuard { child };
+
+        let started = Instant::now();
+        while tokio::net::TcpStream::connect((Ipv4Addr::LOCALHOST, self.local_port))
+            .await
+            .is_err()
+        {
+            if let Some(status) = guard.child.try_wait()? {
+                bail!("ssh tunnel to {} exited with {}", self.destination, status);
+            }
+            if started.elapsed() > TUNNEL_STARTUP_TIMEOUT {
+                bail!(
+                    "ssh tunnel to {} did not come up within {:?}",
+                    self.destination,
+                    TUNNEL_STARTUP_TIMEOUT
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+
+        info!(
+            "ssh tunnel to {} listening on port {}",
+            self.destination, self.local_port
+        );
+        set_upstream_proxy(&format!(
+            "socks5h://{}:{}",
+            Ipv4Addr::LOCALHOST,
+            self.local_port
+        ))?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TunnelGuard {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
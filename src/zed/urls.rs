@@ -0,0 +1,129 @@
+use chrono::{DateTime, NaiveDateTime};
+use serde_json::Value;
+use url::Url;
+
+/// Prefixes of the query parameters of S3 and GCS signed URLs
+const SIGNATURE_PARAM_PREFIXES: [&str; 2] = ["x-amz-", "x-goog-"];
+
+/// Query parameters of CloudFront and GCS V2 signed URLs, Azure SAS tokens and plain
+/// download tokens
+const SIGNATURE_PARAMS: [&str; 7] = [
+    "signature",
+    "expires",
+    "key-pair-id",
+    "policy",
+    "sig",
+    "se",
+    "token",
+];
+
+/// When the signed download URLs in a response stop working
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignedUrls {
+    /// No URL in the response is signed
+    None,
+    /// Unix timestamp at which the first signature expires
+    ExpireAt(u64),
+    /// A URL is signed but when it expires can't be told
+    Unknown,
+}
+
+fn is_signature_param(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SIGNATURE_PARAMS.contains(&name.as_str())
+        || SIGNATURE_PARAM_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// A download URL without the signature and expiry parameters upstream may add, so it can
+/// be stored without going stale. Other parameters and URLs that can't be parsed are kept
+/// as they are.
+pub fn strip_signature(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    if !parsed
+        .query_pairs()
+        .any(|(name, _)| is_signature_param(&name))
+    {
+        return url.to_string();
+    }
+
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(name, _)| !is_signature_param(name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    parsed.to_string()
+}
+
+/// When the signed URLs among the string values of a JSON response expire
+pub fn signed_urls(body: &[u8]) -> SignedUrls {
+    let Ok(value) = serde_json::from_slice::<Value>(body) else {
+        return SignedUrls::None;
+    };
+    let mut signed = SignedUrls::None;
+    collect_signed_urls(&value, &mut signed);
+    signed
+}
+
+fn collect_signed_urls(value: &Value, signed: &mut SignedUrls) {
+    match value {
+        Value::String(text) if text.starts_with("http://") || text.starts_with("https://") => {
+            let expiry = match Url::parse(text) {
+                Ok(url) => url_expiry(&url),
+                Err(_) => SignedUrls::None,
+            };
+            *signed = match (*signed, expiry) {
+                (SignedUrls::Unknown, _) | (_, SignedUrls::Unknown) => SignedUrls::Unknown,
+                (SignedUrls::ExpireAt(a), SignedUrls::ExpireAt(b)) => {
+                    SignedUrls::ExpireAt(a.min(b))
+                }
+                (SignedUrls::None, other) | (other, SignedUrls::None) => other,
+            };
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_signed_urls(v, signed)),
+        Value::Object(map) => map.values().for_each(|v| collect_signed_urls(v, signed)),
+        _ => {}
+    }
+}
+
+/// Expiry of a single URL, from the `Expires` timestamp of CloudFront and GCS V2, the
+/// signing date and lifetime of S3 and GCS V4, or the `se` end of an Azure SAS
+fn url_expiry(url: &Url) -> SignedUrls {
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.into_owned()))
+        .collect();
+    if !params.iter().any(|(name, _)| is_signature_param(name)) {
+        return SignedUrls::None;
+    }
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let signed_for = |vendor: &str| {
+        let date = param(&format!("x-{}-date", vendor))?;
+        let lifetime: u64 = param(&format!("x-{}-expires", vendor))?.parse().ok()?;
+        let signed_at = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").ok()?;
+        Some(signed_at.and_utc().timestamp() as u64 + lifetime)
+    };
+    let expiry = param("expires")
+        .and_then(|expires| expires.parse().ok())
+        .or_else(|| signed_for("amz"))
+        .or_else(|| signed_for("goog"))
+        .or_else(|| {
+            let end = DateTime::parse_from_rfc3339(param("se")?).ok()?;
+            Some(end.timestamp() as u64)
+        });
+    expiry.map_or(SignedUrls::Unknown, SignedUrls::ExpireAt)
+}
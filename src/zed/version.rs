@@ -1,7 +1,54 @@
+use semver::{BuildMetadata, Prerelease, Version as SemverVersion};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt;
 
+/// Parse a version the way extension authors actually write them: a `v` or other
+/// non-numeric prefix is stripped (`v1.2.3`, `release-1.2`), missing minor and patch
+/// components are taken as 0 (`1`, `1.2`) and a fourth component becomes build metadata
+/// (`1.2.3.4` as `1.2.3+4`). Pre-release and build suffixes that aren't valid semver are
+/// dropped. `None` only when there are no leading digits at all.
+pub fn parse_version(version: &str) -> Option<SemverVersion> {
+    let version = version.trim();
+    if let Ok(parsed) = SemverVersion::parse(version) {
+        return Some(parsed);
+    }
+
+    let version = version.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let core_end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+    let (core, suffix) = version.split_at(core_end);
+    let mut parts = core.split('.').filter(|part| !part.is_empty());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    let patch = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    let extra: Vec<&str> = parts.collect();
+
+    let mut parsed = SemverVersion::new(major, minor, patch);
+    let (pre, build) = match suffix.split_once('+') {
+        Some((pre, build)) => (pre, Some(build)),
+        None => (suffix, None),
+    };
+    if let Some(pre) = pre.strip_prefix('-') {
+        parsed.pre = Prerelease::new(pre).unwrap_or_default();
+    }
+    let build = extra.into_iter().chain(build).collect::<Vec<_>>().join(".");
+    parsed.build = BuildMetadata::new(&build).unwrap_or_default();
+    Some(parsed)
+}
+
+/// Compare two version strings, leniently parsed where possible and as plain strings
+/// otherwise
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (parse_version(a), parse_version(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => a.cmp(b),
+    }
+}
+
 /// Represents a Zed release version
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Version {
@@ -10,35 +57,9 @@ pub struct Version {
 }
 
 impl Version {
-    /// Parses semantic version components into (major, minor, patch)
-    pub fn parse_semver(&self) -> Option<(u32, u32, u32)> {
-        let parts: Vec<&str> = self.version.split('.').collect();
-        if parts.len() < 3 {
-            return None;
-        }
-
-        let major = parts[0].parse::<u32>().ok()?;
-        let minor = parts[1].parse::<u32>().ok()?;
-        let patch = parts[2].parse::<u32>().ok()?;
-
-        Some((major, minor, patch))
-    }
-
     /// Compare version semantically
     pub fn compare(&self, other: &Version) -> Ordering {
-        match (self.parse_semver(), other.parse_semver()) {
-            (
-                Some((self_major, self_minor, self_patch)),
-                Some((other_major, other_minor, other_patch)),
-            ) => match self_major.cmp(&other_major) {
-                Ordering::Equal => match self_minor.cmp(&other_minor) {
-                    Ordering::Equal => self_patch.cmp(&other_patch),
-                    ordering => ordering,
-                },
-                ordering => ordering,
-            },
-            _ => self.version.cmp(&other.version),
-        }
+        compare_versions(&self.version, &other.version)
     }
 }
 
@@ -67,3 +88,79 @@ impl Ord for Version {
         self.compare(other)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Version, compare_versions, parse_version};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn parses_lenient_forms() {
+        let cases = [
+            ("1.2.3", Some("1.2.3")),
+            (" 1.2.3 ", Some("1.2.3")),
+            ("1.2.3-beta.1+sha.5", Some("1.2.3-beta.1+sha.5")),
+            ("v1.2.3", Some("1.2.3")),
+            ("v1.2", Some("1.2.0")),
+            ("release-1.2", Some("1.2.0")),
+            ("1", Some("1.0.0")),
+            ("1.2", Some("1.2.0")),
+            ("01.02.03", Some("1.2.3")),
+            ("1.2.3.4", Some("1.2.3+4")),
+            ("1.2.3.4.5", Some("1.2.3+4.5")),
+            ("1.2.3.4+build", Some("1.2.3+4.build")),
+            ("1.2-beta", Some("1.2.0-beta")),
+            // Suffixes that aren't valid semver are dropped
+            ("1.2.3-beta_1", Some("1.2.3")),
+            ("1.2.3+build_1", Some("1.2.3")),
+            ("latest", None),
+            ("v", None),
+            ("", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_version(input).map(|version| version.to_string()),
+                expected.map(str::to_string),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn orders_versions() {
+        let cases = [
+            ("0.10.0", "0.2.0", Ordering::Greater),
+            ("v1.2", "1.2.0", Ordering::Equal),
+            ("1", "1.0.0", Ordering::Equal),
+            ("release-2", "1.9.9", Ordering::Greater),
+            ("1.0.0-alpha", "1.0.0", Ordering::Less),
+            ("1.0.0-alpha", "1.0.0-beta", Ordering::Less),
+            ("1.2.3.4", "1.2.3", Ordering::Greater),
+            ("1.2.3.10", "1.2.3.9", Ordering::Greater),
+            // Anything parsable sorts above anything that isn't
+            ("0.0.1", "latest", Ordering::Greater),
+            ("nightly", "0.0.1", Ordering::Less),
+            // Unparsable versions fall back to comparing strings
+            ("nightly", "latest", Ordering::Greater),
+            ("abc", "abc", Ordering::Equal),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(compare_versions(a, b), expected, "{a} vs {b}");
+            assert_eq!(compare_versions(b, a), expected.reverse(), "{b} vs {a}");
+        }
+    }
+
+    #[test]
+    fn sorts_release_versions() {
+        let mut versions: Vec<Version> = ["0.9.0", "dev", "v0.10.0", "0.10.0-pre", "0.2"]
+            .iter()
+            .map(|version| Version {
+                url: String::new(),
+                version: version.to_string(),
+            })
+            .collect();
+        versions.sort();
+        let sorted: Vec<&str> = versions.iter().map(|v| v.version.as_str()).collect();
+        assert_eq!(sorted, ["dev", "0.2", "0.9.0", "0.10.0-pre", "v0.10.0"]);
+    }
+}
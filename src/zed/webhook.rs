@@ -0,0 +1,144 @@
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+use super::history::SyncRecord;
+use super::server::DriftReport;
+use super::transfer::format_bytes;
+
+/// How long a webhook receiver may take to answer before the notification is dropped
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events a webhook can be notified of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    /// A `get all-extensions` or `sync` run mirrored everything
+    SyncCompleted,
+    /// A run failed or some of its downloads did
+    SyncFailed,
+    /// The drift check found the mirror behind upstream
+    Drift,
+}
+
+/// Body sent to a webhook
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// `{"event", "summary", "details"}`
+    #[default]
+    Json,
+    /// A Slack incoming webhook message, also understood by Mattermost and Rocket.Chat
+    Slack,
+}
+
+/// A URL notified of sync and drift events
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: String,
+    pub format: WebhookFormat,
+    /// Events sent to the URL, all when empty
+    pub events: Vec<WebhookEvent>,
+}
+
+/// The configured webhooks. Delivery failures are logged and never fail the run that
+/// triggered them.
+#[derive(Debug, Clone, Default)]
+pub struct Webhooks {
+    pub hooks: Vec<Webhook>,
+}
+
+impl Webhook {
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    /// Scheme and host of the URL for logs, its path often carries a secret
+    fn target(&self) -> String {
+        reqwest::Url::parse(&self.url)
+            .map(|url| url.origin().ascii_serialization())
+            .unwrap_or_else(|_| "an invalid URL".to_string())
+    }
+}
+
+impl Webhooks {
+    /// Notify of a finished `get all-extensions` or `sync` run
+    pub async fn sync_finished(&self, record: &SyncRecord) {
+        let event = if record.succeeded() {
+            WebhookEvent::SyncCompleted
+        } else {
+            WebhookEvent::SyncFailed
+        };
+        let mut summary = format!(
+            "zedex {} from {} {}: {} mirrored, {} failed, {} in {}s (triggered by {})",
+            record.operation,
+            record.source,
+            record.outcome,
+            record.completed,
+            record.failed,
+            format_bytes(record.bytes),
+            record.finished_at - record.started_at,
+            record.triggered_by
+        );
+        if let Some(error) = &record.error {
+            summary.push_str(&format!(": {}", error));
+        }
+        self.send(event, summary, json!(record)).await;
+    }
+
+    /// Notify that the mirror is behind upstream
+    pub async fn drift_detected(&self, report: &DriftReport) {
+        let summary = format!(
+            "zedex mirror is behind upstream: {}, {} extensions not mirrored",
+            report.summary(),
+            report.missing_extensions
+        );
+        self.send(WebhookEvent::Drift, summary, json!(report)).await;
+    }
+
+    async fn send(&self, event: WebhookEvent, summary: String, details: Value) {
+        let hooks: Vec<&Webhook> = self.hooks.iter().filter(|hook| hook.wants(event)).collect();
+        if hooks.is_empty() {
+            return;
+        }
+        let client = match reqwest::Client::builder()
+            .user_agent("zedex")
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Failed to create the webhook client: {}", e);
+                return;
+            }
+        };
+
+        for hook in hooks {
+            let body = match hook.format {
+                WebhookFormat::Json => json!({
+                    "event": event,
+                    "summary": summary,
+                    "details": details,
+                }),
+                WebhookFormat::Slack => json!({ "text": summary }),
+            };
+            match client.post(&hook.url).json(&body).send().await {
+                Ok(response) if response.status().is_success() => {
+                    debug!("Sent {:?} webhook to {}", event, hook.target());
+                }
+                Ok(response) => warn!(
+                    "Webhook {} rejected the {:?} notification: {}",
+                    hook.target(),
+                    event,
+                    response.status()
+                ),
+                Err(e) => warn!(
+                    "Failed to send webhook to {}: {}",
+                    hook.target(),
+                    e.without_url()
+                ),
+            }
+        }
+    }
+}